@@ -1,7 +1,10 @@
 use std::{
     io::Write,
     process::{Command, Stdio},
+    sync::mpsc,
     task::Poll,
+    thread,
+    time::{Duration, Instant},
 };
 
 use crossterm::{
@@ -11,12 +14,34 @@ use crossterm::{
 };
 
 use crate::{
-    async_process::{AsyncChild, ChildOutput, Executor},
-    tui_util::{AvailableSize, LOG_COLORS},
+    async_process::{
+        command_line, AsyncChild, ChildOptions, ChildOutput, Decoder, Executor,
+    },
+    backend_error::BackendError,
+    forge::parse_pull_request,
+    tui_util::{
+        fit_width, log_colors, phase_badge, signature_badge, str_width,
+        tag_colors, unquote_path, AvailableSize, LOG_PHASE_PART, LOG_REFS_PART,
+        LOG_SIGNATURE_PART, LOG_SUBJECT_PART, LOG_TOPICS_PART, TOPICS_COLOR,
+    },
 };
 
 pub type ActionResult = ChildOutput;
 
+/// Actions whose main command takes the flags configured for them
+pub const FLAGGABLE_ACTIONS: &[ActionKind] = &[
+    ActionKind::Fetch,
+    ActionKind::Pull,
+    ActionKind::Push,
+    ActionKind::CommitAll,
+    ActionKind::CommitSelected,
+    ActionKind::Merge,
+    ActionKind::CurrentDiffAll,
+    ActionKind::CurrentDiffSelected,
+    ActionKind::RevisionDiffAll,
+    ActionKind::RevisionDiffSelected,
+];
+
 #[derive(PartialEq, Eq, Hash, Clone, Copy)]
 pub enum ActionKind {
     Quit,
@@ -24,29 +49,91 @@ pub enum ActionKind {
     Status,
     Log,
     LogCount,
+    LogJump,
+    LogBranch,
+    ViewedRevisions,
     CurrentFullRevision,
     CurrentDiffAll,
     CurrentDiffSelected,
+    ExportSelected,
+    RunOnSelected,
     RevisionChanges,
     RevisionDiffAll,
     RevisionDiffSelected,
+    RevisionNote,
+    SetPhase,
+    BaseChanges,
+    BaseDiffAll,
+    CompareRevisions,
+    SetCompareTarget,
+    ClearCompareTarget,
+    CompareWithTarget,
     CommitAll,
     CommitSelected,
+    CommitPinned,
     Update,
     Merge,
+    Ignore,
     RevertAll,
     RevertSelected,
+    RevertHunks,
+    RevertSelectedHunk,
+    ApplySelectedHunk,
+    UndoDiscard,
     UnresolvedConflicts,
+    EditConflicts,
     MergeTakingOther,
     MergeTakingLocal,
+    InterruptedOperation,
+    ContinueOperation,
+    AbortOperation,
     Fetch,
     Pull,
     Push,
+    ForcePush,
     NewTag,
+    RenameTag,
     ListBranches,
+    ListTags,
+    ListPullRequests,
+    CheckoutPullRequest,
+    OpenInBrowser,
     NewBranch,
+    NewBookmark,
     DeleteBranch,
+    PushBranchTo,
+    SetScope,
+    ClearScope,
+    ToggleIgnored,
+    ToggleUntracked,
+    ToggleUntrackedDirs,
+    ToggleBranchLog,
+    ToggleShowKeys,
+    ToggleQuiet,
+    ToggleLineNumbers,
+    ToggleWrap,
+    ToggleSideBySide,
+    TogglePreview,
+    GrowPreview,
+    ShrinkPreview,
+    JumpToFile,
+    JumpToLine,
+    ExpandFolds,
+    ToggleFileCollapsed,
+    CollapseFiles,
+    ExpandFiles,
+    NoteFiles,
+    RemoveNotes,
+    ListNotes,
+    ExportNotes,
+    PinFiles,
+    UnpinFiles,
+    CopyEntry,
+    CopyDiff,
+    KeyDebug,
+    SwitchRepository,
     CustomAction,
+    CommandPalette,
 }
 
 impl ActionKind {
@@ -57,37 +144,165 @@ impl ActionKind {
             Self::Status => "status",
             Self::Log => "log",
             Self::LogCount => "log count",
+            Self::LogJump => "log jump to date or revision",
+            Self::LogBranch => "log of a branch or revision",
+            Self::ViewedRevisions => "recently viewed revisions",
             Self::CurrentFullRevision => "revision full contents",
             Self::CurrentDiffAll => "current diff all",
             Self::CurrentDiffSelected => "current diff selected",
+            Self::ExportSelected => "export selected paths",
+            Self::RunOnSelected => "run command on selected",
             Self::RevisionChanges => "revision changes",
             Self::RevisionDiffAll => "revision diff all",
             Self::RevisionDiffSelected => "revision diff selected",
+            Self::RevisionNote => "revision note",
+            Self::SetPhase => "set phase of revision",
+            Self::BaseChanges => "changes since base",
+            Self::BaseDiffAll => "diff since base",
+            Self::CompareRevisions => "compare revisions",
+            Self::SetCompareTarget => "set compare target",
+            Self::ClearCompareTarget => "clear compare target",
+            Self::CompareWithTarget => "compare with target",
             Self::CommitAll => "commit all",
             Self::CommitSelected => "commit selected",
+            Self::CommitPinned => "commit pinned",
             Self::Update => "update/checkout",
             Self::Merge => "merge",
+            Self::Ignore => "ignore untracked files",
             Self::RevertAll => "revert all",
             Self::RevertSelected => "revert selected",
+            Self::RevertHunks => "revert hunks",
+            Self::RevertSelectedHunk => "revert the selected hunk",
+            Self::ApplySelectedHunk => {
+                "apply the selected hunk to the working tree"
+            }
+            Self::UndoDiscard => "undo last revert",
             Self::UnresolvedConflicts => "unresolved conflicts",
+            Self::EditConflicts => "edit conflicts in merge tool",
             Self::MergeTakingOther => "merge taking other",
             Self::MergeTakingLocal => "merge taking local",
+            Self::InterruptedOperation => "interrupted operation",
+            Self::ContinueOperation => "continue interrupted operation",
+            Self::AbortOperation => "abort interrupted operation",
             Self::Fetch => "fetch",
             Self::Pull => "pull",
             Self::Push => "push",
+            Self::ForcePush => "force push",
             Self::NewTag => "new tag",
+            Self::RenameTag => "rename tag",
             Self::ListBranches => "list branches",
+            Self::ListTags => "list tags",
+            Self::ListPullRequests => "list pull requests",
+            Self::CheckoutPullRequest => "check out pull request",
+            Self::OpenInBrowser => "open in browser",
             Self::NewBranch => "new branch",
+            Self::NewBookmark => "new bookmark",
             Self::DeleteBranch => "delete branch",
+            Self::PushBranchTo => "push branch to",
+            Self::SetScope => "set path scope",
+            Self::ClearScope => "clear path scope",
+            Self::ToggleIgnored => "toggle ignored files in status",
+            Self::ToggleUntracked => "toggle untracked files in status",
+            Self::ToggleUntrackedDirs => {
+                "toggle collapsing untracked directories in status"
+            }
+            Self::ToggleBranchLog => "toggle log of the current branch only",
+            Self::ToggleShowKeys => "toggle showing pressed keys",
+            Self::ToggleQuiet => "toggle quiet rendering",
+            Self::ToggleLineNumbers => "toggle line numbers",
+            Self::ToggleWrap => "toggle wrapping long lines",
+            Self::ToggleSideBySide => "toggle side by side diff",
+            Self::TogglePreview => "toggle preview pane",
+            Self::GrowPreview => "grow preview pane",
+            Self::ShrinkPreview => "shrink preview pane",
+            Self::JumpToFile => "jump to file in diff",
+            Self::JumpToLine => "jump to line",
+            Self::ExpandFolds => "expand unchanged lines",
+            Self::ToggleFileCollapsed => "collapse or expand file",
+            Self::CollapseFiles => "collapse all files",
+            Self::ExpandFiles => "expand all files",
+            Self::NoteFiles => "note files",
+            Self::RemoveNotes => "remove notes",
+            Self::ListNotes => "list notes",
+            Self::ExportNotes => "export notes",
+            Self::PinFiles => "pin files",
+            Self::UnpinFiles => "unpin files",
+            Self::CopyEntry => "copy entry under the cursor",
+            Self::CopyDiff => "copy diff",
+            Self::KeyDebug => "key debug",
+            Self::SwitchRepository => "switch repository",
             Self::CustomAction => "custom action",
+            Self::CommandPalette => "command palette",
         }
     }
 
+    /// Whether the output is drawn while the action runs instead of only
+    /// once it finishes
+    pub fn streams_output(self) -> bool {
+        matches!(self, Self::Status | Self::CustomAction)
+    }
+
+    /// Whether the action only reads from the repository so a new request
+    /// can cancel the one still running instead of waiting for it
+    pub fn is_read_only(self) -> bool {
+        matches!(
+            self,
+            Self::Status
+                | Self::Log
+                | Self::LogCount
+                | Self::CurrentFullRevision
+                | Self::CurrentDiffAll
+                | Self::CurrentDiffSelected
+                | Self::RevisionChanges
+                | Self::RevisionDiffAll
+                | Self::RevisionDiffSelected
+                | Self::BaseChanges
+                | Self::BaseDiffAll
+                | Self::CompareRevisions
+                | Self::UnresolvedConflicts
+                | Self::ListBranches
+                | Self::ListTags
+                | Self::ListPullRequests
+        )
+    }
+
     pub fn can_select_output(self) -> bool {
-        match self {
-            Self::Log | Self::LogCount | Self::ListBranches => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Self::Log
+                | Self::LogCount
+                | Self::ListBranches
+                | Self::ListTags
+                | Self::ListPullRequests
+                | Self::RevisionChanges
+                | Self::BaseChanges
+                | Self::ViewedRevisions
+        )
+    }
+
+    /// Whether the output is made of per file diffs
+    pub fn is_diff(self) -> bool {
+        matches!(
+            self,
+            Self::CurrentFullRevision
+                | Self::CurrentDiffAll
+                | Self::CurrentDiffSelected
+                | Self::RevisionDiffAll
+                | Self::RevisionDiffSelected
+                | Self::BaseDiffAll
+                | Self::CompareRevisions
+        )
+    }
+
+    /// Whether a hunk of the diff is selected for the hunk actions
+    pub fn selects_hunks(self) -> bool {
+        matches!(
+            self,
+            Self::CurrentDiffAll
+                | Self::CurrentDiffSelected
+                | Self::RevisionDiffAll
+                | Self::RevisionDiffSelected
+        )
     }
 
     pub fn line_formatter<W>(
@@ -98,21 +313,72 @@ impl ActionKind {
     {
         match self {
             Self::Log | Self::LogCount => |write, line, available_size| {
-                let mut slice_end = line
-                    .char_indices()
-                    .take(available_size.width - 1)
-                    .last()
-                    .map(|(i, _)| i + 1)
-                    .unwrap_or(0);
-                while !line.is_char_boundary(slice_end) {
-                    slice_end += 1;
+                let log_colors = log_colors();
+                let parts: Vec<_> =
+                    line.splitn(LOG_TOPICS_PART + 1, '\x1e').collect();
+                let mut drawn = Vec::new();
+                for (i, part) in parts.iter().take(LOG_REFS_PART).enumerate() {
+                    drawn.push(if i == LOG_SIGNATURE_PART {
+                        signature_badge(part)
+                    } else {
+                        (*part, log_colors[i])
+                    });
+                }
+                // hg phases and topics go before the refs
+                let phase = parts.get(LOG_PHASE_PART).copied().unwrap_or("");
+                drawn.extend(phase_badge(phase));
+                let topics = match parts.get(LOG_TOPICS_PART) {
+                    Some(topics) if !topics.is_empty() => {
+                        format!("[{}]", topics)
+                    }
+                    _ => String::new(),
+                };
+                if !topics.is_empty() {
+                    drawn.push((&topics, TOPICS_COLOR));
+                }
+                for (i, part) in parts
+                    .iter()
+                    .enumerate()
+                    .take(LOG_SUBJECT_PART + 1)
+                    .skip(LOG_REFS_PART)
+                {
+                    drawn.push((part, log_colors[i]));
                 }
 
-                let line = &line[..slice_end];
+                let mut width = available_size.width - 1;
+                for (part, color) in drawn {
+                    let part = fit_width(part, width);
+                    width -= str_width(part);
+                    handle_command!(write, SetForegroundColor(color))?;
+                    handle_command!(write, Print(part))?;
+                    if width == 0 {
+                        break;
+                    }
+                    handle_command!(write, Print(' '))?;
+                    width -= 1;
+                }
+                Ok(())
+            },
+            // bookmarks are colored like the refs of the log
+            Self::ListBranches => |write, line, available_size| {
+                let line = fit_width(line, available_size.width - 1);
+                let log_colors = log_colors();
+                let mut parts = line.splitn(2, '\x1e');
+                handle_command!(write, SetForegroundColor(log_colors[0]))?;
+                handle_command!(write, Print(parts.next().unwrap_or("")))?;
+                if let Some(kind) = parts.next() {
+                    handle_command!(write, SetForegroundColor(log_colors[5]))?;
+                    handle_command!(write, Print(format!(" ({})", kind)))?;
+                }
+                Ok(())
+            },
+            Self::ListTags => |write, line, available_size| {
+                let line = fit_width(line, available_size.width - 1);
+                let tag_colors = tag_colors();
                 for (part, color) in
-                    line.splitn(LOG_COLORS.len(), '\x1e').zip(LOG_COLORS.iter())
+                    line.splitn(tag_colors.len(), '\x1e').zip(tag_colors)
                 {
-                    handle_command!(write, SetForegroundColor(*color))?;
+                    handle_command!(write, SetForegroundColor(color))?;
                     handle_command!(write, Print(part))?;
                     handle_command!(write, Print(' '))?;
                 }
@@ -127,44 +393,256 @@ impl ActionKind {
     pub fn parse_target(self, line: &str) -> Option<&str> {
         match self {
             Self::Log | Self::LogCount => line.split('\x1e').nth(1),
-            Self::ListBranches => Some(line),
+            Self::ListBranches => line.split('\x1e').next(),
+            Self::ListTags => line.split('\x1e').next().map(str::trim_end),
+            Self::ListPullRequests => parse_pull_request(line),
+            Self::ViewedRevisions => line.split_whitespace().next(),
             _ => None,
         }
     }
+
+    /// Path of the changed file in a revision changes line like `M\tpath`
+    pub fn parse_changed_file(self, line: &str) -> Option<String> {
+        if !matches!(self, Self::RevisionChanges | Self::BaseChanges) {
+            return None;
+        }
+
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let state = parts.next()?;
+        let state = match state.rfind('m') {
+            Some(i) if state.starts_with('\x1b') => &state[i + 1..],
+            _ => state,
+        };
+        if state.is_empty()
+            || !state
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        {
+            return None;
+        }
+
+        // renames are listed as `R100\told\tnew`
+        let path = parts.next()?.rsplit('\t').next()?;
+        let path = path.trim().trim_end_matches("\x1b[0m");
+        Some(unquote_path(path)).filter(|p| !p.is_empty())
+    }
 }
 
 pub trait ActionTask: Send {
     fn poll(&mut self, executor: &mut Executor) -> Poll<ActionResult>;
+    /// Kills any process still running for this task
+    fn cancel(&mut self);
+    /// Identifies what the task runs so identical requests can share a
+    /// result. `None` when it can't be told apart from other tasks
+    fn request_key(&self) -> Option<String> {
+        None
+    }
+    /// Command line of the command the task is running or about to run.
+    /// `None` when it runs none
+    fn command_line(&self) -> Option<String> {
+        None
+    }
+    /// Output the task printed so far while it runs. `None` when it can't
+    /// show any before finishing
+    fn partial_output(&self) -> Option<String> {
+        None
+    }
+    /// Complete lines of the partial output from byte `from` on and the byte
+    /// they end at, so a view can append them as they're printed. `None`
+    /// when it can't show them before finishing
+    fn partial_lines(&self, _from: usize) -> Option<(String, usize)> {
+        None
+    }
+    /// Adds `flags` right after `subcommand`, made of one or more words, to
+    /// the first command the task runs with it. Returns whether one did
+    fn add_flags(&mut self, _subcommand: &[&str], _flags: &[String]) -> bool {
+        false
+    }
+
+    /// Decodes the output of the commands the task runs with `decoder`
+    fn set_decoder(&mut self, _decoder: &Decoder) {}
+}
+
+/// Rebuilds `command` with `flags` right after `subcommand`, skipping the
+/// `-c name=value` and `-C dir` before it. `None` when it runs another one
+fn with_flags(
+    command: &Command,
+    subcommand: &[&str],
+    flags: &[String],
+) -> Option<Command> {
+    let args: Vec<_> = command.get_args().collect();
+    let mut i = 0;
+    while i < args.len() && args[i].to_str().is_some_and(|a| a.starts_with('-'))
+    {
+        i += if args[i] == "-c" || args[i] == "-C" {
+            2
+        } else {
+            1
+        };
+    }
+    let end = i + subcommand.len();
+    if end > args.len() || args[i..end].iter().ne(subcommand.iter()) {
+        return None;
+    }
+
+    let mut flagged = Command::new(command.get_program());
+    flagged.args(&args[..end]).args(flags).args(&args[end..]);
+    if let Some(dir) = command.get_current_dir() {
+        flagged.current_dir(dir);
+    }
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => flagged.env(key, value),
+            None => flagged.env_remove(key),
+        };
+    }
+    Some(flagged)
 }
 
 pub enum CommandTask {
-    Waiting(Command),
+    Waiting(Command, ChildOptions),
     Running(AsyncChild),
 }
 
+impl CommandTask {
+    pub fn new(command: Command) -> Self {
+        Self::Waiting(command, ChildOptions::default())
+    }
+}
+
 impl ActionTask for CommandTask {
     fn poll(&mut self, executor: &mut Executor) -> Poll<ActionResult> {
         match self {
-            CommandTask::Waiting(command) => {
+            CommandTask::Waiting(..) if !executor.has_capacity() => {
+                Poll::Pending
+            }
+            CommandTask::Waiting(command, options) => {
+                let command_line = command_line(command);
+                let stdin = match options.input {
+                    Some(_) => Stdio::piped(),
+                    None => Stdio::null(),
+                };
                 let child = command
-                    .stdin(Stdio::null())
+                    .stdin(stdin)
                     .stdout(Stdio::piped())
                     .stderr(Stdio::piped())
                     .spawn();
                 match child {
                     Ok(child) => {
-                        let async_child = executor.run_child_async(child);
+                        let async_child = executor.run_child_async(
+                            child,
+                            command_line,
+                            options.clone(),
+                        );
                         *self = CommandTask::Running(async_child);
                         Poll::Pending
                     }
-                    Err(e) => {
-                        Poll::Ready(ActionResult::from_err(e.to_string()))
-                    }
+                    Err(e) => Poll::Ready(ActionResult::from_err(
+                        BackendError::from_spawn(command_line, e.to_string()),
+                    )),
                 }
             }
             CommandTask::Running(child) => child.poll(),
         }
     }
+
+    fn cancel(&mut self) {
+        if let CommandTask::Running(child) = self {
+            child.kill();
+        }
+    }
+
+    fn request_key(&self) -> Option<String> {
+        match self {
+            CommandTask::Waiting(command, options) => {
+                Some(format!("{:?} {:?}", command, options))
+            }
+            CommandTask::Running(_) => None,
+        }
+    }
+
+    fn command_line(&self) -> Option<String> {
+        match self {
+            CommandTask::Waiting(command, _) => Some(command_line(command)),
+            CommandTask::Running(child) => Some(child.command_line.clone()),
+        }
+    }
+
+    fn partial_output(&self) -> Option<String> {
+        match self {
+            CommandTask::Waiting(..) => None,
+            CommandTask::Running(child) => Some(child.partial_output()),
+        }
+    }
+
+    fn partial_lines(&self, from: usize) -> Option<(String, usize)> {
+        match self {
+            CommandTask::Waiting(..) => None,
+            CommandTask::Running(child) => Some(child.partial_lines(from)),
+        }
+    }
+
+    fn add_flags(&mut self, subcommand: &[&str], flags: &[String]) -> bool {
+        let command = match self {
+            CommandTask::Waiting(command, _) => command,
+            CommandTask::Running(_) => return false,
+        };
+        match with_flags(command, subcommand, flags) {
+            Some(flagged) => {
+                *command = flagged;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_decoder(&mut self, decoder: &Decoder) {
+        if let CommandTask::Waiting(_, options) = self {
+            options.decoder = Some(decoder.clone());
+        }
+    }
+}
+
+pub fn ready(result: ActionResult) -> Box<dyn ActionTask> {
+    Box::new(ReadyTask(Some(result)))
+}
+
+struct ReadyTask(Option<ActionResult>);
+
+impl ActionTask for ReadyTask {
+    fn poll(&mut self, _executor: &mut Executor) -> Poll<ActionResult> {
+        match self.0.take() {
+            Some(result) => Poll::Ready(result),
+            None => Poll::Pending,
+        }
+    }
+
+    fn cancel(&mut self) {}
+}
+
+/// Runs `f` once the task is first polled instead of when it's built, for
+/// work like touching files that must follow the tasks before it
+pub fn deferred<F>(f: F) -> Box<dyn ActionTask>
+where
+    F: 'static + Send + FnOnce() -> ActionResult,
+{
+    Box::new(DeferredTask(Some(f)))
+}
+
+struct DeferredTask<F>(Option<F>);
+
+impl<F> ActionTask for DeferredTask<F>
+where
+    F: Send + FnOnce() -> ActionResult,
+{
+    fn poll(&mut self, _executor: &mut Executor) -> Poll<ActionResult> {
+        match self.0.take() {
+            Some(f) => Poll::Ready(f()),
+            None => Poll::Pending,
+        }
+    }
+
+    fn cancel(&mut self) {}
 }
 
 pub fn task_vec() -> Vec<Box<dyn ActionTask>> {
@@ -183,9 +661,322 @@ pub fn serial(tasks: Vec<Box<dyn ActionTask>>) -> Box<dyn ActionTask> {
     Box::new(SerialTasks {
         tasks,
         cached_results: Vec::new(),
+        stop_on_error: false,
     })
 }
 
+/// Like `serial` but skips the remaining tasks once one of them fails
+pub fn serial_until_error(
+    tasks: Vec<Box<dyn ActionTask>>,
+) -> Box<dyn ActionTask> {
+    Box::new(SerialTasks {
+        tasks,
+        cached_results: Vec::new(),
+        stop_on_error: true,
+    })
+}
+
+/// Runs `fallback` in place of `task` when it fails, with its result
+pub fn or_else(
+    task: Box<dyn ActionTask>,
+    fallback: Box<dyn ActionTask>,
+) -> Box<dyn ActionTask> {
+    Box::new(OrElseTask {
+        task,
+        fallback,
+        failed: false,
+    })
+}
+
+struct OrElseTask {
+    task: Box<dyn ActionTask>,
+    fallback: Box<dyn ActionTask>,
+    failed: bool,
+}
+
+impl OrElseTask {
+    fn current(&self) -> &dyn ActionTask {
+        if self.failed {
+            self.fallback.as_ref()
+        } else {
+            self.task.as_ref()
+        }
+    }
+}
+
+impl ActionTask for OrElseTask {
+    fn poll(&mut self, executor: &mut Executor) -> Poll<ActionResult> {
+        if !self.failed {
+            match self.task.poll(executor) {
+                Poll::Ready(result) if result.success => {
+                    return Poll::Ready(result)
+                }
+                Poll::Ready(_) => self.failed = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        self.fallback.poll(executor)
+    }
+
+    fn cancel(&mut self) {
+        if self.failed {
+            self.fallback.cancel();
+        } else {
+            self.task.cancel();
+        }
+    }
+
+    fn request_key(&self) -> Option<String> {
+        let key = format!(
+            "or else({},{})",
+            self.task.request_key()?,
+            self.fallback.request_key()?
+        );
+        Some(key)
+    }
+
+    fn command_line(&self) -> Option<String> {
+        self.current().command_line()
+    }
+
+    fn partial_output(&self) -> Option<String> {
+        self.current().partial_output()
+    }
+
+    /// The output of the fallback replaces what the task printed, so only
+    /// the task streams
+    fn partial_lines(&self, from: usize) -> Option<(String, usize)> {
+        match self.failed {
+            true => None,
+            false => self.task.partial_lines(from),
+        }
+    }
+
+    /// Both get them as either may run
+    fn add_flags(&mut self, subcommand: &[&str], flags: &[String]) -> bool {
+        let task = self.task.add_flags(subcommand, flags);
+        self.fallback.add_flags(subcommand, flags) || task
+    }
+
+    fn set_decoder(&mut self, decoder: &Decoder) {
+        self.task.set_decoder(decoder);
+        self.fallback.set_decoder(decoder);
+    }
+}
+
+pub fn map_result<F>(
+    task: Box<dyn ActionTask>,
+    mapper: F,
+) -> Box<dyn ActionTask>
+where
+    F: 'static + Send + FnMut(ActionResult) -> ActionResult,
+{
+    Box::new(MapResultTask { task, mapper })
+}
+
+struct MapResultTask<F>
+where
+    F: FnMut(ActionResult) -> ActionResult,
+{
+    task: Box<dyn ActionTask>,
+    mapper: F,
+}
+
+impl<F> ActionTask for MapResultTask<F>
+where
+    F: Send + FnMut(ActionResult) -> ActionResult,
+{
+    fn poll(&mut self, executor: &mut Executor) -> Poll<ActionResult> {
+        match self.task.poll(executor) {
+            Poll::Ready(result) => Poll::Ready((self.mapper)(result)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn cancel(&mut self) {
+        self.task.cancel();
+    }
+
+    fn request_key(&self) -> Option<String> {
+        self.task.request_key()
+    }
+
+    fn command_line(&self) -> Option<String> {
+        self.task.command_line()
+    }
+
+    fn partial_output(&self) -> Option<String> {
+        self.task.partial_output()
+    }
+
+    fn partial_lines(&self, from: usize) -> Option<(String, usize)> {
+        self.task.partial_lines(from)
+    }
+
+    fn add_flags(&mut self, subcommand: &[&str], flags: &[String]) -> bool {
+        self.task.add_flags(subcommand, flags)
+    }
+
+    fn set_decoder(&mut self, decoder: &Decoder) {
+        self.task.set_decoder(decoder);
+    }
+}
+
+/// Like `map_result` but runs `mapper` in another thread, for mappers that
+/// read files and would otherwise stall the ui
+pub fn map_result_in_thread<F>(
+    task: Box<dyn ActionTask>,
+    mapper: F,
+) -> Box<dyn ActionTask>
+where
+    F: 'static + Send + FnOnce(ActionResult) -> ActionResult,
+{
+    Box::new(ThreadMapResultTask {
+        task,
+        mapper: Some(mapper),
+        receiver: None,
+    })
+}
+
+struct ThreadMapResultTask<F> {
+    task: Box<dyn ActionTask>,
+    mapper: Option<F>,
+    /// Receives the mapped result once `task` finished
+    receiver: Option<mpsc::Receiver<ActionResult>>,
+}
+
+impl<F> ActionTask for ThreadMapResultTask<F>
+where
+    F: 'static + Send + FnOnce(ActionResult) -> ActionResult,
+{
+    fn poll(&mut self, executor: &mut Executor) -> Poll<ActionResult> {
+        if let Some(receiver) = &self.receiver {
+            return match receiver.try_recv() {
+                Ok(result) => Poll::Ready(result),
+                Err(mpsc::TryRecvError::Empty) => Poll::Pending,
+                Err(mpsc::TryRecvError::Disconnected) => Poll::Ready(
+                    ActionResult::from_err("could not process the output"),
+                ),
+            };
+        }
+        let result = match self.task.poll(executor) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        let mapper = match self.mapper.take() {
+            Some(mapper) => mapper,
+            None => return Poll::Pending,
+        };
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || sender.send(mapper(result)));
+        self.receiver = Some(receiver);
+        Poll::Pending
+    }
+
+    fn cancel(&mut self) {
+        self.task.cancel();
+    }
+
+    fn request_key(&self) -> Option<String> {
+        self.task.request_key()
+    }
+
+    fn command_line(&self) -> Option<String> {
+        self.task.command_line()
+    }
+
+    fn partial_output(&self) -> Option<String> {
+        self.task.partial_output()
+    }
+
+    fn partial_lines(&self, from: usize) -> Option<(String, usize)> {
+        self.task.partial_lines(from)
+    }
+
+    fn add_flags(&mut self, subcommand: &[&str], flags: &[String]) -> bool {
+        self.task.add_flags(subcommand, flags)
+    }
+
+    fn set_decoder(&mut self, decoder: &Decoder) {
+        self.task.set_decoder(decoder);
+    }
+}
+
+/// Like `map_result` but also passes how long `task` took since first polled
+pub fn timed<F>(task: Box<dyn ActionTask>, mapper: F) -> Box<dyn ActionTask>
+where
+    F: 'static + Send + FnMut(ActionResult, Duration) -> ActionResult,
+{
+    Box::new(TimedTask {
+        task,
+        start: None,
+        mapper,
+    })
+}
+
+struct TimedTask<F>
+where
+    F: FnMut(ActionResult, Duration) -> ActionResult,
+{
+    task: Box<dyn ActionTask>,
+    start: Option<Instant>,
+    mapper: F,
+}
+
+impl<F> ActionTask for TimedTask<F>
+where
+    F: Send + FnMut(ActionResult, Duration) -> ActionResult,
+{
+    fn poll(&mut self, executor: &mut Executor) -> Poll<ActionResult> {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        match self.task.poll(executor) {
+            Poll::Ready(result) => {
+                Poll::Ready((self.mapper)(result, start.elapsed()))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn cancel(&mut self) {
+        self.task.cancel();
+    }
+
+    fn request_key(&self) -> Option<String> {
+        self.task.request_key()
+    }
+
+    fn command_line(&self) -> Option<String> {
+        self.task.command_line()
+    }
+
+    fn partial_output(&self) -> Option<String> {
+        self.task.partial_output()
+    }
+
+    fn partial_lines(&self, from: usize) -> Option<(String, usize)> {
+        self.task.partial_lines(from)
+    }
+
+    fn add_flags(&mut self, subcommand: &[&str], flags: &[String]) -> bool {
+        self.task.add_flags(subcommand, flags)
+    }
+
+    fn set_decoder(&mut self, decoder: &Decoder) {
+        self.task.set_decoder(decoder);
+    }
+}
+
+/// Decodes the output of the commands started by `task` with `decoder`
+pub fn decoded(
+    mut task: Box<dyn ActionTask>,
+    decoder: Option<Decoder>,
+) -> Box<dyn ActionTask> {
+    if let Some(decoder) = decoder {
+        task.set_decoder(&decoder);
+    }
+    task
+}
+
 struct ParallelTasks {
     tasks: Vec<Box<dyn ActionTask>>,
     cached_results: Vec<Option<ActionResult>>,
@@ -214,26 +1005,124 @@ impl ActionTask for ParallelTasks {
             Poll::Pending
         }
     }
+
+    fn cancel(&mut self) {
+        for task in &mut self.tasks {
+            task.cancel();
+        }
+    }
+
+    fn request_key(&self) -> Option<String> {
+        combined_request_key("parallel", &self.tasks)
+    }
+
+    fn command_line(&self) -> Option<String> {
+        self.tasks
+            .iter()
+            .zip(self.cached_results.iter())
+            .filter(|(_, result)| result.is_none())
+            .find_map(|(task, _)| task.command_line())
+    }
+
+    fn add_flags(&mut self, subcommand: &[&str], flags: &[String]) -> bool {
+        self.tasks
+            .iter_mut()
+            .any(|t| t.add_flags(subcommand, flags))
+    }
+
+    fn set_decoder(&mut self, decoder: &Decoder) {
+        for task in &mut self.tasks {
+            task.set_decoder(decoder);
+        }
+    }
 }
 
 struct SerialTasks {
     tasks: Vec<Box<dyn ActionTask>>,
     cached_results: Vec<ActionResult>,
+    stop_on_error: bool,
 }
 
 impl ActionTask for SerialTasks {
     fn poll(&mut self, executor: &mut Executor) -> Poll<ActionResult> {
-        match self.tasks[self.cached_results.len()].poll(executor) {
-            Poll::Ready(result) => self.cached_results.push(result),
+        let failed = match self.tasks[self.cached_results.len()].poll(executor)
+        {
+            Poll::Ready(result) => {
+                let failed = !result.success;
+                self.cached_results.push(result);
+                failed
+            }
             Poll::Pending => return Poll::Pending,
-        }
+        };
 
-        if self.cached_results.len() == self.tasks.len() {
+        if self.cached_results.len() == self.tasks.len()
+            || (self.stop_on_error && failed)
+        {
             Poll::Ready(aggregate_results(self.cached_results.drain(..)))
         } else {
             Poll::Pending
         }
     }
+
+    fn cancel(&mut self) {
+        if let Some(task) = self.tasks.get_mut(self.cached_results.len()) {
+            task.cancel();
+        }
+    }
+
+    fn request_key(&self) -> Option<String> {
+        let kind = if self.stop_on_error {
+            "serial until error"
+        } else {
+            "serial"
+        };
+        combined_request_key(kind, &self.tasks)
+    }
+
+    fn command_line(&self) -> Option<String> {
+        self.tasks.get(self.cached_results.len())?.command_line()
+    }
+
+    /// Outputs of the finished tasks, joined like the final result, followed
+    /// by what the running one printed
+    fn partial_output(&self) -> Option<String> {
+        let mut output = String::new();
+        for result in &self.cached_results {
+            output.push('\n');
+            output.push_str(&result.output);
+        }
+        let task = self.tasks.get(self.cached_results.len())?;
+        if let Some(partial) = task.partial_output() {
+            output.push('\n');
+            output.push_str(&partial);
+        }
+        Some(output)
+    }
+
+    fn add_flags(&mut self, subcommand: &[&str], flags: &[String]) -> bool {
+        self.tasks
+            .iter_mut()
+            .any(|t| t.add_flags(subcommand, flags))
+    }
+
+    fn set_decoder(&mut self, decoder: &Decoder) {
+        for task in &mut self.tasks {
+            task.set_decoder(decoder);
+        }
+    }
+}
+
+fn combined_request_key(
+    kind: &str,
+    tasks: &[Box<dyn ActionTask>],
+) -> Option<String> {
+    let mut key = format!("{}(", kind);
+    for task in tasks {
+        key.push_str(&task.request_key()?);
+        key.push(',');
+    }
+    key.push(')');
+    Some(key)
 }
 
 fn aggregate_results<I>(iter: I) -> ActionResult
@@ -242,14 +1131,18 @@ where
 {
     let mut all_success = true;
     let mut aggregated = String::new();
+    let mut error = None;
     for result in iter {
         all_success = all_success && result.success;
+        error = error.or(result.error);
         let result = result.output;
         aggregated.push('\n');
         aggregated.push_str(&result[..]);
     }
     ActionResult {
         success: all_success,
+        canceled: false,
         output: aggregated,
+        error,
     }
 }
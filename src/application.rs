@@ -1,10 +1,29 @@
-use std::{collections::HashMap, task::Poll};
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    path::PathBuf,
+    task::Poll,
+    thread,
+    time::{Duration, Instant},
+};
 
 use crate::{
     action::{ActionKind, ActionResult, ActionTask},
-    async_process::Executor,
+    askpass::AskPass,
+    async_process::{Decoder, Executor},
+    backend_error::BackendResult,
+    color_support::ColorSupport,
+    config::Config,
     custom_actions::CustomAction,
-    version_control_actions::VersionControlActions,
+    encoding,
+    forge::{Forge, ForgeDetection},
+    locale, repositories,
+    select::Entry,
+    theme,
+    version_control_actions::{
+        CommitOptions, StatusOptions, VersionControlActions,
+    },
+    watcher::Watcher,
 };
 
 pub struct ActionFuture {
@@ -12,33 +31,271 @@ pub struct ActionFuture {
     pub task: Box<dyn 'static + ActionTask>,
 }
 
+/// Action being run on behalf of `future.kind` and any `waiters` that
+/// requested the same thing meanwhile
+struct PendingAction {
+    future: ActionFuture,
+    key: Option<String>,
+    waiters: Vec<ActionKind>,
+    start: Instant,
+}
+
+impl PendingAction {
+    fn is_for(&self, kind: ActionKind) -> bool {
+        self.future.kind == kind || self.waiters.contains(&kind)
+    }
+}
+
+/// Previews kept at most, dropping the least recently shown first
+const PREVIEW_CACHE_CAPACITY: usize = 64;
+/// Maximum number of commands running at the same time
+const EXECUTOR_THREAD_COUNT: usize = 4;
+
+struct PreviewFuture {
+    target: String,
+    task: Box<dyn 'static + ActionTask>,
+}
+
+struct BadgeFuture {
+    kind: ActionKind,
+    task: Box<dyn 'static + ActionTask>,
+}
+
 pub struct Application {
     pub version_control: Box<dyn 'static + VersionControlActions>,
     pub custom_actions: Vec<CustomAction>,
+    pub config: Config,
+    pub status_options: StatusOptions,
+    /// Directory relative to the root that status, diff and log are limited to
+    pub scope: Option<String>,
+    /// Branch or revision whose history the log is limited to
+    pub log_branch: Option<String>,
+    /// Branch the current one is compared with, like `origin/main`
+    pub compare_target: Option<String>,
+    pub askpass: Option<AskPass>,
+    /// Decodes diffs of files configured with a legacy encoding
+    pub diff_decoder: Option<Decoder>,
+    /// Hosting service cli used for pull requests, when installed. Detected
+    /// in the background
+    forge: ForgeDetection,
+    /// Notices changes to the files of the repository when `watch` is on
+    pub watcher: Option<Watcher>,
 
+    workspace_dirs: Vec<PathBuf>,
     executor: Executor,
-    pending_actions: Vec<ActionFuture>,
+    pending_actions: Vec<PendingAction>,
+    action_deadlines: HashMap<ActionKind, (Instant, Duration)>,
     action_results: HashMap<ActionKind, ActionResult>,
+    pending_preview: Option<PreviewFuture>,
+    preview_cache: HashMap<String, ActionResult>,
+    /// Targets of the cached previews, the most recently shown last
+    preview_order: VecDeque<String>,
+    pending_badges: Vec<BadgeFuture>,
+    /// Short summaries shown next to the action name in the header
+    badges: HashMap<ActionKind, String>,
+    /// Actions that ran long and finished while not being shown, with
+    /// whether they succeeded
+    finished_in_background: Vec<(ActionKind, bool)>,
+    /// Flags of the config for the current backend
+    action_flags: HashMap<ActionKind, Vec<String>>,
+}
+
+/// Flags of the config for the backend of `version_control` by action,
+/// adding an error for the actions it runs no command to add them to
+fn action_flags(
+    version_control: &dyn VersionControlActions,
+    config: &mut Config,
+) -> HashMap<ActionKind, Vec<String>> {
+    let executable = match version_control.executable_name() {
+        "brz" => "bzr",
+        executable => executable,
+    };
+    let mut flags_by_action: HashMap<_, Vec<_>> = HashMap::new();
+    for action_flags in &config.action_flags {
+        let backend = match action_flags.backend.as_str() {
+            "brz" => "bzr",
+            backend => backend,
+        };
+        if backend != executable {
+            continue;
+        }
+        if version_control
+            .flags_subcommand(action_flags.action)
+            .is_none()
+        {
+            config.errors.push(format!(
+                "flags: {} takes no flags for {}",
+                backend,
+                action_flags.action.name()
+            ));
+            continue;
+        }
+        flags_by_action
+            .entry(action_flags.action)
+            .or_default()
+            .extend(action_flags.flags.iter().cloned());
+    }
+    flags_by_action
 }
 
 impl Application {
     pub fn new(
         version_control: Box<dyn 'static + VersionControlActions>,
         custom_actions: Vec<CustomAction>,
+        mut config: Config,
+        askpass: Option<AskPass>,
     ) -> Self {
+        let status_options = StatusOptions {
+            skip_untracked: config.fast_status,
+            ..Default::default()
+        };
+        if let Err(error) = locale::set_locale(&config.locale) {
+            config.errors.push(error);
+        }
+        let action_flags = action_flags(version_control.as_ref(), &mut config);
+        theme::set_theme(
+            &config.theme,
+            &config.colors,
+            config.color_support.unwrap_or_else(ColorSupport::detect),
+        );
+        let forge = ForgeDetection::start(version_control.as_ref());
+        let watcher = match config.watch {
+            true => match Watcher::new(version_control.get_root()) {
+                Ok(watcher) => Some(watcher),
+                Err(error) => {
+                    config.errors.push(error);
+                    None
+                }
+            },
+            false => None,
+        };
+        let diff_decoder = encoding::diff_decoder(&config.encodings);
+        let workspace_dirs = repositories::workspace_dirs(
+            &config.workspaces,
+            version_control.get_root(),
+        );
         Self {
             version_control,
             custom_actions,
-            executor: Executor::new(2),
+            config,
+            status_options,
+            scope: None,
+            log_branch: None,
+            compare_target: None,
+            askpass,
+            diff_decoder,
+            forge,
+            watcher,
+            workspace_dirs,
+            executor: Executor::new(EXECUTOR_THREAD_COUNT),
             pending_actions: Vec::new(),
+            action_deadlines: HashMap::new(),
             action_results: HashMap::new(),
+            pending_preview: None,
+            preview_cache: HashMap::new(),
+            preview_order: VecDeque::new(),
+            pending_badges: Vec::new(),
+            badges: HashMap::new(),
+            finished_in_background: Vec::new(),
+            action_flags,
+        }
+    }
+
+    /// Flags configured for the main command of `kind`
+    pub fn action_flags(&self, kind: ActionKind) -> &[String] {
+        self.action_flags.get(&kind).map_or(&[], |flags| &flags[..])
+    }
+
+    /// Repositories found in the workspace directories of every repository
+    /// opened so far
+    pub fn workspace_repositories(&self) -> Vec<String> {
+        let root = self.version_control.get_root();
+        let mut repositories =
+            repositories::find_repositories(&self.workspace_dirs);
+        repositories.retain(|r| r != root);
+        repositories
+    }
+
+    /// Replaces the backend with the one of the repository at `dir` reloading
+    /// its config and custom actions. Anything still running is killed
+    pub fn switch_repository(&mut self, dir: &str) -> Result<(), String> {
+        let version_control = repositories::version_control_at(dir)
+            .ok_or_else(|| format!("no repository found at {}", dir))?;
+        env::set_current_dir(version_control.get_root())
+            .map_err(|e| e.to_string())?;
+
+        for mut pending in self.pending_actions.drain(..) {
+            pending.future.task.cancel();
+        }
+        if let Some(mut preview) = self.pending_preview.take() {
+            preview.task.cancel();
+        }
+        for mut badge in self.pending_badges.drain(..) {
+            badge.task.cancel();
+        }
+
+        let mut config = Config::load();
+        let custom_actions =
+            CustomAction::load_custom_actions(&mut config.errors);
+        for dir in repositories::workspace_dirs(
+            &config.workspaces,
+            version_control.get_root(),
+        ) {
+            if !self.workspace_dirs.contains(&dir) {
+                self.workspace_dirs.push(dir);
+            }
+        }
+        let askpass = self.askpass.take();
+        *self = Self {
+            workspace_dirs: std::mem::take(&mut self.workspace_dirs),
+            ..Self::new(version_control, custom_actions, config, askpass)
+        };
+
+        Ok(())
+    }
+
+    /// Runs `task` blocking until it finishes, for use outside the tui
+    pub fn run_to_completion(
+        &mut self,
+        mut task: Box<dyn ActionTask>,
+    ) -> ActionResult {
+        loop {
+            match task.poll(&mut self.executor) {
+                Poll::Ready(result) => return result,
+                Poll::Pending => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    }
+
+    /// Service hosting the repository, `None` until it's detected
+    pub fn forge(&self) -> Option<Forge> {
+        self.forge.get()
+    }
+
+    /// Current branch when it's one of the `protected_branches`
+    pub fn protected_branch(&self) -> Option<String> {
+        let branch = self.version_control.get_current_branch().ok()?;
+        let branch = branch.trim();
+        self.config
+            .protected_branches
+            .iter()
+            .find(|b| *b == branch)
+            .cloned()
+    }
+
+    pub fn commit_options(&self) -> CommitOptions {
+        CommitOptions {
+            sign: self.config.sign_commits,
+            no_verify: false,
         }
     }
 
     pub fn get_cached_action_result(&self, kind: ActionKind) -> &ActionResult {
         static EMPTY_ACTION_RESULT: ActionResult = ActionResult {
             success: true,
+            canceled: false,
             output: String::new(),
+            error: None,
         };
 
         match self.action_results.get(&kind) {
@@ -55,40 +312,364 @@ impl Application {
         self.action_results.insert(kind, result);
     }
 
+    /// Polls all pending actions returning whether the one of `kind` just
+    /// finished. Actions running past their configured timeout are killed
+    /// and finish with a canceled result
     pub fn poll_and_check_action(&mut self, kind: ActionKind) -> bool {
         let mut just_finished = false;
         for i in (0..self.pending_actions.len()).rev() {
-            if let Poll::Ready(result) =
-                self.pending_actions[i].task.poll(&mut self.executor)
-            {
-                let action = self.pending_actions.swap_remove(i);
-                if action.kind == kind {
+            let pending = &mut self.pending_actions[i];
+            let action_kind = pending.future.kind;
+            let result = match self.action_deadlines.get(&action_kind) {
+                Some((deadline, timeout)) if Instant::now() >= *deadline => {
+                    pending.future.task.cancel();
+                    Poll::Ready(ActionResult::from_timed_out(*timeout))
+                }
+                _ => pending.future.task.poll(&mut self.executor),
+            };
+
+            if let Poll::Ready(result) = result {
+                let pending = self.pending_actions.swap_remove(i);
+                self.action_deadlines.remove(&action_kind);
+                if pending.is_for(kind) {
                     just_finished = true;
+                } else if self
+                    .config
+                    .notify_after
+                    .is_some_and(|after| pending.start.elapsed() >= after)
+                {
+                    self.finished_in_background
+                        .push((action_kind, result.success));
                 }
-                self.action_results.insert(action.kind, result);
+                for waiter in pending.waiters {
+                    self.action_results.insert(waiter, result.clone());
+                }
+                self.action_results.insert(action_kind, result);
             }
         }
 
         just_finished
     }
 
-    pub fn run_action(&mut self, action: ActionFuture) {
-        for i in (0..self.pending_actions.len()).rev() {
-            if self.pending_actions[i].kind == action.kind {
+    /// Only one action of each kind runs at a time. A new read only action
+    /// supersedes the pending one since it may be for another target while
+    /// any other action is ignored until the pending one finishes. Requests
+    /// identical to one already running wait for its result instead of
+    /// running again
+    pub fn run_action(&mut self, mut action: ActionFuture) {
+        if let Some(flags) = self.action_flags.get(&action.kind) {
+            let subcommand = self.version_control.flags_subcommand(action.kind);
+            if let Some(subcommand) = subcommand {
+                let subcommand: Vec<_> = subcommand.split(' ').collect();
+                action.task.add_flags(&subcommand, flags);
+            }
+        }
+        let key = action.task.request_key();
+        if let Some(i) = self.pending_index(action.kind) {
+            if key.is_some() && self.pending_actions[i].key == key {
                 return;
             }
+            if !action.kind.is_read_only() {
+                return;
+            }
+            self.stop_waiting(i, action.kind);
         }
 
-        self.pending_actions.push(action);
+        if key.is_some() {
+            let identical =
+                self.pending_actions.iter_mut().find(|p| p.key == key);
+            if let Some(pending) = identical {
+                pending.waiters.push(action.kind);
+                return;
+            }
+        }
+
+        if let Some(timeout) = self.config.timeout(action.kind.name()) {
+            self.action_deadlines
+                .insert(action.kind, (Instant::now() + timeout, timeout));
+        }
+        self.pending_actions.push(PendingAction {
+            future: action,
+            key,
+            waiters: Vec::new(),
+            start: Instant::now(),
+        });
+    }
+
+    /// Kills the pending action of `kind` returning whether there was one.
+    /// It keeps running while other kinds still wait for its result
+    pub fn cancel_action(&mut self, kind: ActionKind) -> bool {
+        match self.pending_index(kind) {
+            Some(i) => {
+                let mut result = ActionResult::from_canceled();
+                // custom actions keep what they printed before being killed
+                if kind == ActionKind::CustomAction {
+                    let task = &self.pending_actions[i].future.task;
+                    if let Some(mut partial) = task.partial_output() {
+                        if !partial.is_empty() && !partial.ends_with('\n') {
+                            partial.push('\n');
+                        }
+                        result.output.insert_str(0, &partial);
+                    }
+                }
+                self.stop_waiting(i, kind);
+                self.action_results.insert(kind, result);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Actions that ran for longer than `notify_after` and finished while
+    /// another one was shown since last called
+    pub fn take_finished_in_background(&mut self) -> Vec<(ActionKind, bool)> {
+        std::mem::take(&mut self.finished_in_background)
     }
 
     pub fn has_pending_action_of_type(&self, kind: ActionKind) -> bool {
-        for action in &self.pending_actions {
-            if action.kind == kind {
-                return true;
+        self.pending_index(kind).is_some()
+    }
+
+    /// Whether an action that changes the repository is running, which the
+    /// background refreshes wait for. Custom actions aren't counted as they
+    /// may keep running, like a build watching files
+    pub fn has_pending_changes(&self) -> bool {
+        self.pending_actions.iter().any(|p| {
+            let kind = p.future.kind;
+            !kind.is_read_only() && kind != ActionKind::CustomAction
+        })
+    }
+
+    /// How long the pending action of `kind` has been running and the
+    /// command it's running, if any
+    pub fn pending_action_progress(
+        &self,
+        kind: ActionKind,
+    ) -> Option<(Duration, Option<String>)> {
+        let pending = &self.pending_actions[self.pending_index(kind)?];
+        Some((pending.start.elapsed(), pending.future.task.command_line()))
+    }
+
+    /// Complete lines the pending action of `kind` printed from byte `from`
+    /// of its output on and the byte they end at, if it can tell
+    pub fn pending_partial_lines(
+        &self,
+        kind: ActionKind,
+        from: usize,
+    ) -> Option<(String, usize)> {
+        let pending = &self.pending_actions[self.pending_index(kind)?];
+        pending.future.task.partial_lines(from)
+    }
+
+    fn pending_index(&self, kind: ActionKind) -> Option<usize> {
+        self.pending_actions.iter().position(|p| p.is_for(kind))
+    }
+
+    /// Stops `kind` from waiting on the pending action at `index` handing
+    /// it over to another waiter or killing it if there's none left
+    fn stop_waiting(&mut self, index: usize, kind: ActionKind) {
+        let pending = &mut self.pending_actions[index];
+        if let Some(i) = pending.waiters.iter().position(|&w| w == kind) {
+            pending.waiters.swap_remove(i);
+            return;
+        }
+
+        let deadline = self.action_deadlines.remove(&kind);
+        match pending.waiters.pop() {
+            Some(waiter) => {
+                pending.future.kind = waiter;
+                if let Some(deadline) = deadline {
+                    self.action_deadlines.insert(waiter, deadline);
+                }
+            }
+            None => {
+                let mut pending = self.pending_actions.swap_remove(index);
+                pending.future.task.cancel();
+            }
+        }
+    }
+
+    /// Requests a preview for `target` canceling any other pending preview
+    pub fn request_preview(
+        &mut self,
+        target: &str,
+        task: Box<dyn 'static + ActionTask>,
+    ) {
+        if let Some(mut preview) = self.pending_preview.take() {
+            preview.task.cancel();
+        }
+        self.pending_preview = Some(PreviewFuture {
+            target: target.into(),
+            task,
+        });
+    }
+
+    pub fn poll_preview(&mut self) -> bool {
+        let preview = match &mut self.pending_preview {
+            Some(preview) => preview,
+            None => return false,
+        };
+
+        match preview.task.poll(&mut self.executor) {
+            Poll::Ready(result) => {
+                let target = preview.target.clone();
+                self.pending_preview = None;
+                if self.preview_cache.len() >= PREVIEW_CACHE_CAPACITY
+                    && !self.preview_cache.contains_key(&target)
+                {
+                    if let Some(oldest) = self.preview_order.pop_front() {
+                        self.preview_cache.remove(&oldest);
+                    }
+                }
+                self.preview_cache.insert(target.clone(), result);
+                self.touch_preview(target);
+                true
+            }
+            Poll::Pending => false,
+        }
+    }
+
+    pub fn is_preview_pending(&self, target: &str) -> bool {
+        match &self.pending_preview {
+            Some(preview) => preview.target == target,
+            None => false,
+        }
+    }
+
+    pub fn get_preview_result(
+        &mut self,
+        target: &str,
+    ) -> Option<&ActionResult> {
+        if self.preview_cache.contains_key(target) {
+            self.touch_preview(target.into());
+        }
+        self.preview_cache.get(target)
+    }
+
+    /// Drops the cached previews whose target starts with `prefix`
+    pub fn forget_previews(&mut self, prefix: &str) {
+        self.preview_cache
+            .retain(|target, _| !target.starts_with(prefix));
+        self.preview_order
+            .retain(|target| !target.starts_with(prefix));
+    }
+
+    /// Marks the preview of `target` as the most recently shown
+    fn touch_preview(&mut self, target: String) {
+        if let Some(i) = self.preview_order.iter().position(|t| *t == target) {
+            self.preview_order.remove(i);
+        }
+        self.preview_order.push_back(target);
+    }
+
+    /// Refreshes the header badge of `kind` in the background unless it's
+    /// already being refreshed. Log count shares the badge of log while
+    /// status and log also refresh the one of the compare target
+    pub fn request_badge(&mut self, kind: ActionKind) {
+        let kind = match kind {
+            ActionKind::LogCount => ActionKind::Log,
+            kind => kind,
+        };
+        if matches!(kind, ActionKind::Status | ActionKind::Log) {
+            self.request_badge(ActionKind::CompareWithTarget);
+        }
+        if self.pending_badges.iter().any(|b| b.kind == kind) {
+            return;
+        }
+
+        let task = match kind {
+            ActionKind::Status => return self.count_changed_files(),
+            ActionKind::Log => self.version_control.ahead_behind_badge(),
+            ActionKind::ListBranches => {
+                self.version_control.unpushed_branches_badge()
+            }
+            ActionKind::CompareWithTarget => match &self.compare_target {
+                Some(target) => {
+                    self.version_control.compare_target_badge(target)
+                }
+                None => None,
+            },
+            _ => None,
+        };
+        if let Some(task) = task {
+            self.pending_badges.push(BadgeFuture { kind, task });
+        }
+    }
+
+    /// Sets the badge of status from its cached output, which it's counted
+    /// from instead of running status again
+    fn count_changed_files(&mut self) {
+        let kind = ActionKind::Status;
+        let badge = match self.action_results.get(&kind) {
+            Some(result) if result.success => {
+                self.version_control.changed_files_badge(&result.output)
+            }
+            _ => None,
+        };
+        match badge {
+            Some(badge) => self.badges.insert(kind, badge),
+            None => self.badges.remove(&kind),
+        };
+    }
+
+    /// Polls the pending badges returning whether any of them changed
+    pub fn poll_badges(&mut self) -> bool {
+        let mut changed = false;
+        for i in (0..self.pending_badges.len()).rev() {
+            let badge = &mut self.pending_badges[i];
+            if let Poll::Ready(result) = badge.task.poll(&mut self.executor) {
+                let kind = self.pending_badges.swap_remove(i).kind;
+                let text = result.output.trim();
+                let previous = if result.success && !text.is_empty() {
+                    self.badges.insert(kind, text.into())
+                } else {
+                    self.badges.remove(&kind)
+                };
+                changed |= previous.as_deref() != self.get_badge(kind);
             }
         }
+        changed
+    }
 
-        false
+    /// Sets the directory actions are limited to, dropping the previews
+    /// limited to the previous one
+    pub fn set_scope(&mut self, scope: Option<String>) {
+        self.scope = scope;
+        self.forget_previews("");
+    }
+
+    /// Changed files of the working tree inside the scope
+    pub fn get_current_changed_files(&self) -> BackendResult<Vec<Entry>> {
+        let mut entries = self.version_control.get_current_changed_files()?;
+        if let Some(scope) = &self.scope {
+            entries.retain(|e| {
+                e.filename.strip_prefix(scope.as_str()).is_some_and(|rest| {
+                    rest.is_empty() || rest.starts_with('/')
+                })
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Sets the branch status and log compare with, refreshing their badge
+    pub fn set_compare_target(&mut self, target: Option<String>) {
+        self.compare_target = target;
+        self.badges.remove(&ActionKind::CompareWithTarget);
+        if let Some(i) = self
+            .pending_badges
+            .iter()
+            .position(|b| b.kind == ActionKind::CompareWithTarget)
+        {
+            self.pending_badges.swap_remove(i).task.cancel();
+        }
+        self.request_badge(ActionKind::CompareWithTarget);
+    }
+
+    pub fn get_badge(&self, kind: ActionKind) -> Option<&str> {
+        let kind = match kind {
+            ActionKind::LogCount => ActionKind::Log,
+            kind => kind,
+        };
+        self.badges.get(&kind).map(String::as_str)
     }
 }
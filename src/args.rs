@@ -0,0 +1,238 @@
+use std::{env, path::Path};
+
+/// View shown right after launching instead of the one from the last session
+#[derive(Clone, Copy)]
+pub enum StartupMode {
+    Status,
+    Log,
+    Branches,
+    Tags,
+}
+
+impl StartupMode {
+    const ALL: [(&'static str, Self); 4] = [
+        ("status", Self::Status),
+        ("log", Self::Log),
+        ("branches", Self::Branches),
+        ("tags", Self::Tags),
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, mode)| *mode)
+    }
+
+    fn names() -> String {
+        let names: Vec<_> = Self::ALL.iter().map(|(n, _)| *n).collect();
+        names.join(", ")
+    }
+}
+
+/// How a mode is printed when it's not shown in the tui
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One entry per line with its fields separated by tabs
+    Plain,
+    Json,
+}
+
+/// Commit made from the shell as in
+/// `verco commit -m <message> [--no-verify] [--branch <name>|--allow-protected]
+/// [--json] [paths...]`
+#[derive(Default)]
+pub struct CommitArgs {
+    pub message: String,
+    /// Files or directories to commit, every change when empty
+    pub paths: Vec<String>,
+    pub no_verify: bool,
+    /// New branch the changes are carried to before committing
+    pub branch: Option<String>,
+    /// Commits even on one of the `protected_branches`
+    pub allow_protected: bool,
+    pub json: bool,
+}
+
+impl CommitArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        while let Some(arg) = args.next() {
+            match &arg[..] {
+                "-m" | "--message" => {
+                    let message =
+                        args.next().ok_or("missing message after '-m'")?;
+                    // like git, each message is a separate paragraph
+                    if !parsed.message.is_empty() {
+                        parsed.message.push_str("\n\n");
+                    }
+                    parsed.message.push_str(&message);
+                }
+                "--no-verify" => parsed.no_verify = true,
+                "-b" | "--branch" => {
+                    let branch =
+                        args.next().ok_or("missing branch after '--branch'")?;
+                    parsed.branch = Some(branch);
+                }
+                "--allow-protected" => parsed.allow_protected = true,
+                "--json" => parsed.json = true,
+                "--" => parsed.paths.extend(args.by_ref()),
+                _ if arg.starts_with('-') => {
+                    return Err(format!("unknown commit option '{}'", arg))
+                }
+                _ => parsed.paths.push(arg),
+            }
+        }
+        if parsed.message.trim().is_empty() {
+            return Err("missing commit message, pass it with -m".into());
+        }
+        Ok(parsed)
+    }
+}
+
+/// Command line as in
+/// `verco [--mode <mode>] [mode] [--plain|--json] [-n <count>] [directory]`,
+/// `verco info [--plain|--json] [directory]`,
+/// `verco --serve [--socket <path>] [directory]`,
+/// `verco --version [--verbose] [directory]` or `verco commit ...`
+#[derive(Default)]
+pub struct Args {
+    pub directory: Option<String>,
+    pub startup_mode: Option<StartupMode>,
+    /// Prints the mode instead of opening the tui
+    pub output: Option<OutputFormat>,
+    /// Number of log entries to print
+    pub count: Option<usize>,
+    /// Commit without opening the tui
+    pub commit: Option<CommitArgs>,
+    /// Prints a summary of the repository instead of opening the tui
+    pub info: bool,
+    /// Answers json-rpc requests instead of opening the tui
+    pub serve: bool,
+    /// Unix socket, or named pipe on Windows, the requests come from
+    /// instead of stdin
+    pub socket: Option<String>,
+    /// Prints the version instead of opening the tui
+    pub version: bool,
+    /// Prints diagnostics for bug reports together with the version
+    pub verbose: bool,
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut args = env::args().skip(1).peekable();
+        if args.peek().map(String::as_str) == Some("commit") {
+            args.next();
+            parsed.commit = Some(CommitArgs::parse(args)?);
+            return Ok(parsed);
+        }
+        while let Some(arg) = args.next() {
+            let mode = if arg == "--version" || arg == "-V" {
+                parsed.version = true;
+                continue;
+            } else if arg == "--serve" {
+                parsed.serve = true;
+                continue;
+            } else if arg == "--socket" {
+                let path =
+                    args.next().ok_or("missing path after '--socket'")?;
+                parsed.socket = Some(path);
+                continue;
+            } else if arg == "--verbose" {
+                parsed.verbose = true;
+                continue;
+            } else if arg == "--plain" || arg == "--json" {
+                parsed.output = Some(match &arg[..] {
+                    "--json" => OutputFormat::Json,
+                    _ => OutputFormat::Plain,
+                });
+                continue;
+            } else if arg == "-n" {
+                let count = args.next().ok_or("missing count after '-n'")?;
+                let count = count
+                    .parse()
+                    .map_err(|_| format!("invalid count '{}'", count))?;
+                parsed.count = Some(count);
+                continue;
+            } else if arg == "--mode" {
+                args.next().ok_or("missing mode after '--mode'")?
+            } else if let Some(mode) = arg.strip_prefix("--mode=") {
+                mode.into()
+            } else if arg.starts_with('-') {
+                return Err(format!("unknown option '{}'", arg));
+            } else if arg == "info"
+                && !parsed.info
+                && parsed.startup_mode.is_none()
+                && parsed.directory.is_none()
+            {
+                parsed.info = true;
+                continue;
+            } else if parsed.startup_mode.is_none()
+                && parsed.directory.is_none()
+                && StartupMode::from_name(&arg).is_some()
+            {
+                arg
+            } else if parsed.directory.is_none() {
+                if !Path::new(&arg).is_dir() {
+                    return Err(format!(
+                        "'{}' is neither a directory nor a mode, available modes: {}",
+                        arg,
+                        StartupMode::names(),
+                    ));
+                }
+                parsed.directory = Some(arg);
+                continue;
+            } else {
+                return Err(format!("unexpected argument '{}'", arg));
+            };
+
+            let mode = StartupMode::from_name(&mode).ok_or_else(|| {
+                format!(
+                    "unknown mode '{}', available modes: {}",
+                    mode,
+                    StartupMode::names()
+                )
+            })?;
+            parsed.startup_mode = Some(mode);
+        }
+
+        if parsed.verbose && !parsed.version {
+            return Err("'--verbose' is only valid with '--version'".into());
+        }
+        if parsed.version && (parsed.startup_mode.is_some() || parsed.info) {
+            return Err("'--version' doesn't take a mode".into());
+        }
+        if parsed.socket.is_some() && !parsed.serve {
+            return Err("'--socket' is only valid with '--serve'".into());
+        }
+        if parsed.serve
+            && (parsed.startup_mode.is_some()
+                || parsed.info
+                || parsed.version
+                || parsed.output.is_some())
+        {
+            return Err(
+                "'--serve' only takes '--socket' and a directory".into()
+            );
+        }
+        if parsed.info && parsed.startup_mode.is_some() {
+            return Err("'info' doesn't take a mode".into());
+        }
+        if parsed.output.is_some()
+            && parsed.startup_mode.is_none()
+            && !parsed.info
+        {
+            return Err(format!(
+                "printing needs one of the modes: {}",
+                StartupMode::names()
+            ));
+        }
+        if parsed.count.is_some()
+            && !matches!(parsed.startup_mode, Some(StartupMode::Log))
+        {
+            return Err("'-n' is only valid for the log mode".into());
+        }
+        Ok(parsed)
+    }
+}
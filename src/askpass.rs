@@ -0,0 +1,149 @@
+use std::{
+    env,
+    fs::{self, DirBuilder, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process, thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+const DIR_ENV: &str = "VERCO_ASKPASS_DIR";
+const PROMPT_FILE: &str = "prompt";
+const ANSWER_FILE: &str = "answer";
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const CREATE_DIR_ATTEMPTS: u32 = 16;
+
+/// Forwards credential prompts from git and ssh to the tui.
+/// Child processes are told to run this executable as their askpass helper
+/// which hands the prompt over through files in a temporary directory only
+/// the current user can access
+pub struct AskPass {
+    dir: PathBuf,
+}
+
+impl AskPass {
+    /// Sets the askpass environment inherited by every command spawned
+    /// from now on
+    pub fn install() -> Option<Self> {
+        let exe = env::current_exe().ok()?;
+        let dir = create_private_dir()?;
+
+        env::set_var(DIR_ENV, &dir);
+        env::set_var("GIT_ASKPASS", &exe);
+        env::set_var("SSH_ASKPASS", &exe);
+        env::set_var("SSH_ASKPASS_REQUIRE", "force");
+        Some(Self { dir })
+    }
+
+    /// Takes the prompt a child process is waiting an answer for
+    pub fn poll_prompt(&self) -> Option<String> {
+        let path = self.dir.join(PROMPT_FILE);
+        let prompt = fs::read_to_string(&path).ok()?;
+        fs::remove_file(path).ok()?;
+        Some(prompt)
+    }
+
+    /// Answers the last prompt. `None` makes the child process fail
+    pub fn answer(&self, answer: Option<&str>) {
+        let content = match answer {
+            Some(answer) => format!("+{}", answer),
+            None => String::from("-"),
+        };
+        let _ = write_atomically(&self.dir, ANSWER_FILE, &content);
+    }
+}
+
+impl Drop for AskPass {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Whether a prompt should have its answer hidden while typing
+pub fn is_secret(prompt: &str) -> bool {
+    let prompt = prompt.to_lowercase();
+    prompt.contains("password") || prompt.contains("passphrase")
+}
+
+/// Runs as the askpass helper if this process was spawned as one.
+/// Returns `false` when running as the regular application
+pub fn run_helper() -> bool {
+    let dir = match env::var_os(DIR_ENV) {
+        Some(dir) => PathBuf::from(dir),
+        None => return false,
+    };
+    let prompt = env::args().nth(1).unwrap_or_default();
+
+    match request_answer(&dir, &prompt) {
+        Some(answer) => {
+            let mut stdout = io::stdout();
+            let _ = writeln!(stdout, "{}", answer);
+        }
+        None => process::exit(1),
+    }
+    true
+}
+
+fn request_answer(dir: &Path, prompt: &str) -> Option<String> {
+    write_atomically(dir, PROMPT_FILE, prompt).ok()?;
+
+    let path = dir.join(ANSWER_FILE);
+    let start = Instant::now();
+    while start.elapsed() < ANSWER_TIMEOUT {
+        if let Ok(content) = fs::read_to_string(&path) {
+            let _ = fs::remove_file(&path);
+            return content.strip_prefix('+').map(String::from);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    let _ = fs::remove_file(dir.join(PROMPT_FILE));
+    None
+}
+
+/// Creates a new directory readable only by the current user. Fails rather
+/// than reusing one that already exists, as someone else could have created
+/// it to read the answers
+fn create_private_dir() -> Option<PathBuf> {
+    let mut builder = DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+
+    for attempt in 0..CREATE_DIR_ATTEMPTS {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        let mut dir = env::temp_dir();
+        dir.push(format!(
+            "{}-askpass-{}-{:08x}{}",
+            env!("CARGO_PKG_NAME"),
+            process::id(),
+            nanos,
+            attempt
+        ));
+        match builder.create(&dir) {
+            Ok(()) => return Some(dir),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => (),
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+fn write_atomically(dir: &Path, name: &str, content: &str) -> io::Result<()> {
+    let temp_path = dir.join(format!("{}.{}", name, process::id()));
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(&temp_path)?.write_all(content.as_bytes())?;
+    fs::rename(temp_path, dir.join(name))
+}
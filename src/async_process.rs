@@ -1,113 +1,343 @@
 use std::{
-    process::Child,
+    fmt,
+    io::{Read, Write},
+    process::{Child, Command},
     sync::mpsc::{
         channel, sync_channel, Receiver, Sender, SyncSender, TryRecvError,
     },
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     task::Poll,
     thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    backend_error::{BackendError, ErrorKind},
+    locale::{tr, trf},
 };
 
-struct ExecutorThread {
-    pub handle: JoinHandle<()>,
-    pub async_child_executor_sender: Sender<AsyncChildExecutor>,
+/// Converts the output bytes of a child into text
+pub type Decoder = Arc<dyn Fn(&[u8]) -> String + Send + Sync>;
+
+/// Command line that can be pasted into a shell to run `command` again
+pub fn command_line(command: &Command) -> String {
+    let mut line = String::new();
+    let program = command.get_program();
+    let args = command.get_args();
+    for (i, arg) in std::iter::once(program).chain(args).enumerate() {
+        if i > 0 {
+            line.push(' ');
+        }
+        let arg = arg.to_string_lossy();
+        let is_plain = !arg.is_empty()
+            && arg.chars().all(|c| {
+                c.is_ascii_alphanumeric() || "-_=./:@%+,^~{}".contains(c)
+            });
+        if is_plain {
+            line.push_str(&arg);
+        } else {
+            line.push('\'');
+            line.push_str(&arg.replace('\'', "'\\''"));
+            line.push('\'');
+        }
+    }
+    line
 }
 
+/// How a child is run besides its command line
+#[derive(Clone, Default)]
+pub struct ChildOptions {
+    /// Written to stdin, which is closed right away otherwise
+    pub input: Option<String>,
+    /// Exit code 1 is not a failure, for commands that use it to report they
+    /// found differences
+    pub allow_exit_one: bool,
+    /// Stderr also gathers in the partial output, for commands whose output
+    /// is shown as printed like custom actions
+    pub stream_stderr: bool,
+    /// Converts the output, which is read as utf-8 otherwise
+    pub decoder: Option<Decoder>,
+}
+
+impl fmt::Debug for ChildOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChildOptions")
+            .field("input", &self.input)
+            .field("allow_exit_one", &self.allow_exit_one)
+            .field("stream_stderr", &self.stream_stderr)
+            .field("decoded", &self.decoder.is_some())
+            .finish()
+    }
+}
+
+/// Runs children on a fixed number of threads. Tasks only start a new child
+/// while fewer than that many are running so bursts of requests can't pile
+/// up processes
 pub struct Executor {
-    thread_pool: Vec<ExecutorThread>,
-    next_thread_index: usize,
+    _threads: Vec<JoinHandle<()>>,
+    child_sender: Sender<AsyncChildExecutor>,
+    running_count: Arc<AtomicUsize>,
+    max_running_count: usize,
 }
 
 impl Executor {
     pub fn new(thread_pool_size: usize) -> Self {
-        let mut thread_pool = Vec::new();
+        let (child_sender, child_receiver) = channel::<AsyncChildExecutor>();
+        let child_receiver = Arc::new(Mutex::new(child_receiver));
+        let running_count = Arc::new(AtomicUsize::new(0));
+
+        let mut threads = Vec::new();
         for _ in 0..thread_pool_size {
-            let (async_child_executor_sender, async_child_executor_receiver) =
-                channel();
+            let child_receiver = child_receiver.clone();
+            let running_count = running_count.clone();
             let handle = thread::spawn(move || loop {
-                let child = match async_child_executor_receiver.recv() {
+                let child = match child_receiver.lock().unwrap().recv() {
                     Ok(child) => child,
                     Err(_) => break,
                 };
-                match AsyncChildExecutor::wait_for_output(child) {
-                    Ok(()) => (),
-                    Err(()) => break,
-                }
-            });
-            thread_pool.push(ExecutorThread {
-                handle,
-                async_child_executor_sender,
+                child.wait_for_output();
+                running_count.fetch_sub(1, Ordering::SeqCst);
             });
+            threads.push(handle);
         }
 
         Self {
-            thread_pool,
-            next_thread_index: 0,
+            _threads: threads,
+            child_sender,
+            running_count,
+            max_running_count: thread_pool_size,
         }
     }
 
-    pub fn run_child_async(&mut self, child: Child) -> AsyncChild {
+    /// Whether a new child can start right away
+    pub fn has_capacity(&self) -> bool {
+        self.running_count.load(Ordering::SeqCst) < self.max_running_count
+    }
+
+    pub fn run_child_async(
+        &mut self,
+        child: Child,
+        command_line: String,
+        options: ChildOptions,
+    ) -> AsyncChild {
         let (output_sender, output_receiver) = sync_channel(1);
 
+        let child = Arc::new(Mutex::new(child));
+        let partial = Arc::new(Mutex::new(Vec::new()));
+        let async_child = AsyncChild {
+            child: child.clone(),
+            partial: partial.clone(),
+            output_receiver,
+            command_line: command_line.clone(),
+        };
         let child = AsyncChildExecutor {
             child,
+            partial,
+            options,
+            command_line,
             output_sender,
         };
 
-        let thread = &mut self.thread_pool[self.next_thread_index];
-        thread.async_child_executor_sender.send(child).unwrap();
-        self.next_thread_index =
-            (self.next_thread_index + 1) % self.thread_pool.len();
+        self.running_count.fetch_add(1, Ordering::SeqCst);
+        self.child_sender.send(child).unwrap();
 
-        AsyncChild { output_receiver }
+        async_child
     }
 }
 
 #[derive(Clone)]
 pub struct ChildOutput {
     pub success: bool,
+    pub canceled: bool,
     pub output: String,
+    /// Why it failed. `None` when it succeeded or was canceled
+    pub error: Option<BackendError>,
 }
 
 impl ChildOutput {
     pub fn from_ok(output: String) -> Self {
         Self {
             success: true,
+            canceled: false,
             output,
+            error: None,
         }
     }
 
-    pub fn from_err(output: String) -> Self {
+    pub fn from_err(error: impl Into<BackendError>) -> Self {
+        let error = error.into();
         Self {
             success: false,
-            output,
+            canceled: false,
+            output: error.to_string(),
+            error: Some(error),
         }
     }
 
-    pub fn from_child(child: Child) -> Self {
-        let success;
-        let output;
+    pub fn from_canceled() -> Self {
+        Self {
+            success: false,
+            canceled: true,
+            output: String::from(tr("canceled")),
+            error: None,
+        }
+    }
 
-        match child.wait_with_output() {
-            Ok(out) => {
-                success = out.status.success();
-                let bytes = if success { out.stdout } else { out.stderr };
-                output = String::from_utf8_lossy(&bytes[..]).into_owned();
-            }
-            Err(error) => {
-                success = false;
-                output = error.to_string();
-            }
+    pub fn from_timed_out(timeout: Duration) -> Self {
+        Self {
+            success: false,
+            canceled: true,
+            output: trf("timed out after {}s", &[&timeout.as_secs()]),
+            error: None,
+        }
+    }
+
+    /// Waits for a child that may be killed from another thread meanwhile.
+    /// Failures mention `command_line` so it can be run again by hand.
+    /// Stdout, and stderr when streamed, also gather in `partial` as they're
+    /// read
+    fn from_shared_child(
+        child: &Mutex<Child>,
+        partial: &Arc<Mutex<Vec<u8>>>,
+        options: ChildOptions,
+        command_line: &str,
+    ) -> Self {
+        let (stdin, stdout, stderr) = {
+            let mut child = child.lock().unwrap();
+            (child.stdin.take(), child.stdout.take(), child.stderr.take())
+        };
+
+        // write on its own thread too as the child may only read stdin after
+        // printing more than the pipes hold
+        if let (Some(mut stdin), Some(input)) = (stdin, options.input) {
+            thread::spawn(move || {
+                let _ = stdin.write_all(input.as_bytes());
+            });
+        }
+
+        // read stderr on its own thread so a child filling both pipes can't
+        // block us
+        let stream_stderr = options.stream_stderr;
+        let stderr_partial = match stream_stderr {
+            true => Some(partial.clone()),
+            false => None,
         };
+        let stderr_thread = stderr.map(|stderr| {
+            thread::spawn(move || {
+                read_streamed(stderr, stderr_partial.as_deref(), true)
+            })
+        });
+        // stdout is only in `partial` unless stderr is mixed into it
+        let stdout_copy = stdout
+            .map(|stdout| {
+                read_streamed(stdout, Some(partial.as_ref()), stream_stderr)
+            })
+            .unwrap_or_default();
+        let stderr_bytes = stderr_thread
+            .and_then(|t| t.join().ok())
+            .unwrap_or_default();
 
-        Self { success, output }
+        match child.lock().unwrap().wait() {
+            Ok(status) => {
+                let partial = partial.lock().unwrap();
+                let stdout_bytes = match stream_stderr {
+                    true => &stdout_copy[..],
+                    false => &partial[..],
+                };
+                let success = status.success()
+                    || (options.allow_exit_one && status.code() == Some(1));
+                // some commands, like git merge, report failures on stdout
+                let bytes = if success || stderr_bytes.is_empty() {
+                    stdout_bytes
+                } else {
+                    &stderr_bytes[..]
+                };
+                let output = match &options.decoder {
+                    Some(decoder) => decoder(bytes),
+                    None => String::from_utf8_lossy(bytes).into_owned(),
+                };
+                if success {
+                    return Self::from_ok(output);
+                }
+                let mut error = BackendError::from_command(
+                    command_line.into(),
+                    status.code(),
+                    output,
+                );
+                if error.kind == ErrorKind::Unknown {
+                    let stdout = String::from_utf8_lossy(stdout_bytes);
+                    error.kind = ErrorKind::classify(status.code(), &stdout);
+                }
+                Self::from_err(error)
+            }
+            Err(error) => Self::from_err(error.to_string()),
+        }
     }
 }
 
+/// Reads `reader` to the end copying what arrives to `partial` right away.
+/// Returns what it read when `keeps_copy`, empty otherwise
+fn read_streamed<R: Read>(
+    mut reader: R,
+    partial: Option<&Mutex<Vec<u8>>>,
+    keeps_copy: bool,
+) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(len) => {
+                if keeps_copy {
+                    bytes.extend_from_slice(&buf[..len]);
+                }
+                if let Some(partial) = partial {
+                    partial.lock().unwrap().extend_from_slice(&buf[..len]);
+                }
+            }
+        }
+    }
+    bytes
+}
+
 pub struct AsyncChild {
+    child: Arc<Mutex<Child>>,
+    partial: Arc<Mutex<Vec<u8>>>,
     output_receiver: Receiver<ChildOutput>,
+    pub command_line: String,
 }
 
 impl AsyncChild {
+    pub fn kill(&self) {
+        if let Ok(mut child) = self.child.lock() {
+            let _ = child.kill();
+        }
+    }
+
+    /// Stdout, and stderr when streamed, read so far in the order they
+    /// arrived
+    pub fn partial_output(&self) -> String {
+        String::from_utf8_lossy(&self.partial.lock().unwrap()).into_owned()
+    }
+
+    /// Complete lines of the partial output from byte `from` on, and the
+    /// byte they end at so the next call continues from there
+    pub fn partial_lines(&self, from: usize) -> (String, usize) {
+        let partial = self.partial.lock().unwrap();
+        let from = from.min(partial.len());
+        let end = match partial[from..].iter().rposition(|&b| b == b'\n') {
+            Some(i) => from + i + 1,
+            None => from,
+        };
+        (
+            String::from_utf8_lossy(&partial[from..end]).into_owned(),
+            end,
+        )
+    }
+
     pub fn poll(&self) -> Poll<ChildOutput> {
         match self.output_receiver.try_recv() {
             Ok(result) => Poll::Ready(result),
@@ -120,13 +350,22 @@ impl AsyncChild {
 }
 
 struct AsyncChildExecutor {
-    pub child: Child,
+    pub child: Arc<Mutex<Child>>,
+    pub partial: Arc<Mutex<Vec<u8>>>,
+    pub options: ChildOptions,
+    pub command_line: String,
     pub output_sender: SyncSender<ChildOutput>,
 }
 
 impl AsyncChildExecutor {
-    fn wait_for_output(self) -> Result<(), ()> {
-        let output = ChildOutput::from_child(self.child);
-        self.output_sender.send(output).map_err(|_| ())
+    /// The receiver is gone when the task was canceled meanwhile
+    fn wait_for_output(self) {
+        let output = ChildOutput::from_shared_child(
+            &self.child,
+            &self.partial,
+            self.options,
+            &self.command_line,
+        );
+        let _ = self.output_sender.send(output);
     }
 }
@@ -0,0 +1,149 @@
+use std::fmt;
+
+/// Yellow so the failed command stands out from its error output
+const FAILED_COMMAND_COLOR: &str = "\x1b[33m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+pub type BackendResult<T> = Result<T, BackendError>;
+
+/// Lines the backends start their conflict reports with: git, hg, bzr and
+/// darcs in order
+const CONFLICT_MARKERS: &[&str] = &[
+    "CONFLICT (",
+    "Automatic merge failed; fix conflicts",
+    "error: could not apply",
+    "warning: conflicts while merging",
+    "unresolved conflicts (see",
+    "Text conflict in",
+    "Contents conflict in",
+    "We have conflicts in the following files",
+];
+
+/// What went wrong, told by the exit code and the output of the backend so
+/// the ui can react to it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorKind {
+    Conflict,
+    HookFailure,
+    Unknown,
+}
+
+impl ErrorKind {
+    /// Backends stopping on conflicts exit with 1 and report them with one
+    /// of the `CONFLICT_MARKERS`
+    pub fn classify(exit_code: Option<i32>, output: &str) -> Self {
+        let has_marker = || {
+            output.lines().any(|line| {
+                let line = line.trim_start();
+                CONFLICT_MARKERS.iter().any(|m| line.starts_with(m))
+            })
+        };
+        if exit_code == Some(1) && has_marker() {
+            Self::Conflict
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+/// Failure of a backend command or of the work around it
+#[derive(Clone, Debug)]
+pub struct BackendError {
+    pub kind: ErrorKind,
+    /// Command line that can be pasted into a shell to run it again
+    pub command: Option<String>,
+    /// `None` when the command was killed or could not be started
+    pub exit_code: Option<i32>,
+    pub stderr: String,
+    started: bool,
+}
+
+impl BackendError {
+    /// Error that did not come from running a command
+    pub fn new(message: String) -> Self {
+        Self {
+            kind: ErrorKind::Unknown,
+            command: None,
+            exit_code: None,
+            stderr: message,
+            started: true,
+        }
+    }
+
+    /// Error of `command` which exited with `exit_code`, or was killed when
+    /// there's none, printing `stderr`
+    pub fn from_command(
+        command: String,
+        exit_code: Option<i32>,
+        stderr: String,
+    ) -> Self {
+        Self {
+            kind: ErrorKind::classify(exit_code, &stderr),
+            command: Some(command),
+            exit_code,
+            stderr,
+            started: true,
+        }
+    }
+
+    /// Error of `command` that could not even be started
+    pub fn from_spawn(command: String, error: String) -> Self {
+        Self {
+            command: Some(command),
+            started: false,
+            ..Self::new(error)
+        }
+    }
+
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stderr = self.stderr.trim_end();
+        f.write_str(stderr)?;
+
+        let command = match &self.command {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        if !stderr.is_empty() {
+            f.write_str("\n\n")?;
+        }
+        let how = match self.exit_code {
+            Some(code) => format!("exited with code {}", code),
+            None if self.started => "terminated by a signal".into(),
+            None => "could not be started".into(),
+        };
+        // colored per line so each one keeps it when scrolled alone into view
+        write!(
+            f,
+            "{color}$ {}{reset}\n{color}{}{reset}",
+            command,
+            how,
+            color = FAILED_COMMAND_COLOR,
+            reset = RESET_COLOR,
+        )
+    }
+}
+
+impl From<String> for BackendError {
+    fn from(message: String) -> Self {
+        Self::new(message)
+    }
+}
+
+impl From<&str> for BackendError {
+    fn from(message: &str) -> Self {
+        Self::new(message.into())
+    }
+}
+
+impl From<BackendError> for String {
+    fn from(error: BackendError) -> Self {
+        error.to_string()
+    }
+}
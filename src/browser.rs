@@ -0,0 +1,148 @@
+use std::process::Command;
+
+use crate::action::{map_result, ActionTask, CommandTask};
+
+/// Page of the repository web interface
+pub enum WebTarget<'a> {
+    Commit(&'a str),
+    Branch(&'a str),
+    File { revision: &'a str, path: &'a str },
+}
+
+/// Url templates for each kind of page. `{repo}` is replaced by the web
+/// address of the remote, `{rev}`, `{branch}` and `{path}` by the target
+#[derive(Default, Clone)]
+pub struct WebUrlTemplates {
+    pub commit: Option<String>,
+    pub branch: Option<String>,
+    pub file: Option<String>,
+}
+
+/// Built in templates as `(commit, branch, file)` for hosts containing
+/// `gitlab` or `bitbucket`, otherwise GitHub style
+fn default_templates(repo: &str) -> (&'static str, &'static str, &'static str) {
+    if repo.contains("gitlab") {
+        (
+            "{repo}/-/commit/{rev}",
+            "{repo}/-/tree/{branch}",
+            "{repo}/-/blob/{rev}/{path}",
+        )
+    } else if repo.contains("bitbucket") {
+        (
+            "{repo}/commits/{rev}",
+            "{repo}/branch/{branch}",
+            "{repo}/src/{rev}/{path}",
+        )
+    } else {
+        (
+            "{repo}/commit/{rev}",
+            "{repo}/tree/{branch}",
+            "{repo}/blob/{rev}/{path}",
+        )
+    }
+}
+
+/// Web address of a repository cloned from `remote_url`, like
+/// `https://github.com/user/repo` for `git@github.com:user/repo.git`
+fn repository_web_url(remote_url: &str) -> Option<String> {
+    let url = remote_url.trim().trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+
+    let (host, path) = if let Some(i) = url.find("://") {
+        let rest = &url[i + 3..];
+        let rest = rest.rsplit_once('@').map_or(rest, |(_, r)| r);
+        rest.split_once('/')?
+    } else {
+        // scp like syntax as in `git@host:user/repo`
+        let rest = url.rsplit_once('@').map_or(url, |(_, r)| r);
+        rest.split_once(':')?
+    };
+    // drops ssh ports which the web interface doesn't use
+    let host = match url.starts_with("http") {
+        true => host,
+        false => host.split(':').next()?,
+    };
+    if host.is_empty() || path.is_empty() {
+        return None;
+    }
+
+    let scheme = match url.starts_with("http://") {
+        true => "http",
+        false => "https",
+    };
+    Some(format!("{}://{}/{}", scheme, host, path))
+}
+
+/// Escapes the chars of `text` not allowed in the path of an url, keeping
+/// the `/` between its components
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Url of `target` in the web interface of the repository at `remote_url`
+pub fn web_url(
+    templates: &WebUrlTemplates,
+    remote_url: &str,
+    target: WebTarget,
+) -> Result<String, String> {
+    let repo = repository_web_url(remote_url).ok_or_else(|| {
+        format!("could not get a web address from remote '{}'", remote_url)
+    })?;
+    let (commit, branch, file) = default_templates(&repo);
+    let url = match target {
+        WebTarget::Commit(revision) => templates
+            .commit
+            .as_deref()
+            .unwrap_or(commit)
+            .replace("{rev}", &percent_encode(revision)),
+        WebTarget::Branch(name) => templates
+            .branch
+            .as_deref()
+            .unwrap_or(branch)
+            .replace("{branch}", &percent_encode(name)),
+        WebTarget::File { revision, path } => templates
+            .file
+            .as_deref()
+            .unwrap_or(file)
+            .replace("{rev}", &percent_encode(revision))
+            .replace("{path}", &percent_encode(path)),
+    };
+    Ok(url.replace("{repo}", &repo))
+}
+
+/// Opens `url` with the default browser of the platform
+pub fn open(url: &str) -> Box<dyn ActionTask> {
+    // `start` through `cmd` would treat `&` and `^` in the url as its own
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+    command.arg(url);
+
+    let message = format!("opened {}", url);
+    map_result(Box::new(CommandTask::new(command)), move |mut result| {
+        if result.success {
+            result.output = message.clone();
+        }
+        result
+    })
+}
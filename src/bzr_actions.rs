@@ -0,0 +1,647 @@
+use std::{fs, path::Path, process::Command, time::Duration};
+
+use crate::{
+    action::{
+        deferred, map_result, parallel, ready, serial, task_vec, ActionKind,
+        ActionResult, ActionTask,
+    },
+    backend_error::BackendResult,
+    select::{Entry, State},
+    version_control_actions::{
+        discard_snapshot_age, discard_snapshot_name, handle_command, task,
+        task_allowing_exit_one, CommitOptions, StatusOptions,
+        VersionControlActions,
+    },
+};
+
+fn str_to_state(s: &str) -> State {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some('?'), _) => State::Untracked,
+        (Some('+'), _) => State::Added,
+        (Some('-'), _) => State::Deleted,
+        (Some('R'), _) => State::Renamed,
+        (Some('C'), _) => State::Unmerged,
+        (Some('X'), _) | (Some('I'), _) => State::Ignored,
+        (_, Some('N')) => State::Added,
+        (_, Some('D')) => State::Missing,
+        _ => State::Modified,
+    }
+}
+
+/// Parses `status --short` output where the first three columns hold the
+/// state of the file and renames are shown as `old => new`
+fn parse_status(output: &str) -> Vec<Entry> {
+    output
+        .lines()
+        .filter(|l| l.len() > 4)
+        .map(|l| {
+            let (state, filename) = l.split_at(4);
+            let (filename, renamed_from) = match filename.find(" => ") {
+                Some(i) => (&filename[i + 4..], Some(filename[..i].trim())),
+                None => (filename, None),
+            };
+            Entry {
+                filename: filename.trim().into(),
+                selected: false,
+                state: str_to_state(state),
+                renamed_from: renamed_from.map(String::from),
+            }
+        })
+        .collect()
+}
+
+/// Converts a `log --line` line like `12: author 2020-01-31 {tag} message`
+/// into the separated parts the log view expects
+fn format_log_line(line: &str) -> Option<String> {
+    let (revno, rest) = line.trim().split_once(": ")?;
+    let mut words = rest.split(' ');
+    let mut author = Vec::new();
+    let date = loop {
+        let word = words.next()?;
+        if is_date(word) {
+            break word;
+        }
+        author.push(word);
+    };
+
+    let mut tags = String::new();
+    let mut message: Vec<_> = words.collect();
+    if let Some(first) = message.first() {
+        if first.starts_with('{') {
+            let end = message.iter().position(|w| w.ends_with('}'))?;
+            tags = message.drain(..=end).collect::<Vec<_>>().join(" ");
+            tags = tags.trim_matches(|c| c == '{' || c == '}').into();
+        }
+    }
+
+    Some(format!(
+        "\x1e{}\x1e\x1e{}\x1e{}\x1e{}\x1e{}",
+        revno,
+        date,
+        author.join(" "),
+        tags,
+        message.join(" ")
+    ))
+}
+
+fn is_date(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    bytes.len() == 10
+        && bytes.iter().enumerate().all(|(i, b)| match i {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+/// Branch names from `branches` output which marks the current one with `*`
+fn parse_branches(output: &str) -> Vec<&str> {
+    output
+        .lines()
+        .map(|l| l.trim_start_matches('*').trim())
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+/// Shelf ids and messages from `shelve --list` lines like `  2: message`
+fn parse_shelves(output: &str) -> Vec<(&str, &str)> {
+    output
+        .lines()
+        .filter_map(|l| l.trim().split_once(": "))
+        .collect()
+}
+
+const SIGN_UNSUPPORTED: &str = "verco does not support signing bzr commits";
+const NOTES_UNSUPPORTED: &str = "bzr does not support notes";
+
+pub struct BzrActions {
+    pub current_dir: String,
+    /// Either `brz` for Breezy or `bzr` for the original Bazaar
+    pub executable: &'static str,
+}
+
+impl BzrActions {
+    /// `diff` exits with 1 when there are differences
+    fn diff_task<F>(&self, builder: F) -> Box<dyn ActionTask>
+    where
+        F: FnOnce(&mut Command),
+    {
+        task_allowing_exit_one(self, |command| {
+            command.arg("diff");
+            builder(command);
+        })
+    }
+}
+
+impl VersionControlActions for BzrActions {
+    fn executable_name(&self) -> &'static str {
+        self.executable
+    }
+
+    fn current_dir(&self) -> &str {
+        &self.current_dir[..]
+    }
+
+    fn flags_subcommand(&self, kind: ActionKind) -> Option<&'static str> {
+        let subcommand = match kind {
+            ActionKind::Fetch => "missing",
+            ActionKind::Pull => "pull",
+            ActionKind::Push => "push",
+            ActionKind::CommitAll | ActionKind::CommitSelected => "commit",
+            ActionKind::Merge => "merge",
+            ActionKind::CurrentDiffAll
+            | ActionKind::CurrentDiffSelected
+            | ActionKind::RevisionDiffAll
+            | ActionKind::RevisionDiffSelected => "diff",
+            _ => return None,
+        };
+        Some(subcommand)
+    }
+
+    fn set_root(&mut self) -> BackendResult<()> {
+        let mut command = self.command();
+        let dir = handle_command(command.arg("root"))?;
+
+        let dir = dir
+            .lines()
+            .next()
+            .expect("root directory is an empty string");
+        self.current_dir = dir.to_owned();
+
+        Ok(())
+    }
+
+    fn get_root(&self) -> &str {
+        &self.current_dir[..]
+    }
+
+    fn get_current_changed_files(&self) -> BackendResult<Vec<Entry>> {
+        let output =
+            handle_command(self.command().args(["status", "--short"]))?;
+        Ok(parse_status(&output))
+    }
+
+    fn get_revision_changed_files(
+        &self,
+        target: &str,
+    ) -> BackendResult<Vec<Entry>> {
+        let output = handle_command(
+            self.command().args(["status", "--short", "-c"]).arg(target),
+        )?;
+        Ok(parse_status(&output))
+    }
+
+    /// Location aliases that are configured for the branch
+    fn get_remotes(&self) -> BackendResult<Vec<String>> {
+        let mut remotes = Vec::new();
+        for (alias, option) in
+            [(":parent", "parent_location"), (":push", "push_location")]
+        {
+            let location =
+                handle_command(self.command().args(["config", option]));
+            if location.map(|l| !l.trim().is_empty()).unwrap_or(false) {
+                remotes.push(alias.into());
+            }
+        }
+        Ok(remotes)
+    }
+
+    fn get_branch_names(&self) -> BackendResult<Vec<String>> {
+        let output = handle_command(self.command().arg("branches"))?;
+        Ok(parse_branches(&output)
+            .into_iter()
+            .map(String::from)
+            .collect())
+    }
+
+    fn get_current_branch(&self) -> BackendResult<String> {
+        let output = handle_command(self.command().arg("nick"))?;
+        Ok(output.trim().into())
+    }
+
+    fn has_upstream(&self) -> bool {
+        true
+    }
+
+    fn version(&self) -> BackendResult<String> {
+        handle_command(self.command().arg("--version"))
+    }
+
+    fn status(
+        &self,
+        options: StatusOptions,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        let mut tasks = task_vec();
+        tasks.push(task(self, |command| {
+            command.arg("info");
+        }));
+        tasks.push(task(self, |command| {
+            command.arg("status");
+            if options.skip_untracked {
+                command.arg("--versioned");
+            }
+            command.args(scope);
+        }));
+        if options.show_ignored {
+            tasks.push(task(self, |command| {
+                command.arg("ignored");
+            }));
+        }
+        parallel(tasks)
+    }
+
+    fn current_export(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["log", "-p", "-r", "-1"]);
+        })
+    }
+
+    /// Other branches are separate locations whose log is read with the
+    /// `branch:` revision prefix
+    fn log(
+        &self,
+        count: usize,
+        scope: Option<&str>,
+        branch: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        let log = task(self, |command| {
+            command.args(["log", "--line", "-l"]).arg(count.to_string());
+            if let Some(branch) = branch {
+                command.arg(format!("-r..branch:{}", branch));
+            }
+            command.args(scope);
+        });
+        map_result(log, |mut result| {
+            if result.success {
+                let lines: Vec<_> = result
+                    .output
+                    .lines()
+                    .map(|l| format_log_line(l).unwrap_or_else(|| l.into()))
+                    .collect();
+                result.output = lines.join("\n");
+            }
+            result
+        })
+    }
+
+    fn current_diff_all(&self, scope: Option<&str>) -> Box<dyn ActionTask> {
+        self.diff_task(|command| {
+            command.args(scope);
+        })
+    }
+
+    fn current_diff_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask> {
+        self.diff_task(|command| {
+            command.arg("--");
+            for e in entries.iter().filter(|e| e.selected) {
+                command.arg(&e.filename);
+            }
+        })
+    }
+
+    fn revision_changes(
+        &self,
+        target: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["status", "--short", "-c"]).arg(target);
+            command.args(scope);
+        })
+    }
+
+    fn revision_diff_all(
+        &self,
+        target: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        self.diff_task(|command| {
+            command.arg("-c").arg(target);
+            command.args(scope);
+        })
+    }
+
+    fn revision_diff_selected(
+        &self,
+        target: &str,
+        entries: &[Entry],
+    ) -> Box<dyn ActionTask> {
+        self.diff_task(|command| {
+            command.arg("-c").arg(target).arg("--");
+            for e in entries.iter().filter(|e| e.selected) {
+                command.arg(&e.filename);
+            }
+        })
+    }
+
+    fn base_changes(
+        &self,
+        base: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["status", "--short", "-r"]).arg(base);
+            command.args(scope);
+        })
+    }
+
+    fn base_diff_all(
+        &self,
+        base: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        self.diff_task(|command| {
+            command.arg("-r").arg(base);
+            command.args(scope);
+        })
+    }
+
+    fn supports_notes(&self) -> bool {
+        false
+    }
+
+    fn get_note(
+        &self,
+        _target: &str,
+        _notes_ref: &str,
+    ) -> BackendResult<String> {
+        Err(NOTES_UNSUPPORTED.into())
+    }
+
+    fn revision_signature(&self, _target: &str) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_ok(String::new()))
+    }
+
+    fn revision_notes(
+        &self,
+        _target: &str,
+        _notes_ref: &str,
+    ) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_ok(String::new()))
+    }
+
+    fn set_note(
+        &self,
+        _target: &str,
+        _notes_ref: &str,
+        _message: &str,
+    ) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(NOTES_UNSUPPORTED))
+    }
+
+    fn commit_all(
+        &self,
+        message: &str,
+        options: CommitOptions,
+    ) -> Box<dyn ActionTask> {
+        if options.sign {
+            return ready(ActionResult::from_err(SIGN_UNSUPPORTED));
+        }
+        let mut tasks = task_vec();
+        tasks.push(task(self, |command| {
+            command.arg("add");
+        }));
+        tasks.push(task(self, |command| {
+            command.arg("commit").arg("-m").arg(message);
+        }));
+        serial(tasks)
+    }
+
+    fn commit_selected(
+        &self,
+        message: &str,
+        entries: &[Entry],
+        options: CommitOptions,
+    ) -> Box<dyn ActionTask> {
+        if options.sign {
+            return ready(ActionResult::from_err(SIGN_UNSUPPORTED));
+        }
+        let mut tasks = task_vec();
+        let mut files_to_commit = Vec::new();
+        for e in entries.iter().filter(|e| e.selected) {
+            match e.state {
+                State::Missing => tasks.push(task(self, |command| {
+                    command.arg("remove").arg(&e.filename);
+                })),
+                State::Untracked => tasks.push(task(self, |command| {
+                    command.arg("add").arg(&e.filename);
+                })),
+                _ => (),
+            }
+            files_to_commit.push(&e.filename);
+        }
+        tasks.push(task(self, |command| {
+            command.arg("commit").arg("-m").arg(message).arg("--");
+            for file in files_to_commit {
+                command.arg(file);
+            }
+        }));
+        serial(tasks)
+    }
+
+    fn revert_all(&self) -> Box<dyn ActionTask> {
+        let mut tasks = task_vec();
+        tasks.push(task(self, |command| {
+            command.arg("revert");
+        }));
+        tasks.push(task(self, |command| {
+            command.args(["clean-tree", "--unknown", "--force"]);
+        }));
+        serial(tasks)
+    }
+
+    fn revert_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask> {
+        let mut tasks = task_vec();
+        let mut files_to_revert = Vec::new();
+        for e in entries.iter().filter(|e| e.selected) {
+            match e.state {
+                // clean-tree can't be limited to some files
+                State::Untracked => {
+                    let path = Path::new(self.get_root()).join(&e.filename);
+                    let filename = e.filename.clone();
+                    tasks.push(deferred(move || match fs::remove_file(path) {
+                        Ok(()) => ActionResult::from_ok(String::new()),
+                        Err(error) => ActionResult::from_err(format!(
+                            "could not remove {}: {}",
+                            filename, error
+                        )),
+                    }));
+                }
+                _ => files_to_revert.push(&e.filename),
+            }
+        }
+        if !files_to_revert.is_empty() {
+            tasks.push(task(self, |command| {
+                command.arg("revert").arg("--");
+                for file in files_to_revert {
+                    command.arg(file);
+                }
+            }));
+        }
+        parallel(tasks)
+    }
+
+    fn create_discard_snapshot(&self) -> BackendResult<()> {
+        let name = discard_snapshot_name();
+        match handle_command(
+            self.command().args(["shelve", "--all", "-m"]).arg(&name),
+        ) {
+            Ok(_) => (),
+            Err(error) if error.stderr.contains("No changes") => return Ok(()),
+            Err(error) => return Err(error),
+        }
+
+        handle_command(self.command().args(["unshelve", "--keep"])).map(|_| ())
+    }
+
+    fn prune_discard_snapshots(&self, max_age: Duration) -> BackendResult<()> {
+        let output = handle_command(self.command().args(["shelve", "--list"]))?;
+
+        for (id, message) in parse_shelves(&output) {
+            match discard_snapshot_age(message) {
+                Some(age) if age > max_age => {
+                    handle_command(
+                        self.command()
+                            .args(["unshelve", "--delete-only"])
+                            .arg(id),
+                    )?;
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn restore_discard_snapshot(&self) -> Box<dyn ActionTask> {
+        let output = handle_command(self.command().args(["shelve", "--list"]))
+            .unwrap_or_default();
+        let id = parse_shelves(&output)
+            .into_iter()
+            .filter_map(|(id, message)| {
+                discard_snapshot_age(message).map(|age| (age, id))
+            })
+            .min_by_key(|(age, _)| *age)
+            .map(|(_, id)| id);
+
+        match id {
+            Some(id) => task(self, |command| {
+                command.args(["unshelve", "--keep"]).arg(id);
+            }),
+            None => {
+                ready(ActionResult::from_err("no discard snapshot to restore"))
+            }
+        }
+    }
+
+    fn update(&self, target: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("update").arg("-r").arg(target);
+        })
+    }
+
+    fn merge(&self, target: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("merge").arg("-r").arg(target);
+        })
+    }
+
+    fn conflicts(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("conflicts");
+        })
+    }
+
+    fn take_other(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["resolve", "--all", "--action=take-other"]);
+        })
+    }
+
+    fn take_local(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["resolve", "--all", "--action=take-this"]);
+        })
+    }
+
+    /// Branches have no remote tracking state to update, so this only
+    /// lists the revisions a pull would bring in. `missing` exits with 1
+    /// when there are any
+    fn fetch(&self) -> Box<dyn ActionTask> {
+        task_allowing_exit_one(self, |command| {
+            command.args(["missing", "--theirs-only", "--line"]);
+        })
+    }
+
+    fn pull(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("pull");
+        })
+    }
+
+    fn push(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("push");
+        })
+    }
+
+    /// Branches are separate locations so only the remote is used
+    fn push_to(
+        &self,
+        remote: &str,
+        _branch: &str,
+        set_upstream: bool,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("push");
+            if set_upstream {
+                command.arg("--remember");
+            }
+            command.arg(remote);
+        })
+    }
+
+    /// There's no lease check so this overwrites the remote unconditionally
+    fn force_push(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["push", "--overwrite"]);
+        })
+    }
+
+    fn create_tag(&self, name: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("tag").arg(name);
+        })
+    }
+
+    fn list_branches(&self) -> Box<dyn ActionTask> {
+        let branches = task(self, |command| {
+            command.arg("branches");
+        });
+        map_result(branches, |mut result| {
+            if result.success {
+                result.output = parse_branches(&result.output).join("\n");
+            }
+            result
+        })
+    }
+
+    fn create_branch(&self, name: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["switch", "--create-branch"]).arg(name);
+        })
+    }
+
+    fn close_branch(&self, name: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("rmbranch").arg(name);
+        })
+    }
+
+    fn ignore(&self, pattern: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("ignore").arg(pattern);
+        })
+    }
+}
@@ -0,0 +1,549 @@
+use std::{
+    env, io,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crossterm::tty::IsTty;
+
+use crate::{
+    action::ActionResult,
+    application::Application,
+    args::{CommitArgs, OutputFormat, StartupMode},
+    backend_error::ErrorKind,
+    color_support::ColorSupport,
+    config::{user_config_dir, Config},
+    json::quote,
+    repositories,
+    select::{Entry, State},
+    tui::{branch_off_before_commit, format_before_commit, refresh_formatted},
+    tui_util::strip_ansi,
+    version_control_actions::CommitOptions,
+};
+
+/// Log entries printed when no count is given
+const DEFAULT_LOG_COUNT: usize = 20;
+
+/// Executables of the backends whose versions the diagnostics print
+const BACKEND_EXECUTABLES: &[&str] = &["git", "hg", "jj", "bzr", "darcs"];
+
+/// Value of a field of a printed entry
+pub enum Field {
+    Text(String),
+    Flag(bool),
+    Number(usize),
+    Missing,
+}
+
+/// Fields of a printed entry by their names
+pub type Fields = Vec<(&'static str, Field)>;
+
+impl Field {
+    fn plain(&self) -> String {
+        match self {
+            Field::Text(text) => text.clone(),
+            Field::Flag(flag) => flag.to_string(),
+            Field::Number(number) => number.to_string(),
+            Field::Missing => String::new(),
+        }
+    }
+
+    pub fn json(&self) -> String {
+        match self {
+            Field::Text(text) => quote(text),
+            Field::Flag(flag) => flag.to_string(),
+            Field::Number(number) => number.to_string(),
+            Field::Missing => "null".into(),
+        }
+    }
+}
+
+pub fn json_object(fields: &[(&str, Field)]) -> String {
+    let fields: Vec<_> = fields
+        .iter()
+        .map(|(name, field)| format!("{}: {}", quote(name), field.json()))
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+impl From<Option<String>> for Field {
+    fn from(text: Option<String>) -> Self {
+        text.map_or(Field::Missing, Field::Text)
+    }
+}
+
+/// Prints the entries of `mode` without opening the tui so scripts can use
+/// them. Returns the exit code of the process
+pub fn print(
+    mode: StartupMode,
+    format: OutputFormat,
+    count: Option<usize>,
+    directory: Option<&str>,
+) -> i32 {
+    let mut app = match open_application(directory) {
+        Some(app) => app,
+        None => return 1,
+    };
+    let entries = match entries(&mut app, mode, count) {
+        Ok(entries) => entries,
+        Err(error) => return print_error(&error),
+    };
+
+    match format {
+        OutputFormat::Plain => {
+            for fields in entries {
+                let values: Vec<_> =
+                    fields.iter().map(|(_, field)| field.plain()).collect();
+                println!("{}", values.join("\t").trim_end());
+            }
+        }
+        OutputFormat::Json => {
+            let entries: Vec<_> =
+                entries.iter().map(|fields| json_object(fields)).collect();
+            println!("[{}]", entries.join(",\n "));
+        }
+    }
+    0
+}
+
+/// Entries of `mode` as named fields, or the output of the failed command
+pub fn entries(
+    app: &mut Application,
+    mode: StartupMode,
+    count: Option<usize>,
+) -> Result<Vec<Fields>, String> {
+    let entries = match mode {
+        StartupMode::Status => {
+            match app.version_control.get_current_changed_files() {
+                Ok(entries) => entries
+                    .into_iter()
+                    .map(|e| {
+                        vec![
+                            ("state", Field::Text(e.state.name().into())),
+                            ("path", Field::Text(e.filename)),
+                            ("renamed_from", e.renamed_from.into()),
+                        ]
+                    })
+                    .collect(),
+                Err(error) => return Err(error.to_string()),
+            }
+        }
+        StartupMode::Log => {
+            let count = count.unwrap_or(DEFAULT_LOG_COUNT);
+            let log = app.version_control.log_for_scripts(count);
+            let result = app.run_to_completion(log);
+            if !result.success {
+                return Err(result.output);
+            }
+            result
+                .output
+                .lines()
+                .filter_map(|line| {
+                    let line = strip_ansi(line);
+                    let fields: Vec<_> = line.split('\x1e').collect();
+                    let field =
+                        |i: usize| fields.get(i).map(|f| f.trim().to_owned());
+                    field(1).filter(|hash| !hash.is_empty())?;
+                    Some(vec![
+                        ("hash", field(1).into()),
+                        ("date", field(3).into()),
+                        ("author", field(4).into()),
+                        ("refs", field(5).into()),
+                        ("subject", field(6).into()),
+                        ("phase", field(7).into()),
+                        ("topics", field(8).into()),
+                    ])
+                })
+                .collect()
+        }
+        StartupMode::Branches => {
+            let current = app.version_control.get_current_branch().ok();
+            match app.version_control.get_branch_names() {
+                Ok(branches) => branches
+                    .into_iter()
+                    .map(|name| {
+                        let is_current = current.as_deref() == Some(&name[..]);
+                        vec![
+                            ("name", Field::Text(name)),
+                            ("current", Field::Flag(is_current)),
+                        ]
+                    })
+                    .collect(),
+                Err(error) => return Err(error.to_string()),
+            }
+        }
+        StartupMode::Tags => match app.version_control.get_tag_names() {
+            Ok(tags) => tags
+                .into_iter()
+                .map(|name| vec![("name", Field::Text(name))])
+                .collect(),
+            Err(error) => return Err(error.to_string()),
+        },
+    };
+    Ok(entries)
+}
+
+/// Prints a summary of the repository the same way for every backend, for
+/// statuslines and editors. Returns the exit code of the process
+pub fn info(format: OutputFormat, directory: Option<&str>) -> i32 {
+    let mut app = match open_application(directory) {
+        Some(app) => app,
+        None => return 1,
+    };
+    let fields = match info_fields(&mut app) {
+        Ok(fields) => fields,
+        Err(error) => return print_error(&error),
+    };
+
+    match format {
+        OutputFormat::Plain => {
+            for (name, field) in &fields {
+                println!("{}\t{}", name, field.plain());
+            }
+        }
+        OutputFormat::Json => println!("{}", json_object(&fields)),
+    }
+    0
+}
+
+/// Summary of the repository printed by `info`, or the output of the failed
+/// command
+pub fn info_fields(app: &mut Application) -> Result<Fields, String> {
+    let entries = match app.version_control.get_current_changed_files() {
+        Ok(entries) => entries,
+        Err(error) => return Err(error.to_string()),
+    };
+    let conflicts = entries
+        .iter()
+        .filter(|e| matches!(e.state, State::Unmerged))
+        .count();
+
+    // fails without an upstream
+    let (ahead, behind) = match app.version_control.ahead_behind() {
+        Some(Ok((ahead, behind))) => {
+            (Field::Number(ahead), Field::Number(behind))
+        }
+        Some(Err(_)) | None => (Field::Missing, Field::Missing),
+    };
+
+    let version_control = &app.version_control;
+    Ok(vec![
+        (
+            "backend",
+            Field::Text(version_control.executable_name().into()),
+        ),
+        ("root", Field::Text(version_control.get_root().into())),
+        ("branch", version_control.get_current_branch().ok().into()),
+        (
+            "operation",
+            version_control
+                .operation_in_progress()
+                .map(|o| o.name().to_owned())
+                .into(),
+        ),
+        ("changed", Field::Number(entries.len())),
+        ("conflicts", Field::Number(conflicts)),
+        ("ahead", ahead),
+        ("behind", behind),
+    ])
+}
+
+/// Opens the repository at `directory`, or the current one, moving there
+/// since the config is read from the repository root
+pub fn open_application(directory: Option<&str>) -> Option<Application> {
+    let version_control = repositories::get_current_version_control(directory)?;
+    let root = PathBuf::from(version_control.get_root());
+    if env::set_current_dir(&root).is_err() {
+        eprintln!("could not open {}", root.display());
+        return None;
+    }
+    Some(Application::new(
+        version_control,
+        Vec::new(),
+        Config::load(),
+        None,
+    ))
+}
+
+/// Prints the output of a failed command to stderr without colors when it's
+/// not a terminal. Returns the exit code of the process
+fn print_error(output: &str) -> i32 {
+    let output = output.trim();
+    if io::stderr().is_tty() {
+        eprintln!("{}", output);
+    } else {
+        for line in output.lines() {
+            eprintln!("{}", strip_ansi(line));
+        }
+    }
+    1
+}
+
+/// Prints the version and, when `verbose`, diagnostics to paste into bug
+/// reports about the build, backends, config files and terminal as seen from
+/// `directory` or the current one. Returns the exit code of the process
+pub fn version(verbose: bool, directory: Option<&str>) -> i32 {
+    println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    if !verbose {
+        return 0;
+    }
+
+    if let Some(directory) = directory {
+        if env::set_current_dir(directory).is_err() {
+            eprintln!("could not open {}", directory);
+            return 1;
+        }
+    }
+
+    println!();
+    println!(
+        "target: {}-{} ({})",
+        env::consts::ARCH,
+        env::consts::OS,
+        if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        }
+    );
+
+    println!("backends:");
+    for executable in BACKEND_EXECUTABLES {
+        let version = Command::new(executable)
+            .arg("--version")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output();
+        let version = match version {
+            Ok(output) => String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_owned(),
+            Err(_) => "not found".into(),
+        };
+        println!("  {}: {}", executable, version);
+    }
+
+    let current_dir = env::current_dir().unwrap_or_default();
+    let repository = current_dir
+        .to_str()
+        .and_then(repositories::version_control_at)
+        .map(|v| format!("{} ({})", v.get_root(), v.executable_name()));
+    println!(
+        "repository: {}",
+        repository.as_deref().unwrap_or("none found")
+    );
+
+    println!("config files:");
+    let mut paths = Config::paths().unwrap_or_default();
+    let repository_dir = current_dir.join(concat!(".", env!("CARGO_PKG_NAME")));
+    paths.push(repository_dir.join("custom_actions.txt"));
+    paths.push(repository_dir.join("custom_actions.toml"));
+    if let Some(dir) = user_config_dir() {
+        paths.push(dir.join("custom_actions.toml"));
+    }
+    for path in paths {
+        let found = if path.is_file() { "found" } else { "missing" };
+        println!("  {} ({})", path.display(), found);
+    }
+
+    println!("terminal:");
+    let size = crossterm::terminal::size()
+        .map(|(width, height)| format!("{}x{}", width, height))
+        .unwrap_or_else(|_| "unknown".into());
+    println!("  size: {}", size);
+    println!(
+        "  stdin tty: {}, stdout tty: {}",
+        io::stdin().is_tty(),
+        io::stdout().is_tty()
+    );
+    let config = Config::load();
+    let color_support = match config.color_support {
+        Some(color_support) => format!("{:?} (from config)", color_support),
+        None => format!("{:?} (detected)", ColorSupport::detect()),
+    };
+    println!("  colors: {}", color_support);
+    for name in &["TERM", "COLORTERM", "TERM_PROGRAM", "LANG"] {
+        let value = env::var(name).unwrap_or_default();
+        println!("  {}: {}", name, value);
+    }
+    0
+}
+
+/// Commits from the shell without opening the tui, printing the backend
+/// output. Returns the exit code of the process
+pub fn commit(args: CommitArgs) -> i32 {
+    let current_dir = match env::current_dir() {
+        Ok(dir) => dir,
+        Err(error) => {
+            eprintln!("could not get current directory: {}", error);
+            return 1;
+        }
+    };
+    let mut app = match open_application(None) {
+        Some(app) => app,
+        None => return 1,
+    };
+    let entries = match path_entries(&app, &current_dir, &args.paths) {
+        Ok(entries) => entries,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 1;
+        }
+    };
+    if entries.is_empty() {
+        eprintln!("nothing to commit");
+        return 1;
+    }
+
+    if let Some(branch) = app.protected_branch() {
+        if args.branch.is_none() && !args.allow_protected {
+            eprintln!(
+                "'{}' is a protected branch, pass --branch <name> to commit \
+                 on a new branch or --allow-protected to commit anyway",
+                branch
+            );
+            return 1;
+        }
+    }
+
+    let result = run_commit(
+        &mut app,
+        &args.message,
+        &entries,
+        args.paths.is_empty(),
+        args.no_verify,
+        args.branch,
+    );
+    if args.json {
+        let output = strip_ansi(result.output.trim());
+        println!(
+            "{{\"success\": {}, \"output\": {}}}",
+            result.success,
+            quote(&output)
+        );
+        return if result.success { 0 } else { 1 };
+    }
+    if result.success {
+        println!("{}", result.output.trim());
+        return 0;
+    }
+    print_error(&result.output);
+    let hook_failed = result
+        .error
+        .is_some_and(|e| e.kind == ErrorKind::HookFailure);
+    if hook_failed && !args.no_verify {
+        eprintln!(
+            "\na commit hook rejected the commit, \
+             run again with --no-verify to skip the hooks"
+        );
+    }
+    1
+}
+
+/// Changed files at `paths`, relative to `current_dir`, as entries of the
+/// repository. Every changed file when `paths` is empty
+pub fn path_entries(
+    app: &Application,
+    current_dir: &Path,
+    paths: &[String],
+) -> Result<Vec<Entry>, String> {
+    let root = PathBuf::from(app.version_control.get_root());
+    let changed = app
+        .version_control
+        .get_current_changed_files()
+        .map_err(|e| e.to_string())?;
+    if paths.is_empty() {
+        let mut entries = changed;
+        for e in &mut entries {
+            e.selected = true;
+        }
+        return Ok(entries);
+    }
+
+    let mut entries = Vec::new();
+    for path in paths {
+        let filename = root_relative(&root, current_dir, path)
+            .ok_or_else(|| format!("'{}' is outside the repository", path))?;
+        let changed = changed.iter().find(|e| e.filename == filename);
+        // checked upfront as staging the other paths would go on and
+        // commit them without it
+        if changed.is_none() && !root.join(&filename).exists() {
+            return Err(format!("'{}' did not match any file", path));
+        }
+        entries.push(Entry {
+            state: changed.map_or(State::Modified, |e| e.state.clone()),
+            renamed_from: changed.and_then(|e| e.renamed_from.clone()),
+            filename,
+            selected: true,
+        });
+    }
+    Ok(entries)
+}
+
+/// Commits `entries`, or every change when `all`, with the trailers,
+/// signing and formatting of the config. With `branch`, they're committed
+/// on that new branch
+pub fn run_commit(
+    app: &mut Application,
+    message: &str,
+    entries: &[Entry],
+    all: bool,
+    no_verify: bool,
+    branch: Option<String>,
+) -> ActionResult {
+    let mut message = String::from(message.trim());
+    if !app.config.trailers.is_empty() {
+        message.push_str("\n\n");
+        message.push_str(&app.config.trailers.join("\n"));
+    }
+    let mut entries = entries.to_vec();
+    if let Some(format) = format_before_commit(app, &entries) {
+        let result = app.run_to_completion(format);
+        if !result.success {
+            return result;
+        }
+        if let Err(error) = refresh_formatted(app, &mut entries) {
+            return ActionResult::from_err(error);
+        }
+    }
+
+    let options = CommitOptions {
+        no_verify,
+        ..app.commit_options()
+    };
+    let commit = if all {
+        app.version_control.commit_all(&message, options)
+    } else {
+        app.version_control
+            .commit_selected(&message, &entries, options)
+    };
+    let commit = branch_off_before_commit(app, branch, commit);
+    app.run_to_completion(commit)
+}
+
+/// `path`, relative to `current_dir`, as a path relative to `root` with
+/// forward slashes. `None` when it's outside of `root`
+fn root_relative(
+    root: &Path,
+    current_dir: &Path,
+    path: &str,
+) -> Option<String> {
+    let mut absolute = PathBuf::new();
+    for component in current_dir.join(path).components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            component => absolute.push(component),
+        }
+    }
+    let relative = absolute.strip_prefix(root).ok()?.to_str()?;
+    match relative {
+        "" => Some(".".into()),
+        relative => Some(relative.replace('\\', "/")),
+    }
+}
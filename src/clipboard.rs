@@ -0,0 +1,108 @@
+use std::{
+    env,
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+#[cfg(target_os = "windows")]
+const COPY_COMMANDS: &[&[&str]] = &[&["clip"]];
+#[cfg(target_os = "macos")]
+const COPY_COMMANDS: &[&[&str]] = &[&["pbcopy"]];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const COPY_COMMANDS: &[&[&str]] = &[
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--input"],
+];
+
+const BASE64_CHARS: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// How the text was copied
+pub enum Copied {
+    /// By a clipboard command that succeeded
+    Command,
+    /// By asking the terminal, which gives no answer and ignores it without
+    /// support, so the copy may not have happened
+    Terminal,
+}
+
+/// Copies with the native clipboard commands falling back to asking the
+/// terminal to do it. Over ssh the terminal goes first since the commands
+/// would copy to the clipboard of the remote machine
+pub fn copy(text: &str) -> Result<Copied, String> {
+    let is_remote = env::var_os("SSH_TTY").is_some()
+        || env::var_os("SSH_CONNECTION").is_some();
+    if !is_remote {
+        for command in COPY_COMMANDS {
+            if copy_with(command, text).is_ok() {
+                return Ok(Copied::Command);
+            }
+        }
+    }
+
+    copy_with_osc52(text).map_err(|e| e.to_string())?;
+    Ok(Copied::Terminal)
+}
+
+/// Sends `text` to the terminal in an OSC 52 escape sequence. Terminals
+/// without support ignore it
+fn copy_with_osc52(text: &str) -> io::Result<()> {
+    let mut sequence = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    // tmux only forwards it to the outer terminal when wrapped
+    if env::var_os("TMUX").is_some() {
+        sequence = format!("\x1bPtmux;\x1b{}\x1b\\", sequence);
+    }
+    let mut stdout = io::stdout();
+    stdout.write_all(sequence.as_bytes())?;
+    stdout.flush()
+}
+
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let indices = [
+            b[0] >> 2,
+            (b[0] & 0x03) << 4 | b[1] >> 4,
+            (b[1] & 0x0f) << 2 | b[2] >> 6,
+            b[2] & 0x3f,
+        ];
+        for (i, index) in indices.iter().enumerate() {
+            if i <= chunk.len() {
+                encoded.push(BASE64_CHARS[*index as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn copy_with(command: &[&str], text: &str) -> Result<(), String> {
+    let mut child = Command::new(command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    // stdin is dropped right after writing so the command sees the end
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(text.as_bytes())
+            .map_err(|e| e.to_string())?;
+    }
+
+    let status = child.wait().map_err(|e| e.to_string())?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} failed", command[0]))
+    }
+}
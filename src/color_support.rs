@@ -0,0 +1,139 @@
+use std::env;
+
+use crossterm::style::Color;
+
+/// Colors the terminal can show
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorSupport {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+/// Approximate rgb of the 16 named colors as most terminals show them
+const NAMED_COLORS: &[(Color, (u8, u8, u8))] = &[
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Channel values of the 6x6x6 color cube of 256 color terminals
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorSupport {
+    /// Parses `truecolor`, `256` or `16`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "truecolor" | "24bit" => Some(Self::TrueColor),
+            "256" => Some(Self::Ansi256),
+            "16" => Some(Self::Ansi16),
+            _ => None,
+        }
+    }
+
+    /// Guesses from the environment like most terminal programs do, as
+    /// terminals don't report it: `COLORTERM` tells about 24 bit colors and
+    /// `TERM` names like `xterm-256color` about 256 colors
+    pub fn detect() -> Self {
+        let var = |name| env::var(name).unwrap_or_default();
+
+        let colorterm = var("COLORTERM");
+        let term_program = var("TERM_PROGRAM");
+        if colorterm == "truecolor"
+            || colorterm == "24bit"
+            || env::var_os("WT_SESSION").is_some()
+            || matches!(
+                &term_program[..],
+                "iTerm.app" | "WezTerm" | "vscode" | "Hyper"
+            )
+        {
+            return Self::TrueColor;
+        }
+
+        // windows terminals usually don't set it
+        let term = var("TERM");
+        if term.contains("256color") || term.is_empty() {
+            Self::Ansi256
+        } else {
+            Self::Ansi16
+        }
+    }
+
+    /// Closest color to `color` that the terminal can show
+    pub fn fit(self, color: Color) -> Color {
+        match (self, color) {
+            (Self::TrueColor, _) => color,
+            (Self::Ansi256, Color::Rgb { r, g, b }) => {
+                Color::AnsiValue(ansi256_from_rgb(r, g, b))
+            }
+            (Self::Ansi16, Color::Rgb { r, g, b }) => nearest_named(r, g, b),
+            (Self::Ansi16, Color::AnsiValue(value)) => {
+                let (r, g, b) = rgb_from_ansi256(value);
+                nearest_named(r, g, b)
+            }
+            _ => color,
+        }
+    }
+}
+
+fn ansi256_from_rgb(r: u8, g: u8, b: u8) -> u8 {
+    if r == g && g == b {
+        // the greyscale ramp goes from 8 to 238 in steps of 10
+        return match r {
+            0..=3 => 16,
+            244..=255 => 231,
+            _ => 232 + ((r - 3) / 10).min(23),
+        };
+    }
+
+    let level = |v: u8| {
+        CUBE_LEVELS
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, &l)| (l as i16 - v as i16).abs())
+            .map_or(0, |(i, _)| i as u8)
+    };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+fn rgb_from_ansi256(value: u8) -> (u8, u8, u8) {
+    match value {
+        0..=15 => NAMED_COLORS[value as usize].1,
+        16..=231 => {
+            let i = value - 16;
+            (
+                CUBE_LEVELS[(i / 36) as usize],
+                CUBE_LEVELS[(i / 6 % 6) as usize],
+                CUBE_LEVELS[(i % 6) as usize],
+            )
+        }
+        _ => {
+            let grey = 8 + 10 * (value - 232);
+            (grey, grey, grey)
+        }
+    }
+}
+
+fn nearest_named(r: u8, g: u8, b: u8) -> Color {
+    let distance = |(nr, ng, nb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, nr) + d(g, ng) + d(b, nb)
+    };
+    NAMED_COLORS
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
@@ -0,0 +1,452 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    action::{ActionKind, FLAGGABLE_ACTIONS},
+    browser::WebUrlTemplates,
+    color_support::ColorSupport,
+    encoding::EncodingRule,
+    key_bindings::{action_of_keys, keys_of_action},
+    notification::Notification,
+    select::SortKey,
+};
+
+/// Backends the flags of the config can be for
+const FLAG_BACKENDS: &[&str] = &["git", "hg", "jj", "bzr", "brz", "darcs"];
+
+/// Flags added to the main command of an action when using a backend
+pub struct ActionFlags {
+    pub backend: String,
+    pub action: ActionKind,
+    pub flags: Vec<String>,
+}
+
+pub struct Config {
+    pub notes_ref: String,
+    pub discard_snapshot: bool,
+    pub discard_snapshot_max_age: Duration,
+    pub format_command: Option<String>,
+    /// Shell command `re` opens the conflicted files with
+    pub merge_tool: Option<String>,
+    pub sign_commits: bool,
+    /// Lines like `Signed-off-by: Name <email>` offered when committing
+    pub trailers: Vec<String>,
+    /// Branches that should not be committed to directly
+    pub protected_branches: Vec<String>,
+    pub fast_status: bool,
+    /// Shows the backend next to the repository root in the header
+    pub header_backend: bool,
+    pub select_sort: SortKey,
+    pub encodings: Vec<EncodingRule>,
+    /// Directories scanned for repositories to switch to
+    pub workspaces: Vec<String>,
+    /// Overrides the pages opened in the browser, for self hosted forges
+    pub web_urls: WebUrlTemplates,
+    /// Shows the pressed keys on screen, for recording tutorials
+    pub show_keys: bool,
+    /// Draws without the spinner, color changes and bell
+    pub quiet: bool,
+    /// Numbers the lines of diffs and other outputs
+    pub line_numbers: bool,
+    /// Actions whose long lines are truncated instead of wrapped, set by
+    /// their keys
+    pub truncated_views: HashSet<ActionKind>,
+    /// Shows diffs in two columns on wide terminals
+    pub side_by_side: bool,
+    /// Highlights the changed words of modified lines in diffs
+    pub word_diff: bool,
+    /// Enables the kitty keyboard protocol on terminals supporting it
+    pub kitty_keyboard: bool,
+    /// Name of the built in theme, like `light`
+    pub theme: String,
+    /// Colors overriding parts of the theme as `(part, color)`
+    pub colors: Vec<(String, String)>,
+    /// Colors the terminal can show, guessed from the environment if `None`
+    pub color_support: Option<ColorSupport>,
+    /// Language of the ui, like `pt-BR`
+    pub locale: String,
+    /// Actions running for longer than this notify when they finish while
+    /// another action is shown. `None` never notifies
+    pub notify_after: Option<Duration>,
+    pub notification: Notification,
+    /// Status reruns after this long without input while shown. `None`
+    /// never reruns it
+    pub auto_refresh: Option<Duration>,
+    /// Status reruns when files of the repository change while shown
+    pub watch: bool,
+    pub action_flags: Vec<ActionFlags>,
+    /// Invalid lines of the config files, shown at startup
+    pub errors: Vec<String>,
+    default_timeout: Option<Duration>,
+    action_timeouts: HashMap<String, Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            notes_ref: "commits".into(),
+            discard_snapshot: false,
+            discard_snapshot_max_age: Duration::from_secs(24 * 60 * 60),
+            format_command: None,
+            merge_tool: None,
+            sign_commits: false,
+            trailers: Vec::new(),
+            protected_branches: Vec::new(),
+            fast_status: false,
+            header_backend: false,
+            select_sort: SortKey::Status,
+            encodings: Vec::new(),
+            workspaces: Vec::new(),
+            web_urls: WebUrlTemplates::default(),
+            show_keys: false,
+            quiet: false,
+            line_numbers: false,
+            truncated_views: HashSet::new(),
+            side_by_side: false,
+            word_diff: true,
+            kitty_keyboard: true,
+            theme: "dark".into(),
+            colors: Vec::new(),
+            color_support: None,
+            locale: "en".into(),
+            notify_after: None,
+            notification: Notification::Bell,
+            auto_refresh: None,
+            watch: false,
+            action_flags: Vec::new(),
+            errors: Vec::new(),
+            default_timeout: None,
+            action_timeouts: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Config {
+        Self::try_load().unwrap_or_default()
+    }
+
+    fn try_load() -> io::Result<Config> {
+        let mut config = Config::default();
+        for path in Self::paths()? {
+            config.load_file(&path)?;
+        }
+        Ok(config)
+    }
+
+    /// Files the config is read from, in order. Settings of the repository
+    /// take precedence over the user ones
+    pub fn paths() -> io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        if let Some(dir) = user_config_dir() {
+            paths.push(dir.join("config.txt"));
+        }
+        let mut path = env::current_dir()?;
+        path.push(concat!(".", env!("CARGO_PKG_NAME"), "/config.txt"));
+        paths.push(path);
+        Ok(paths)
+    }
+
+    fn load_file(&mut self, path: &Path) -> io::Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(file);
+
+        let mut line = String::new();
+        while next_line(&mut reader, &mut line) {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.find(char::is_whitespace) {
+                Some(i) => (&line[..i], line[i..].trim()),
+                None => (line, ""),
+            };
+
+            self.set(key, value);
+        }
+
+        Ok(())
+    }
+
+    /// Keeps whether the long lines of the view of `action` are truncated
+    /// by adding its `truncate` line to the user config or removing it from
+    /// every config file
+    pub fn save_truncated(
+        action: ActionKind,
+        truncated: bool,
+    ) -> io::Result<()> {
+        let keys = keys_of_action(action).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "view has no keys")
+        })?;
+        let is_truncate_line = |line: &str| {
+            let mut words = line.split_whitespace();
+            words.next() == Some("truncate") && words.next() == Some(keys)
+        };
+
+        if truncated {
+            let dir = user_config_dir().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "no user config dir")
+            })?;
+            fs::create_dir_all(&dir)?;
+            let path = dir.join("config.txt");
+            let mut content = fs::read_to_string(&path).unwrap_or_default();
+            if !content.lines().any(is_truncate_line) {
+                if !content.is_empty() && !content.ends_with('\n') {
+                    content.push('\n');
+                }
+                content.push_str(&format!("truncate {}\n", keys));
+                fs::write(&path, content)?;
+            }
+            return Ok(());
+        }
+
+        for path in Self::paths()? {
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+            if content.lines().any(is_truncate_line) {
+                let mut kept = String::new();
+                for line in content.lines().filter(|l| !is_truncate_line(l)) {
+                    kept.push_str(line);
+                    kept.push('\n');
+                }
+                fs::write(&path, kept)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Timeout for the action named `action_name` if any
+    pub fn timeout(&self, action_name: &str) -> Option<Duration> {
+        self.action_timeouts
+            .get(action_name)
+            .copied()
+            .or(self.default_timeout)
+            .filter(|t| !t.is_zero())
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "notes_ref" => self.notes_ref = value.into(),
+            "discard_snapshot" => {
+                self.discard_snapshot = parse_bool(value, self.discard_snapshot)
+            }
+            "discard_snapshot_max_age_hours" => {
+                if let Ok(hours) = value.parse::<u64>() {
+                    self.discard_snapshot_max_age =
+                        Duration::from_secs(hours * 60 * 60);
+                }
+            }
+            "format_command" => {
+                self.format_command = match value {
+                    "" => None,
+                    _ => Some(value.into()),
+                }
+            }
+            "merge_tool" => {
+                self.merge_tool = match value {
+                    "" => None,
+                    _ => Some(value.into()),
+                }
+            }
+            "sign_commits" => {
+                self.sign_commits = parse_bool(value, self.sign_commits)
+            }
+            "trailer" if !value.is_empty() => self.trailers.push(value.into()),
+            "protected_branches" => {
+                self.protected_branches =
+                    value.split_whitespace().map(String::from).collect()
+            }
+            "fast_status" => {
+                self.fast_status = parse_bool(value, self.fast_status)
+            }
+            "header_backend" => {
+                self.header_backend = parse_bool(value, self.header_backend)
+            }
+            "select_sort" => {
+                if let Some(sort_key) = SortKey::from_name(value) {
+                    self.select_sort = sort_key;
+                }
+            }
+            "encoding" => {
+                let mut parts = value.split_whitespace();
+                if let (Some(pattern), Some(encoding)) =
+                    (parts.next(), parts.next())
+                {
+                    self.encodings.push(EncodingRule {
+                        pattern: pattern.into(),
+                        encoding: encoding.into(),
+                    });
+                }
+            }
+            "timeout" => self.set_timeout(value),
+            "flags" => self.add_action_flags(value),
+            "web_commit_url" => self.web_urls.commit = Some(value.into()),
+            "web_branch_url" => self.web_urls.branch = Some(value.into()),
+            "web_file_url" => self.web_urls.file = Some(value.into()),
+            "show_keys" => self.show_keys = parse_bool(value, self.show_keys),
+            "quiet" => self.quiet = parse_bool(value, self.quiet),
+            "side_by_side" => {
+                self.side_by_side = parse_bool(value, self.side_by_side)
+            }
+            "word_diff" => self.word_diff = parse_bool(value, self.word_diff),
+            "kitty_keyboard" => {
+                self.kitty_keyboard = parse_bool(value, self.kitty_keyboard)
+            }
+            "line_numbers" => {
+                self.line_numbers = parse_bool(value, self.line_numbers)
+            }
+            "truncate" => match action_of_keys(value) {
+                Some(action) => {
+                    self.truncated_views.insert(action);
+                }
+                None => self.errors.push(format!(
+                    "truncate '{}': expected the keys of a view, like DD",
+                    value
+                )),
+            },
+            "theme" if !value.is_empty() => self.theme = value.into(),
+            "color" => {
+                if let Some((part, color)) =
+                    value.split_once(char::is_whitespace)
+                {
+                    self.colors.push((part.into(), color.trim().into()));
+                }
+            }
+            "color_mode" => self.color_support = ColorSupport::from_name(value),
+            "locale" if !value.is_empty() => self.locale = value.into(),
+            "notify_after" => {
+                if let Ok(secs) = value.parse::<u64>() {
+                    self.notify_after = Some(Duration::from_secs(secs))
+                        .filter(|d| !d.is_zero());
+                }
+            }
+            "auto_refresh" => {
+                if let Ok(secs) = value.parse::<u64>() {
+                    self.auto_refresh = Some(Duration::from_secs(secs))
+                        .filter(|d| !d.is_zero());
+                }
+            }
+            "watch" => self.watch = parse_bool(value, self.watch),
+            "notification" => {
+                if let Some(notification) = Notification::from_name(value) {
+                    self.notification = notification;
+                }
+            }
+            "workspace" if !value.is_empty() => {
+                self.workspaces.push(value.into())
+            }
+            _ => (),
+        }
+    }
+
+    /// Parses `<backend> <action name> <flags...>` where the flags must
+    /// start with one
+    fn add_action_flags(&mut self, value: &str) {
+        let (backend, rest) = match value.split_once(char::is_whitespace) {
+            Some((backend, rest)) if FLAG_BACKENDS.contains(&backend) => {
+                (backend, rest.trim_start())
+            }
+            _ => {
+                self.errors.push(format!(
+                    "flags '{}': expected one of the backends {} first",
+                    value,
+                    FLAG_BACKENDS.join(", ")
+                ));
+                return;
+            }
+        };
+        let action = FLAGGABLE_ACTIONS.iter().copied().find(|action| {
+            rest.strip_prefix(action.name())
+                .is_some_and(|flags| flags.starts_with(char::is_whitespace))
+        });
+        let action = match action {
+            Some(action) => action,
+            None => {
+                let names: Vec<_> =
+                    FLAGGABLE_ACTIONS.iter().map(|a| a.name()).collect();
+                self.errors.push(format!(
+                    "flags '{}': expected one of the actions {} after '{}'",
+                    value,
+                    names.join(", "),
+                    backend
+                ));
+                return;
+            }
+        };
+        let flags: Vec<_> = rest[action.name().len()..]
+            .split_whitespace()
+            .map(String::from)
+            .collect();
+        if !flags.first().is_some_and(|f| f.starts_with('-')) {
+            self.errors.push(format!(
+                "flags '{}': expected flags starting with '-' after '{}'",
+                value,
+                action.name()
+            ));
+            return;
+        }
+        self.action_flags.push(ActionFlags {
+            backend: backend.into(),
+            action,
+            flags,
+        });
+    }
+
+    /// Parses `<seconds> [action name]` where a zero timeout disables it
+    fn set_timeout(&mut self, value: &str) {
+        let (secs, action_name) = match value.find(char::is_whitespace) {
+            Some(i) => (&value[..i], value[i..].trim()),
+            None => (value, ""),
+        };
+        let timeout = match secs.parse::<u64>() {
+            Ok(secs) => Duration::from_secs(secs),
+            Err(_) => return,
+        };
+
+        if action_name.is_empty() {
+            self.default_timeout = Some(timeout);
+        } else {
+            self.action_timeouts.insert(action_name.into(), timeout);
+        }
+    }
+}
+
+fn parse_bool(value: &str, default: bool) -> bool {
+    match value {
+        "true" | "yes" | "on" => true,
+        "false" | "no" | "off" => false,
+        _ => default,
+    }
+}
+
+fn next_line<R: BufRead>(reader: &mut R, line: &mut String) -> bool {
+    line.clear();
+    reader.read_line(line).unwrap_or(0) > 0
+}
+
+/// Directory with the settings and custom actions shared by every repository,
+/// like `~/.config/verco`
+pub fn user_config_dir() -> Option<PathBuf> {
+    let dir = if cfg!(target_os = "windows") {
+        PathBuf::from(env::var_os("APPDATA")?)
+    } else {
+        match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        }
+    };
+    Some(dir.join(env!("CARGO_PKG_NAME")))
+}
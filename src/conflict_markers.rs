@@ -0,0 +1,65 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    locale::tr,
+    theme::{ansi_color, theme},
+    tui_util::strip_ansi,
+};
+
+const RESET_COLOR: &str = "\x1b[0m";
+
+/// Whether the file at `path` still has the lines git, hg and jj write
+/// around each side of a conflict
+pub fn has_markers(path: &Path) -> bool {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(_) => return false,
+    };
+    let mut has_start = false;
+    for line in content.split(|&b| b == b'\n') {
+        if line.starts_with(b"<<<<<<<") {
+            has_start = true;
+        } else if has_start && line.starts_with(b">>>>>>>") {
+            return true;
+        }
+    }
+    false
+}
+
+/// Marks the files in the conflicts listed by the backend whose conflict
+/// markers were already removed, as they only need to be marked resolved
+pub fn mark_edited(root: &Path, conflicts: &str) -> String {
+    let mut marked = String::with_capacity(conflicts.len());
+    for line in conflicts.lines() {
+        marked.push_str(line);
+        let is_edited = conflict_path(root, &strip_ansi(line))
+            .is_some_and(|path| !has_markers(&path));
+        if is_edited {
+            marked.push(' ');
+            marked.push_str(&ansi_color(theme().note));
+            marked.push_str(tr("[no markers left]"));
+            marked.push_str(RESET_COLOR);
+        }
+        marked.push('\n');
+    }
+    marked
+}
+
+/// Path of the unresolved file in a line like git's `path`, hg's `U path`
+/// or jj's `path    2-sided conflict`
+fn conflict_path(root: &Path, line: &str) -> Option<PathBuf> {
+    let line = line.trim();
+    let candidates = [
+        Some(line),
+        line.strip_prefix("U "),
+        line.split_whitespace().next(),
+    ];
+    candidates
+        .iter()
+        .flatten()
+        .map(|path| root.join(path))
+        .find(|path| path.is_file())
+}
@@ -1,21 +1,74 @@
 use std::{
     env,
-    fs::File,
+    fs::{self, File},
     io::{self, BufRead, BufReader},
-    process::Command,
+    iter,
+    path::Path,
+    process::{Command, Stdio},
+    task::Poll,
 };
 
-use crate::action::ActionResult;
+use crate::{
+    action::{ready, ActionResult, ActionTask, CommandTask},
+    async_process::{command_line, ChildOptions, Executor},
+    backend_error::BackendError,
+    config::user_config_dir,
+    input,
+    locale::{tr, trf},
+};
+
+const FILES_PLACEHOLDER: &str = "{}";
+/// Placeholders filled from the entry under the cursor
+pub const ENTRY_PLACEHOLDERS: &[&str] = &["hash", "file", "branch"];
+/// Prefix of the placeholders asking for text, like `{prompt:message}`
+const PROMPT_PREFIX: &str = "prompt:";
 
+/// How a custom action runs
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Run {
+    /// Output shows while the command runs, like the other actions
+    Attached,
+    /// Starts the command and forgets about it, like to open a gui
+    Detached,
+}
+
+#[derive(Clone)]
 pub struct CustomAction {
+    /// Key chord as parsed by `input::parse_key_chord`
     pub shortcut: String,
     pub command: String,
     pub args: Vec<String>,
+    /// Whether `command` is a whole command line run through the shell, as
+    /// in `custom_actions.toml`
+    pub shell: bool,
+    pub run: Run,
 }
 
 impl CustomAction {
-    pub fn load_custom_actions() -> Vec<CustomAction> {
-        Self::try_load_custom_actions().unwrap_or(Vec::new())
+    /// Loads the actions of the repository followed by the ones of the user
+    /// whose shortcut isn't taken by them. Errors of the toml files go to
+    /// `errors`
+    pub fn load_custom_actions(errors: &mut Vec<String>) -> Vec<CustomAction> {
+        let mut actions = Self::try_load_custom_actions().unwrap_or_default();
+        if let Ok(mut path) = env::current_dir() {
+            path.push(concat!(
+                ".",
+                env!("CARGO_PKG_NAME"),
+                "/custom_actions.toml"
+            ));
+            let toml_actions = Self::try_load_toml_actions(&path, errors);
+            actions.extend(toml_actions.unwrap_or_default());
+        }
+        if let Some(dir) = user_config_dir() {
+            let path = dir.join("custom_actions.toml");
+            let toml_actions = Self::try_load_toml_actions(&path, errors);
+            for action in toml_actions.unwrap_or_default() {
+                if actions.iter().all(|a| a.shortcut != action.shortcut) {
+                    actions.push(action);
+                }
+            }
+        }
+        actions
     }
 
     fn try_load_custom_actions() -> io::Result<Vec<CustomAction>> {
@@ -32,7 +85,7 @@ impl CustomAction {
         let mut line = String::new();
         while next_line(&mut reader, &mut line) {
             let line = line.trim();
-            if line.len() == 0 {
+            if line.is_empty() {
                 continue;
             }
 
@@ -49,9 +102,11 @@ impl CustomAction {
             }
 
             let command = CustomAction {
-                shortcut: shortcut.unwrap().into(),
+                shortcut: input::parse_key_chord(shortcut.unwrap()),
                 command: command.unwrap().into(),
                 args: it.map(|s| s.into()).collect(),
+                shell: false,
+                run: Run::Attached,
             };
             actions.push(command);
         }
@@ -59,38 +114,307 @@ impl CustomAction {
         Ok(actions)
     }
 
-    pub fn execute(&self, current_dir: &str) -> ActionResult {
-        let mut command = Command::new(&self.command);
-        command.current_dir(current_dir);
-        for a in &self.args {
-            command.arg(a);
+    fn try_load_toml_actions(
+        path: &Path,
+        errors: &mut Vec<String>,
+    ) -> io::Result<Vec<CustomAction>> {
+        if !path.exists() {
+            return Ok(Vec::new());
         }
+        let text = fs::read_to_string(path)?;
+        Ok(parse_toml_actions(path, &text, errors))
+    }
 
-        match command.output() {
-            Ok(output) => {
-                if output.status.success() {
-                    ActionResult::from_ok(
-                        String::from_utf8_lossy(&output.stdout[..])
-                            .into_owned(),
-                    )
-                } else {
-                    let mut out = String::new();
-                    out.push_str(
-                        &String::from_utf8_lossy(&output.stdout[..])
-                            .into_owned()[..],
-                    );
-                    out.push('\n');
-                    out.push('\n');
-                    out.push_str(
-                        &String::from_utf8_lossy(&output.stderr[..])
-                            .into_owned()[..],
-                    );
-                    ActionResult::from_err(out)
+    /// Names inside the braces of the placeholders in the command, in order
+    /// and without repeating
+    pub fn placeholders(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        for text in iter::once(&self.command).chain(&self.args) {
+            for name in placeholder_names(text) {
+                if !names.contains(&name) {
+                    names.push(name);
                 }
             }
-            Err(error) => ActionResult::from_err(error.to_string()),
+        }
+        names
+    }
+
+    /// Label of a placeholder asking for text, like `message` for
+    /// `{prompt:message}`
+    pub fn prompt_label(placeholder: &str) -> Option<&str> {
+        placeholder.strip_prefix(PROMPT_PREFIX)
+    }
+
+    /// Runs the action with its placeholders replaced by `values`, pairs of
+    /// the name inside the braces and its value
+    pub fn task(
+        &self,
+        current_dir: &str,
+        values: &[(&str, String)],
+    ) -> Box<dyn ActionTask> {
+        let mut command = if self.shell {
+            let mut command = shell_command();
+            command.arg(fill_placeholders(&self.command, values, quote));
+            command
+        } else {
+            let mut command = Command::new(&self.command);
+            for a in &self.args {
+                command.arg(fill_placeholders(a, values, str::to_owned));
+            }
+            command
+        };
+        command.current_dir(current_dir);
+
+        match self.run {
+            Run::Attached => Box::new(StreamedTask::new(command)),
+            Run::Detached => {
+                let command_line = command_line(&command);
+                let child = command
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn();
+                let result = match child {
+                    Ok(_) => ActionResult::from_ok(trf(
+                        "started {} detached",
+                        &[&command_line],
+                    )),
+                    Err(error) => {
+                        ActionResult::from_err(BackendError::from_spawn(
+                            command_line,
+                            error.to_string(),
+                        ))
+                    }
+                };
+                ready(result)
+            }
+        }
+    }
+}
+
+/// Runs a command showing its stdout and stderr together, in the order they
+/// were printed, followed by how it exited
+struct StreamedTask(CommandTask);
+
+impl StreamedTask {
+    fn new(command: Command) -> Self {
+        let options = ChildOptions {
+            stream_stderr: true,
+            ..Default::default()
+        };
+        Self(CommandTask::Waiting(command, options))
+    }
+}
+
+impl ActionTask for StreamedTask {
+    fn poll(&mut self, executor: &mut Executor) -> Poll<ActionResult> {
+        let mut result = match self.0.poll(executor) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        // there's no output when the command could not even start
+        let output = match self.0.partial_output() {
+            Some(output) if !result.canceled => output,
+            _ => return Poll::Ready(result),
+        };
+
+        let exit_code = match &result.error {
+            Some(error) => error.exit_code,
+            None => Some(0),
+        };
+        result.output = output;
+        if !result.output.is_empty() && !result.output.ends_with('\n') {
+            result.output.push('\n');
+        }
+        result.output.push_str(&match exit_code {
+            Some(code) => trf("exited with code {}", &[&code]),
+            None => String::from(tr("killed by a signal")),
+        });
+        Poll::Ready(result)
+    }
+
+    fn cancel(&mut self) {
+        self.0.cancel();
+    }
+
+    fn command_line(&self) -> Option<String> {
+        self.0.command_line()
+    }
+
+    fn partial_output(&self) -> Option<String> {
+        self.0.partial_output()
+    }
+
+    fn partial_lines(&self, from: usize) -> Option<(String, usize)> {
+        self.0.partial_lines(from)
+    }
+}
+
+/// Parses the `[[action]]` tables of `custom_actions.toml`. Invalid actions
+/// are skipped adding why to `errors`
+fn parse_toml_actions(
+    path: &Path,
+    text: &str,
+    errors: &mut Vec<String>,
+) -> Vec<CustomAction> {
+    let mut error = |message: String| {
+        errors.push(format!("{}: {}", path.display(), message));
+    };
+    let value = match text.parse::<toml::Value>() {
+        Ok(value) => value,
+        Err(e) => {
+            error(e.to_string());
+            return Vec::new();
+        }
+    };
+    let tables = match value.get("action") {
+        Some(toml::Value::Array(tables)) => &tables[..],
+        Some(_) => {
+            error("expected the actions as [[action]] tables".into());
+            return Vec::new();
+        }
+        None => &[],
+    };
+
+    let mut actions = Vec::new();
+    for (i, table) in tables.iter().enumerate() {
+        match toml_action(table) {
+            Ok(action) => actions.push(action),
+            Err(e) => error(format!("action {}: {}", i + 1, e)),
+        }
+    }
+    actions
+}
+
+fn toml_action(table: &toml::Value) -> Result<CustomAction, String> {
+    let get = |name: &str| match table.get(name) {
+        Some(toml::Value::String(value)) => Ok(Some(&value[..])),
+        Some(_) => Err(format!("expected '{}' to be a string", name)),
+        None => Ok(None),
+    };
+    let get_required =
+        |name: &str| get(name)?.ok_or_else(|| format!("missing '{}'", name));
+    let run = match get("run")? {
+        None | Some("attached") => Run::Attached,
+        Some("detached") => Run::Detached,
+        Some(run) => {
+            return Err(format!("run '{}': expected attached or detached", run))
+        }
+    };
+    Ok(CustomAction {
+        shortcut: input::parse_key_chord(get_required("keys")?),
+        command: get_required("command")?.into(),
+        args: Vec::new(),
+        shell: true,
+        run,
+    })
+}
+
+/// Names of the placeholders in `text` that get filled, in order
+fn placeholder_names(text: &str) -> impl Iterator<Item = &str> {
+    text.split('{').skip(1).filter_map(|part| {
+        let name = &part[..part.find('}')?];
+        let is_placeholder = ENTRY_PLACEHOLDERS.contains(&name)
+            || name.starts_with(PROMPT_PREFIX);
+        Some(name).filter(|_| is_placeholder)
+    })
+}
+
+/// Replaces the placeholders in a single pass so braces inside the values
+/// are never taken for placeholders
+fn fill_placeholders<F>(
+    text: &str,
+    values: &[(&str, String)],
+    escape: F,
+) -> String
+where
+    F: Fn(&str) -> String,
+{
+    let mut filled = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            let (_, value) =
+                values.iter().find(|(n, _)| *n == &rest[1..end])?;
+            Some((value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                filled.push_str(&escape(value));
+                rest = &rest[end + 1..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
         }
     }
+    filled.push_str(rest);
+    filled
+}
+
+/// Runs `command_line` through the shell with `files` quoted and either
+/// substituted for every `{}` or appended at the end
+pub fn run_on_files(
+    current_dir: &str,
+    command_line: &str,
+    files: &[&str],
+) -> Box<dyn ActionTask> {
+    let command = files_command(current_dir, command_line, files);
+    Box::new(CommandTask::new(command))
+}
+
+/// Shell command `run_on_files` runs, also for the programs taking over the
+/// terminal like a merge tool
+pub fn files_command(
+    current_dir: &str,
+    command_line: &str,
+    files: &[&str],
+) -> Command {
+    let mut quoted = String::new();
+    for file in files {
+        if !quoted.is_empty() {
+            quoted.push(' ');
+        }
+        quoted.push_str(&quote(file));
+    }
+
+    let script = if command_line.contains(FILES_PLACEHOLDER) {
+        command_line.replace(FILES_PLACEHOLDER, &quoted)
+    } else {
+        format!("{} {}", command_line, quoted)
+    };
+
+    let mut command = shell_command();
+    command.arg(script);
+    command.current_dir(current_dir);
+    command
+}
+
+#[cfg(target_os = "windows")]
+fn shell_command() -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C");
+    command
+}
+
+#[cfg(not(target_os = "windows"))]
+fn shell_command() -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c");
+    command
+}
+
+#[cfg(target_os = "windows")]
+fn quote(file: &str) -> String {
+    format!("\"{}\"", file.replace('"', "\"\""))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn quote(file: &str) -> String {
+    format!("'{}'", file.replace('\'', "'\\''"))
 }
 
 fn next_line<R: BufRead>(reader: &mut R, line: &mut String) -> bool {
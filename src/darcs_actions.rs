@@ -0,0 +1,747 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    action::{
+        deferred, map_result, parallel, ready, serial, task_vec, ActionKind,
+        ActionResult, ActionTask,
+    },
+    backend_error::BackendResult,
+    select::{Entry, State},
+    version_control_actions::{
+        append_to_root_file, discard_snapshot_age, discard_snapshot_name,
+        handle_command, task, task_allowing_exit_one, CommitOptions,
+        StatusOptions, VersionControlActions,
+    },
+};
+
+/// Length of the hash prefix used to refer to patches
+const HASH_LEN: usize = 12;
+/// Regexes of the files darcs ignores
+const BORING_FILE: &str = "_darcs/prefs/boring";
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct",
+    "Nov", "Dec",
+];
+
+const SIGN_UNSUPPORTED: &str = "verco does not support signing darcs patches";
+const NOTES_UNSUPPORTED: &str = "darcs does not support notes";
+const BRANCHES_UNSUPPORTED: &str =
+    "darcs has no branches, each repository is one";
+const BASE_UNSUPPORTED: &str =
+    "darcs only compares the working tree with the last recorded patch";
+const UPDATE_UNSUPPORTED: &str =
+    "darcs can't move the working tree to another patch, use unpull instead";
+const FORCE_PUSH_UNSUPPORTED: &str = "darcs can't force push";
+const TAKE_LOCAL_UNSUPPORTED: &str =
+    "darcs doesn't tell which side of a conflict is local, edit the \
+     conflict markers or unpull the conflicting patches instead";
+
+fn str_to_state(s: &str) -> State {
+    match s {
+        "M" => State::Modified,
+        "A" => State::Added,
+        "R" => State::Deleted,
+        "a" => State::Untracked,
+        _ => State::Modified,
+    }
+}
+
+/// Parses summary lines like `M ./path -1 +2` or `a ./path` from
+/// `whatsnew --summary`. Moves like ` ./old -> ./new` map to the new path
+fn parse_summary(output: &str) -> Vec<Entry> {
+    output
+        .lines()
+        .map(|l| l.trim())
+        .filter_map(|l| {
+            let (state, rest) = l.split_once(' ')?;
+            let path = match rest.split_once(" -> ") {
+                Some((_, new_path)) => new_path,
+                None => rest.split(' ').next()?,
+            };
+            let path = path.strip_prefix("./")?;
+            Some(Entry {
+                filename: path.into(),
+                selected: false,
+                state: str_to_state(state),
+                renamed_from: None,
+            })
+        })
+        .collect()
+}
+
+/// Converts entries of `darcs log` into the separated parts the log view
+/// expects. Each entry spans a `patch`, `Author` and `Date` line followed by
+/// the indented patch name
+fn format_log(output: &str) -> String {
+    let mut lines = Vec::new();
+    let mut hash = "";
+    let mut author = "";
+    let mut date = String::new();
+    for line in output.lines() {
+        if let Some(h) = line.strip_prefix("patch ") {
+            hash = &h[..h.len().min(HASH_LEN)];
+        } else if let Some(a) = line.strip_prefix("Author: ") {
+            author = a.split(" <").next().unwrap_or(a);
+        } else if let Some(d) = line.strip_prefix("Date: ") {
+            date = format_date(d.trim());
+        } else if let Some(name) = line.trim_start().strip_prefix("* ") {
+            lines.push(format!(
+                "\x1e{}\x1e\x1e{}\x1e{}\x1e\x1e{}",
+                hash, date, author, name
+            ));
+        } else if let Some(tag) = line.trim_start().strip_prefix("tagged ") {
+            lines.push(format!(
+                "\x1e{}\x1e\x1e{}\x1e{}\x1e{}\x1e",
+                hash, date, author, tag
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Patches listed by `pull --dry-run` or `push --dry-run` as entries named
+/// by their hash prefix followed by their name
+fn parse_patches(output: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut hash = "";
+    for line in output.lines() {
+        if let Some(h) = line.strip_prefix("patch ") {
+            hash = &h[..h.len().min(HASH_LEN)];
+        } else if let Some(name) = line.trim_start().strip_prefix("* ") {
+            entries.push(Entry {
+                filename: format!("{} {}", hash, name),
+                selected: false,
+                state: State::Added,
+                renamed_from: None,
+            });
+        }
+    }
+    entries
+}
+
+/// `--match` pattern picking the selected patches of `parse_patches`
+fn patches_match(entries: &[Entry]) -> String {
+    let hashes: Vec<_> = entries
+        .iter()
+        .filter(|e| e.selected)
+        .filter_map(|e| e.filename.split(' ').next())
+        .map(|hash| format!("hash {}", hash))
+        .collect();
+    hashes.join(" || ")
+}
+
+/// Removes the untracked file at `path` relative to `root`
+fn remove_untracked(root: &str, path: &str) -> ActionResult {
+    match fs::remove_file(Path::new(root).join(path)) {
+        Ok(()) => ActionResult::from_ok(String::new()),
+        Err(error) => ActionResult::from_err(format!(
+            "could not remove {}: {}",
+            path, error
+        )),
+    }
+}
+
+/// Copies the files under `from` to the same paths under `to`, skipping the
+/// ones already there
+fn copy_missing_files(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            fs::create_dir_all(&target)?;
+            copy_missing_files(&entry.path(), &target)?;
+        } else if !target.exists() {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Shortens dates like `Tue Jan  2 12:00:00 UTC 2024` to `2024-01-02`
+fn format_date(date: &str) -> String {
+    let parts: Vec<_> = date.split_whitespace().collect();
+    let month = parts
+        .get(1)
+        .and_then(|m| MONTHS.iter().position(|n| n == m));
+    match (month, parts.get(2), parts.last()) {
+        (Some(month), Some(day), Some(year)) => {
+            format!("{}-{:02}-{:0>2}", year, month + 1, day)
+        }
+        _ => date.into(),
+    }
+}
+
+/// Converts a glob relative to the root into a boring file regex which
+/// darcs matches against paths starting with `./`
+fn glob_to_regex(glob: &str) -> String {
+    let glob = glob.trim_end_matches('/');
+    let mut regex =
+        String::from(if glob.contains('/') { "^\\./" } else { "(^|/)" });
+    for c in glob.trim_start_matches('/').chars() {
+        match c {
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '.' | '+' | '(' | ')' | '|' | '^' | '$' | '[' | ']' | '{' | '}'
+            | '\\' => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            c => regex.push(c),
+        }
+    }
+    regex.push_str("(/|$)");
+    regex
+}
+
+pub struct DarcsActions {
+    pub current_dir: String,
+}
+
+impl DarcsActions {
+    /// `whatsnew` exits with 1 when there are no changes
+    fn whatsnew(&self, args: &[&str]) -> BackendResult<String> {
+        let output = self
+            .command()
+            .arg("whatsnew")
+            .args(args)
+            .output()
+            .map_err(|e| e.to_string())?;
+        match output.status.code() {
+            Some(0) => String::from_utf8(output.stdout)
+                .map_err(|e| e.to_string().into()),
+            Some(1) => Ok(String::new()),
+            _ => Err(String::from_utf8_lossy(&output.stderr).as_ref().into()),
+        }
+    }
+
+    /// Directories under `_darcs` holding copies of the untracked files a
+    /// discard removes, as `unrevert` only restores tracked ones
+    fn discard_snapshots(&self) -> Vec<(Duration, PathBuf)> {
+        let dir = Path::new(self.get_root()).join("_darcs");
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let age = discard_snapshot_age(entry.file_name().to_str()?)?;
+                Some((age, entry.path()))
+            })
+            .collect()
+    }
+}
+
+impl VersionControlActions for DarcsActions {
+    fn executable_name(&self) -> &'static str {
+        "darcs"
+    }
+
+    fn current_dir(&self) -> &str {
+        &self.current_dir[..]
+    }
+
+    fn flags_subcommand(&self, kind: ActionKind) -> Option<&'static str> {
+        let subcommand = match kind {
+            ActionKind::Fetch => "fetch",
+            ActionKind::Pull | ActionKind::Merge => "pull",
+            ActionKind::Push => "push",
+            ActionKind::CommitAll | ActionKind::CommitSelected => "record",
+            ActionKind::CurrentDiffAll
+            | ActionKind::CurrentDiffSelected
+            | ActionKind::RevisionDiffAll
+            | ActionKind::RevisionDiffSelected => "diff",
+            _ => return None,
+        };
+        Some(subcommand)
+    }
+
+    fn set_root(&mut self) -> BackendResult<()> {
+        let output = handle_command(self.command().args(["show", "repo"]))?;
+        let dir = output
+            .lines()
+            .filter_map(|l| l.trim().strip_prefix("Root:"))
+            .map(|d| d.trim())
+            .next()
+            .ok_or_else(|| String::from("could not find repository root"))?;
+        self.current_dir = dir.to_owned();
+
+        Ok(())
+    }
+
+    fn get_root(&self) -> &str {
+        &self.current_dir[..]
+    }
+
+    fn get_current_changed_files(&self) -> BackendResult<Vec<Entry>> {
+        let output = self.whatsnew(&["--summary", "--look-for-adds"])?;
+        Ok(parse_summary(&output))
+    }
+
+    fn get_revision_changed_files(
+        &self,
+        target: &str,
+    ) -> BackendResult<Vec<Entry>> {
+        let output = handle_command(
+            self.command()
+                .args(["log", "--summary", "--hash"])
+                .arg(target),
+        )?;
+        Ok(parse_summary(&output))
+    }
+
+    /// Repositories this one pushed to or pulled from
+    fn get_remotes(&self) -> BackendResult<Vec<String>> {
+        let path = Path::new(self.get_root()).join("_darcs/prefs/repos");
+        let repos = fs::read_to_string(path).unwrap_or_default();
+        Ok(repos.lines().map(String::from).collect())
+    }
+
+    fn get_branch_names(&self) -> BackendResult<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    fn get_current_branch(&self) -> BackendResult<String> {
+        Err(BRANCHES_UNSUPPORTED.into())
+    }
+
+    fn has_upstream(&self) -> bool {
+        true
+    }
+
+    fn version(&self) -> BackendResult<String> {
+        handle_command(self.command().arg("--version"))
+    }
+
+    fn status(
+        &self,
+        options: StatusOptions,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task_allowing_exit_one(self, |command| {
+            command.args(["whatsnew", "--summary"]);
+            if !options.skip_untracked {
+                command.arg("--look-for-adds");
+            }
+            if options.show_ignored {
+                command.arg("--boring");
+            }
+            command.args(scope);
+        })
+    }
+
+    fn current_export(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["log", "--last=1", "--verbose"]);
+        })
+    }
+
+    /// Every darcs repository is a single branch
+    fn log(
+        &self,
+        count: usize,
+        scope: Option<&str>,
+        _branch: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        let log = task(self, |command| {
+            command.arg("log").arg(format!("--max-count={}", count));
+            command.args(scope);
+        });
+        map_result(log, |mut result| {
+            if result.success {
+                result.output = format_log(&result.output);
+            }
+            result
+        })
+    }
+
+    fn current_diff_all(&self, scope: Option<&str>) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("diff").args(scope);
+        })
+    }
+
+    fn current_diff_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("diff");
+            for e in entries.iter().filter(|e| e.selected) {
+                command.arg(&e.filename);
+            }
+        })
+    }
+
+    fn revision_changes(
+        &self,
+        target: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["log", "--summary", "--hash"]).arg(target);
+            command.args(scope);
+        })
+    }
+
+    fn revision_diff_all(
+        &self,
+        target: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["diff", "--hash"]).arg(target);
+            command.args(scope);
+        })
+    }
+
+    fn revision_diff_selected(
+        &self,
+        target: &str,
+        entries: &[Entry],
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["diff", "--hash"]).arg(target);
+            for e in entries.iter().filter(|e| e.selected) {
+                command.arg(&e.filename);
+            }
+        })
+    }
+
+    fn base_changes(
+        &self,
+        _base: &str,
+        _scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(BASE_UNSUPPORTED))
+    }
+
+    fn base_diff_all(
+        &self,
+        _base: &str,
+        _scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(BASE_UNSUPPORTED))
+    }
+
+    fn supports_notes(&self) -> bool {
+        false
+    }
+
+    fn get_note(
+        &self,
+        _target: &str,
+        _notes_ref: &str,
+    ) -> BackendResult<String> {
+        Err(NOTES_UNSUPPORTED.into())
+    }
+
+    fn revision_signature(&self, _target: &str) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_ok(String::new()))
+    }
+
+    fn revision_notes(
+        &self,
+        _target: &str,
+        _notes_ref: &str,
+    ) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_ok(String::new()))
+    }
+
+    fn set_note(
+        &self,
+        _target: &str,
+        _notes_ref: &str,
+        _message: &str,
+    ) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(NOTES_UNSUPPORTED))
+    }
+
+    fn commit_all(
+        &self,
+        message: &str,
+        options: CommitOptions,
+    ) -> Box<dyn ActionTask> {
+        if options.sign {
+            return ready(ActionResult::from_err(SIGN_UNSUPPORTED));
+        }
+        task(self, |command| {
+            command
+                .args(["record", "--all", "--look-for-adds", "-m"])
+                .arg(message);
+        })
+    }
+
+    /// Records every change of the selected files as a single patch
+    fn commit_selected(
+        &self,
+        message: &str,
+        entries: &[Entry],
+        options: CommitOptions,
+    ) -> Box<dyn ActionTask> {
+        if options.sign {
+            return ready(ActionResult::from_err(SIGN_UNSUPPORTED));
+        }
+        let mut tasks = task_vec();
+        let untracked: Vec<_> = entries
+            .iter()
+            .filter(|e| e.selected && matches!(e.state, State::Untracked))
+            .collect();
+        if !untracked.is_empty() {
+            tasks.push(task(self, |command| {
+                command.arg("add");
+                for e in untracked {
+                    command.arg(&e.filename);
+                }
+            }));
+        }
+        tasks.push(task(self, |command| {
+            command.args(["record", "--all", "-m"]).arg(message);
+            for e in entries.iter().filter(|e| e.selected) {
+                command.arg(&e.filename);
+            }
+        }));
+        serial(tasks)
+    }
+
+    fn revert_all(&self) -> Box<dyn ActionTask> {
+        let darcs = DarcsActions {
+            current_dir: self.current_dir.clone(),
+        };
+        // untracked files are listed once the task runs, not when built
+        let remove_untracked_files = deferred(move || {
+            let entries = match darcs.get_current_changed_files() {
+                Ok(entries) => entries,
+                Err(error) => return ActionResult::from_err(error),
+            };
+            let mut result = ActionResult::from_ok(String::new());
+            for e in entries {
+                if matches!(e.state, State::Untracked) {
+                    let removed =
+                        remove_untracked(darcs.get_root(), &e.filename);
+                    if !removed.success {
+                        result = removed;
+                    }
+                }
+            }
+            result
+        });
+
+        let mut tasks = task_vec();
+        tasks.push(task_allowing_exit_one(self, |command| {
+            command.args(["revert", "--all"]);
+        }));
+        tasks.push(remove_untracked_files);
+        serial(tasks)
+    }
+
+    fn revert_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask> {
+        let mut tasks = task_vec();
+        let mut files_to_revert = Vec::new();
+        for e in entries.iter().filter(|e| e.selected) {
+            match e.state {
+                State::Untracked => {
+                    let root = self.get_root().to_owned();
+                    let path = e.filename.clone();
+                    tasks
+                        .push(deferred(move || remove_untracked(&root, &path)));
+                }
+                _ => files_to_revert.push(&e.filename),
+            }
+        }
+        if !files_to_revert.is_empty() {
+            tasks.push(task(self, |command| {
+                command.args(["revert", "--all"]);
+                for file in files_to_revert {
+                    command.arg(file);
+                }
+            }));
+        }
+        parallel(tasks)
+    }
+
+    /// darcs keeps the last reverted changes of tracked files for
+    /// `unrevert`, so only untracked files are copied
+    fn create_discard_snapshot(&self) -> BackendResult<()> {
+        let untracked: Vec<_> = self
+            .get_current_changed_files()?
+            .into_iter()
+            .filter(|e| matches!(e.state, State::Untracked))
+            .collect();
+        if untracked.is_empty() {
+            return Ok(());
+        }
+
+        let root = Path::new(self.get_root());
+        let snapshot = root.join("_darcs").join(discard_snapshot_name());
+        for e in untracked {
+            let target = snapshot.join(&e.filename);
+            let copied = target
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::copy(root.join(&e.filename), &target));
+            if let Err(error) = copied {
+                return Err(format!(
+                    "could not snapshot {}: {}",
+                    e.filename, error
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    fn prune_discard_snapshots(&self, max_age: Duration) -> BackendResult<()> {
+        for (age, path) in self.discard_snapshots() {
+            if age > max_age {
+                fs::remove_dir_all(&path).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn restore_discard_snapshot(&self) -> Box<dyn ActionTask> {
+        let snapshot = self
+            .discard_snapshots()
+            .into_iter()
+            .min_by_key(|(age, _)| *age)
+            .map(|(_, path)| path);
+
+        let mut tasks = task_vec();
+        tasks.push(task(self, |command| {
+            command.args(["unrevert", "--all"]);
+        }));
+        if let Some(snapshot) = snapshot {
+            let root = PathBuf::from(self.get_root());
+            tasks.push(deferred(move || {
+                match copy_missing_files(&snapshot, &root) {
+                    Ok(()) => ActionResult::from_ok(String::new()),
+                    Err(error) => ActionResult::from_err(format!(
+                        "could not restore untracked files: {}",
+                        error
+                    )),
+                }
+            }));
+        }
+        serial(tasks)
+    }
+
+    fn update(&self, _target: &str) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(UPDATE_UNSUPPORTED))
+    }
+
+    /// Merging another repository means pulling all its patches
+    fn merge(&self, target: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["pull", "--all"]).arg(target);
+        })
+    }
+
+    fn conflicts(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["mark-conflicts", "--dry-run"]);
+        })
+    }
+
+    fn take_other(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["mark-conflicts", "--all"]);
+        })
+    }
+
+    /// Conflict markers list the sides sorted rather than local first, so
+    /// there's no telling which one to keep
+    fn take_local(&self) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(TAKE_LOCAL_UNSUPPORTED))
+    }
+
+    fn fetch(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["fetch", "--all"]);
+        })
+    }
+
+    fn pull(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["pull", "--all"]);
+        })
+    }
+
+    fn push(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["push", "--all"]);
+        })
+    }
+
+    fn get_incoming_patches(&self) -> BackendResult<Option<Vec<Entry>>> {
+        let output =
+            handle_command(self.command().args(["pull", "--dry-run"]))?;
+        Ok(Some(parse_patches(&output)))
+    }
+
+    fn get_outgoing_patches(&self) -> BackendResult<Option<Vec<Entry>>> {
+        let output =
+            handle_command(self.command().args(["push", "--dry-run"]))?;
+        Ok(Some(parse_patches(&output)))
+    }
+
+    /// Pulls the selected patches and the ones they depend on
+    fn pull_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command
+                .args(["pull", "--all", "--match"])
+                .arg(patches_match(entries));
+        })
+    }
+
+    /// Pushes the selected patches and the ones they depend on
+    fn push_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command
+                .args(["push", "--all", "--match"])
+                .arg(patches_match(entries));
+        })
+    }
+
+    fn push_to(
+        &self,
+        remote: &str,
+        _branch: &str,
+        set_upstream: bool,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["push", "--all"]);
+            if set_upstream {
+                command.arg("--set-default");
+            }
+            command.arg(remote);
+        })
+    }
+
+    fn force_push(&self) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(FORCE_PUSH_UNSUPPORTED))
+    }
+
+    fn create_tag(&self, name: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("tag").arg(name);
+        })
+    }
+
+    fn list_branches(&self) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(BRANCHES_UNSUPPORTED))
+    }
+
+    fn create_branch(&self, _name: &str) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(BRANCHES_UNSUPPORTED))
+    }
+
+    fn close_branch(&self, _name: &str) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(BRANCHES_UNSUPPORTED))
+    }
+
+    fn ignore(&self, pattern: &str) -> Box<dyn ActionTask> {
+        append_to_root_file(self, BORING_FILE, &glob_to_regex(pattern))
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+use crate::{async_process::Decoder, tui_util::diff_header_path};
+
+/// Windows-1252 characters for the bytes 0x80 to 0x9f. Everything else
+/// matches Latin-1
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ',
+    '\u{8d}', 'Ž', '\u{8f}', '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜',
+    '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+/// Files matching `pattern` are displayed as if encoded with `encoding`
+#[derive(Clone)]
+pub struct EncodingRule {
+    pub pattern: String,
+    pub encoding: String,
+}
+
+/// Decodes diff output converting the sections of files matched by `rules`
+/// to utf8. Files themselves are never touched
+pub fn diff_decoder(rules: &[EncodingRule]) -> Option<Decoder> {
+    if rules.is_empty() {
+        return None;
+    }
+
+    let rules = rules.to_vec();
+    Some(Arc::new(move |bytes: &[u8]| decode_diff(&rules, bytes)))
+}
+
+/// Whether the diff of the file at `path` is shown converted from another
+/// encoding, so its text doesn't match the file
+pub fn is_decoded(rules: &[EncodingRule], path: &str) -> bool {
+    rules
+        .iter()
+        .any(|r| glob_matches(r.pattern.as_bytes(), path.as_bytes()))
+}
+
+fn decode_diff(rules: &[EncodingRule], bytes: &[u8]) -> String {
+    let mut output = String::with_capacity(bytes.len());
+    let mut section_start = 0;
+    let mut encoding = None;
+
+    for (line_start, line) in lines_with_offsets(bytes) {
+        let path = match diff_header_path(&String::from_utf8_lossy(line)) {
+            Some(path) => path,
+            None => continue,
+        };
+
+        decode_section(
+            &mut output,
+            &bytes[section_start..line_start],
+            encoding,
+        );
+        section_start = line_start;
+        encoding = rules
+            .iter()
+            .find(|r| glob_matches(r.pattern.as_bytes(), path.as_bytes()))
+            .map(|r| &r.encoding[..]);
+    }
+    decode_section(&mut output, &bytes[section_start..], encoding);
+
+    output
+}
+
+fn decode_section(output: &mut String, bytes: &[u8], encoding: Option<&str>) {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        output.push_str(text);
+        return;
+    }
+
+    let decoded = match encoding {
+        Some(encoding) => decode(bytes, encoding),
+        None => None,
+    };
+    match decoded {
+        Some(decoded) => output.push_str(&decoded),
+        None => output.push_str(&String::from_utf8_lossy(bytes)),
+    }
+}
+
+fn decode(bytes: &[u8], encoding: &str) -> Option<String> {
+    match &encoding.to_lowercase()[..] {
+        "latin1" | "latin-1" | "iso-8859-1" => {
+            Some(bytes.iter().map(|&b| b as char).collect())
+        }
+        "cp1252" | "windows-1252" => Some(
+            bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0x9f => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                    _ => b as char,
+                })
+                .collect(),
+        ),
+        _ => decode_with_iconv(bytes, encoding),
+    }
+}
+
+/// Any other encoding, like Shift-JIS, is converted through iconv
+fn decode_with_iconv(bytes: &[u8], encoding: &str) -> Option<String> {
+    let mut child = Command::new("iconv")
+        .args(["-f", encoding, "-t", "UTF-8"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    // write from another thread so iconv can't block on a full stdout pipe
+    let mut stdin = child.stdin.take()?;
+    let input = bytes.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+    let output = child.wait_with_output().ok()?;
+    let _ = writer.join();
+    if output.status.success() {
+        String::from_utf8(output.stdout).ok()
+    } else {
+        None
+    }
+}
+
+fn lines_with_offsets(bytes: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    let mut start = 0;
+    bytes.split_inclusive(|&b| b == b'\n').map(move |line| {
+        let line_start = start;
+        start += line.len();
+        (line_start, line)
+    })
+}
+
+/// Matches `*` to any sequence of characters and `?` to a single one.
+/// On a mismatch only the last `*` takes one more character, which keeps it
+/// at `pattern.len() * text.len()` steps at worst
+pub fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    let mut p = 0;
+    let mut t = 0;
+    // position of the last `*` and of the text it matched up to
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
@@ -0,0 +1,217 @@
+use crate::{
+    locale::trf,
+    tui_util::{diff_header_path, strip_ansi, HunkLines},
+};
+
+/// Starts the line standing for a folded run, dim and italic so it doesn't
+/// look like a line of the diff
+const FOLD_COLOR: &str = "\x1b[2;3m";
+/// Starts the line summing up the changes of a collapsed file
+const FILE_STAT_COLOR: &str = "\x1b[2m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+/// Unchanged lines diffs are asked to show around each change, as with the
+/// usual 3 no run gets long enough to be folded
+pub const CONTEXT_LINES: usize = 25;
+/// Runs of unchanged lines longer than this get folded
+const MIN_FOLDED_LINES: usize = 12;
+/// Unchanged lines kept visible at each end of a folded run
+const KEPT_CONTEXT_LINES: usize = 3;
+
+/// Hidden lines `start..end` of a diff
+struct Fold {
+    start: usize,
+    end: usize,
+    expanded: bool,
+}
+
+/// Lines `start..end` of a diff about a single file, starting at its header
+struct File {
+    start: usize,
+    end: usize,
+    added: usize,
+    removed: usize,
+    collapsed: bool,
+}
+
+/// Colored unified diff with its long runs of unchanged lines folded and
+/// its files possibly collapsed to their header
+pub struct FoldedDiff {
+    source: String,
+    folds: Vec<Fold>,
+    files: Vec<File>,
+}
+
+impl FoldedDiff {
+    pub fn new(diff: String) -> Self {
+        let mut folds = Vec::new();
+        let mut push_fold = |run_start: usize, run_end: usize| {
+            if run_end - run_start > MIN_FOLDED_LINES {
+                folds.push(Fold {
+                    start: run_start + KEPT_CONTEXT_LINES,
+                    end: run_end - KEPT_CONTEXT_LINES,
+                    expanded: false,
+                });
+            }
+        };
+        let mut files: Vec<File> = Vec::new();
+
+        let mut hunk_lines = HunkLines::default();
+        let mut run_start = None;
+        let mut line_count = 0;
+        for (i, line) in diff.lines().enumerate() {
+            line_count += 1;
+            let plain = strip_ansi(line);
+            let is_unchanged = if hunk_lines.start(&plain) {
+                false
+            } else if hunk_lines.next(&plain) {
+                if let Some(file) = files.last_mut() {
+                    match plain.chars().next() {
+                        Some('+') => file.added += 1,
+                        Some('-') => file.removed += 1,
+                        _ => (),
+                    }
+                }
+                plain.starts_with(' ')
+            } else if diff_header_path(line).is_some() {
+                if let Some(file) = files.last_mut() {
+                    file.end = i;
+                }
+                files.push(File {
+                    start: i,
+                    end: i,
+                    added: 0,
+                    removed: 0,
+                    collapsed: false,
+                });
+                false
+            } else {
+                false
+            };
+
+            match (is_unchanged, run_start) {
+                (true, None) => run_start = Some(i),
+                (false, Some(start)) => {
+                    push_fold(start, i);
+                    run_start = None;
+                }
+                _ => (),
+            }
+        }
+        if let Some(start) = run_start {
+            push_fold(start, line_count);
+        }
+        if let Some(file) = files.last_mut() {
+            file.end = line_count;
+        }
+
+        Self {
+            source: diff,
+            folds,
+            files,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The diff with each collapsed fold replaced by a line telling how many
+    /// lines it hides and each collapsed file by its header and the number
+    /// of lines it adds and removes
+    pub fn text(&self) -> String {
+        let lines: Vec<_> = self.source.lines().collect();
+        let mut text = String::with_capacity(self.source.len());
+        let mut i = 0;
+        while i < lines.len() {
+            if let Some(file) = self.file_starting_at(i).filter(|f| f.collapsed)
+            {
+                text.push_str(lines[i]);
+                text.push('\n');
+                text.push_str(FILE_STAT_COLOR);
+                text.push_str(&format!("+{} -{}", file.added, file.removed));
+                text.push_str(RESET_COLOR);
+                text.push('\n');
+                i = file.end;
+                continue;
+            }
+            if let Some(fold) = self.fold_starting_at(i).filter(|f| !f.expanded)
+            {
+                text.push_str(FOLD_COLOR);
+                text.push_str(&trf(
+                    "\u{2026} {} unchanged lines \u{2026}",
+                    &[&(fold.end - fold.start)],
+                ));
+                text.push_str(RESET_COLOR);
+                text.push('\n');
+                i = fold.end;
+                continue;
+            }
+            text.push_str(lines[i]);
+            text.push('\n');
+            i += 1;
+        }
+        text
+    }
+
+    fn file_starting_at(&self, line: usize) -> Option<&File> {
+        let index = self.files.binary_search_by_key(&line, |f| f.start).ok()?;
+        Some(&self.files[index])
+    }
+
+    fn fold_starting_at(&self, line: usize) -> Option<&Fold> {
+        let index = self.folds.binary_search_by_key(&line, |f| f.start).ok()?;
+        Some(&self.folds[index])
+    }
+
+    /// Expands the collapsed folds at `positions`, counted among the ones
+    /// drawn in order
+    pub fn expand(&mut self, positions: &[usize]) {
+        let files = &self.files;
+        let drawn_folds = self.folds.iter_mut().filter(|fold| {
+            !fold.expanded
+                && !files.iter().any(|file| {
+                    file.collapsed
+                        && file.start < fold.start
+                        && fold.start < file.end
+                })
+        });
+        for (i, fold) in drawn_folds.enumerate() {
+            if positions.contains(&i) {
+                fold.expanded = true;
+            }
+        }
+    }
+
+    /// Collapses or expands the file at `index`, counted in order
+    pub fn toggle_file(&mut self, index: usize) {
+        if let Some(file) = self.files.get_mut(index) {
+            file.collapsed = !file.collapsed;
+        }
+    }
+
+    pub fn set_files_collapsed(&mut self, collapsed: bool) {
+        for file in &mut self.files {
+            file.collapsed = collapsed;
+        }
+    }
+}
+
+/// Whether `line` of a rendered diff stands for a collapsed fold
+pub fn is_fold_line(line: &str) -> bool {
+    line.starts_with(FOLD_COLOR)
+}
+
+/// Number of unchanged lines hidden by the fold `line` stands for. `None`
+/// when it's a line of the diff
+pub fn hidden_lines(line: &str) -> Option<usize> {
+    if !is_fold_line(line) {
+        return None;
+    }
+    let count: String = strip_ansi(line)
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(char::is_ascii_digit)
+        .collect();
+    count.parse().ok()
+}
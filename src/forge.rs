@@ -0,0 +1,153 @@
+use std::{
+    process::{Command, Stdio},
+    sync::{Arc, OnceLock},
+    thread,
+};
+
+use crate::{
+    action::{ActionTask, CommandTask},
+    version_control_actions::{handle_command, VersionControlActions},
+};
+
+/// Code hosting service whose cli lists and checks out pull requests
+#[derive(Clone, Copy)]
+pub enum Forge {
+    GitHub,
+    GitLab,
+}
+
+/// Forge of the repository detected on another thread since it runs the
+/// clis of the services and git, which could take a while to start
+pub struct ForgeDetection(Arc<OnceLock<Option<Forge>>>);
+
+impl ForgeDetection {
+    pub fn start(version_control: &dyn VersionControlActions) -> Self {
+        let detected = Arc::new(OnceLock::new());
+        if version_control.executable_name() == "git" {
+            let mut remotes = version_control.command();
+            remotes.args(["remote", "-v"]);
+            let thread_detected = detected.clone();
+            thread::spawn(move || {
+                let _ = thread_detected.set(Forge::detect(remotes));
+            });
+        }
+        Self(detected)
+    }
+
+    /// `None` while it's still being detected
+    pub fn get(&self) -> Option<Forge> {
+        self.0.get().copied().flatten()
+    }
+}
+
+impl Forge {
+    /// Picks the service hosting the git remotes, listed by `remotes`, among
+    /// the ones whose cli is installed. GitHub when the remotes don't tell
+    fn detect(mut remotes: Command) -> Option<Self> {
+        let installed: Vec<_> = [Self::GitHub, Self::GitLab]
+            .iter()
+            .copied()
+            .filter(|forge| forge.is_installed())
+            .collect();
+        if installed.len() < 2 {
+            return installed.into_iter().next();
+        }
+
+        let remotes = handle_command(&mut remotes).unwrap_or_default();
+        if remotes.contains("gitlab") {
+            Some(Self::GitLab)
+        } else {
+            Some(Self::GitHub)
+        }
+    }
+
+    fn executable_name(self) -> &'static str {
+        match self {
+            Self::GitHub => "gh",
+            Self::GitLab => "glab",
+        }
+    }
+
+    fn subcommand(self) -> &'static str {
+        match self {
+            Self::GitHub => "pr",
+            Self::GitLab => "mr",
+        }
+    }
+
+    fn is_installed(self) -> bool {
+        Command::new(self.executable_name())
+            .arg("--version")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+    }
+
+    fn task<F>(self, current_dir: &str, builder: F) -> Box<dyn ActionTask>
+    where
+        F: FnOnce(&mut Command),
+    {
+        let mut command = Command::new(self.executable_name());
+        command.current_dir(current_dir).env("NO_COLOR", "1");
+        builder(&mut command);
+        Box::new(CommandTask::new(command))
+    }
+
+    /// Open pull requests, one per line starting with their number
+    pub fn list_pull_requests(self, current_dir: &str) -> Box<dyn ActionTask> {
+        self.task(current_dir, |command| {
+            command.arg(self.subcommand()).arg("list");
+        })
+    }
+
+    /// Fetches the branch of pull request `number` and switches to it
+    pub fn checkout_pull_request(
+        self,
+        current_dir: &str,
+        number: &str,
+    ) -> Box<dyn ActionTask> {
+        self.task(current_dir, |command| {
+            command.arg(self.subcommand()).arg("checkout").arg(number);
+        })
+    }
+
+    /// Opens commit `revision` in the browser through the cli, which knows
+    /// the address of self hosted instances. Only `gh` can
+    pub fn open_commit(
+        self,
+        current_dir: &str,
+        revision: &str,
+    ) -> Option<Box<dyn ActionTask>> {
+        match self {
+            Self::GitHub => Some(self.task(current_dir, |command| {
+                command.arg("browse").arg(revision);
+            })),
+            Self::GitLab => None,
+        }
+    }
+
+    pub fn open_pull_request(
+        self,
+        current_dir: &str,
+        number: &str,
+    ) -> Box<dyn ActionTask> {
+        self.task(current_dir, |command| {
+            command
+                .arg(self.subcommand())
+                .arg("view")
+                .arg(number)
+                .arg("--web");
+        })
+    }
+}
+
+/// Number of the pull request listed in `line`, like `#12` or `!12`
+pub fn parse_pull_request(line: &str) -> Option<&str> {
+    let number = line.split_whitespace().next()?;
+    let number = number.trim_start_matches(['#', '!']);
+    Some(number)
+        .filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
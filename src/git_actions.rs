@@ -1,9 +1,51 @@
+use std::{
+    path::Path,
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
 use crate::{
-    action::{parallel, serial, task_vec, ActionTask},
+    action::{
+        map_result, or_else, parallel, ready, serial, serial_until_error,
+        task_vec, timed, ActionKind, ActionResult, ActionTask,
+    },
+    backend_error::{BackendResult, ErrorKind},
     select::{Entry, State},
-    version_control_actions::{handle_command, task, VersionControlActions},
+    version_control_actions::{
+        append_to_root_file, changed_files_text, discard_snapshot_age,
+        discard_snapshot_name, handle_command, task, task_with_input,
+        CommitOptions, Operation, StatusOptions, VersionControlActions,
+    },
 };
 
+/// Outputs the number of commits only in HEAD and only in `target`
+fn ahead_behind_counts(git: &GitActions, target: &str) -> Box<dyn ActionTask> {
+    task(git, |command| {
+        command
+            .args(["rev-list", "--left-right", "--count"])
+            .arg(format!("HEAD...{}", target));
+    })
+}
+
+/// Entries of `git status --porcelain -z`, where renames and copies are
+/// followed by a field with their source
+fn count_porcelain_entries(output: &str) -> usize {
+    let mut fields = output.split('\0').filter(|f| !f.is_empty());
+    let mut count = 0;
+    while let Some(field) = fields.next() {
+        let state = field.get(..2).unwrap_or_default();
+        if state.contains(['R', 'C']) {
+            fields.next();
+        }
+        count += 1;
+    }
+    count
+}
+
 fn str_to_state(s: &str) -> State {
     match s {
         "?" => State::Untracked,
@@ -17,8 +59,167 @@ fn str_to_state(s: &str) -> State {
     }
 }
 
+/// Entries of `git status --porcelain=v2 -z`, whose state is the one of the
+/// index or, when unchanged there, of the working tree
+fn parse_porcelain_v2(output: &str) -> Vec<Entry> {
+    let mut fields = output.split('\0');
+    let mut files = Vec::new();
+    while let Some(field) = fields.next() {
+        // changed entries have a fixed number of space separated parts, the
+        // last being the path
+        let path_at = |parts: usize| field.splitn(parts, ' ').last();
+        let (state, filename) = match field.get(..2) {
+            Some("? ") => (State::Untracked, field.get(2..)),
+            Some("! ") => (State::Ignored, field.get(2..)),
+            Some("u ") => (State::Unmerged, path_at(11)),
+            Some("1 ") | Some("2 ") => {
+                let state = match field.get(2..4) {
+                    Some(xy) if xy.starts_with('.') => str_to_state(&xy[1..]),
+                    Some(xy) => str_to_state(&xy[..1]),
+                    None => continue,
+                };
+                let parts = if field.starts_with('1') { 9 } else { 10 };
+                (state, path_at(parts))
+            }
+            _ => continue,
+        };
+        let filename = match filename {
+            Some(filename) => filename,
+            None => continue,
+        };
+        // renames and copies are followed by a field with the original path
+        let renamed_from = match field.starts_with('2') {
+            true => fields.next().map(String::from),
+            false => None,
+        };
+        files.push(Entry {
+            filename: filename.into(),
+            selected: false,
+            state,
+            renamed_from,
+        });
+    }
+    files
+}
+
+/// Describes a `%G?` signature status code
+fn signature_status_name(code: &str) -> &'static str {
+    match code {
+        "G" => "good",
+        "B" => "bad",
+        "U" => "good with unknown validity",
+        "X" => "good but expired",
+        "Y" => "good made by an expired key",
+        "R" => "good made by a revoked key",
+        "E" => "cannot be checked",
+        _ => "unsigned",
+    }
+}
+
+/// What git prints when it refuses to commit by itself, before or without
+/// running the hooks
+const COMMIT_ERRORS: &[&str] = &[
+    "fatal:",
+    "nothing to commit",
+    "nothing added to commit",
+    "no changes added to commit",
+];
+
+/// Arguments of the log, of its first `count` entries or all of them
+fn log_args(
+    command: &mut Command,
+    count: Option<usize>,
+    scope: Option<&str>,
+    branch: Option<&str>,
+) {
+    // the signature part is left empty as `%G?` runs gpg for every entry,
+    // the details of a revision show it instead
+    let template =
+        "--format=format:%x1e%h%x1e%x1e%as%x1e%<(10,trunc)%aN%x1e%D%x1e%s";
+    command
+        .arg("log")
+        .arg(branch.unwrap_or("--all"))
+        .arg("--decorate")
+        .arg("--oneline")
+        .arg("--graph")
+        .args(count.map(|count| format!("-{}", count)))
+        .arg(template);
+    if let Some(scope) = scope {
+        command.arg("--").arg(scope);
+    }
+}
+
+/// Explains gpg failures which usually happen because pinentry could not
+/// ask for the passphrase while verco owns the terminal. Failures git didn't
+/// report itself are blamed on the commit hooks when there are any
+fn commit_with_hints(
+    commit: Box<dyn ActionTask>,
+    has_hooks: bool,
+) -> Box<dyn ActionTask> {
+    map_result(commit, move |mut result| {
+        if result.success {
+            return result;
+        }
+        if result.output.contains("gpg failed to sign") {
+            result.output.insert_str(
+                0,
+                "gpg failed to sign the commit\n\
+                 make sure gpg-agent is running with a graphical pinentry \
+                 or that the key passphrase is cached\n\n",
+            );
+        } else if has_hooks
+            && !result.output.lines().any(|line| {
+                COMMIT_ERRORS
+                    .iter()
+                    .any(|e| line.trim_start().starts_with(e))
+            })
+        {
+            result.error =
+                result.error.map(|e| e.with_kind(ErrorKind::HookFailure));
+        }
+        result
+    })
+}
+
+/// Status runs slower than this count towards suggesting fsmonitor
+const SLOW_STATUS_THRESHOLD: Duration = Duration::from_secs(2);
+/// Consecutive slow status runs before suggesting fsmonitor
+const SLOW_STATUS_HINT_AFTER: usize = 3;
+const FSMONITOR_HINT: &str = "status is slow in this repository. \
+    enabling fsmonitor and the untracked cache can make it near instant:\n\
+    git config core.fsmonitor true (builtin daemon on macOS and Windows)\n\
+    or set core.fsmonitor to the watchman hook from .git/hooks\n\
+    git config core.untrackedCache true\n\n";
+
 pub struct GitActions {
     pub current_dir: String,
+    /// Whether `core.fsmonitor` is configured for the repository
+    fsmonitor: bool,
+    /// `core.untrackedCache` of the repository, `None` when unset
+    untracked_cache: Option<bool>,
+    slow_status_count: Arc<AtomicUsize>,
+    /// Changed files of the last status, `usize::MAX` when unknown
+    changed_files_count: Arc<AtomicUsize>,
+}
+
+impl GitActions {
+    pub fn new(current_dir: String) -> Self {
+        Self {
+            current_dir,
+            fsmonitor: false,
+            untracked_cache: None,
+            slow_status_count: Arc::new(AtomicUsize::new(0)),
+            changed_files_count: Arc::new(AtomicUsize::new(usize::MAX)),
+        }
+    }
+
+    /// Turns the untracked cache on for a status, as fsmonitor only speeds
+    /// up untracked files with it, unless the repository turned it off
+    fn add_untracked_cache_args(&self, command: &mut Command) {
+        if self.fsmonitor && self.untracked_cache.is_none() {
+            command.args(["-c", "core.untrackedCache=true"]);
+        }
+    }
 }
 
 impl VersionControlActions for GitActions {
@@ -26,14 +227,39 @@ impl VersionControlActions for GitActions {
         "git"
     }
 
+    /// Keeps non ascii paths readable instead of escaped in every output
+    fn global_args(&self) -> &'static [&'static str] {
+        &["-c", "core.quotepath=false"]
+    }
+
     fn current_dir(&self) -> &str {
         &self.current_dir[..]
     }
 
-    fn set_root(&mut self) -> Result<(), String> {
+    fn flags_subcommand(&self, kind: ActionKind) -> Option<&'static str> {
+        let subcommand = match kind {
+            ActionKind::Fetch => "fetch",
+            ActionKind::Pull => "pull",
+            ActionKind::Push => "push",
+            ActionKind::CommitAll | ActionKind::CommitSelected => "commit",
+            ActionKind::Merge => "merge",
+            ActionKind::CurrentDiffAll
+            | ActionKind::CurrentDiffSelected
+            | ActionKind::RevisionDiffAll
+            | ActionKind::RevisionDiffSelected => "diff",
+            _ => return None,
+        };
+        Some(subcommand)
+    }
+
+    fn diff_context_flags(&self, lines: usize) -> Vec<String> {
+        vec![format!("-U{}", lines)]
+    }
+
+    fn set_root(&mut self) -> BackendResult<()> {
         let mut command = self.command();
         let dir =
-            handle_command(command.args(&["rev-parse", "--show-toplevel"]))?;
+            handle_command(command.args(["rev-parse", "--show-toplevel"]))?;
 
         let dir = dir
             .lines()
@@ -41,6 +267,22 @@ impl VersionControlActions for GitActions {
             .expect("root directory is an empty string");
         self.current_dir = dir.to_owned();
 
+        let fsmonitor =
+            handle_command(self.command().args(["config", "core.fsmonitor"]))
+                .unwrap_or_default();
+        self.fsmonitor = !matches!(fsmonitor.trim(), "" | "false");
+        let untracked_cache = handle_command(self.command().args([
+            "config",
+            "--type=bool",
+            "core.untrackedCache",
+        ]))
+        .unwrap_or_default();
+        self.untracked_cache = match untracked_cache.trim() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        };
+
         Ok(())
     }
 
@@ -48,97 +290,250 @@ impl VersionControlActions for GitActions {
         &self.current_dir[..]
     }
 
-    fn get_current_changed_files(&self) -> Result<Vec<Entry>, String> {
-        let output = handle_command(self.command().args(&["status", "-z"]))?;
-
-        let files = output
-            .trim()
-            .split('\0')
-            .map(|e| e.trim())
-            .filter(|e| e.len() > 2)
-            .map(|e| {
-                let (state, filename) = e.split_at(2);
-                Entry {
-                    filename: String::from(filename.trim()),
-                    selected: false,
-                    state: str_to_state(&state[..1]),
-                }
-            })
-            .collect();
-        Ok(files)
+    fn get_current_changed_files(&self) -> BackendResult<Vec<Entry>> {
+        let mut command = self.command();
+        self.add_untracked_cache_args(&mut command);
+        let output =
+            handle_command(command.args(["status", "--porcelain=v2", "-z"]))?;
+        Ok(parse_porcelain_v2(&output))
     }
 
     fn get_revision_changed_files(
         &self,
         target: &str,
-    ) -> Result<Vec<Entry>, String> {
+    ) -> BackendResult<Vec<Entry>> {
         let output = handle_command(
             self.command()
                 .arg("diff-tree")
                 .arg("--no-commit-id")
                 .arg("--name-status")
+                .arg("-M")
                 .arg("-z")
                 .arg("-r")
                 .arg(target),
         )?;
 
-        let state_iter = output.split('\0').map(|e| e.trim()).step_by(2);
-        let filename_iter =
-            output.split('\0').map(|e| e.trim()).skip(1).step_by(2);
-
-        let files = state_iter
-            .zip(filename_iter)
-            .map(|(s, f)| Entry {
-                filename: String::from(f),
+        // renames and copies like `R100` hold both the old and new paths
+        let mut fields = output.split('\0').map(|e| e.trim());
+        let mut files = Vec::new();
+        while let (Some(state), Some(filename)) = (fields.next(), fields.next())
+        {
+            let (filename, renamed_from) =
+                if state.starts_with('R') || state.starts_with('C') {
+                    match fields.next() {
+                        Some(new_filename) => (new_filename, Some(filename)),
+                        None => break,
+                    }
+                } else {
+                    (filename, None)
+                };
+            files.push(Entry {
+                filename: String::from(filename),
                 selected: false,
-                state: str_to_state(s),
-            })
-            .collect();
+                state: str_to_state(state.get(..1).unwrap_or(state)),
+                renamed_from: renamed_from.map(String::from),
+            });
+        }
         Ok(files)
     }
 
-    fn version(&self) -> Result<String, String> {
+    fn get_remotes(&self) -> BackendResult<Vec<String>> {
+        let output = handle_command(self.command().arg("remote"))?;
+        Ok(output.lines().map(String::from).collect())
+    }
+
+    fn get_remote_url(&self, name: &str) -> BackendResult<String> {
+        let output =
+            handle_command(self.command().args(["remote", "get-url", name]))?;
+        Ok(output.trim().into())
+    }
+
+    fn get_branch_names(&self) -> BackendResult<Vec<String>> {
+        let output = handle_command(
+            self.command().args(["branch", "--format=%(refname:short)"]),
+        )?;
+        Ok(output.lines().map(String::from).collect())
+    }
+
+    fn get_current_branch(&self) -> BackendResult<String> {
+        let output = handle_command(self.command().args([
+            "rev-parse",
+            "--abbrev-ref",
+            "HEAD",
+        ]))?;
+        Ok(output.trim().into())
+    }
+
+    fn has_upstream(&self) -> bool {
+        handle_command(self.command().args([
+            "rev-parse",
+            "--abbrev-ref",
+            "--symbolic-full-name",
+            "@{u}",
+        ]))
+        .is_ok()
+    }
+
+    fn version(&self) -> BackendResult<String> {
         handle_command(self.command().arg("--version"))
     }
 
-    fn status(&self) -> Box<dyn ActionTask> {
-        task(self, |command| {
-            command.args(&["-c", "color.status=always", "status"]);
+    fn status(
+        &self,
+        options: StatusOptions,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        let add_options = |command: &mut Command| {
+            if options.skip_untracked {
+                command.arg("--untracked-files=no");
+            } else if options.expand_untracked {
+                command.arg("--untracked-files=all");
+            }
+            if let Some(scope) = scope {
+                command.arg("--").arg(scope);
+            }
+        };
+        let status = task(self, |command| {
+            command.args(["-c", "color.status=always"]);
+            self.add_untracked_cache_args(command);
+            command.arg("status");
+            if options.show_ignored {
+                command.arg("--ignored");
+            }
+            add_options(command);
+        });
+        // the long output is translated, so its files are counted from the
+        // porcelain one, which also lists a partially staged file once
+        let count = task(self, |command| {
+            self.add_untracked_cache_args(command);
+            command.args(["status", "--porcelain", "-z"]);
+            add_options(command);
+        });
+        let changed_files_count = self.changed_files_count.clone();
+        let count = map_result(count, move |result| {
+            let count = match result.success {
+                true => count_porcelain_entries(&result.output),
+                false => usize::MAX,
+            };
+            changed_files_count.store(count, Ordering::Relaxed);
+            ActionResult::from_ok(String::new())
+        });
+        let status = map_result(parallel(vec![status, count]), |mut result| {
+            // the line breaks the outputs were joined with
+            result.output.remove(0);
+            result.output.pop();
+            result
+        });
+        if self.fsmonitor {
+            return status;
+        }
+        let slow_status_count = self.slow_status_count.clone();
+        timed(status, move |mut result, elapsed| {
+            if elapsed < SLOW_STATUS_THRESHOLD {
+                slow_status_count.store(0, Ordering::Relaxed);
+            } else if slow_status_count.fetch_add(1, Ordering::Relaxed) + 1
+                >= SLOW_STATUS_HINT_AFTER
+                && result.success
+            {
+                result.output.insert_str(0, FSMONITOR_HINT);
+            }
+            result
         })
     }
 
     fn current_export(&self) -> Box<dyn ActionTask> {
         task(self, |command| {
-            command.args(&["show", "--color"]);
+            command.args(["show", "--color"]);
         })
     }
 
-    fn log(&self, count: usize) -> Box<dyn ActionTask> {
+    fn log(
+        &self,
+        count: usize,
+        scope: Option<&str>,
+        branch: Option<&str>,
+    ) -> Box<dyn ActionTask> {
         task(self, |command| {
-            let count_str = format!("-{}", count);
-            let template =
-                "--format=format:%x1e%h%x1e%as%x1e%<(10,trunc)%aN%x1e%D%x1e%s";
-            command
-                .arg("log")
-                .arg("--all")
-                .arg("--decorate")
-                .arg("--oneline")
-                .arg("--graph")
-                .arg(&count_str)
-                .arg(template);
+            log_args(command, Some(count), scope, branch)
         })
     }
 
-    fn current_diff_all(&self) -> Box<dyn ActionTask> {
+    fn log_command(
+        &self,
+        scope: Option<&str>,
+        branch: Option<&str>,
+    ) -> Option<Command> {
+        let mut command = self.command();
+        log_args(&mut command, None, scope, branch);
+        Some(command)
+    }
+
+    /// Leaves the signature status out as checking it runs gpg for every
+    /// entry
+    fn log_for_scripts(&self, count: usize) -> Box<dyn ActionTask> {
         task(self, |command| {
-            command.args(&["diff", "--color"]);
+            command
+                .args(["log", "--all", "--decorate"])
+                .arg(format!("-{}", count))
+                .arg("--format=format:%x1e%h%x1e%x1e%as%x1e%aN%x1e%D%x1e%s");
         })
     }
 
-    fn current_diff_selected(
+    fn log_position(
         &self,
-        entries: &Vec<Entry>,
-    ) -> Box<dyn ActionTask> {
+        target: &str,
+        scope: Option<&str>,
+        branch: Option<&str>,
+    ) -> BackendResult<(usize, String)> {
+        let revision = match handle_command(
+            self.command()
+                .args(["rev-parse", "--verify", "--quiet"])
+                .arg(format!("{}^{{commit}}", target)),
+        ) {
+            Ok(revision) => revision,
+            Err(_) => handle_command(
+                self.command()
+                    .arg("rev-list")
+                    .arg(branch.unwrap_or("--all"))
+                    .arg("--max-count=1")
+                    .arg(format!("--before={}", target))
+                    .arg("--")
+                    .args(scope),
+            )?,
+        };
+        let revision = revision.trim();
+        if revision.is_empty() {
+            return Err(format!("no revision found for '{}'", target).into());
+        }
+
+        let time = handle_command(
+            self.command()
+                .args(["log", "-1", "--format=%ct"])
+                .arg(revision),
+        )?;
+        let count = handle_command(
+            self.command()
+                .arg("rev-list")
+                .arg(branch.unwrap_or("--all"))
+                .arg("--count")
+                .arg(format!("--since=@{}", time.trim()))
+                .arg("--")
+                .args(scope),
+        )?;
+        let count = count.trim().parse().map_err(|_| count.clone())?;
+        Ok((count, revision.into()))
+    }
+
+    fn current_diff_all(&self, scope: Option<&str>) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["diff", "--color"]);
+            if let Some(scope) = scope {
+                command.arg("--").arg(scope);
+            }
+        })
+    }
+
+    fn current_diff_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask> {
         task(self, |command| {
             command.arg("diff").arg("--color").arg("--");
             for e in entries.iter().filter(|e| e.selected) {
@@ -147,30 +542,45 @@ impl VersionControlActions for GitActions {
         })
     }
 
-    fn revision_changes(&self, target: &str) -> Box<dyn ActionTask> {
+    fn revision_changes(
+        &self,
+        target: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
         task(self, |command| {
             command
                 .arg("diff-tree")
                 .arg("--no-commit-id")
                 .arg("--name-status")
+                .arg("-M")
                 .arg("-r")
                 .arg(target)
                 .arg("--color");
+            if let Some(scope) = scope {
+                command.arg("--").arg(scope);
+            }
         })
     }
 
-    fn revision_diff_all(&self, target: &str) -> Box<dyn ActionTask> {
+    fn revision_diff_all(
+        &self,
+        target: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
         task(self, |command| {
             let mut parents = String::from(target);
             parents.push_str("^@");
             command.arg("diff").arg(parents).arg(target).arg("--color");
+            if let Some(scope) = scope {
+                command.arg("--").arg(scope);
+            }
         })
     }
 
     fn revision_diff_selected(
         &self,
         target: &str,
-        entries: &Vec<Entry>,
+        entries: &[Entry],
     ) -> Box<dyn ActionTask> {
         task(self, |command| {
             let mut parents = String::from(target);
@@ -189,21 +599,161 @@ impl VersionControlActions for GitActions {
         })
     }
 
-    fn commit_all(&self, message: &str) -> Box<dyn ActionTask> {
+    fn base_changes(
+        &self,
+        base: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["diff", "--name-status", "--color"]).arg(base);
+            if let Some(scope) = scope {
+                command.arg("--").arg(scope);
+            }
+        })
+    }
+
+    fn base_diff_all(
+        &self,
+        base: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["diff", "--color"]).arg(base);
+            if let Some(scope) = scope {
+                command.arg("--").arg(scope);
+            }
+        })
+    }
+
+    fn compare(&self, base: &str, target: &str) -> Box<dyn ActionTask> {
         let mut tasks = task_vec();
+        let commits = task(self, |command| {
+            command
+                .args(["log", "--oneline", "--color"])
+                .arg(format!("{}..{}", base, target));
+        });
+        let heading = format!("commits in '{}' not in '{}':\n", target, base);
+        tasks.push(map_result(commits, move |mut result| {
+            if result.success {
+                if result.output.trim().is_empty() {
+                    result.output.push_str("none\n");
+                }
+                result.output.insert_str(0, &heading);
+            }
+            result
+        }));
         tasks.push(task(self, |command| {
-            command.args(&["add", "--all"]);
+            command
+                .args(["diff", "--color"])
+                .arg(format!("{}...{}", base, target));
+        }));
+        serial_until_error(tasks)
+    }
+
+    fn supports_notes(&self) -> bool {
+        true
+    }
+
+    fn get_note(&self, target: &str, notes_ref: &str) -> BackendResult<String> {
+        handle_command(
+            self.command()
+                .arg("log")
+                .arg("-1")
+                .arg(format!("--notes={}", notes_ref))
+                .arg("--format=%N")
+                .arg(target),
+        )
+        .map(|note| note.trim_end().into())
+    }
+
+    fn revision_signature(&self, target: &str) -> Box<dyn ActionTask> {
+        let signature = task(self, |command| {
+            command
+                .arg("log")
+                .arg("-1")
+                .arg("--format=%G?%x1e%GS")
+                .arg(target);
+        });
+        map_result(signature, |mut result| {
+            if result.success {
+                let mut parts = result.output.trim().splitn(2, '\x1e');
+                let status = signature_status_name(parts.next().unwrap_or(""));
+                result.output = match parts.next() {
+                    Some(signer) if !signer.is_empty() => {
+                        format!("signature: {} by {}\n", status, signer)
+                    }
+                    _ => format!("signature: {}\n", status),
+                };
+            }
+            result
+        })
+    }
+
+    fn revision_notes(
+        &self,
+        target: &str,
+        notes_ref: &str,
+    ) -> Box<dyn ActionTask> {
+        let notes = task(self, |command| {
+            command
+                .arg("log")
+                .arg("-1")
+                .arg(format!("--notes={}", notes_ref))
+                .arg("--format=%N")
+                .arg(target);
+        });
+        map_result(notes, |mut result| {
+            if result.success && !result.output.trim().is_empty() {
+                result.output.insert_str(0, "notes:\n");
+            }
+            result
+        })
+    }
+
+    fn set_note(
+        &self,
+        target: &str,
+        notes_ref: &str,
+        message: &str,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("notes").arg("--ref").arg(notes_ref);
+            if message.is_empty() {
+                command.arg("remove").arg("--ignore-missing");
+            } else {
+                command.arg("add").arg("-f").arg("-m").arg(message);
+            }
+            command.arg(target);
+        })
+    }
+
+    fn commit_all(
+        &self,
+        message: &str,
+        options: CommitOptions,
+    ) -> Box<dyn ActionTask> {
+        let mut tasks = task_vec();
+        tasks.push(task(self, |command| {
+            command.args(["add", "--all"]);
         }));
         tasks.push(task(self, |command| {
             command.arg("commit").arg("-m").arg(message);
+            if options.sign {
+                command.arg("-S");
+            }
+            if options.no_verify {
+                command.arg("--no-verify");
+            }
         }));
-        serial(tasks)
+        let has_hooks = !options.no_verify && self.has_commit_hooks();
+        commit_with_hints(serial(tasks), has_hooks)
     }
 
     fn commit_selected(
         &self,
         message: &str,
-        entries: &Vec<Entry>,
+        entries: &[Entry],
+        options: CommitOptions,
     ) -> Box<dyn ActionTask> {
         let mut tasks = task_vec();
         for e in entries.iter().filter(|e| e.selected) {
@@ -214,22 +764,41 @@ impl VersionControlActions for GitActions {
 
         tasks.push(task(self, |command| {
             command.arg("commit").arg("-m").arg(message);
+            if options.sign {
+                command.arg("-S");
+            }
+            if options.no_verify {
+                command.arg("--no-verify");
+            }
         }));
-        serial(tasks)
+        let has_hooks = !options.no_verify && self.has_commit_hooks();
+        commit_with_hints(serial(tasks), has_hooks)
+    }
+
+    fn has_commit_hooks(&self) -> bool {
+        ["hooks/pre-commit", "hooks/commit-msg"].iter().any(|hook| {
+            handle_command(self.command().args([
+                "rev-parse",
+                "--git-path",
+                hook,
+            ]))
+            .map(|path| Path::new(self.get_root()).join(path.trim()).is_file())
+            .unwrap_or(false)
+        })
     }
 
     fn revert_all(&self) -> Box<dyn ActionTask> {
         let mut tasks = task_vec();
         tasks.push(task(self, |command| {
-            command.args(&["reset", "--hard"]);
+            command.args(["reset", "--hard"]);
         }));
         tasks.push(task(self, |command| {
-            command.args(&["clean", "-df"]);
+            command.args(["clean", "-df"]);
         }));
         serial(tasks)
     }
 
-    fn revert_selected(&self, entries: &Vec<Entry>) -> Box<dyn ActionTask> {
+    fn revert_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask> {
         let mut tasks = task_vec();
         for e in entries.iter().filter(|e| e.selected) {
             match e.state {
@@ -257,6 +826,139 @@ impl VersionControlActions for GitActions {
         parallel(tasks)
     }
 
+    fn get_current_diff(
+        &self,
+        entries: &[Entry],
+        flags: &[String],
+    ) -> BackendResult<String> {
+        let mut command = self.command();
+        command.arg("diff").args(flags).args([
+            "--no-color",
+            "--no-ext-diff",
+            "--",
+        ]);
+        for e in entries.iter().filter(|e| e.selected) {
+            command.arg(&e.filename);
+        }
+        handle_command(&mut command)
+    }
+
+    fn revert_hunks(&self, patch: &str) -> Box<dyn ActionTask> {
+        task_with_input(self, patch, |command| {
+            command.args(["apply", "-R", "-"]);
+        })
+    }
+
+    fn apply_hunks(&self, patch: &str) -> Box<dyn ActionTask> {
+        // a three way merge leaves conflict markers but needs the file to
+        // match the index, so it's only used when a plain apply fails and
+        // the file has no unstaged changes
+        let paths = patch.lines().filter_map(|l| l.strip_prefix("+++ b/"));
+        let has_unstaged = handle_command(
+            self.command().args(["diff", "--quiet", "--"]).args(paths),
+        )
+        .is_err();
+        let fallback = if has_unstaged {
+            ready(ActionResult::from_err(
+                "the hunk does not apply cleanly and the file has changes \
+                 not yet staged\n\
+                 commit or stash them to apply it with conflict markers",
+            ))
+        } else {
+            task_with_input(self, patch, |command| {
+                command.args(["apply", "--3way", "-"]);
+            })
+        };
+        or_else(
+            task_with_input(self, patch, |command| {
+                command.args(["apply", "-"]);
+            }),
+            fallback,
+        )
+    }
+
+    /// `git stash create` would leave the untracked files out, so the
+    /// changes are pushed and applied back. Nothing was saved when the push
+    /// left the stash ref as it was
+    fn create_discard_snapshot(&self) -> BackendResult<()> {
+        let stash_ref = || {
+            let mut command = self.command();
+            command.args(["rev-parse", "-q", "--verify", "refs/stash"]);
+            // fails when there is no stash yet
+            handle_command(&mut command).unwrap_or_default()
+        };
+        let before = stash_ref();
+        handle_command(
+            self.command()
+                .args(["stash", "push", "--include-untracked", "-m"])
+                .arg(discard_snapshot_name()),
+        )?;
+        let after = stash_ref();
+        if after.trim().is_empty() || after == before {
+            return Ok(());
+        }
+
+        handle_command(
+            self.command()
+                .args(["stash", "apply", "--index"])
+                .arg(after.trim()),
+        )
+        .map(|_| ())
+    }
+
+    fn prune_discard_snapshots(&self, max_age: Duration) -> BackendResult<()> {
+        let output = handle_command(self.command().args([
+            "stash",
+            "list",
+            "--format=%gd%x1f%gs",
+        ]))?;
+
+        // drop from the oldest so the remaining stash indices stay valid
+        for line in output.lines().rev() {
+            let mut parts = line.splitn(2, '\x1f');
+            let (stash, subject) = match (parts.next(), parts.next()) {
+                (Some(stash), Some(subject)) => (stash, subject),
+                _ => continue,
+            };
+            match discard_snapshot_age(subject) {
+                Some(age) if age > max_age => {
+                    handle_command(
+                        self.command().arg("stash").arg("drop").arg(stash),
+                    )?;
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn restore_discard_snapshot(&self) -> Box<dyn ActionTask> {
+        let output = handle_command(self.command().args([
+            "stash",
+            "list",
+            "--format=%gd%x1f%gs",
+        ]))
+        .unwrap_or_default();
+        let stash = output
+            .lines()
+            .filter_map(|l| {
+                let mut parts = l.splitn(2, '\x1f');
+                let stash = parts.next()?;
+                discard_snapshot_age(parts.next()?).map(|_| stash)
+            })
+            .next();
+
+        match stash {
+            Some(stash) => task(self, |command| {
+                command.arg("stash").arg("apply").arg(stash);
+            }),
+            None => {
+                ready(ActionResult::from_err("no discard snapshot to restore"))
+            }
+        }
+    }
+
     fn update(&self, target: &str) -> Box<dyn ActionTask> {
         task(self, |command| {
             command.arg("checkout").arg(target);
@@ -271,37 +973,78 @@ impl VersionControlActions for GitActions {
 
     fn conflicts(&self) -> Box<dyn ActionTask> {
         task(self, |command| {
-            command.args(&["diff", "--name-only", "--diff-filter=U"]);
+            command.args(["diff", "--name-only", "--diff-filter=U"]);
         })
     }
 
     fn take_other(&self) -> Box<dyn ActionTask> {
         task(self, |command| {
-            command.args(&["checkout", ".", "--theirs"]);
+            command.args(["checkout", ".", "--theirs"]);
         })
     }
 
     fn take_local(&self) -> Box<dyn ActionTask> {
         task(self, |command| {
-            command.args(&["checkout", ".", "--ours"]);
+            command.args(["checkout", ".", "--ours"]);
         })
     }
 
     fn fetch(&self) -> Box<dyn ActionTask> {
         task(self, |command| {
-            command.args(&["fetch", "--all"]);
+            command.args(["fetch", "--all"]);
         })
     }
 
     fn pull(&self) -> Box<dyn ActionTask> {
         task(self, |command| {
-            command.args(&["pull", "--all"]);
+            command.args(["pull", "--all"]);
         })
     }
 
     fn push(&self) -> Box<dyn ActionTask> {
+        let push = task(self, |command| {
+            command.arg("push");
+        });
+        map_result(push, |mut result| {
+            if !result.success && result.output.contains("no upstream branch") {
+                result.output.insert_str(
+                    0,
+                    "current branch has no upstream\n\
+                     use 'bp' to push it to a remote branch\n\n",
+                );
+            }
+            result
+        })
+    }
+
+    fn push_to(
+        &self,
+        remote: &str,
+        branch: &str,
+        set_upstream: bool,
+    ) -> Box<dyn ActionTask> {
         task(self, |command| {
             command.arg("push");
+            if set_upstream {
+                command.arg("--set-upstream");
+            }
+            command.arg(remote).arg(format!("HEAD:{}", branch));
+        })
+    }
+
+    fn force_push(&self) -> Box<dyn ActionTask> {
+        let push = task(self, |command| {
+            command.args(["push", "--force-with-lease"]);
+        });
+        map_result(push, |mut result| {
+            if !result.success && result.output.contains("stale info") {
+                result.output.insert_str(
+                    0,
+                    "lease failed: remote branch changed since last fetch\n\
+                     fetch and review the new commits before force pushing\n\n",
+                );
+            }
+            result
         })
     }
 
@@ -316,9 +1059,144 @@ impl VersionControlActions for GitActions {
         serial(tasks)
     }
 
+    fn list_tags(&self, pattern: Option<&str>) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            // annotated tags are peeled with `*` to show the tagged commit
+            let format = "--format=%(align:24)%(refname:short)%(end)\
+                %1e%(if)%(*objectname)%(then)%(*objectname:short)\
+                %(else)%(objectname:short)%(end)\
+                %1e%(creatordate:short)\
+                %1e%(if)%(*subject)%(then)%(*subject)%(else)%(subject)%(end)";
+            command
+                .args(["tag", "--list", "--sort=-creatordate"])
+                .arg(format);
+            command.args(pattern);
+        })
+    }
+
+    fn operation_in_progress(&self) -> Option<Operation> {
+        let git_dir = handle_command(
+            self.command().args(["rev-parse", "--absolute-git-dir"]),
+        )
+        .ok()?;
+        let git_dir = Path::new(git_dir.trim());
+        // a rebase stopped at a cherry-pick also leaves CHERRY_PICK_HEAD
+        // behind so it's checked first. `git am` also uses rebase-apply,
+        // marking it with applying
+        [
+            ("rebase-merge", Operation::Rebase),
+            ("rebase-apply/applying", Operation::Am),
+            ("rebase-apply", Operation::Rebase),
+            ("MERGE_HEAD", Operation::Merge),
+            ("CHERRY_PICK_HEAD", Operation::CherryPick),
+            ("REVERT_HEAD", Operation::Revert),
+            ("BISECT_LOG", Operation::Bisect),
+        ]
+        .iter()
+        .find(|(name, _)| git_dir.join(name).exists())
+        .map(|&(_, operation)| operation)
+    }
+
+    fn continue_operation(&self, operation: Operation) -> Box<dyn ActionTask> {
+        if operation == Operation::Bisect {
+            return ready(ActionResult::from_err(
+                "a bisect continues by marking revisions with \
+                 'git bisect good' or 'git bisect bad'",
+            ));
+        }
+        // keeps the message git prepared instead of opening an editor
+        task(self, |command| {
+            command
+                .args(["-c", "core.editor=true", operation.name()])
+                .arg("--continue");
+        })
+    }
+
+    fn abort_operation(&self, operation: Operation) -> Box<dyn ActionTask> {
+        task(self, |command| match operation {
+            Operation::Bisect => {
+                command.args(["bisect", "reset"]);
+            }
+            _ => {
+                command.args([operation.name(), "--abort"]);
+            }
+        })
+    }
+
+    fn get_tag_names(&self) -> BackendResult<Vec<String>> {
+        let output = handle_command(self.command().arg("tag"))?;
+        Ok(output.lines().map(String::from).collect())
+    }
+
+    fn rename_tag(
+        &self,
+        old_name: &str,
+        new_name: &str,
+        remote: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        let old_ref = format!("refs/tags/{}", old_name);
+        let kind = match handle_command(
+            self.command().args(["cat-file", "-t"]).arg(&old_ref),
+        ) {
+            Ok(kind) => kind,
+            Err(error) => return ready(ActionResult::from_err(error)),
+        };
+        // only annotated tags are objects of their own with a message. The
+        // signature of a signed one is left out as it wouldn't verify for
+        // the new tag
+        let message = if kind.trim() == "tag" {
+            match handle_command(
+                self.command()
+                    .args(["tag", "--list"])
+                    .arg("--format=%(contents:signature)%00%(contents)")
+                    .arg(old_name),
+            ) {
+                Ok(output) => {
+                    let (signature, contents) =
+                        output.split_once('\0').unwrap_or(("", &output));
+                    let contents = contents.trim_end();
+                    let message = contents
+                        .strip_suffix(signature.trim_end())
+                        .unwrap_or(contents);
+                    Some(message.to_owned())
+                }
+                Err(error) => return ready(ActionResult::from_err(error)),
+            }
+        } else {
+            None
+        };
+
+        let mut tasks = task_vec();
+        tasks.push(task(self, |command| {
+            command.arg("tag");
+            if let Some(message) = &message {
+                command
+                    .arg("--annotate")
+                    .arg("--message")
+                    .arg(message.trim_end());
+            }
+            // peeled so an annotated tag points at the commit and not at
+            // the old tag object
+            command.arg(new_name).arg(format!("{}^{{}}", old_ref));
+        }));
+        tasks.push(task(self, |command| {
+            command.arg("tag").arg("--delete").arg(old_name);
+        }));
+        if let Some(remote) = remote {
+            tasks.push(task(self, |command| {
+                command
+                    .arg("push")
+                    .arg(remote)
+                    .arg(format!("refs/tags/{}", new_name))
+                    .arg(format!(":{}", old_ref));
+            }));
+        }
+        serial_until_error(tasks)
+    }
+
     fn list_branches(&self) -> Box<dyn ActionTask> {
         task(self, |command| {
-            command.args(&["branch", "--all", "--format=%(refname:short)"]);
+            command.args(["branch", "--all", "--format=%(refname:short)"]);
         })
     }
 
@@ -338,6 +1216,18 @@ impl VersionControlActions for GitActions {
         serial(tasks)
     }
 
+    fn branch_off(&self, name: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["switch", "--create"]).arg(name);
+        })
+    }
+
+    fn branch_from(&self, name: &str, target: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["switch", "--create"]).arg(name).arg(target);
+        })
+    }
+
     fn close_branch(&self, name: &str) -> Box<dyn ActionTask> {
         let mut tasks = task_vec();
         tasks.push(task(self, |command| {
@@ -348,4 +1238,85 @@ impl VersionControlActions for GitActions {
         }));
         serial(tasks)
     }
+
+    fn ignore(&self, pattern: &str) -> Box<dyn ActionTask> {
+        append_to_root_file(self, ".gitignore", pattern)
+    }
+
+    /// Counted by the porcelain status run along with the last status
+    fn changed_files_badge(&self, _status: &str) -> Option<String> {
+        match self.changed_files_count.load(Ordering::Relaxed) {
+            usize::MAX => None,
+            count => Some(changed_files_text(count)),
+        }
+    }
+
+    fn ahead_behind_badge(&self) -> Option<Box<dyn ActionTask>> {
+        let counts = ahead_behind_counts(self, "@{upstream}");
+        Some(map_result(counts, |mut result| {
+            let mut counts = result.output.split_whitespace();
+            result.output = match (counts.next(), counts.next()) {
+                (Some("0"), Some("0")) | (None, _) | (_, None) => String::new(),
+                (Some(ahead), Some(behind)) => {
+                    format!("+{}/-{}", ahead, behind)
+                }
+            };
+            result
+        }))
+    }
+
+    fn ahead_behind(&self) -> Option<BackendResult<(usize, usize)>> {
+        let counts = handle_command(
+            self.command()
+                .args(["rev-list", "--left-right", "--count"])
+                .arg("HEAD...@{upstream}"),
+        );
+        Some(counts.and_then(|output| {
+            let mut counts = output.split_whitespace().map(str::parse);
+            match (counts.next(), counts.next()) {
+                (Some(Ok(ahead)), Some(Ok(behind))) => Ok((ahead, behind)),
+                _ => Err(output.into()),
+            }
+        }))
+    }
+
+    fn compare_target_badge(
+        &self,
+        target: &str,
+    ) -> Option<Box<dyn ActionTask>> {
+        let counts = ahead_behind_counts(self, target);
+        let target = String::from(target);
+        Some(map_result(counts, move |mut result| {
+            let mut counts = result.output.split_whitespace();
+            result.output = match (counts.next(), counts.next()) {
+                (Some(ahead), Some(behind)) => {
+                    format!("+{}/-{} vs {}", ahead, behind, target)
+                }
+                _ => String::new(),
+            };
+            result
+        }))
+    }
+
+    fn unpushed_branches_badge(&self) -> Option<Box<dyn ActionTask>> {
+        let tracking = task(self, |command| {
+            command.args([
+                "for-each-ref",
+                "--format=%(upstream:track)",
+                "refs/heads",
+            ]);
+        });
+        Some(map_result(tracking, |mut result| {
+            let count = result
+                .output
+                .lines()
+                .filter(|l| l.contains("ahead"))
+                .count();
+            result.output = match count {
+                0 => String::new(),
+                count => format!("{} unpushed", count),
+            };
+            result
+        }))
+    }
 }
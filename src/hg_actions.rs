@@ -1,9 +1,39 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
 use crate::{
-    action::{parallel, serial, task_vec, ActionTask},
+    action::{
+        map_result, map_result_in_thread, parallel, ready, serial,
+        serial_until_error, task_vec, ActionKind, ActionResult, ActionTask,
+    },
+    backend_error::BackendResult,
     select::{Entry, State},
-    version_control_actions::{handle_command, task, VersionControlActions},
+    tui_util::strip_ansi,
+    version_control_actions::{
+        append_to_root_file, changed_files_text, discard_snapshot_age,
+        discard_snapshot_name, handle_command, task, task_allowing_exit_one,
+        CommitOptions, StatusOptions, VersionControlActions, BOOKMARK_SUFFIX,
+    },
 };
 
+fn bookmark_names(hg: &HgActions) -> BackendResult<Vec<String>> {
+    let output = handle_command(hg.command().args([
+        "bookmarks",
+        "--template",
+        "{bookmark}\n",
+    ]))?;
+    Ok(output.lines().map(String::from).collect())
+}
+
+fn is_bookmark(hg: &HgActions, name: &str) -> bool {
+    bookmark_names(hg).is_ok_and(|names| names.iter().any(|n| n == name))
+}
+
 fn str_to_state(s: &str) -> State {
     match s {
         "?" => State::Untracked,
@@ -17,11 +47,139 @@ fn str_to_state(s: &str) -> State {
     }
 }
 
+/// Entries of `hg status --copies`, which lists the source of a copied file
+/// indented below it. A copy whose source was removed is a rename
+fn parse_status(output: &str) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for line in output.lines() {
+        if let Some(source) = line.strip_prefix("  ") {
+            if let Some(e) = entries.last_mut() {
+                e.state = State::Copied;
+                e.renamed_from = Some(source.trim().into());
+            }
+            continue;
+        }
+        let line = line.trim();
+        if line.len() <= 1 {
+            continue;
+        }
+        let (state, filename) = line.split_at(1);
+        entries.push(Entry {
+            filename: String::from(filename.trim()),
+            selected: false,
+            state: str_to_state(state),
+            renamed_from: None,
+        });
+    }
+
+    let removed: HashSet<_> = entries
+        .iter()
+        .filter(|e| matches!(e.state, State::Deleted))
+        .map(|e| e.filename.clone())
+        .collect();
+    let mut renamed = HashSet::new();
+    for e in &mut entries {
+        if let Some(source) = &e.renamed_from {
+            if removed.contains(source) {
+                e.state = State::Renamed;
+                renamed.insert(source.clone());
+            }
+        }
+    }
+    entries.retain(|e| {
+        !(matches!(e.state, State::Deleted) && renamed.contains(&e.filename))
+    });
+    entries
+}
+
+/// Arguments of the log, of its first `count` entries or all of them
+fn log_args(
+    command: &mut Command,
+    count: Option<usize>,
+    scope: Option<&str>,
+    branch: Option<&str>,
+) {
+    let template = "\x1e{node|short}\x1e\x1e{date|shortdate}\x1e{author|person}\x1e{tags % '{tag} '}{branch}\x1e{desc|firstline|strip}\x1e{phase}\x1e{topics}";
+    command
+        .arg("log")
+        .arg("--config")
+        .arg("experimental.graphshorten=True")
+        .arg("--graph")
+        .arg("--template")
+        .arg(template);
+    if let Some(count) = count {
+        command.arg("-l").arg(count.to_string());
+    }
+    if let Some(branch) = branch {
+        command.arg("--follow").arg("-r").arg(branch);
+    }
+    if let Some(scope) = scope {
+        command.arg("--").arg(scope);
+    }
+}
+
+/// Collapses the untracked `files` into the topmost directory that holds
+/// nothing else, like git does, returning them as status lines
+fn collapse_untracked(root: &Path, files: &str) -> String {
+    let untracked: HashSet<_> = files.lines().collect();
+    let mut checked_dirs = HashSet::new();
+    let mut collapsed_dirs = HashSet::new();
+    let mut lines = Vec::new();
+    'files: for file in files.lines() {
+        let mut dir_end = 0;
+        while let Some(i) = file[dir_end..].find('/') {
+            dir_end += i + 1;
+            let dir = &file[..dir_end];
+            if collapsed_dirs.contains(dir) {
+                continue 'files;
+            }
+            if checked_dirs.insert(dir)
+                && holds_only(&root.join(dir), dir, &untracked)
+            {
+                collapsed_dirs.insert(dir);
+                lines.push(format!("? {}", dir));
+                continue 'files;
+            }
+        }
+        lines.push(format!("? {}", file));
+    }
+    lines.join("\n")
+}
+
+/// Whether every file inside `dir`, relative to the root as `prefix`, is in
+/// `files`. Stops at the first one that is not
+fn holds_only(dir: &Path, prefix: &str, files: &HashSet<&str>) -> bool {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let path = match name.to_str() {
+            Some(name) => format!("{}{}", prefix, name),
+            None => return false,
+        };
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        let only = if is_dir {
+            holds_only(&entry.path(), &format!("{}/", path), files)
+        } else {
+            files.contains(&path[..])
+        };
+        if !only {
+            return false;
+        }
+    }
+    true
+}
+
+const SHELVE_CONFIG: &str = "extensions.shelve=";
+const SIGN_UNSUPPORTED: &str = "hg does not support signing commits";
+
 pub struct HgActions {
     pub current_dir: String,
 }
 
-impl<'a> VersionControlActions for HgActions {
+impl VersionControlActions for HgActions {
     fn executable_name(&self) -> &'static str {
         "hg"
     }
@@ -30,7 +188,26 @@ impl<'a> VersionControlActions for HgActions {
         &self.current_dir[..]
     }
 
-    fn set_root(&mut self) -> Result<(), String> {
+    fn flags_subcommand(&self, kind: ActionKind) -> Option<&'static str> {
+        let subcommand = match kind {
+            ActionKind::Fetch | ActionKind::Pull => "pull",
+            ActionKind::Push => "push",
+            ActionKind::CommitAll | ActionKind::CommitSelected => "commit",
+            ActionKind::Merge => "merge",
+            ActionKind::CurrentDiffAll
+            | ActionKind::CurrentDiffSelected
+            | ActionKind::RevisionDiffAll
+            | ActionKind::RevisionDiffSelected => "diff",
+            _ => return None,
+        };
+        Some(subcommand)
+    }
+
+    fn diff_context_flags(&self, lines: usize) -> Vec<String> {
+        vec![format!("-U{}", lines)]
+    }
+
+    fn set_root(&mut self) -> BackendResult<()> {
         let mut command = self.command();
         let dir = handle_command(command.arg("root"))?;
 
@@ -47,98 +224,138 @@ impl<'a> VersionControlActions for HgActions {
         &self.current_dir[..]
     }
 
-    fn get_current_changed_files(&self) -> Result<Vec<Entry>, String> {
-        let output = handle_command(self.command().arg("status"))?;
-
-        let files = output
-            .trim()
-            .split('\n')
-            .map(|e| e.trim())
-            .filter(|e| e.len() > 1)
-            .map(|e| {
-                let (state, filename) = e.split_at(1);
-                Entry {
-                    filename: String::from(filename.trim()),
-                    selected: false,
-                    state: str_to_state(state),
-                }
-            })
-            .collect();
-        Ok(files)
+    fn get_current_changed_files(&self) -> BackendResult<Vec<Entry>> {
+        let output =
+            handle_command(self.command().args(["status", "--copies"]))?;
+        Ok(parse_status(&output))
     }
 
     fn get_revision_changed_files(
         &self,
         target: &str,
-    ) -> Result<Vec<Entry>, String> {
+    ) -> BackendResult<Vec<Entry>> {
         let output = handle_command(
-            self.command().arg("status").arg("--change").arg(target),
+            self.command()
+                .args(["status", "--copies", "--change"])
+                .arg(target),
         )?;
+        Ok(parse_status(&output))
+    }
 
-        let files = output
-            .trim()
-            .split('\n')
-            .map(|e| e.trim())
-            .filter(|e| e.len() > 1)
-            .map(|e| {
-                let (state, filename) = e.split_at(1);
-                Entry {
-                    filename: String::from(filename.trim()),
-                    selected: false,
-                    state: str_to_state(state),
-                }
-            })
-            .collect();
-        Ok(files)
+    fn get_remotes(&self) -> BackendResult<Vec<String>> {
+        let output = handle_command(self.command().args(["paths", "-q"]))?;
+        Ok(output.lines().map(String::from).collect())
+    }
+
+    fn get_branch_names(&self) -> BackendResult<Vec<String>> {
+        let output = handle_command(self.command().args([
+            "branches",
+            "--template",
+            "{branch}\n",
+        ]))?;
+        let mut names: Vec<_> = output.lines().map(String::from).collect();
+        for bookmark in bookmark_names(self)? {
+            if !names.contains(&bookmark) {
+                names.push(bookmark);
+            }
+        }
+        Ok(names)
+    }
+
+    fn get_current_branch(&self) -> BackendResult<String> {
+        let output = handle_command(self.command().arg("branch"))?;
+        Ok(output.trim().into())
+    }
+
+    fn has_upstream(&self) -> bool {
+        true
     }
 
-    fn version(&self) -> Result<String, String> {
+    fn version(&self) -> BackendResult<String> {
         handle_command(self.command().arg("--version"))
     }
 
-    fn status(&self) -> Box<dyn ActionTask> {
+    fn status(
+        &self,
+        options: StatusOptions,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
         let mut tasks = task_vec();
         tasks.push(task(self, |command| {
-            command.args(&["summary", "--color", "always"]);
+            command.args(["summary", "--color", "always"]);
         }));
+        let collapse = !options.show_ignored
+            && !options.skip_untracked
+            && !options.expand_untracked;
         tasks.push(task(self, |command| {
-            command.args(&["status", "--color", "always"]);
+            command.args(["status", "--copies", "--color", "always"]);
+            if options.show_ignored && options.skip_untracked {
+                command.arg("-mardi");
+            } else if options.show_ignored {
+                command.arg("-mardui");
+            } else if options.skip_untracked || collapse {
+                command.arg("-mard");
+            }
+            if let Some(scope) = scope {
+                command.arg("--").arg(scope);
+            }
         }));
+        if collapse {
+            let unknown = task(self, |command| {
+                command.args(["status", "--unknown", "--no-status"]);
+                if let Some(scope) = scope {
+                    command.arg("--").arg(scope);
+                }
+            });
+            let root = PathBuf::from(self.get_root());
+            // it reads the directories, which could stall the ui
+            tasks.push(map_result_in_thread(unknown, move |mut result| {
+                if result.success {
+                    result.output = collapse_untracked(&root, &result.output);
+                }
+                result
+            }));
+        }
         parallel(tasks)
     }
 
     fn current_export(&self) -> Box<dyn ActionTask> {
         task(self, |command| {
-            command.args(&["export", "--color", "always"]);
+            command.args(["export", "--color", "always"]);
         })
     }
 
-    fn log(&self, count: usize) -> Box<dyn ActionTask> {
+    fn log(
+        &self,
+        count: usize,
+        scope: Option<&str>,
+        branch: Option<&str>,
+    ) -> Box<dyn ActionTask> {
         task(self, |command| {
-            let count_str = format!("{}", count);
-            let template = "\x1e{node|short}\x1e{date|shortdate}\x1e{author|person}\x1e{ifeq(phase,'secret','(secret) ','')}{ifeq(phase,'draft','(draft) ','')}{if(topics,'[{topics}] ')}{tags % '{tag} '}{branch}\x1e{desc|firstline|strip}";
-            command
-                .arg("log")
-                .arg("--config")
-                .arg("experimental.graphshorten=True")
-                .arg("--graph")
-                .arg("--template")
-                .arg(template)
-                .arg("-l")
-                .arg(&count_str);
+            log_args(command, Some(count), scope, branch)
         })
     }
 
-    fn current_diff_all(&self) -> Box<dyn ActionTask> {
+    fn log_command(
+        &self,
+        scope: Option<&str>,
+        branch: Option<&str>,
+    ) -> Option<Command> {
+        let mut command = self.command();
+        log_args(&mut command, None, scope, branch);
+        Some(command)
+    }
+
+    fn current_diff_all(&self, scope: Option<&str>) -> Box<dyn ActionTask> {
         task(self, |command| {
             command.arg("diff").arg("--color").arg("always");
+            if let Some(scope) = scope {
+                command.arg("--").arg(scope);
+            }
         })
     }
 
-    fn current_diff_selected(
-        &self,
-        entries: &Vec<Entry>,
-    ) -> Box<dyn ActionTask> {
+    fn current_diff_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask> {
         task(self, |command| {
             command.arg("diff").arg("--color").arg("always").arg("--");
             for e in entries.iter().filter(|e| e.selected) {
@@ -147,18 +364,30 @@ impl<'a> VersionControlActions for HgActions {
         })
     }
 
-    fn revision_changes(&self, target: &str) -> Box<dyn ActionTask> {
+    fn revision_changes(
+        &self,
+        target: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
         task(self, |command| {
             command
                 .arg("status")
+                .arg("--copies")
                 .arg("--change")
                 .arg(target)
                 .arg("--color")
                 .arg("always");
+            if let Some(scope) = scope {
+                command.arg("--").arg(scope);
+            }
         })
     }
 
-    fn revision_diff_all(&self, target: &str) -> Box<dyn ActionTask> {
+    fn revision_diff_all(
+        &self,
+        target: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
         task(self, |command| {
             command
                 .arg("diff")
@@ -166,13 +395,16 @@ impl<'a> VersionControlActions for HgActions {
                 .arg(target)
                 .arg("--color")
                 .arg("always");
+            if let Some(scope) = scope {
+                command.arg("--").arg(scope);
+            }
         })
     }
 
     fn revision_diff_selected(
         &self,
         target: &str,
-        entries: &Vec<Entry>,
+        entries: &[Entry],
     ) -> Box<dyn ActionTask> {
         task(self, |command| {
             command
@@ -189,7 +421,79 @@ impl<'a> VersionControlActions for HgActions {
         })
     }
 
-    fn commit_all(&self, message: &str) -> Box<dyn ActionTask> {
+    fn base_changes(
+        &self,
+        base: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command
+                .args(["status", "--rev"])
+                .arg(base)
+                .args(["--color", "always"]);
+            if let Some(scope) = scope {
+                command.arg("--").arg(scope);
+            }
+        })
+    }
+
+    fn base_diff_all(
+        &self,
+        base: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command
+                .args(["diff", "--rev"])
+                .arg(base)
+                .args(["--color", "always"]);
+            if let Some(scope) = scope {
+                command.arg("--").arg(scope);
+            }
+        })
+    }
+
+    fn supports_notes(&self) -> bool {
+        false
+    }
+
+    fn get_note(
+        &self,
+        _target: &str,
+        _notes_ref: &str,
+    ) -> BackendResult<String> {
+        Err("hg does not support notes".into())
+    }
+
+    fn revision_signature(&self, _target: &str) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_ok(String::new()))
+    }
+
+    fn revision_notes(
+        &self,
+        _target: &str,
+        _notes_ref: &str,
+    ) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_ok(String::new()))
+    }
+
+    fn set_note(
+        &self,
+        _target: &str,
+        _notes_ref: &str,
+        _message: &str,
+    ) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err("hg does not support notes"))
+    }
+
+    fn commit_all(
+        &self,
+        message: &str,
+        options: CommitOptions,
+    ) -> Box<dyn ActionTask> {
+        if options.sign {
+            return ready(ActionResult::from_err(SIGN_UNSUPPORTED));
+        }
         task(self, |command| {
             command
                 .arg("commit")
@@ -204,8 +508,12 @@ impl<'a> VersionControlActions for HgActions {
     fn commit_selected(
         &self,
         message: &str,
-        entries: &Vec<Entry>,
+        entries: &[Entry],
+        options: CommitOptions,
     ) -> Box<dyn ActionTask> {
+        if options.sign {
+            return ready(ActionResult::from_err(SIGN_UNSUPPORTED));
+        }
         let mut tasks = task_vec();
         let mut files_to_commit = Vec::new();
         for e in entries.iter().filter(|e| e.selected) {
@@ -221,6 +529,11 @@ impl<'a> VersionControlActions for HgActions {
                 _ => (),
             }
             files_to_commit.push(&e.filename);
+            // so the removal of the source goes with it
+            if let (State::Renamed, Some(source)) = (&e.state, &e.renamed_from)
+            {
+                files_to_commit.push(source);
+            }
         }
         tasks.push(task(self, |command| {
             command
@@ -239,15 +552,15 @@ impl<'a> VersionControlActions for HgActions {
     fn revert_all(&self) -> Box<dyn ActionTask> {
         let mut tasks = task_vec();
         tasks.push(task(self, |command| {
-            command.args(&["revert", "-C", "--all"]);
+            command.args(["revert", "-C", "--all"]);
         }));
         tasks.push(task(self, |command| {
-            command.args(&["purge"]);
+            command.args(["purge"]);
         }));
         serial(tasks)
     }
 
-    fn revert_selected(&self, entries: &Vec<Entry>) -> Box<dyn ActionTask> {
+    fn revert_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask> {
         let mut tasks = task_vec();
         let mut files_to_revert = Vec::new();
         for e in entries.iter().filter(|e| e.selected) {
@@ -257,8 +570,12 @@ impl<'a> VersionControlActions for HgActions {
                 })),
                 _ => files_to_revert.push(&e.filename),
             }
+            if let (State::Renamed, Some(source)) = (&e.state, &e.renamed_from)
+            {
+                files_to_revert.push(source);
+            }
         }
-        if files_to_revert.len() > 0 {
+        if !files_to_revert.is_empty() {
             tasks.push(task(self, |command| {
                 command.arg("revert").arg("-C").arg("--color").arg("always");
                 for file in files_to_revert {
@@ -269,6 +586,83 @@ impl<'a> VersionControlActions for HgActions {
         parallel(tasks)
     }
 
+    fn create_discard_snapshot(&self) -> BackendResult<()> {
+        let name = discard_snapshot_name();
+        match handle_command(
+            self.command()
+                .args(["--config", SHELVE_CONFIG, "shelve", "--addremove"])
+                .arg("--name")
+                .arg(&name),
+        ) {
+            Ok(_) => (),
+            Err(error) if error.stderr.contains("nothing changed") => {
+                return Ok(())
+            }
+            Err(error) => return Err(error),
+        }
+
+        handle_command(
+            self.command()
+                .args(["--config", SHELVE_CONFIG, "unshelve", "--keep"])
+                .arg(&name),
+        )
+        .map(|_| ())
+    }
+
+    fn prune_discard_snapshots(&self, max_age: Duration) -> BackendResult<()> {
+        let output = handle_command(self.command().args([
+            "--config",
+            SHELVE_CONFIG,
+            "shelve",
+            "--list",
+            "--quiet",
+        ]))?;
+
+        for name in output.lines().map(|l| l.trim()) {
+            match discard_snapshot_age(name) {
+                Some(age) if age > max_age => {
+                    handle_command(
+                        self.command()
+                            .args(["--config", SHELVE_CONFIG, "shelve"])
+                            .arg("--delete")
+                            .arg(name),
+                    )?;
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn restore_discard_snapshot(&self) -> Box<dyn ActionTask> {
+        let output = handle_command(self.command().args([
+            "--config",
+            SHELVE_CONFIG,
+            "shelve",
+            "--list",
+            "--quiet",
+        ]))
+        .unwrap_or_default();
+        let name = output
+            .lines()
+            .map(|l| l.trim())
+            .filter_map(|l| discard_snapshot_age(l).map(|age| (age, l)))
+            .min_by_key(|(age, _)| *age)
+            .map(|(_, name)| name);
+
+        match name {
+            Some(name) => task(self, |command| {
+                command
+                    .args(["--config", SHELVE_CONFIG, "unshelve", "--keep"])
+                    .arg(name);
+            }),
+            None => {
+                ready(ActionResult::from_err("no discard snapshot to restore"))
+            }
+        }
+    }
+
     fn update(&self, target: &str) -> Box<dyn ActionTask> {
         task(self, |command| {
             command.arg("update").arg(target);
@@ -283,19 +677,19 @@ impl<'a> VersionControlActions for HgActions {
 
     fn conflicts(&self) -> Box<dyn ActionTask> {
         task(self, |command| {
-            command.args(&["resolve", "-l", "--color", "always"]);
+            command.args(["resolve", "-l", "--color", "always"]);
         })
     }
 
     fn take_other(&self) -> Box<dyn ActionTask> {
         task(self, |command| {
-            command.args(&["resolve", "-a", "-t", "internal:other"]);
+            command.args(["resolve", "-a", "-t", "internal:other"]);
         })
     }
 
     fn take_local(&self) -> Box<dyn ActionTask> {
         task(self, |command| {
-            command.args(&["resolve", "-a", "-t", "internal:local"]);
+            command.args(["resolve", "-a", "-t", "internal:local"]);
         })
     }
 
@@ -311,10 +705,58 @@ impl<'a> VersionControlActions for HgActions {
 
     fn push(&self) -> Box<dyn ActionTask> {
         task(self, |command| {
-            command.args(&["push", "--new-branch"]);
+            command.args(["push", "--new-branch"]);
+        })
+    }
+
+    fn push_to(
+        &self,
+        remote: &str,
+        branch: &str,
+        _set_upstream: bool,
+    ) -> Box<dyn ActionTask> {
+        let flag = if is_bookmark(self, branch) {
+            "-B"
+        } else {
+            "-b"
+        };
+        task(self, |command| {
+            command
+                .arg("push")
+                .arg("--new-branch")
+                .arg(flag)
+                .arg(branch)
+                .arg(remote);
         })
     }
 
+    /// There's no lease, so it's refused while the remote has changesets
+    /// that weren't pulled yet instead, which `--force` would leave there as
+    /// extra heads
+    fn force_push(&self) -> Box<dyn ActionTask> {
+        // exits with 1 when there's nothing incoming
+        let incoming = task_allowing_exit_one(self, |command| {
+            command
+                .args(["incoming", "--quiet", "--template"])
+                .arg("{node|short} {desc|firstline}\n");
+        });
+        let mut tasks = task_vec();
+        tasks.push(map_result(incoming, |result| {
+            if !result.success || result.output.trim().is_empty() {
+                return result;
+            }
+            ActionResult::from_err(format!(
+                "lease failed: remote has changesets not pulled yet\n\
+                 pull and review them before force pushing\n\n{}",
+                result.output
+            ))
+        }));
+        tasks.push(task(self, |command| {
+            command.args(["push", "--new-branch", "--force"]);
+        }));
+        serial_until_error(tasks)
+    }
+
     fn create_tag(&self, name: &str) -> Box<dyn ActionTask> {
         task(self, |command| {
             command.arg("tag").arg(name).arg("-f");
@@ -322,8 +764,20 @@ impl<'a> VersionControlActions for HgActions {
     }
 
     fn list_branches(&self) -> Box<dyn ActionTask> {
-        task(self, |command| {
-            command.args(&["branches", "--template", "{branch}\n"]);
+        let mut tasks = task_vec();
+        tasks.push(task(self, |command| {
+            command.args(["branches", "--template", "{branch}\n"]);
+        }));
+        tasks.push(task(self, |command| {
+            command
+                .args(["bookmarks", "--template"])
+                .arg(format!("{{bookmark}}{}\n", BOOKMARK_SUFFIX));
+        }));
+        map_result(serial(tasks), |mut result| {
+            let lines: Vec<_> =
+                result.output.lines().filter(|l| !l.is_empty()).collect();
+            result.output = lines.join("\n");
+            result
         })
     }
 
@@ -333,14 +787,22 @@ impl<'a> VersionControlActions for HgActions {
         })
     }
 
+    /// Deletes the bookmark when there's one called `name`, closing the
+    /// branch otherwise
     fn close_branch(&self, name: &str) -> Box<dyn ActionTask> {
+        if is_bookmark(self, name) {
+            return task(self, |command| {
+                command.args(["bookmarks", "--delete"]).arg(name);
+            });
+        }
+
         let changeset =
-            handle_command(self.command().args(&["identify", "--num"])).ok();
+            handle_command(self.command().args(["identify", "--num"])).ok();
 
         let mut tasks = task_vec();
         tasks.push(self.update(name));
         tasks.push(task(self, |command| {
-            command.args(&[
+            command.args([
                 "commit",
                 "-m",
                 "\"close branch\"",
@@ -352,4 +814,61 @@ impl<'a> VersionControlActions for HgActions {
         }
         serial(tasks)
     }
+
+    fn supports_phases(&self) -> bool {
+        true
+    }
+
+    fn get_phases(&self, target: &str) -> BackendResult<Vec<String>> {
+        let output = handle_command(
+            self.command()
+                .args(["log", "--template", "{phase}\n", "-r"])
+                .arg(target),
+        )?;
+        Ok(output.lines().map(String::from).collect())
+    }
+
+    fn set_phase(
+        &self,
+        target: &str,
+        phase: &str,
+        force: bool,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("phase");
+            if force {
+                command.arg("--force");
+            }
+            command.arg(format!("--{}", phase)).arg("-r").arg(target);
+        })
+    }
+
+    fn supports_bookmarks(&self) -> bool {
+        true
+    }
+
+    fn create_bookmark(&self, name: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("bookmarks").arg(name);
+        })
+    }
+
+    fn ignore(&self, pattern: &str) -> Box<dyn ActionTask> {
+        append_to_root_file(self, ".hgignore", &format!("glob:{}", pattern))
+    }
+
+    /// Lines of changed files start with their state, unlike the ones of
+    /// the summary, and the ignored ones don't count
+    fn changed_files_badge(&self, status: &str) -> Option<String> {
+        let count = status
+            .lines()
+            .map(strip_ansi)
+            .filter(|line| {
+                let mut chars = line.chars();
+                matches!(chars.next(), Some('M' | 'A' | 'R' | 'D' | '!' | '?'))
+                    && chars.next() == Some(' ')
+            })
+            .count();
+        Some(changed_files_text(count))
+    }
 }
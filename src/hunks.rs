@@ -0,0 +1,101 @@
+use crate::tui_util::{diff_header_path, HunkLines};
+
+/// A single `@@` section of a unified diff together with the header of the
+/// file it belongs to
+pub struct Hunk {
+    pub path: String,
+    file_header: String,
+    text: String,
+}
+
+impl Hunk {
+    /// Short description like `src/main.rs:12 -let a = 1;` pointing at the
+    /// first changed line
+    pub fn summary(&self) -> String {
+        let start: usize = self
+            .text
+            .split(" +")
+            .nth(1)
+            .and_then(|s| s.split([',', ' ']).next())
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0);
+        let context = self
+            .text
+            .lines()
+            .skip(1)
+            .take_while(|l| !l.starts_with('+') && !l.starts_with('-'))
+            .count();
+        let change = self.text.lines().nth(context + 1).unwrap_or("");
+        format!("{}:{} {}", self.path, start + context, change)
+    }
+
+    /// The hunk as a change to the file at `path` when its header renames,
+    /// copies or creates it, so it applies where the file is now
+    pub fn onto_current_path(mut self) -> Self {
+        let moves_file = self.file_header.lines().any(|line| {
+            line.starts_with("rename from ")
+                || line.starts_with("copy from ")
+                || line.starts_with("new file mode ")
+        });
+        if moves_file {
+            self.file_header = format!(
+                "diff --git a/{0} b/{0}\n--- a/{0}\n+++ b/{0}\n",
+                self.path
+            );
+        }
+        self
+    }
+
+    /// Lines of the hunk starting with its `@@` line
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.text.lines()
+    }
+}
+
+/// Splits an uncolored unified diff into its hunks. Files without hunks,
+/// like binary ones, are skipped
+pub fn parse_hunks(diff: &str) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let mut path = String::new();
+    let mut file_header = String::new();
+    let mut hunk_lines = HunkLines::default();
+
+    for line in diff.split_inclusive('\n') {
+        if hunk_lines.start(line) {
+            hunks.push(Hunk {
+                path: path.clone(),
+                file_header: file_header.clone(),
+                text: line.into(),
+            });
+        } else if hunk_lines.next(line) {
+            if let Some(hunk) = hunks.last_mut() {
+                hunk.text.push_str(line);
+            }
+        } else {
+            if let Some(header_path) = diff_header_path(line) {
+                path = header_path;
+                file_header.clear();
+            }
+            file_header.push_str(line);
+        }
+    }
+
+    hunks
+}
+
+/// Patch holding only `hunks`, which must be in the order they were parsed
+pub fn build_patch<'a, I>(hunks: I) -> String
+where
+    I: IntoIterator<Item = &'a Hunk>,
+{
+    let mut patch = String::new();
+    let mut last_header = None;
+    for hunk in hunks {
+        if last_header != Some(&hunk.file_header) {
+            patch.push_str(&hunk.file_header);
+            last_header = Some(&hunk.file_header);
+        }
+        patch.push_str(&hunk.text);
+    }
+    patch
+}
@@ -1,30 +1,111 @@
-use std::time::Duration;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
-use crossterm::event::{self, KeyCode, KeyEvent, KeyModifiers};
-use rustyline::{error::ReadlineError, Editor};
+use crossterm::event::{self, KeyCode, KeyEvent, KeyModifiers, MouseEvent};
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter,
+    hint::Hinter, validate::Validator, Context, Editor, Helper,
+};
 
-use crate::tui_util::TerminalSize;
+use crate::{terminal_input, tui_util::TerminalSize};
+
+/// Chord characters standing for keys that don't type one themselves.
+/// They live in the unicode private use area so they never clash with text
+const F_KEY_CHAR_BASE: u32 = 0xe000;
+const ALT_KEY_CHAR_BASE: u32 = 0xe100;
+const MAX_F_KEY: u8 = 24;
+
+/// Keys read since last taken, with when they were read. Kept only while
+/// they are shown, from every loop reading the input
+static PRESSED_KEYS: Mutex<Option<Vec<(KeyEvent, Instant)>>> = Mutex::new(None);
 
 pub enum Event {
     None,
     Resize(TerminalSize),
     Key(KeyEvent),
+    Mouse(MouseEvent),
 }
 
 pub fn poll_event() -> Event {
-    if event::poll(Duration::from_millis(10)).unwrap() {
-        match event::read().unwrap() {
+    match poll_raw_event() {
+        Event::Key(key) => Event::Key(normalize_key(key)),
+        event => event,
+    }
+}
+
+/// Polls an event without normalizing keys
+pub fn poll_raw_event() -> Event {
+    if poll(Duration::from_millis(10)).unwrap() {
+        match read().unwrap() {
             event::Event::Resize(width, height) => {
                 Event::Resize(TerminalSize { width, height })
             }
             event::Event::Key(key) => Event::Key(key),
-            _ => Event::None,
+            event::Event::Mouse(mouse) => Event::Mouse(mouse),
         }
     } else {
         Event::None
     }
 }
 
+/// Like `crossterm::event::poll` but parsing the sequences crossterm drops
+/// once `terminal_input` started
+pub fn poll(timeout: Duration) -> crossterm::Result<bool> {
+    terminal_input::poll(timeout).unwrap_or_else(|| event::poll(timeout))
+}
+
+/// Like `crossterm::event::read` but parsing the sequences crossterm drops
+/// once `terminal_input` started
+pub fn read() -> crossterm::Result<event::Event> {
+    let event = terminal_input::read().unwrap_or_else(event::read);
+    if let Ok(event::Event::Key(key)) = &event {
+        if let Some(keys) = PRESSED_KEYS.lock().unwrap().as_mut() {
+            keys.push((normalize_key(*key), Instant::now()));
+        }
+    }
+    event
+}
+
+/// Starts or stops keeping the keys read for `take_pressed_keys`
+pub fn keep_pressed_keys(keep: bool) {
+    *PRESSED_KEYS.lock().unwrap() = if keep { Some(Vec::new()) } else { None };
+}
+
+/// Keys read since last called
+pub fn take_pressed_keys() -> Vec<(KeyEvent, Instant)> {
+    match PRESSED_KEYS.lock().unwrap().as_mut() {
+        Some(keys) => std::mem::take(keys),
+        None => Vec::new(),
+    }
+}
+
+/// Makes modified navigation keys, which terminals speaking the newer
+/// protocols report with their modifiers, behave as the plain ones
+pub fn normalize_key(key: KeyEvent) -> KeyEvent {
+    let modifiers = match key.code {
+        KeyCode::Up
+        | KeyCode::Down
+        | KeyCode::Left
+        | KeyCode::Right
+        | KeyCode::PageUp
+        | KeyCode::PageDown
+        | KeyCode::Home
+        | KeyCode::End
+        | KeyCode::Enter
+        | KeyCode::Backspace
+        | KeyCode::Tab
+        | KeyCode::Esc => KeyModifiers::NONE,
+        _ => key.modifiers,
+    };
+
+    KeyEvent {
+        code: key.code,
+        modifiers,
+    }
+}
+
 pub fn key_to_char(key: KeyEvent) -> Option<char> {
     match key {
         KeyEvent {
@@ -43,9 +124,181 @@ pub fn key_to_char(key: KeyEvent) -> Option<char> {
     }
 }
 
-pub fn read_line(initial: &str) -> Result<String, ReadlineError> {
-    let mut readline = Editor::<()>::new();
-    match readline.readline_with_initial("", (initial, "")) {
+/// Like `key_to_char` but also maps F keys and alt modified characters so
+/// they can be part of key chords
+pub fn key_to_chord_char(key: KeyEvent) -> Option<char> {
+    match key {
+        KeyEvent {
+            code: KeyCode::F(n),
+            modifiers: m,
+        } if m.is_empty() => f_key_char(n),
+        KeyEvent {
+            code: KeyCode::Char(c),
+            modifiers: m,
+        } if m == KeyModifiers::ALT => alt_key_char(c),
+        KeyEvent {
+            code: KeyCode::Char(c),
+            modifiers: m,
+        } if m == KeyModifiers::ALT | KeyModifiers::SHIFT => {
+            alt_key_char(c.to_ascii_uppercase())
+        }
+        key => key_to_char(key),
+    }
+}
+
+fn f_key_char(n: u8) -> Option<char> {
+    if (1..=MAX_F_KEY).contains(&n) {
+        std::char::from_u32(F_KEY_CHAR_BASE + n as u32)
+    } else {
+        None
+    }
+}
+
+fn alt_key_char(c: char) -> Option<char> {
+    if c.is_ascii_graphic() {
+        std::char::from_u32(ALT_KEY_CHAR_BASE + c as u32)
+    } else {
+        None
+    }
+}
+
+/// Parses a key chord written with `<F1>` to `<F24>` for F keys and `<A-x>`
+/// for alt modified characters. Anything else is taken literally
+pub fn parse_key_chord(text: &str) -> String {
+    let mut chord = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let special = if c == '<' {
+            rest.find('>').and_then(|end| {
+                let key = parse_special_key(&rest[1..end])?;
+                Some((key, end + 1))
+            })
+        } else {
+            None
+        };
+
+        match special {
+            Some((key, len)) => {
+                chord.push(key);
+                rest = &rest[len..];
+            }
+            None => {
+                chord.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    chord
+}
+
+fn parse_special_key(name: &str) -> Option<char> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next(), chars.next(), chars.next()) {
+        (Some('A'), Some('-'), Some(c), None)
+        | (Some('a'), Some('-'), Some(c), None) => alt_key_char(c),
+        (Some('F'), ..) | (Some('f'), ..) => {
+            name[1..].parse().ok().and_then(f_key_char)
+        }
+        _ => None,
+    }
+}
+
+/// Displays a chord character the same way it's written in `parse_key_chord`
+pub fn chord_char_name(c: char) -> String {
+    let code = c as u32;
+    if code > F_KEY_CHAR_BASE && code <= F_KEY_CHAR_BASE + MAX_F_KEY as u32 {
+        format!("<F{}>", code - F_KEY_CHAR_BASE)
+    } else if code > ALT_KEY_CHAR_BASE && code < ALT_KEY_CHAR_BASE + 0x80 {
+        let c = (code - ALT_KEY_CHAR_BASE) as u8 as char;
+        format!("<A-{}>", c)
+    } else {
+        c.to_string()
+    }
+}
+
+/// Readable name of a key like `ctrl+n`, `enter` or `G`
+pub fn key_name(key: KeyEvent) -> String {
+    let mut name = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        name.push_str("ctrl+");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        name.push_str("alt+");
+    }
+    match key.code {
+        KeyCode::Char(' ') => name.push_str("space"),
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::SHIFT) => {
+            name.push(c.to_ascii_uppercase())
+        }
+        KeyCode::Char(c) => name.push(c),
+        KeyCode::F(n) => name.push_str(&format!("F{}", n)),
+        KeyCode::Enter => name.push_str("enter"),
+        KeyCode::Esc => name.push_str("esc"),
+        KeyCode::Tab | KeyCode::BackTab => name.push_str("tab"),
+        KeyCode::Backspace => name.push_str("backspace"),
+        KeyCode::Delete => name.push_str("delete"),
+        KeyCode::Up => name.push_str("up"),
+        KeyCode::Down => name.push_str("down"),
+        KeyCode::Left => name.push_str("left"),
+        KeyCode::Right => name.push_str("right"),
+        KeyCode::PageUp => name.push_str("pageup"),
+        KeyCode::PageDown => name.push_str("pagedown"),
+        KeyCode::Home => name.push_str("home"),
+        KeyCode::End => name.push_str("end"),
+        _ => name.push('?'),
+    }
+    name
+}
+
+pub fn chord_name<I>(chord: I) -> String
+where
+    I: IntoIterator<Item = char>,
+{
+    chord.into_iter().map(chord_char_name).collect()
+}
+
+struct Candidates(Vec<String>);
+
+impl Completer for Candidates {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(char::is_whitespace)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let word = &line[start..pos];
+        let candidates = self
+            .0
+            .iter()
+            .filter(|c| c.starts_with(word))
+            .cloned()
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for Candidates {}
+impl Highlighter for Candidates {}
+impl Validator for Candidates {}
+impl Helper for Candidates {}
+
+/// Reads a line completing words from `candidates` on tab
+pub fn read_line(
+    initial: &str,
+    candidates: Vec<String>,
+) -> Result<String, ReadlineError> {
+    let mut readline = Editor::<Candidates>::new();
+    readline.set_helper(Some(Candidates(candidates)));
+    terminal_input::suspend();
+    let line = readline.readline_with_initial("", (initial, ""));
+    terminal_input::resume();
+    match line {
         Ok(line) => Ok(line),
         Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
             Ok("".into())
@@ -53,3 +306,135 @@ pub fn read_line(initial: &str) -> Result<String, ReadlineError> {
         Err(error) => Err(error),
     }
 }
+
+/// Reads lines until an empty one is entered. Each line of `initial` is
+/// offered for editing first. Returns `None` if interrupted.
+pub fn read_lines(initial: &str) -> Result<Option<String>, ReadlineError> {
+    terminal_input::suspend();
+    let lines = read_lines_with_rustyline(initial);
+    terminal_input::resume();
+    lines
+}
+
+fn read_lines_with_rustyline(
+    initial: &str,
+) -> Result<Option<String>, ReadlineError> {
+    let mut readline = Editor::<()>::new();
+    let mut initial_lines = initial.lines();
+    let mut lines = Vec::new();
+    loop {
+        let initial = initial_lines.next().unwrap_or("");
+        match readline.readline_with_initial("", (initial, "")) {
+            Ok(line) => {
+                if line.is_empty() {
+                    break;
+                }
+                lines.push(line);
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(ReadlineError::Interrupted) => return Ok(None),
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(Some(lines.join("\n")))
+}
+
+pub enum MessageEdit {
+    Editing,
+    Submit,
+    Cancel,
+}
+
+/// Multi-line text edited in place, used when the kitty keyboard protocol
+/// tells enter, which breaks the line, from ctrl+enter, which submits
+pub struct MessageEditor {
+    pub lines: Vec<String>,
+    pub line: usize,
+    /// In chars
+    pub column: usize,
+}
+
+impl MessageEditor {
+    pub fn new(initial: &str) -> Self {
+        let mut lines: Vec<String> = initial.lines().map(Into::into).collect();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        let line = lines.len() - 1;
+        let column = lines[line].chars().count();
+        Self {
+            lines,
+            line,
+            column,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n").trim_end().into()
+    }
+
+    fn byte_index(&self) -> usize {
+        let line = &self.lines[self.line];
+        line.char_indices()
+            .nth(self.column)
+            .map_or(line.len(), |(i, _)| i)
+    }
+
+    fn line_len(&self) -> usize {
+        self.lines[self.line].chars().count()
+    }
+
+    pub fn on_key(&mut self, key: KeyEvent) -> MessageEdit {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Enter if control => return MessageEdit::Submit,
+            KeyCode::Esc => return MessageEdit::Cancel,
+            KeyCode::Char('c') if control => return MessageEdit::Cancel,
+            KeyCode::Enter => {
+                let index = self.byte_index();
+                let rest = self.lines[self.line].split_off(index);
+                self.line += 1;
+                self.column = 0;
+                self.lines.insert(self.line, rest);
+            }
+            KeyCode::Backspace if self.column > 0 => {
+                self.column -= 1;
+                let index = self.byte_index();
+                self.lines[self.line].remove(index);
+            }
+            KeyCode::Backspace if self.line > 0 => {
+                let line = self.lines.remove(self.line);
+                self.line -= 1;
+                self.column = self.line_len();
+                self.lines[self.line].push_str(&line);
+            }
+            KeyCode::Delete if self.column < self.line_len() => {
+                let index = self.byte_index();
+                self.lines[self.line].remove(index);
+            }
+            KeyCode::Delete if self.line + 1 < self.lines.len() => {
+                let line = self.lines.remove(self.line + 1);
+                self.lines[self.line].push_str(&line);
+            }
+            KeyCode::Left if self.column > 0 => self.column -= 1,
+            KeyCode::Right if self.column < self.line_len() => self.column += 1,
+            KeyCode::Up if self.line > 0 => {
+                self.line -= 1;
+                self.column = self.column.min(self.line_len());
+            }
+            KeyCode::Down if self.line + 1 < self.lines.len() => {
+                self.line += 1;
+                self.column = self.column.min(self.line_len());
+            }
+            KeyCode::Home => self.column = 0,
+            KeyCode::End => self.column = self.line_len(),
+            KeyCode::Char(c) if !control => {
+                let index = self.byte_index();
+                self.lines[self.line].insert(index, c);
+                self.column += 1;
+            }
+            _ => (),
+        }
+        MessageEdit::Editing
+    }
+}
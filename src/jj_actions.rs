@@ -0,0 +1,593 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+use crate::{
+    action::{
+        ready, serial, serial_until_error, task_vec, ActionKind, ActionResult,
+        ActionTask,
+    },
+    backend_error::BackendResult,
+    select::{Entry, State},
+    version_control_actions::{
+        append_to_root_file, discard_snapshot_age, discard_snapshot_name,
+        handle_command, task, CommitOptions, StatusOptions,
+        VersionControlActions,
+    },
+};
+
+fn str_to_state(s: &str) -> State {
+    match s {
+        "M" => State::Modified,
+        "A" => State::Added,
+        "D" => State::Deleted,
+        "R" => State::Renamed,
+        "C" => State::Copied,
+        _ => State::Modified,
+    }
+}
+
+/// Path a `jj diff --summary` line refers to. Renames and copies are shown
+/// like `dir/{old => new}` and map to the new path plus the original one
+fn summary_path(path: &str) -> (String, Option<String>) {
+    match (path.find('{'), path.find(" => "), path.rfind('}')) {
+        (Some(open), Some(arrow), Some(close)) if open < arrow => {
+            let join = |middle: &str| {
+                let joined = format!(
+                    "{}{}{}",
+                    &path[..open],
+                    middle,
+                    &path[close + 1..]
+                );
+                joined.replace("//", "/")
+            };
+            let old_path = join(&path[open + 1..arrow]);
+            (join(&path[arrow + 4..close]), Some(old_path))
+        }
+        _ => (path.into(), None),
+    }
+}
+
+fn parse_summary(output: &str) -> Vec<Entry> {
+    output
+        .lines()
+        .map(|e| e.trim())
+        .filter(|e| e.len() > 2)
+        .map(|e| {
+            let (state, filename) = e.split_at(1);
+            let (filename, renamed_from) = summary_path(filename.trim());
+            Entry {
+                filename,
+                selected: false,
+                state: str_to_state(state),
+                renamed_from,
+            }
+        })
+        .collect()
+}
+
+/// Arguments of the log, of its first `count` entries or all of them
+fn log_args(
+    command: &mut Command,
+    count: Option<usize>,
+    scope: Option<&str>,
+    branch: Option<&str>,
+) {
+    let template = "\"\x1e\" ++ change_id.short() ++ \"\x1e\x1e\" ++ committer.timestamp().format(\"%Y-%m-%d\") ++ \"\x1e\" ++ author.name() ++ \"\x1e\" ++ bookmarks.join(\" \") ++ \"\x1e\" ++ description.first_line() ++ \"\\n\"";
+    command.arg("log");
+    if let Some(count) = count {
+        command.arg("--limit").arg(count.to_string());
+    }
+    command
+        .arg("-T")
+        .arg(template)
+        .args(branch.map(|b| format!("-r=::{}", b)))
+        .args(scope.map(dir_fileset));
+}
+
+/// Fileset matching exactly `path` relative to the repository root
+fn fileset(path: &str) -> String {
+    format!("root-file:{}", quote(path))
+}
+
+/// Fileset matching everything inside the directory `path` relative to the
+/// repository root
+fn dir_fileset(path: &str) -> String {
+    format!("root:{}", quote(path))
+}
+
+fn quote(path: &str) -> String {
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+const SIGN_UNSUPPORTED: &str =
+    "jj signs commits through its own `signing` config";
+const TAGS_UNSUPPORTED: &str = "jj does not support creating tags";
+const NOTES_UNSUPPORTED: &str = "jj does not support notes";
+/// Lists local bookmarks only, one per line
+const BOOKMARKS_TEMPLATE: &str = "if(remote, \"\", name ++ \"\\n\")";
+
+pub struct JjActions {
+    pub current_dir: String,
+}
+
+impl JjActions {
+    /// Files in `.jj` holding the operation to restore, named after the
+    /// time of the discard
+    fn discard_snapshots(&self) -> Vec<(Duration, PathBuf)> {
+        let dir = Path::new(self.get_root()).join(".jj");
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let age = discard_snapshot_age(entry.file_name().to_str()?)?;
+                Some((age, entry.path()))
+            })
+            .collect()
+    }
+}
+
+impl VersionControlActions for JjActions {
+    fn executable_name(&self) -> &'static str {
+        "jj"
+    }
+
+    fn current_dir(&self) -> &str {
+        &self.current_dir[..]
+    }
+
+    fn flags_subcommand(&self, kind: ActionKind) -> Option<&'static str> {
+        let subcommand = match kind {
+            ActionKind::Fetch | ActionKind::Pull => "git fetch",
+            ActionKind::Push => "git push",
+            ActionKind::CommitAll => "describe",
+            ActionKind::CommitSelected => "commit",
+            ActionKind::Merge => "new",
+            ActionKind::CurrentDiffAll
+            | ActionKind::CurrentDiffSelected
+            | ActionKind::RevisionDiffAll
+            | ActionKind::RevisionDiffSelected => "diff",
+            _ => return None,
+        };
+        Some(subcommand)
+    }
+
+    fn diff_context_flags(&self, lines: usize) -> Vec<String> {
+        vec![format!("--context={}", lines)]
+    }
+
+    fn set_root(&mut self) -> BackendResult<()> {
+        let mut command = self.command();
+        let dir = handle_command(command.arg("root"))?;
+
+        let dir = dir
+            .lines()
+            .next()
+            .expect("root directory is an empty string");
+        self.current_dir = dir.to_owned();
+
+        Ok(())
+    }
+
+    fn get_root(&self) -> &str {
+        &self.current_dir[..]
+    }
+
+    fn get_current_changed_files(&self) -> BackendResult<Vec<Entry>> {
+        let output =
+            handle_command(self.command().args(["diff", "--summary"]))?;
+        Ok(parse_summary(&output))
+    }
+
+    fn get_revision_changed_files(
+        &self,
+        target: &str,
+    ) -> BackendResult<Vec<Entry>> {
+        let output = handle_command(
+            self.command().args(["diff", "--summary", "-r"]).arg(target),
+        )?;
+        Ok(parse_summary(&output))
+    }
+
+    fn get_remotes(&self) -> BackendResult<Vec<String>> {
+        let output =
+            handle_command(self.command().args(["git", "remote", "list"]))?;
+        Ok(output
+            .lines()
+            .filter_map(|l| l.split_whitespace().next())
+            .map(String::from)
+            .collect())
+    }
+
+    fn get_branch_names(&self) -> BackendResult<Vec<String>> {
+        let output = handle_command(self.command().args([
+            "bookmark",
+            "list",
+            "-T",
+            BOOKMARKS_TEMPLATE,
+        ]))?;
+        Ok(output.lines().map(String::from).collect())
+    }
+
+    /// The closest bookmark among the ancestors of the working copy
+    fn get_current_branch(&self) -> BackendResult<String> {
+        let output = handle_command(self.command().args([
+            "log",
+            "--no-graph",
+            "-r",
+            "latest(::@ & bookmarks())",
+            "-T",
+            "local_bookmarks.map(|b| b.name()).join(\"\\n\")",
+        ]))?;
+        Ok(output.lines().next().unwrap_or("").into())
+    }
+
+    fn has_upstream(&self) -> bool {
+        true
+    }
+
+    fn version(&self) -> BackendResult<String> {
+        handle_command(self.command().arg("--version"))
+    }
+
+    fn status(
+        &self,
+        _options: StatusOptions,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["status", "--color", "always"]);
+            command.args(scope.map(dir_fileset));
+        })
+    }
+
+    fn current_export(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["show", "--git", "--color", "always"]);
+        })
+    }
+
+    fn log(
+        &self,
+        count: usize,
+        scope: Option<&str>,
+        branch: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            log_args(command, Some(count), scope, branch)
+        })
+    }
+
+    fn log_command(
+        &self,
+        scope: Option<&str>,
+        branch: Option<&str>,
+    ) -> Option<Command> {
+        let mut command = self.command();
+        log_args(&mut command, None, scope, branch);
+        Some(command)
+    }
+
+    fn current_diff_all(&self, scope: Option<&str>) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["diff", "--git", "--color", "always"]);
+            command.args(scope.map(dir_fileset));
+        })
+    }
+
+    fn current_diff_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["diff", "--git", "--color", "always"]);
+            for e in entries.iter().filter(|e| e.selected) {
+                command.arg(fileset(&e.filename));
+            }
+        })
+    }
+
+    fn revision_changes(
+        &self,
+        target: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command
+                .args(["diff", "--summary", "--color", "always", "-r"])
+                .arg(target)
+                .args(scope.map(dir_fileset));
+        })
+    }
+
+    fn revision_diff_all(
+        &self,
+        target: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command
+                .args(["diff", "--git", "--color", "always", "-r"])
+                .arg(target)
+                .args(scope.map(dir_fileset));
+        })
+    }
+
+    fn revision_diff_selected(
+        &self,
+        target: &str,
+        entries: &[Entry],
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command
+                .args(["diff", "--git", "--color", "always", "-r"])
+                .arg(target);
+            for e in entries.iter().filter(|e| e.selected) {
+                command.arg(fileset(&e.filename));
+            }
+        })
+    }
+
+    fn base_changes(
+        &self,
+        base: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command
+                .args(["diff", "--summary", "--color", "always", "--from"])
+                .arg(base)
+                .args(scope.map(dir_fileset));
+        })
+    }
+
+    fn base_diff_all(
+        &self,
+        base: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command
+                .args(["diff", "--git", "--color", "always", "--from"])
+                .arg(base)
+                .args(scope.map(dir_fileset));
+        })
+    }
+
+    fn supports_notes(&self) -> bool {
+        false
+    }
+
+    fn get_note(
+        &self,
+        _target: &str,
+        _notes_ref: &str,
+    ) -> BackendResult<String> {
+        Err(NOTES_UNSUPPORTED.into())
+    }
+
+    fn revision_signature(&self, _target: &str) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_ok(String::new()))
+    }
+
+    fn revision_notes(
+        &self,
+        _target: &str,
+        _notes_ref: &str,
+    ) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_ok(String::new()))
+    }
+
+    fn set_note(
+        &self,
+        _target: &str,
+        _notes_ref: &str,
+        _message: &str,
+    ) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(NOTES_UNSUPPORTED))
+    }
+
+    /// Describes the working copy change and starts a new one on top of it
+    fn commit_all(
+        &self,
+        message: &str,
+        options: CommitOptions,
+    ) -> Box<dyn ActionTask> {
+        if options.sign {
+            return ready(ActionResult::from_err(SIGN_UNSUPPORTED));
+        }
+        let mut tasks = task_vec();
+        tasks.push(task(self, |command| {
+            command.arg("describe").arg("-m").arg(message);
+        }));
+        tasks.push(task(self, |command| {
+            command.arg("new");
+        }));
+        serial(tasks)
+    }
+
+    /// Splits the selected files out of the working copy change into a
+    /// new commit. The remaining changes stay in the working copy
+    fn commit_selected(
+        &self,
+        message: &str,
+        entries: &[Entry],
+        options: CommitOptions,
+    ) -> Box<dyn ActionTask> {
+        if options.sign {
+            return ready(ActionResult::from_err(SIGN_UNSUPPORTED));
+        }
+        task(self, |command| {
+            command.arg("commit").arg("-m").arg(message);
+            for e in entries.iter().filter(|e| e.selected) {
+                command.arg(fileset(&e.filename));
+            }
+        })
+    }
+
+    fn revert_all(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("restore");
+        })
+    }
+
+    fn revert_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("restore");
+            for e in entries.iter().filter(|e| e.selected) {
+                command.arg(fileset(&e.filename));
+            }
+        })
+    }
+
+    /// Records the operation holding the changes about to be discarded.
+    /// `status` snapshots the working copy into it first
+    fn create_discard_snapshot(&self) -> BackendResult<()> {
+        handle_command(self.command().arg("status"))?;
+        let operation = handle_command(self.command().args([
+            "op",
+            "log",
+            "--no-graph",
+            "--limit",
+            "1",
+            "-T",
+            "id",
+        ]))?;
+
+        let path = Path::new(self.get_root())
+            .join(".jj")
+            .join(discard_snapshot_name());
+        fs::write(path, operation.trim()).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn prune_discard_snapshots(&self, max_age: Duration) -> BackendResult<()> {
+        for (age, path) in self.discard_snapshots() {
+            if age > max_age {
+                fs::remove_file(&path).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores the repository to the operation recorded before the last
+    /// discard, which also undoes anything done since
+    fn restore_discard_snapshot(&self) -> Box<dyn ActionTask> {
+        let operation = self
+            .discard_snapshots()
+            .into_iter()
+            .min_by_key(|(age, _)| *age)
+            .and_then(|(_, path)| fs::read_to_string(path).ok());
+        match operation {
+            Some(operation) => task(self, |command| {
+                command.args(["op", "restore"]).arg(operation.trim());
+            }),
+            None => {
+                ready(ActionResult::from_err("no discard snapshot to restore"))
+            }
+        }
+    }
+
+    fn update(&self, target: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("new").arg(target);
+        })
+    }
+
+    fn merge(&self, target: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.arg("new").arg("@").arg(target);
+        })
+    }
+
+    fn conflicts(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["resolve", "--list", "--color", "always"]);
+        })
+    }
+
+    fn take_other(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["resolve", "--tool", ":theirs"]);
+        })
+    }
+
+    fn take_local(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["resolve", "--tool", ":ours"]);
+        })
+    }
+
+    fn fetch(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["git", "fetch"]);
+        })
+    }
+
+    /// Fetches and rebases the local commits of the working copy branch
+    /// onto the trunk, like `git pull --rebase`
+    fn pull(&self) -> Box<dyn ActionTask> {
+        let mut tasks = task_vec();
+        tasks.push(self.fetch());
+        tasks.push(task(self, |command| {
+            command.args(["rebase", "-b", "@", "-d", "trunk()"]);
+        }));
+        serial_until_error(tasks)
+    }
+
+    fn push(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["git", "push"]);
+        })
+    }
+
+    fn push_to(
+        &self,
+        remote: &str,
+        branch: &str,
+        _set_upstream: bool,
+    ) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command
+                .args(["git", "push", "--remote"])
+                .arg(remote)
+                .arg("--bookmark")
+                .arg(branch);
+        })
+    }
+
+    /// jj refuses to push when a remote bookmark moved since the last fetch
+    /// so a regular push already behaves like a force push with lease
+    fn force_push(&self) -> Box<dyn ActionTask> {
+        self.push()
+    }
+
+    fn create_tag(&self, _name: &str) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(TAGS_UNSUPPORTED))
+    }
+
+    fn list_branches(&self) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["bookmark", "list", "-T", BOOKMARKS_TEMPLATE]);
+        })
+    }
+
+    fn create_branch(&self, name: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["bookmark", "create", "-r", "@"]).arg(name);
+        })
+    }
+
+    fn close_branch(&self, name: &str) -> Box<dyn ActionTask> {
+        task(self, |command| {
+            command.args(["bookmark", "delete"]).arg(name);
+        })
+    }
+
+    fn ignore(&self, pattern: &str) -> Box<dyn ActionTask> {
+        append_to_root_file(self, ".gitignore", pattern)
+    }
+}
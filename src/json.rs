@@ -0,0 +1,244 @@
+use std::{collections::HashMap, iter::Peekable, str::Chars};
+
+/// Arrays and objects nested deeper than this are rejected so a request
+/// can't overflow the stack
+const MAX_DEPTH: usize = 64;
+
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// Number as written, so ids are echoed back unchanged
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(HashMap<String, Value>),
+}
+
+/// Json string literal holding `text`
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => {
+                quoted.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl Value {
+    /// The number when it's an unsigned integer
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Value::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a json object, `None` when it's invalid or followed by anything
+/// other than whitespace
+pub fn parse_object(text: &str) -> Option<HashMap<String, Value>> {
+    let mut chars = text.chars().peekable();
+    expect(&mut chars, '{')?;
+    let values = parse_members(&mut chars, 1)?;
+    match skip_whitespace(&mut chars) {
+        None => Some(values),
+        Some(_) => None,
+    }
+}
+
+/// Parses the rest of an object whose opening brace was already consumed
+fn parse_members(
+    chars: &mut Peekable<Chars>,
+    depth: usize,
+) -> Option<HashMap<String, Value>> {
+    let mut values = HashMap::new();
+    if skip_whitespace(chars) == Some('}') {
+        chars.next();
+        return Some(values);
+    }
+
+    loop {
+        expect(chars, '"')?;
+        let key = parse_string(chars)?;
+        expect(chars, ':')?;
+        let value = parse_value(chars, depth)?;
+        values.insert(key, value);
+
+        match skip_whitespace(chars) {
+            Some(',') => {
+                chars.next();
+            }
+            Some('}') => {
+                chars.next();
+                return Some(values);
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Parses the rest of an array whose opening bracket was already consumed
+fn parse_elements(
+    chars: &mut Peekable<Chars>,
+    depth: usize,
+) -> Option<Vec<Value>> {
+    let mut values = Vec::new();
+    if skip_whitespace(chars) == Some(']') {
+        chars.next();
+        return Some(values);
+    }
+
+    loop {
+        values.push(parse_value(chars, depth)?);
+        match skip_whitespace(chars) {
+            Some(',') => {
+                chars.next();
+            }
+            Some(']') => {
+                chars.next();
+                return Some(values);
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) -> Option<char> {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    chars.peek().copied()
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Option<()> {
+    skip_whitespace(chars);
+    chars.next().filter(|&c| c == expected).map(|_| ())
+}
+
+/// Parses a value inside `depth` arrays or objects
+fn parse_value(chars: &mut Peekable<Chars>, depth: usize) -> Option<Value> {
+    match skip_whitespace(chars)? {
+        '"' => {
+            chars.next();
+            parse_string(chars).map(Value::String)
+        }
+        '[' if depth < MAX_DEPTH => {
+            chars.next();
+            parse_elements(chars, depth + 1).map(Value::Array)
+        }
+        '{' if depth < MAX_DEPTH => {
+            chars.next();
+            parse_members(chars, depth + 1).map(Value::Object)
+        }
+        '[' | '{' => None,
+        c if c == '-' || c.is_ascii_digit() => {
+            parse_number(chars).map(Value::Number)
+        }
+        _ => {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(char::is_ascii_alphabetic) {
+                word.push(c);
+            }
+            match &word[..] {
+                "null" => Some(Value::Null),
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Parses a number like `-12.5e3`, without leading zeros
+fn parse_number(chars: &mut Peekable<Chars>) -> Option<String> {
+    fn push_digits(chars: &mut Peekable<Chars>, number: &mut String) -> bool {
+        let len = number.len();
+        while let Some(c) = chars.next_if(char::is_ascii_digit) {
+            number.push(c);
+        }
+        number.len() > len
+    }
+
+    let mut number = String::new();
+    if let Some(c) = chars.next_if_eq(&'-') {
+        number.push(c);
+    }
+    if let Some(c) = chars.next_if_eq(&'0') {
+        number.push(c);
+    } else if !push_digits(chars, &mut number) {
+        return None;
+    }
+    if let Some(c) = chars.next_if_eq(&'.') {
+        number.push(c);
+        if !push_digits(chars, &mut number) {
+            return None;
+        }
+    }
+    if let Some(c) = chars.next_if(|&c| c == 'e' || c == 'E') {
+        number.push(c);
+        if let Some(c) = chars.next_if(|&c| c == '+' || c == '-') {
+            number.push(c);
+        }
+        if !push_digits(chars, &mut number) {
+            return None;
+        }
+    }
+    Some(number)
+}
+
+/// Parses the rest of a string whose opening quote was already consumed
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut string = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(string),
+            '\\' => match chars.next()? {
+                '"' => string.push('"'),
+                '\\' => string.push('\\'),
+                '/' => string.push('/'),
+                'b' => string.push('\u{8}'),
+                'f' => string.push('\u{c}'),
+                'n' => string.push('\n'),
+                't' => string.push('\t'),
+                'r' => string.push('\r'),
+                'u' => string.push(parse_escaped_char(chars)?),
+                _ => return None,
+            },
+            c => string.push(c),
+        }
+    }
+}
+
+/// Parses the hex digits of a `\u` escape, combining a surrogate pair
+/// written as two escapes into one char
+fn parse_escaped_char(chars: &mut Peekable<Chars>) -> Option<char> {
+    let high = parse_hex_code(chars)?;
+    if !(0xd800..0xdc00).contains(&high) {
+        return char::from_u32(high);
+    }
+
+    if chars.next()? != '\\' || chars.next()? != 'u' {
+        return None;
+    }
+    let low = parse_hex_code(chars)?;
+    if !(0xdc00..0xe000).contains(&low) {
+        return None;
+    }
+    char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+}
+
+fn parse_hex_code(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let code: String = chars.by_ref().take(4).collect();
+    if code.len() != 4 || !code.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(&code, 16).ok()
+}
@@ -0,0 +1,173 @@
+use crate::{action::ActionKind, application::Application};
+
+/// Key chord running an action
+pub struct KeyBinding {
+    pub keys: &'static str,
+    /// Action run, which is also the mode the ui shows afterwards
+    pub action: ActionKind,
+}
+
+const fn bind(keys: &'static str, action: ActionKind) -> KeyBinding {
+    KeyBinding { keys, action }
+}
+
+/// Every action reachable from the keyboard, in the groups the help shows
+pub const KEY_BINDINGS: &[&[KeyBinding]] = &[
+    &[
+        bind("h", ActionKind::Help),
+        bind("q", ActionKind::Quit),
+        bind("ctrl+p", ActionKind::CommandPalette),
+    ],
+    &[
+        bind("s", ActionKind::Status),
+        bind("l", ActionKind::Log),
+        bind("LC", ActionKind::LogCount),
+        bind("Lj", ActionKind::LogJump),
+        bind("Lb", ActionKind::LogBranch),
+        bind("Lr", ActionKind::ViewedRevisions),
+        bind("ee", ActionKind::CurrentFullRevision),
+        bind("dd", ActionKind::CurrentDiffAll),
+        bind("ds", ActionKind::CurrentDiffSelected),
+        bind("ep", ActionKind::ExportSelected),
+        bind("!", ActionKind::RunOnSelected),
+        bind("DC", ActionKind::RevisionChanges),
+        bind("DD", ActionKind::RevisionDiffAll),
+        bind("DS", ActionKind::RevisionDiffSelected),
+        bind("DB", ActionKind::BaseChanges),
+        bind("DR", ActionKind::CompareRevisions),
+        bind("DN", ActionKind::RevisionNote),
+        bind("DP", ActionKind::SetPhase),
+        bind("DA", ActionKind::ApplySelectedHunk),
+        bind("J", ActionKind::JumpToFile),
+        bind(":", ActionKind::JumpToLine),
+        bind("z", ActionKind::ExpandFolds),
+        bind("Zf", ActionKind::ToggleFileCollapsed),
+        bind("Zc", ActionKind::CollapseFiles),
+        bind("Ze", ActionKind::ExpandFiles),
+        bind("nn", ActionKind::NoteFiles),
+        bind("nr", ActionKind::RemoveNotes),
+        bind("nl", ActionKind::ListNotes),
+        bind("ne", ActionKind::ExportNotes),
+        bind("np", ActionKind::PinFiles),
+        bind("nu", ActionKind::UnpinFiles),
+        bind("yy", ActionKind::CopyEntry),
+        bind("yd", ActionKind::CopyDiff),
+    ],
+    &[
+        bind("cc", ActionKind::CommitAll),
+        bind("cs", ActionKind::CommitSelected),
+        bind("cp", ActionKind::CommitPinned),
+        bind("u", ActionKind::Update),
+        bind("m", ActionKind::Merge),
+        bind("I", ActionKind::Ignore),
+        bind("RA", ActionKind::RevertAll),
+        bind("rs", ActionKind::RevertSelected),
+        bind("rh", ActionKind::RevertHunks),
+        bind("RH", ActionKind::RevertSelectedHunk),
+        bind("RU", ActionKind::UndoDiscard),
+    ],
+    &[
+        bind("rr", ActionKind::UnresolvedConflicts),
+        bind("re", ActionKind::EditConflicts),
+        bind("ro", ActionKind::MergeTakingOther),
+        bind("rl", ActionKind::MergeTakingLocal),
+    ],
+    &[
+        bind("Oo", ActionKind::InterruptedOperation),
+        bind("Oc", ActionKind::ContinueOperation),
+        bind("Oa", ActionKind::AbortOperation),
+    ],
+    &[
+        bind("f", ActionKind::Fetch),
+        bind("p", ActionKind::Pull),
+        bind("P", ActionKind::Push),
+        bind("FP", ActionKind::ForcePush),
+    ],
+    &[
+        bind("tt", ActionKind::ListTags),
+        bind("tn", ActionKind::NewTag),
+        bind("tr", ActionKind::RenameTag),
+    ],
+    &[
+        bind("bb", ActionKind::ListBranches),
+        bind("bn", ActionKind::NewBranch),
+        bind("bm", ActionKind::NewBookmark),
+        bind("bd", ActionKind::DeleteBranch),
+        bind("bp", ActionKind::PushBranchTo),
+    ],
+    &[
+        bind("gl", ActionKind::ListPullRequests),
+        bind("gc", ActionKind::CheckoutPullRequest),
+        bind("go", ActionKind::OpenInBrowser),
+    ],
+    &[
+        bind("Ss", ActionKind::SetScope),
+        bind("Sc", ActionKind::ClearScope),
+    ],
+    &[
+        bind("Cs", ActionKind::SetCompareTarget),
+        bind("Cc", ActionKind::ClearCompareTarget),
+        bind("Cd", ActionKind::CompareWithTarget),
+    ],
+    &[
+        bind("Ti", ActionKind::ToggleIgnored),
+        bind("Tu", ActionKind::ToggleUntracked),
+        bind("Td", ActionKind::ToggleUntrackedDirs),
+        bind("Tb", ActionKind::ToggleBranchLog),
+        bind("Tk", ActionKind::ToggleShowKeys),
+        bind("Tq", ActionKind::ToggleQuiet),
+        bind("Tl", ActionKind::ToggleLineNumbers),
+        bind("Ts", ActionKind::ToggleSideBySide),
+        bind("Tw", ActionKind::ToggleWrap),
+        bind("Tp", ActionKind::TogglePreview),
+        bind("<", ActionKind::GrowPreview),
+        bind(">", ActionKind::ShrinkPreview),
+    ],
+    &[
+        bind("w", ActionKind::SwitchRepository),
+        bind("x", ActionKind::CustomAction),
+        bind("K", ActionKind::KeyDebug),
+    ],
+];
+
+/// Action run by the chord `keys`, like `dd`
+pub fn action_of_keys(keys: &str) -> Option<ActionKind> {
+    KEY_BINDINGS
+        .iter()
+        .flat_map(|group| group.iter())
+        .find(|b| b.keys == keys)
+        .map(|b| b.action)
+}
+
+/// Keys of the chord running `action`
+pub fn keys_of_action(action: ActionKind) -> Option<&'static str> {
+    KEY_BINDINGS
+        .iter()
+        .flat_map(|group| group.iter())
+        .find(|b| b.action == action)
+        .map(|b| b.keys)
+}
+
+impl KeyBinding {
+    /// Whether the action can run in the repository, as notes need git and
+    /// pull requests need a forge
+    pub fn is_available(&self, app: &Application) -> bool {
+        match self.action {
+            ActionKind::RevisionNote => app.version_control.supports_notes(),
+            ActionKind::NewBookmark => app.version_control.supports_bookmarks(),
+            ActionKind::SetPhase => app.version_control.supports_phases(),
+            ActionKind::ListPullRequests
+            | ActionKind::CheckoutPullRequest
+            | ActionKind::OpenInBrowser => app.forge().is_some(),
+            _ => true,
+        }
+    }
+
+    /// Chord handled by the tui, `None` for keys that aren't chords
+    pub fn chord(&self) -> Option<Vec<char>> {
+        match self.action {
+            ActionKind::CommandPalette => None,
+            _ => Some(self.keys.chars().collect()),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+    sync::{Mutex, OnceLock},
+};
+
+/// Translated ui strings for each locale as a toml table of
+/// `"english" = "translation"`. English is the source language so it needs
+/// no catalog
+const CATALOGS: &[(&str, &str)] =
+    &[("pt-BR", include_str!("../locales/pt-BR.toml"))];
+
+/// Catalog of the locale picked at startup. Unset while showing english
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+struct Catalog {
+    name: &'static str,
+    messages: HashMap<String, String>,
+    /// Strings shown in english for lacking a translation, as when their
+    /// english text changed and the catalog didn't follow
+    missing: Mutex<BTreeSet<String>>,
+}
+
+/// Shows the ui in the locale `name`, like `pt-BR` or `pt_BR`. A bare
+/// language like `pt` picks its first catalog. Unknown locales show english.
+/// The locale is picked once, so switching repositories keeps it
+pub fn set_locale(name: &str) -> Result<(), String> {
+    if CATALOG.get().is_some() {
+        return Ok(());
+    }
+    let name = name.replace('_', "-");
+    let catalog = CATALOGS
+        .iter()
+        .find(|(locale, _)| locale.eq_ignore_ascii_case(&name))
+        .or_else(|| {
+            CATALOGS.iter().find(|(locale, _)| {
+                locale
+                    .split('-')
+                    .next()
+                    .is_some_and(|l| l.eq_ignore_ascii_case(&name))
+            })
+        });
+    let (name, catalog) = match catalog {
+        Some(catalog) => catalog,
+        None => return Ok(()),
+    };
+    let messages = parse_catalog(catalog)
+        .map_err(|e| format!("locale {}: {}", name, e))?;
+    let _ = CATALOG.set(Catalog {
+        name,
+        messages,
+        missing: Mutex::new(BTreeSet::new()),
+    });
+    Ok(())
+}
+
+fn parse_catalog(catalog: &str) -> Result<HashMap<String, String>, String> {
+    let table = match catalog.parse::<toml::Value>() {
+        Ok(toml::Value::Table(table)) => table,
+        Ok(_) => return Err("expected a table of translations".into()),
+        Err(e) => return Err(e.to_string()),
+    };
+    table
+        .into_iter()
+        .map(|(text, translation)| match translation {
+            toml::Value::String(translation) => Ok((text, translation)),
+            _ => Err(format!("expected '{}' to be a string", text)),
+        })
+        .collect()
+}
+
+/// Translation of the ui string `text` or `text` itself if there's none
+pub fn tr(text: &str) -> &str {
+    let catalog = match CATALOG.get() {
+        Some(catalog) => catalog,
+        None => return text,
+    };
+    match catalog.messages.get(text) {
+        Some(translation) => translation,
+        None => {
+            let mut missing = catalog.missing.lock().unwrap();
+            if !missing.contains(text) {
+                missing.insert(text.into());
+            }
+            text
+        }
+    }
+}
+
+/// Name of the locale and the ui strings shown so far that its catalog lacks.
+/// `None` when nothing was missing
+pub fn missing_translations() -> Option<(&'static str, Vec<String>)> {
+    let catalog = CATALOG.get()?;
+    let missing = catalog.missing.lock().unwrap();
+    if missing.is_empty() {
+        return None;
+    }
+    Some((catalog.name, missing.iter().cloned().collect()))
+}
+
+/// Like `tr` but fills each `{}` of the translation with the next of `args`
+pub fn trf(text: &str, args: &[&dyn Display]) -> String {
+    let mut parts = tr(text).split("{}");
+    let mut formatted = String::from(parts.next().unwrap_or_default());
+    let mut args = args.iter();
+    for part in parts {
+        if let Some(arg) = args.next() {
+            formatted.push_str(&arg.to_string());
+        }
+        formatted.push_str(part);
+    }
+    formatted
+}
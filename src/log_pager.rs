@@ -0,0 +1,155 @@
+use std::{
+    io::{self, BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Condvar, Mutex},
+    task::Poll,
+    thread,
+};
+
+use crate::{
+    action::{ActionResult, ActionTask},
+    async_process::{command_line, Executor},
+    backend_error::BackendError,
+};
+
+/// Log read from a single child as more entries are asked for, so loading
+/// more of it never runs it again. Meanwhile the child waits blocked on its
+/// full stdout
+pub struct LogPager {
+    child: Arc<Mutex<Child>>,
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    pages: Mutex<Pages>,
+    /// Wakes the reader when more entries are wanted
+    wanted_changed: Condvar,
+}
+
+#[derive(Default)]
+struct Pages {
+    lines: Vec<String>,
+    entries: usize,
+    /// Entries read before pausing
+    wanted: usize,
+    /// Set once the whole log was read. The error tells why it failed
+    finished: Option<Result<(), BackendError>>,
+}
+
+impl LogPager {
+    pub fn start(mut command: Command) -> io::Result<Self> {
+        let command_line = command_line(&command);
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            // left unread as it could fill while stdout waits
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdout = child.stdout.take();
+        let child = Arc::new(Mutex::new(child));
+        let shared = Arc::new(Shared {
+            pages: Mutex::new(Pages::default()),
+            wanted_changed: Condvar::new(),
+        });
+
+        let thread_child = child.clone();
+        let thread_shared = shared.clone();
+        thread::spawn(move || {
+            let mut reader = stdout.map(BufReader::new);
+            let mut line = Vec::new();
+            while let Some(stdout) = &mut reader {
+                let mut pages = thread_shared.pages.lock().unwrap();
+                while pages.entries >= pages.wanted {
+                    pages = thread_shared.wanted_changed.wait(pages).unwrap();
+                }
+                drop(pages);
+
+                line.clear();
+                match stdout.read_until(b'\n', &mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => (),
+                }
+                let text = String::from_utf8_lossy(&line);
+                let text = text.trim_end_matches(['\n', '\r']);
+                let mut pages = thread_shared.pages.lock().unwrap();
+                // entries are the lines with fields, the others only graph
+                if text.contains('\x1e') {
+                    pages.entries += 1;
+                }
+                pages.lines.push(text.into());
+            }
+
+            let status = thread_child.lock().unwrap().wait();
+            let finished = match status {
+                Ok(status) if status.success() => Ok(()),
+                Ok(status) => Err(BackendError::from_command(
+                    command_line,
+                    status.code(),
+                    String::new(),
+                )),
+                Err(error) => Err(BackendError::from_command(
+                    command_line,
+                    None,
+                    error.to_string(),
+                )),
+            };
+            thread_shared.pages.lock().unwrap().finished = Some(finished);
+        });
+
+        Ok(Self { child, shared })
+    }
+
+    /// Whether `output` is what the last page read showed, so the next one
+    /// continues it
+    pub fn is_showing(&self, output: &str) -> bool {
+        let pages = self.shared.pages.lock().unwrap();
+        output.lines().eq(pages.lines.iter().map(String::as_str))
+    }
+
+    /// Reads the log up to `count` entries, or all of it if it has fewer,
+    /// resulting in every line read so far
+    pub fn read(&self, count: usize) -> Box<dyn ActionTask> {
+        let mut pages = self.shared.pages.lock().unwrap();
+        pages.wanted = pages.wanted.max(count);
+        self.shared.wanted_changed.notify_one();
+        Box::new(PageTask {
+            shared: self.shared.clone(),
+            count,
+        })
+    }
+}
+
+impl Drop for LogPager {
+    fn drop(&mut self) {
+        let _ = self.child.lock().unwrap().kill();
+        // so the reader stops waiting and sees the end of the killed child
+        self.shared.pages.lock().unwrap().wanted = usize::MAX;
+        self.shared.wanted_changed.notify_one();
+    }
+}
+
+struct PageTask {
+    shared: Arc<Shared>,
+    count: usize,
+}
+
+impl ActionTask for PageTask {
+    fn poll(&mut self, _executor: &mut Executor) -> Poll<ActionResult> {
+        let pages = self.shared.pages.lock().unwrap();
+        match &pages.finished {
+            Some(Err(error)) => {
+                Poll::Ready(ActionResult::from_err(error.clone()))
+            }
+            Some(Ok(())) => {
+                Poll::Ready(ActionResult::from_ok(pages.lines.join("\n")))
+            }
+            None if pages.entries >= self.count => {
+                Poll::Ready(ActionResult::from_ok(pages.lines.join("\n")))
+            }
+            None => Poll::Pending,
+        }
+    }
+
+    /// The child keeps paused for the next page
+    fn cancel(&mut self) {}
+}
@@ -1,29 +1,118 @@
 mod action;
 mod application;
+mod args;
+mod askpass;
 mod async_process;
+mod backend_error;
+mod browser;
+mod bzr_actions;
+mod cli;
+mod clipboard;
+mod color_support;
+mod config;
+mod conflict_markers;
 mod custom_actions;
+mod darcs_actions;
+mod encoding;
+mod folds;
+mod forge;
 mod git_actions;
 mod hg_actions;
+mod hunks;
 mod input;
+mod jj_actions;
+mod json;
+mod key_bindings;
+mod locale;
+mod log_pager;
+mod notification;
+mod palette;
+mod preview;
 mod repositories;
 mod scroll_view;
 mod select;
+mod server;
+mod side_by_side;
+mod state;
+mod terminal_input;
+mod theme;
 mod tui;
 mod tui_util;
 mod version_control_actions;
+mod watcher;
+mod word_diff;
 
 fn main() {
+    if askpass::run_helper() {
+        return;
+    }
+
+    let args = match args::Args::parse() {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(2);
+        }
+    };
+
+    if args.version {
+        std::process::exit(cli::version(
+            args.verbose,
+            args.directory.as_deref(),
+        ));
+    }
+
+    if args.serve {
+        std::process::exit(server::serve(
+            args.socket.as_deref(),
+            args.directory.as_deref(),
+        ));
+    }
+
+    if args.info {
+        std::process::exit(cli::info(
+            args.output.unwrap_or(args::OutputFormat::Plain),
+            args.directory.as_deref(),
+        ));
+    }
+
+    if let Some(commit) = args.commit {
+        std::process::exit(cli::commit(commit));
+    }
+
+    // printed when asked to or when the output goes to another program
+    let output = args.output.or_else(|| {
+        let piped = !crossterm::tty::IsTty::is_tty(&std::io::stdout());
+        (piped || args.count.is_some()).then_some(args::OutputFormat::Plain)
+    });
+    if let (Some(mode), Some(output)) = (args.startup_mode, output) {
+        std::process::exit(cli::print(
+            mode,
+            output,
+            args.count,
+            args.directory.as_deref(),
+        ));
+    }
+
     if !crossterm::tty::IsTty::is_tty(&std::io::stdin()) {
         eprintln!("not tty");
         return;
     }
 
     ctrlc::set_handler(|| {}).unwrap();
-    if let Some(version_control) = repositories::get_current_version_control() {
+    if let Some(version_control) =
+        repositories::get_current_version_control(args.directory.as_deref())
+    {
+        let mut config = config::Config::load();
+        let custom_actions = custom_actions::CustomAction::load_custom_actions(
+            &mut config.errors,
+        );
         let application = application::Application::new(
             version_control,
-            custom_actions::CustomAction::load_custom_actions(),
+            custom_actions,
+            config,
+            askpass::AskPass::install(),
         );
-        tui::show_tui(application);
+        tui::show_tui(application, args.startup_mode);
     }
 }
@@ -0,0 +1,38 @@
+use std::io::Write;
+
+use crossterm::{queue, style::Print, Result};
+
+/// How the terminal is asked to get the user's attention
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Notification {
+    Bell,
+    /// Desktop notification with a message, shown by terminals like iTerm2,
+    /// kitty, WezTerm and Windows Terminal
+    Osc9,
+}
+
+impl Notification {
+    /// Parses `bell` or `osc9`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bell" => Some(Self::Bell),
+            "osc9" => Some(Self::Osc9),
+            _ => None,
+        }
+    }
+
+    pub fn send<W>(self, write: &mut W, message: &str) -> Result<()>
+    where
+        W: Write,
+    {
+        match self {
+            Self::Bell => queue!(write, Print('\x07')),
+            Self::Osc9 => {
+                // the message ends at the first control char
+                let message: String =
+                    message.chars().filter(|c| !c.is_control()).collect();
+                queue!(write, Print(format!("\x1b]9;{}\x07", message)))
+            }
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use crossterm::{
+    cursor,
+    event::{self, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent},
+    handle_command,
+    style::{Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal::{Clear, ClearType},
+    QueueableCommand, Result,
+};
+
+use std::io::Write;
+
+use crate::{
+    input,
+    key_bindings::KeyBinding,
+    locale::tr,
+    theme::theme,
+    tui_util::{
+        draw_filter_bar, fit_width, fuzzy_matches, move_cursor, AvailableSize,
+        TerminalSize, MOUSE_SCROLL_DELTA,
+    },
+};
+
+const KEYS_COLUMN_WIDTH: usize = 8;
+
+struct Palette<'a> {
+    bindings: &'a [&'a KeyBinding],
+    /// Lowercase names the filter is matched against
+    names: Vec<String>,
+    scroll: usize,
+    cursor: usize,
+    filter: Vec<char>,
+}
+
+impl<'a> Palette<'a> {
+    /// Indices of the bindings whose name fuzzy matches the filter or whose
+    /// keys start with it
+    fn filtered(&self) -> Vec<usize> {
+        let lowercase: Vec<_> =
+            self.filter.iter().flat_map(|c| c.to_lowercase()).collect();
+        let typed: String = self.filter.iter().collect();
+        (0..self.bindings.len())
+            .filter(|&i| {
+                fuzzy_matches(&self.names[i], &lowercase[..])
+                    || self.bindings[i].keys.starts_with(&typed[..])
+            })
+            .collect()
+    }
+
+    fn move_cursor<W>(
+        &mut self,
+        write: &mut W,
+        available_size: AvailableSize,
+        delta: i32,
+    ) -> Result<()>
+    where
+        W: Write,
+    {
+        let count = self.filtered().len();
+        move_cursor(
+            &mut self.scroll,
+            &mut self.cursor,
+            available_size,
+            count,
+            delta,
+        );
+        self.draw(write, available_size)
+    }
+
+    fn on_filter_changed<W>(
+        &mut self,
+        write: &mut W,
+        available_size: AvailableSize,
+    ) -> Result<()>
+    where
+        W: Write,
+    {
+        self.cursor = 0;
+        self.scroll = 0;
+        self.draw(write, available_size)
+    }
+
+    fn draw<W>(
+        &self,
+        write: &mut W,
+        available_size: AvailableSize,
+    ) -> Result<()>
+    where
+        W: Write,
+    {
+        handle_command!(write, cursor::MoveTo(0, 1))?;
+        handle_command!(write, ResetColor)?;
+
+        let name_width = available_size.width.saturating_sub(KEYS_COLUMN_WIDTH);
+        for (i, &index) in self
+            .filtered()
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(available_size.height)
+        {
+            let binding = self.bindings[index];
+            if i == self.cursor {
+                handle_command!(
+                    write,
+                    SetBackgroundColor(theme().selected_bg)
+                )?;
+            }
+            handle_command!(write, SetForegroundColor(theme().entry))?;
+            handle_command!(
+                write,
+                Print(format!(
+                    "{:width$}",
+                    binding.keys,
+                    width = KEYS_COLUMN_WIDTH
+                ))
+            )?;
+            handle_command!(write, SetForegroundColor(theme().text))?;
+            handle_command!(
+                write,
+                Print(fit_width(tr(binding.action.name()), name_width))
+            )?;
+            handle_command!(write, Clear(ClearType::UntilNewLine))?;
+            handle_command!(write, ResetColor)?;
+            handle_command!(write, cursor::MoveToNextLine(1))?;
+        }
+
+        handle_command!(write, ResetColor)?;
+        handle_command!(write, Clear(ClearType::FromCursorDown))?;
+        draw_filter_bar(write, &self.filter[..], true)?;
+
+        Ok(())
+    }
+
+    fn selected(&self) -> Option<usize> {
+        self.filtered().get(self.cursor).copied()
+    }
+}
+
+/// Lists `bindings` by their keys and name, filtered while typing, until one
+/// is picked. Returns its index
+pub fn pick<W>(write: &mut W, bindings: &[&KeyBinding]) -> Result<Option<usize>>
+where
+    W: Write,
+{
+    let mut palette = Palette {
+        bindings,
+        names: bindings
+            .iter()
+            .map(|b| tr(b.action.name()).to_lowercase())
+            .collect(),
+        scroll: 0,
+        cursor: 0,
+        filter: Vec::new(),
+    };
+
+    let mut available_size =
+        AvailableSize::from_temrinal_size(TerminalSize::get()?);
+    palette.draw(write, available_size)?;
+
+    loop {
+        write.queue(cursor::MoveTo(0, 2))?;
+        write.flush()?;
+        match input::read()? {
+            event::Event::Resize(width, height) => {
+                available_size =
+                    AvailableSize::from_temrinal_size(TerminalSize {
+                        width,
+                        height,
+                    });
+                palette.draw(write, available_size)?;
+            }
+            event::Event::Mouse(mouse_event) => match mouse_event {
+                MouseEvent::ScrollDown(..) => {
+                    palette.move_cursor(
+                        write,
+                        available_size,
+                        MOUSE_SCROLL_DELTA,
+                    )?;
+                }
+                MouseEvent::ScrollUp(..) => {
+                    palette.move_cursor(
+                        write,
+                        available_size,
+                        -MOUSE_SCROLL_DELTA,
+                    )?;
+                }
+                MouseEvent::Down(MouseButton::Left, _, row, _) if row > 0 => {
+                    let index = palette.scroll + row as usize - 1;
+                    if index < palette.filtered().len() {
+                        palette.cursor = index;
+                        return Ok(palette.selected());
+                    }
+                }
+                _ => (),
+            },
+            event::Event::Key(key_event) => {
+                match input::normalize_key(key_event) {
+                    KeyEvent {
+                        code: KeyCode::Esc, ..
+                    }
+                    | KeyEvent {
+                        code: KeyCode::Char('c'),
+                        modifiers: KeyModifiers::CONTROL,
+                    } => {
+                        if palette.filter.is_empty() {
+                            return Ok(None);
+                        }
+                        palette.filter.clear();
+                        palette.on_filter_changed(write, available_size)?;
+                    }
+                    KeyEvent {
+                        code: KeyCode::Enter,
+                        ..
+                    }
+                    | KeyEvent {
+                        code: KeyCode::Char('m'),
+                        modifiers: KeyModifiers::CONTROL,
+                    } => {
+                        if let Some(index) = palette.selected() {
+                            return Ok(Some(index));
+                        }
+                    }
+                    KeyEvent {
+                        code: KeyCode::Char('j'),
+                        modifiers: KeyModifiers::CONTROL,
+                    }
+                    | KeyEvent {
+                        code: KeyCode::Char('n'),
+                        modifiers: KeyModifiers::CONTROL,
+                    }
+                    | KeyEvent {
+                        code: KeyCode::Down,
+                        ..
+                    } => {
+                        palette.move_cursor(write, available_size, 1)?;
+                    }
+                    KeyEvent {
+                        code: KeyCode::Char('k'),
+                        modifiers: KeyModifiers::CONTROL,
+                    }
+                    | KeyEvent {
+                        code: KeyCode::Char('p'),
+                        modifiers: KeyModifiers::CONTROL,
+                    }
+                    | KeyEvent {
+                        code: KeyCode::Up, ..
+                    } => {
+                        palette.move_cursor(write, available_size, -1)?;
+                    }
+                    KeyEvent {
+                        code: KeyCode::Char('h'),
+                        modifiers: KeyModifiers::CONTROL,
+                    }
+                    | KeyEvent {
+                        code: KeyCode::Backspace,
+                        ..
+                    } => {
+                        palette.filter.pop();
+                        palette.on_filter_changed(write, available_size)?;
+                    }
+                    KeyEvent {
+                        code: KeyCode::Char('w'),
+                        modifiers: KeyModifiers::CONTROL,
+                    } => {
+                        palette.filter.clear();
+                        palette.on_filter_changed(write, available_size)?;
+                    }
+                    key_event => {
+                        if let Some(c) = input::key_to_char(key_event) {
+                            palette.filter.push(c);
+                            palette.on_filter_changed(write, available_size)?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
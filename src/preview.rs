@@ -0,0 +1,102 @@
+use crossterm::{
+    cursor, handle_command,
+    style::{Print, ResetColor},
+    terminal::{Clear, ClearType},
+    Result,
+};
+
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use crate::tui_util::{print_clipped, AvailableSize, TerminalSize};
+
+const MIN_SPLIT_PERCENT: i32 = 20;
+const MAX_SPLIT_PERCENT: i32 = 80;
+const SPLIT_PERCENT_STEP: i32 = 5;
+const HOVER_DEBOUNCE: Duration = Duration::from_millis(150);
+
+pub struct Preview {
+    pub enabled: bool,
+    split_percent: i32,
+    hovered: Option<(String, Instant)>,
+}
+
+impl Default for Preview {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            split_percent: 50,
+            hovered: None,
+        }
+    }
+}
+
+impl Preview {
+    /// Width left for the list pane at the left of the preview
+    pub fn list_width(&self, terminal_size: TerminalSize) -> usize {
+        terminal_size.width as usize * self.split_percent as usize / 100
+    }
+
+    /// Marks `target` as hovered returning whether it changed
+    pub fn hover(&mut self, target: &str) -> bool {
+        match &self.hovered {
+            Some((hovered, _)) if hovered == target => false,
+            _ => {
+                self.hovered = Some((target.into(), Instant::now()));
+                true
+            }
+        }
+    }
+
+    /// Whether the cursor rested long enough on the hovered target to
+    /// request its preview
+    pub fn is_hover_settled(&self) -> bool {
+        match &self.hovered {
+            Some((_, since)) => since.elapsed() >= HOVER_DEBOUNCE,
+            None => false,
+        }
+    }
+
+    pub fn grow(&mut self) {
+        self.resize(-SPLIT_PERCENT_STEP);
+    }
+
+    pub fn shrink(&mut self) {
+        self.resize(SPLIT_PERCENT_STEP);
+    }
+
+    fn resize(&mut self, delta: i32) {
+        self.split_percent = (self.split_percent + delta)
+            .clamp(MIN_SPLIT_PERCENT, MAX_SPLIT_PERCENT);
+    }
+
+    pub fn draw<W>(
+        &self,
+        write: &mut W,
+        content: &str,
+        terminal_size: TerminalSize,
+    ) -> Result<()>
+    where
+        W: Write,
+    {
+        let available_size = AvailableSize::from_temrinal_size(terminal_size);
+        let x = self.list_width(terminal_size);
+        let width = available_size.width.saturating_sub(x + 1);
+
+        let mut lines = content.lines();
+        for row in 0..available_size.height {
+            handle_command!(write, cursor::MoveTo(x as u16, row as u16 + 1))?;
+            handle_command!(write, ResetColor)?;
+            handle_command!(write, Print('|'))?;
+            if let Some(line) = lines.next() {
+                print_clipped(write, line, width)?;
+            }
+            handle_command!(write, ResetColor)?;
+            handle_command!(write, Clear(ClearType::UntilNewLine))?;
+        }
+
+        Ok(())
+    }
+}
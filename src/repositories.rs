@@ -1,14 +1,20 @@
-use std::{env, path::Path};
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
 
 use crate::{
-    git_actions::GitActions, hg_actions::HgActions,
+    bzr_actions::BzrActions, darcs_actions::DarcsActions,
+    git_actions::GitActions, hg_actions::HgActions, jj_actions::JjActions,
     version_control_actions::VersionControlActions,
 };
 
-pub fn get_current_version_control() -> Option<Box<dyn VersionControlActions>> {
-    let mut args = env::args();
-    if let Some(dir) = args.nth(1) {
-        let dir = Path::new(&dir);
+pub fn get_current_version_control(
+    directory: Option<&str>,
+) -> Option<Box<dyn VersionControlActions>> {
+    if let Some(dir) = directory {
+        let dir = Path::new(dir);
         if dir.canonicalize().is_err() {
             eprintln!("{:?} is not a valid directory", dir);
             return None;
@@ -27,11 +33,84 @@ pub fn get_current_version_control() -> Option<Box<dyn VersionControlActions>> {
         }
     };
 
+    let enclosing: Vec<_> = Path::new(current_dir)
+        .ancestors()
+        .filter(|dir| is_repository(dir))
+        .filter_map(|dir| dir.to_str())
+        .collect();
+    let version_control = if enclosing.len() > 1 {
+        pick_repository(&enclosing)
+    } else {
+        version_control_at(current_dir)
+    };
+    if version_control.is_none() {
+        eprintln!("no repository found");
+    }
+    version_control
+}
+
+/// Asks which of the nested repositories in `dirs`, innermost first, to open
+fn pick_repository(dirs: &[&str]) -> Option<Box<dyn VersionControlActions>> {
+    let mut repositories: Vec<Box<dyn VersionControlActions>> = Vec::new();
+    for dir in dirs {
+        if let Some(version_control) = version_control_at(dir) {
+            let root = version_control.get_root();
+            if repositories.iter().all(|r| r.get_root() != root) {
+                repositories.push(version_control);
+            }
+        }
+    }
+    if repositories.len() < 2 {
+        return repositories.pop();
+    }
+
+    println!("nested repositories found:");
+    for (i, repository) in repositories.iter().enumerate() {
+        println!(
+            "  {}) {} ({})",
+            i + 1,
+            repository.get_root(),
+            repository.executable_name()
+        );
+    }
+
+    let index = loop {
+        print!("open which one? [1] ");
+        io::stdout().flush().ok()?;
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        match line.trim() {
+            "" => break 0,
+            line => match line.parse::<usize>() {
+                Ok(n) if n >= 1 && n <= repositories.len() => break n - 1,
+                _ => continue,
+            },
+        }
+    };
+
+    let version_control = repositories.swap_remove(index);
+    env::set_current_dir(version_control.get_root()).ok()?;
+    Some(version_control)
+}
+
+/// Backend for the repository containing `current_dir` if there's one
+pub fn version_control_at(
+    current_dir: &str,
+) -> Option<Box<dyn VersionControlActions>> {
     // first try Git because it's the most common and also responds the fastest
-    let mut git_actions = Box::from(GitActions {
-        current_dir: current_dir.into(),
-    });
+    let mut git_actions = Box::from(GitActions::new(current_dir.into()));
     if git_actions.set_root().is_ok() {
+        // jj repositories colocated with git should still be handled by jj
+        if Path::new(git_actions.get_root()).join(".jj").is_dir() {
+            let mut jj_actions = Box::from(JjActions {
+                current_dir: current_dir.into(),
+            });
+            if jj_actions.set_root().is_ok() {
+                return Some(jj_actions);
+            }
+        }
         return Some(git_actions);
     }
 
@@ -43,6 +122,84 @@ pub fn get_current_version_control() -> Option<Box<dyn VersionControlActions>> {
         return Some(hg_actions);
     }
 
-    eprintln!("no repository found");
+    // then Jujutsu
+    let mut jj_actions = Box::from(JjActions {
+        current_dir: current_dir.into(),
+    });
+    if jj_actions.set_root().is_ok() {
+        return Some(jj_actions);
+    }
+
+    // and Bazaar through either Breezy or the original client
+    for executable in ["brz", "bzr"] {
+        let mut bzr_actions = Box::from(BzrActions {
+            current_dir: current_dir.into(),
+            executable,
+        });
+        if bzr_actions.set_root().is_ok() {
+            return Some(bzr_actions);
+        }
+    }
+
+    // and finally Darcs
+    let mut darcs_actions = Box::from(DarcsActions {
+        current_dir: current_dir.into(),
+    });
+    if darcs_actions.set_root().is_ok() {
+        return Some(darcs_actions);
+    }
+
     None
 }
+
+/// Directories whose presence marks the root of a repository
+const REPOSITORY_MARKERS: [&str; 5] = [".git", ".hg", ".jj", ".bzr", "_darcs"];
+
+fn is_repository(dir: &Path) -> bool {
+    REPOSITORY_MARKERS
+        .iter()
+        .any(|marker| dir.join(marker).exists())
+}
+
+/// Resolves the configured workspace directories relative to `root`. Without
+/// any, the directory containing `root` is used so sibling checkouts are found
+pub fn workspace_dirs(configured: &[String], root: &str) -> Vec<PathBuf> {
+    if configured.is_empty() {
+        return Path::new(root)
+            .parent()
+            .map(PathBuf::from)
+            .into_iter()
+            .collect();
+    }
+
+    configured
+        .iter()
+        .map(|dir| match (dir.strip_prefix("~/"), env::var_os("HOME")) {
+            (Some(dir), Some(home)) => Path::new(&home).join(dir),
+            _ => Path::new(root).join(dir),
+        })
+        .collect()
+}
+
+/// Repositories directly inside any of `dirs` sorted by path. Only looks for
+/// repository markers so no backend command is run while scanning
+pub fn find_repositories(dirs: &[PathBuf]) -> Vec<String> {
+    let mut repositories = Vec::new();
+    for dir in dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if is_repository(&path) {
+                if let Some(path) = path.to_str() {
+                    repositories.push(path.into());
+                }
+            }
+        }
+    }
+    repositories.sort();
+    repositories.dedup();
+    repositories
+}
@@ -1,23 +1,47 @@
 use crossterm::{
     cursor,
-    event::{KeyCode, KeyEvent, KeyModifiers},
+    event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent},
     handle_command,
-    style::{ResetColor, SetBackgroundColor},
+    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
     terminal::{Clear, ClearType},
     Result,
 };
 
-use std::io::Write;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    io::Write,
+    ops::Range,
+};
 
 use crate::{
     action::ActionKind,
+    folds::{self, FoldedDiff},
     input,
+    locale::tr,
+    side_by_side,
+    state::ViewState,
+    theme::theme,
     tui_util::{
-        draw_filter_bar, fuzzy_matches, move_cursor, AvailableSize,
-        TerminalSize, SELECTED_BG_COLOR,
+        char_width, diff_header_path, draw_filter_bar, fuzzy_matches,
+        move_cursor, print_clipped, sanitize_ansi, strip_ansi, visible_width,
+        AvailableSize, TerminalSize, MOUSE_SCROLL_DELTA, PIN_MARKER,
     },
+    word_diff,
 };
 
+/// Drawn in the left column next to the lines of the selected hunk
+const HUNK_MARKER: char = '\u{258e}';
+/// Columns between the tab stops of the terminal
+const TAB_WIDTH: usize = 8;
+
+/// Hunk of the diff being shown
+struct DrawnHunk {
+    path: String,
+    /// Position among the hunks of its file
+    index: usize,
+    lines: Range<usize>,
+}
+
 pub struct ScrollView {
     action_kind: ActionKind,
     content: String,
@@ -25,6 +49,30 @@ pub struct ScrollView {
     cursor: Option<usize>,
     is_filtering: bool,
     filter: Vec<char>,
+    max_width: Option<usize>,
+    /// Numbers the lines of outputs without a cursor, like diffs
+    pub show_line_numbers: bool,
+    /// Review notes by revision and path, drawn next to the status and
+    /// changed file lines ending with it. The revision is empty for the
+    /// notes of the working tree
+    pub file_notes: BTreeMap<(String, String), String>,
+    /// Revision of the files shown by revision changes
+    pub notes_revision: String,
+    /// Files whose status lines are repeated at the top of status and drawn
+    /// with a pin marker
+    pub pinned_files: BTreeSet<String>,
+    /// Actions whose long lines are truncated instead of wrapped
+    pub truncated_views: HashSet<ActionKind>,
+    /// Shows diffs in two columns when the terminal is wide enough
+    pub side_by_side: bool,
+    pub word_diff: bool,
+    /// Diff being shown, kept apart from its rendering so its folds keep
+    /// their state while it's drawn again
+    diff: Option<FoldedDiff>,
+    /// Hunk the hunk actions work on, as the path of its file and its
+    /// position among the hunks of that file. It stays on screen, moving to
+    /// the first hunk shown when scrolled away
+    selected_hunk: Option<(String, usize)>,
 }
 
 impl Default for ScrollView {
@@ -36,6 +84,16 @@ impl Default for ScrollView {
             cursor: None,
             is_filtering: false,
             filter: Vec::new(),
+            max_width: None,
+            show_line_numbers: false,
+            file_notes: BTreeMap::new(),
+            notes_revision: String::new(),
+            pinned_files: BTreeSet::new(),
+            truncated_views: HashSet::new(),
+            side_by_side: false,
+            word_diff: true,
+            diff: None,
+            selected_hunk: None,
         }
     }
 }
@@ -45,6 +103,321 @@ impl ScrollView {
         self.cursor
     }
 
+    pub fn action_kind(&self) -> ActionKind {
+        self.action_kind
+    }
+
+    pub fn is_filtering(&self) -> bool {
+        self.is_filtering
+    }
+
+    pub fn set_filtering(&mut self, is_filtering: bool) {
+        self.is_filtering = is_filtering;
+    }
+
+    pub fn view_state(&self) -> ViewState {
+        ViewState {
+            scroll: self.scroll,
+            cursor: self.cursor,
+            filter: self.filter.iter().collect(),
+        }
+    }
+
+    /// Applies a previously saved position to the current content
+    pub fn restore(&mut self, view: &ViewState, terminal_size: TerminalSize) {
+        self.filter = view.filter.chars().collect();
+        let line_count = self.filtered_lines().count();
+        if let (Some(cursor), Some(saved)) = (&mut self.cursor, view.cursor) {
+            *cursor = saved.min(line_count.saturating_sub(1));
+        }
+        self.scroll = view.scroll;
+        self.scroll(self.available_size(terminal_size), 0);
+    }
+
+    /// Restricts drawing to the leftmost `max_width` columns
+    pub fn set_max_width(&mut self, max_width: Option<usize>) {
+        self.max_width = max_width;
+    }
+
+    /// Whether the cursor is on the last line of the unfiltered content
+    pub fn is_cursor_at_end(&self) -> bool {
+        match self.cursor {
+            Some(cursor) if self.filter.is_empty() => {
+                cursor + 1 >= self.content.lines().count()
+            }
+            _ => false,
+        }
+    }
+
+    pub fn cursor_line(&self) -> Option<&str> {
+        self.cursor.and_then(|c| self.filtered_lines().nth(c))
+    }
+
+    /// Paths of the files in a diff in the order they appear
+    pub fn diff_files(&self) -> Vec<String> {
+        self.content.lines().filter_map(diff_header_path).collect()
+    }
+
+    /// Scrolls to the diff of `path` returning whether it was found
+    pub fn jump_to_file(
+        &mut self,
+        path: &str,
+        terminal_size: TerminalSize,
+    ) -> bool {
+        let index = self
+            .filtered_lines()
+            .position(|l| diff_header_path(l).as_deref() == Some(path));
+        match index {
+            Some(index) => {
+                self.scroll = index;
+                self.scroll(self.available_size(terminal_size), 0);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Scrolls to line `number`, counting from 1 in the unfiltered content,
+    /// or to the closest one after it that matches the filter
+    pub fn jump_to_line(&mut self, number: usize, terminal_size: TerminalSize) {
+        let index = self
+            .numbered_lines()
+            .position(|(n, _)| n >= number)
+            .unwrap_or_else(|| self.filtered_lines().count().saturating_sub(1));
+        self.scroll = index;
+        if let Some(cursor) = &mut self.cursor {
+            *cursor = index;
+        }
+        self.scroll(self.available_size(terminal_size), 0);
+    }
+
+    /// Expands the folded runs of unchanged lines on screen, which shows once
+    /// the content is set again
+    pub fn expand_visible_folds(&mut self, terminal_size: TerminalSize) {
+        let available_size = self.available_size(terminal_size);
+        let visible: Vec<_> = self
+            .numbered_lines()
+            .skip(self.scroll)
+            .take(available_size.height)
+            .map(|(number, _)| number - 1)
+            .collect();
+        // folds are drawn in order so a fold line's position among them is
+        // its position among the collapsed folds
+        let positions: Vec<_> = self
+            .content
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| folds::is_fold_line(l))
+            .enumerate()
+            .filter(|(_, (index, _))| visible.contains(index))
+            .map(|(position, _)| position)
+            .collect();
+        if let Some(diff) = &mut self.diff {
+            diff.expand(&positions);
+        }
+    }
+
+    /// Collapses the file of the diff at the top of the screen to its header
+    /// or expands it back, which shows once the content is set again.
+    /// Returns the path of the file to jump back to it then
+    pub fn toggle_top_file(&mut self) -> Option<String> {
+        let (index, path) = self.top_file()?;
+        self.diff.as_mut()?.toggle_file(index);
+        Some(path)
+    }
+
+    /// Collapses or expands every file of the diff, returning the path of the
+    /// one at the top of the screen like `toggle_top_file`
+    pub fn set_files_collapsed(&mut self, collapsed: bool) -> Option<String> {
+        self.diff.as_mut()?.set_files_collapsed(collapsed);
+        self.top_file().map(|(_, path)| path)
+    }
+
+    /// Path of the file of the diff at the top of the screen
+    pub fn top_file_path(&self) -> Option<String> {
+        self.top_file().map(|(_, path)| path)
+    }
+
+    /// Index and path of the file of the diff at the top of the screen
+    fn top_file(&self) -> Option<(usize, String)> {
+        let (top, _) = self.numbered_lines().nth(self.scroll)?;
+        self.content
+            .lines()
+            .take(top)
+            .filter_map(diff_header_path)
+            .enumerate()
+            .last()
+    }
+
+    /// Path of the file of the selected hunk and its position among the
+    /// hunks of that file
+    pub fn selected_hunk(&self) -> Option<(String, usize)> {
+        self.selected_hunk.clone()
+    }
+
+    /// Diff being shown as it was output, without folds or side by side
+    /// layout
+    pub fn diff_source(&self) -> Option<&str> {
+        self.diff.as_ref().map(FoldedDiff::source)
+    }
+
+    /// Hunks of the diff being shown, each ending where the next hunk or
+    /// file starts
+    fn drawn_hunks(&self) -> Vec<DrawnHunk> {
+        let mut hunks: Vec<DrawnHunk> = Vec::new();
+        let mut path = None;
+        let mut index = 0;
+        let mut line_count = 0;
+        for (i, line) in self.content.lines().enumerate() {
+            line_count += 1;
+            let header_path = diff_header_path(line);
+            let is_hunk_header = strip_ansi(line).starts_with("@@");
+            if header_path.is_none() && !is_hunk_header {
+                continue;
+            }
+            if let Some(hunk) = hunks.last_mut().filter(|h| h.lines.end == 0) {
+                hunk.lines.end = i;
+            }
+            if header_path.is_some() {
+                path = header_path;
+                index = 0;
+            } else if let Some(path) = &path {
+                hunks.push(DrawnHunk {
+                    path: path.clone(),
+                    index,
+                    lines: i..0,
+                });
+                index += 1;
+            }
+        }
+        if let Some(hunk) = hunks.last_mut().filter(|h| h.lines.end == 0) {
+            hunk.lines.end = line_count;
+        }
+        hunks
+    }
+
+    fn is_selected(&self, hunk: &DrawnHunk) -> bool {
+        matches!(
+            &self.selected_hunk,
+            Some((path, index)) if *path == hunk.path && *index == hunk.index
+        )
+    }
+
+    /// Lines of the content on screen
+    fn visible_lines(&self, available_size: AvailableSize) -> Range<usize> {
+        let mut lines = self
+            .numbered_lines()
+            .skip(self.scroll)
+            .take(available_size.height)
+            .map(|(number, _)| number - 1);
+        let first = lines.next().unwrap_or(0);
+        let end = lines.last().unwrap_or(first) + 1;
+        first..end
+    }
+
+    /// Selects the first hunk on screen when the selected one isn't
+    fn keep_selected_hunk_visible(&mut self, available_size: AvailableSize) {
+        if !self.action_kind.selects_hunks() {
+            self.selected_hunk = None;
+            return;
+        }
+        let visible = self.visible_lines(available_size);
+        let on_screen = |h: &DrawnHunk| {
+            h.lines.start < visible.end && h.lines.end > visible.start
+        };
+        let hunks = self.drawn_hunks();
+        if hunks.iter().any(|h| self.is_selected(h) && on_screen(h)) {
+            return;
+        }
+        self.selected_hunk = hunks
+            .into_iter()
+            .find(|h| on_screen(h))
+            .map(|h| (h.path, h.index));
+    }
+
+    /// Selects the next or previous hunk scrolling to it if needed
+    fn select_sibling_hunk(
+        &mut self,
+        available_size: AvailableSize,
+        forward: bool,
+    ) {
+        let hunks = self.drawn_hunks();
+        let selected = hunks.iter().position(|h| self.is_selected(h));
+        let index = match (selected, forward) {
+            (Some(i), true) => (i + 1).min(hunks.len().saturating_sub(1)),
+            (Some(i), false) => i.saturating_sub(1),
+            (None, _) => 0,
+        };
+        let hunk = match hunks.into_iter().nth(index) {
+            Some(hunk) => hunk,
+            None => return,
+        };
+
+        let visible = self.visible_lines(available_size);
+        if !visible.contains(&hunk.lines.start) {
+            let position = self
+                .numbered_lines()
+                .position(|(number, _)| number > hunk.lines.start);
+            if let Some(position) = position {
+                self.scroll = position;
+            }
+        }
+        self.selected_hunk = Some((hunk.path, hunk.index));
+        self.scroll(available_size, 0);
+    }
+
+    /// Selects the hunk with the line shown at `row` of the screen
+    fn select_hunk_at(&mut self, row: usize) {
+        let line = match self.numbered_lines().nth(self.scroll + row) {
+            Some((number, _)) => number - 1,
+            None => return,
+        };
+        if let Some(hunk) = self
+            .drawn_hunks()
+            .into_iter()
+            .find(|h| h.lines.contains(&line))
+        {
+            self.selected_hunk = Some((hunk.path, hunk.index));
+        }
+    }
+
+    /// Scrolls to the next or previous file of a diff
+    fn jump_to_sibling_file(
+        &mut self,
+        available_size: AvailableSize,
+        forward: bool,
+    ) {
+        let scroll = self.scroll;
+        let headers: Vec<_> = self
+            .filtered_lines()
+            .enumerate()
+            .filter(|(_, l)| diff_header_path(l).is_some())
+            .map(|(i, _)| i)
+            .collect();
+        let index = if forward {
+            headers.into_iter().find(|&i| i > scroll)
+        } else {
+            headers.into_iter().rev().find(|&i| i < scroll)
+        };
+
+        if let Some(index) = index {
+            self.scroll = index;
+            self.scroll(available_size, 0);
+        }
+    }
+
+    fn available_size(&self, terminal_size: TerminalSize) -> AvailableSize {
+        let mut available_size =
+            AvailableSize::from_temrinal_size(terminal_size);
+        if let Some(max_width) = self.max_width {
+            available_size.width = available_size.width.min(max_width);
+        }
+        available_size.width = available_size
+            .width
+            .saturating_sub(self.line_number_width() + self.gutter_width());
+        available_size
+    }
+
     pub fn set_content(
         &mut self,
         content: &str,
@@ -52,23 +425,133 @@ impl ScrollView {
         terminal_size: TerminalSize,
     ) {
         self.content.clear();
-        self.content.push_str(content);
+        let content = sanitize_ansi(content);
+        if !action_kind.is_diff() {
+            self.diff = None;
+            if action_kind == ActionKind::Status {
+                self.push_pinned_lines(&content);
+            }
+            self.content.push_str(&content);
+            return self.on_content_changed(action_kind, terminal_size);
+        }
+        let diff = match self.diff.take() {
+            Some(diff)
+                if self.action_kind == action_kind
+                    && diff.source() == content =>
+            {
+                diff
+            }
+            _ => FoldedDiff::new(content),
+        };
+        let content = &diff.text();
+
+        let side_by_side_width = if self.side_by_side {
+            let width = AvailableSize::from_temrinal_size(terminal_size).width;
+            let width = self.max_width.map_or(width, |w| width.min(w));
+            let width = match action_kind.selects_hunks() {
+                true => width.saturating_sub(1),
+                false => width,
+            };
+            // diffs have no cursor so they get numbered when enabled
+            let width = match self.show_line_numbers {
+                true => {
+                    let digits = content.lines().count().max(1).to_string();
+                    width.saturating_sub(digits.len() + 1)
+                }
+                false => width,
+            };
+            Some(width).filter(|&w| w >= side_by_side::MIN_WIDTH)
+        } else {
+            None
+        };
+        if let Some(width) = side_by_side_width {
+            self.content.push_str(&side_by_side::layout(
+                content,
+                width - 1,
+                self.word_diff,
+            ));
+        } else {
+            self.content
+                .push_str(&word_diff::color_changes(content, self.word_diff));
+        }
+        self.diff = Some(diff);
+        self.on_content_changed(action_kind, terminal_size);
+    }
 
+    /// Adds lines the running action printed after the ones shown, keeping
+    /// the view
+    pub fn append_content(&mut self, lines: &str, terminal_size: TerminalSize) {
+        self.content.push_str(&sanitize_ansi(lines));
+        self.scroll(self.available_size(terminal_size), 0);
+    }
+
+    /// Sets the final output of an action whose lines were appended as it
+    /// ran. When it only adds lines above them, like the notes of status,
+    /// the cursor and the scroll move down with them so the lines shown stay
+    /// in place
+    pub fn finish_content(
+        &mut self,
+        content: &str,
+        action_kind: ActionKind,
+        terminal_size: TerminalSize,
+    ) {
+        let streamed = std::mem::take(&mut self.content);
+        self.set_content(content, action_kind, terminal_size);
+        let added = match self.content.strip_suffix(&streamed[..]) {
+            Some(added) if !streamed.is_empty() => added.lines().count(),
+            _ => return,
+        };
+        if let Some(cursor) = &mut self.cursor {
+            *cursor += added;
+        }
+        if self.scroll > 0 {
+            self.scroll += added;
+        }
+        self.scroll(self.available_size(terminal_size), 0);
+    }
+
+    /// Repeats the status lines of the pinned files under their own title
+    fn push_pinned_lines(&mut self, status: &str) {
+        let lines: Vec<_> = status
+            .lines()
+            .filter(|line| {
+                let line = strip_ansi(line);
+                self.pinned_files.iter().any(|p| ends_with_path(&line, p))
+            })
+            .collect();
+        if lines.is_empty() {
+            return;
+        }
+
+        self.content.push_str(tr("pinned files:"));
+        self.content.push('\n');
+        for line in lines {
+            self.content.push_str(line);
+            self.content.push('\n');
+        }
+        self.content.push('\n');
+    }
+
+    fn on_content_changed(
+        &mut self,
+        action_kind: ActionKind,
+        terminal_size: TerminalSize,
+    ) {
         self.is_filtering = false;
         self.filter.clear();
 
-        if self.action_kind != action_kind {
+        let changed_kind = self.action_kind != action_kind;
+        self.action_kind = action_kind;
+        if changed_kind {
             self.scroll = 0;
             self.cursor = if action_kind.can_select_output() {
                 Some(0)
             } else {
                 None
             };
-        } else {
-            self.scroll(AvailableSize::from_temrinal_size(terminal_size), 0);
+            self.selected_hunk = None;
         }
-
-        self.action_kind = action_kind;
+        self.scroll(self.available_size(terminal_size), 0);
     }
 
     pub fn draw_content<W>(
@@ -80,13 +563,22 @@ impl ScrollView {
         W: Write,
     {
         let line_formatter = self.action_kind.line_formatter();
-        let available_size = AvailableSize::from_temrinal_size(terminal_size);
+        let available_size = self.available_size(terminal_size);
 
         handle_command!(write, cursor::MoveTo(0, 1))?;
         handle_command!(write, ResetColor)?;
 
-        for (i, line) in self
-            .filtered_lines()
+        let line_number_width = self.line_number_width();
+        let selected_lines = match self.gutter_width() {
+            0 => None,
+            _ => self
+                .drawn_hunks()
+                .into_iter()
+                .find(|h| self.is_selected(h))
+                .map(|h| h.lines),
+        };
+        for (i, (number, line)) in self
+            .numbered_lines()
             .enumerate()
             .skip(self.scroll)
             .take(available_size.height)
@@ -95,17 +587,52 @@ impl ScrollView {
                 if cursor == i {
                     handle_command!(
                         write,
-                        SetBackgroundColor(SELECTED_BG_COLOR)
+                        SetBackgroundColor(theme().selected_bg)
                     )?;
                 }
 
                 line_formatter(write, line, available_size)?;
+                self.draw_note(write, line, available_size.width)?;
                 handle_command!(write, Clear(ClearType::UntilNewLine))?;
                 handle_command!(write, cursor::MoveToNextLine(1))?;
                 handle_command!(write, ResetColor)?;
             } else {
                 handle_command!(write, Clear(ClearType::CurrentLine))?;
-                line_formatter(write, line, available_size)?;
+                if self.gutter_width() > 0 {
+                    match &selected_lines {
+                        Some(lines) if lines.contains(&(number - 1)) => {
+                            handle_command!(
+                                write,
+                                SetForegroundColor(theme().entry)
+                            )?;
+                            handle_command!(write, Print(HUNK_MARKER))?;
+                            handle_command!(write, ResetColor)?;
+                        }
+                        _ => handle_command!(write, Print(' '))?,
+                    }
+                }
+                if line_number_width > 0 {
+                    handle_command!(
+                        write,
+                        SetForegroundColor(theme().line_number)
+                    )?;
+                    handle_command!(
+                        write,
+                        Print(format!(
+                            "{:>width$} ",
+                            number,
+                            width = line_number_width - 1
+                        ))
+                    )?;
+                    handle_command!(write, ResetColor)?;
+                }
+                if self.is_truncating() {
+                    print_clipped(write, line, available_size.width - 1)?;
+                    handle_command!(write, ResetColor)?;
+                } else {
+                    line_formatter(write, line, available_size)?;
+                }
+                self.draw_note(write, line, available_size.width)?;
                 handle_command!(write, cursor::MoveToNextLine(1))?;
             }
         }
@@ -125,7 +652,7 @@ impl ScrollView {
     where
         W: Write,
     {
-        let available_size = AvailableSize::from_temrinal_size(terminal_size);
+        let available_size = self.available_size(terminal_size);
         match key_event {
             KeyEvent {
                 code: KeyCode::Char('j'),
@@ -147,10 +674,6 @@ impl ScrollView {
                 code: KeyCode::Char('k'),
                 modifiers: KeyModifiers::CONTROL,
             }
-            | KeyEvent {
-                code: KeyCode::Char('p'),
-                modifiers: KeyModifiers::CONTROL,
-            }
             | KeyEvent {
                 code: KeyCode::Up, ..
             } => {
@@ -199,6 +722,7 @@ impl ScrollView {
                 if let Some(ref mut cursor) = self.cursor {
                     *cursor = 0;
                 }
+                self.keep_selected_hunk_visible(available_size);
                 self.draw_content(write, terminal_size)?;
             }
             KeyEvent {
@@ -217,6 +741,29 @@ impl ScrollView {
                 if let Some(ref mut cursor) = self.cursor {
                     *cursor = content_height - 1;
                 }
+                self.keep_selected_hunk_visible(available_size);
+                self.draw_content(write, terminal_size)?;
+            }
+            KeyEvent {
+                code: KeyCode::Char(c @ ']'),
+                ..
+            }
+            | KeyEvent {
+                code: KeyCode::Char(c @ '['),
+                ..
+            } if !self.is_filtering && self.action_kind.is_diff() => {
+                self.jump_to_sibling_file(available_size, c == ']');
+                self.draw_content(write, terminal_size)?;
+            }
+            KeyEvent {
+                code: KeyCode::Char(c @ '}'),
+                ..
+            }
+            | KeyEvent {
+                code: KeyCode::Char(c @ '{'),
+                ..
+            } if !self.is_filtering && self.action_kind.selects_hunks() => {
+                self.select_sibling_hunk(available_size, c == '}');
                 self.draw_content(write, terminal_size)?;
             }
             KeyEvent {
@@ -240,7 +787,7 @@ impl ScrollView {
                 code: KeyCode::Backspace,
                 ..
             } => {
-                if self.filter.len() > 0 {
+                if !self.filter.is_empty() {
                     self.filter.remove(self.filter.len() - 1);
                 }
                 self.on_filter_changed(write, terminal_size)?;
@@ -259,7 +806,7 @@ impl ScrollView {
                 code: KeyCode::Char('c'),
                 modifiers: KeyModifiers::CONTROL,
             } => {
-                if self.is_filtering || self.filter.len() > 0 {
+                if self.is_filtering || !self.filter.is_empty() {
                     self.is_filtering = false;
                     self.filter.clear();
                     self.on_filter_changed(write, terminal_size)?;
@@ -284,19 +831,158 @@ impl ScrollView {
         Ok(true)
     }
 
+    pub fn update_mouse<W>(
+        &mut self,
+        write: &mut W,
+        mouse_event: MouseEvent,
+        terminal_size: TerminalSize,
+    ) -> Result<()>
+    where
+        W: Write,
+    {
+        let available_size = self.available_size(terminal_size);
+        match mouse_event {
+            MouseEvent::ScrollDown(..) => {
+                self.scroll(available_size, MOUSE_SCROLL_DELTA)
+            }
+            MouseEvent::ScrollUp(..) => {
+                self.scroll(available_size, -MOUSE_SCROLL_DELTA)
+            }
+            MouseEvent::Down(MouseButton::Left, _, row, _) => {
+                let line_count = self.filtered_lines().count();
+                match self.cursor {
+                    Some(ref mut cursor) if row > 0 => {
+                        let index = self.scroll + row as usize - 1;
+                        if index < line_count {
+                            *cursor = index;
+                        }
+                    }
+                    None if row > 0 && self.action_kind.selects_hunks() => {
+                        self.select_hunk_at(row as usize - 1);
+                    }
+                    _ => return Ok(()),
+                }
+            }
+            _ => return Ok(()),
+        }
+
+        self.draw_content(write, terminal_size)
+    }
+
+    /// Marks `line` with the note of the file it ends with, if any, and
+    /// whether that file is pinned, in what is left of the `columns` of its
+    /// last row
+    fn draw_note<W>(
+        &self,
+        write: &mut W,
+        line: &str,
+        columns: usize,
+    ) -> Result<()>
+    where
+        W: Write,
+    {
+        let revision = match self.action_kind {
+            ActionKind::Status | ActionKind::BaseChanges => "",
+            ActionKind::RevisionChanges => &self.notes_revision[..],
+            _ => return Ok(()),
+        };
+        if self.file_notes.is_empty() && self.pinned_files.is_empty() {
+            return Ok(());
+        }
+
+        let line = strip_ansi(line);
+        // status lines of git start with a tab
+        let width = line.chars().fold(0, |width, c| match c {
+            '\t' => (width / TAB_WIDTH + 1) * TAB_WIDTH,
+            c => width + char_width(c),
+        });
+        let mut room = match width {
+            0 => columns,
+            width if self.is_truncating() => columns.saturating_sub(width),
+            width => columns - ((width - 1) % columns + 1),
+        };
+        let note = self.file_notes.iter().find(|((r, path), _)| {
+            r == revision && ends_with_path(&line, path)
+        });
+        if let Some((_, note)) = note {
+            let note = format!(" [{}]", note);
+            handle_command!(write, SetForegroundColor(theme().note))?;
+            print_clipped(write, &note, room)?;
+            handle_command!(write, SetForegroundColor(Color::Reset))?;
+            room = room.saturating_sub(visible_width(&note));
+        }
+        let pinned = self.pinned_files.iter().any(|p| ends_with_path(&line, p));
+        if pinned && room >= 2 {
+            handle_command!(write, SetForegroundColor(theme().entry))?;
+            handle_command!(write, Print(' '))?;
+            handle_command!(write, Print(PIN_MARKER))?;
+            handle_command!(write, SetForegroundColor(Color::Reset))?;
+        }
+        Ok(())
+    }
+
+    /// Columns taken by the line numbers and the space after them
+    fn line_number_width(&self) -> usize {
+        if !self.show_line_numbers || self.cursor.is_some() {
+            return 0;
+        }
+        self.content.lines().count().max(1).to_string().len() + 1
+    }
+
+    /// Column marking the lines of the selected hunk
+    fn gutter_width(&self) -> usize {
+        match self.cursor {
+            None if self.action_kind.selects_hunks() => 1,
+            _ => 0,
+        }
+    }
+
+    /// Lines matching the filter together with their number in the whole
+    /// content, counting from 1
+    fn numbered_lines(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.content
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .filter(move |(_, line)| self.matches_filter(line))
+    }
+
     fn filtered_lines(&self) -> impl Iterator<Item = &str> {
         self.content
             .lines()
-            .filter(move |line| fuzzy_matches(line, &self.filter[..]))
+            .filter(move |line| self.matches_filter(line))
+    }
+
+    /// Matches the text of `line` without its color sequences
+    fn matches_filter(&self, line: &str) -> bool {
+        self.filter.is_empty()
+            || fuzzy_matches(&strip_ansi(line), &self.filter[..])
+    }
+
+    /// Whether long lines of the output are cut at the edge of the screen.
+    /// Entry lists always are
+    fn is_truncating(&self) -> bool {
+        self.cursor.is_none()
+            && self.truncated_views.contains(&self.action_kind)
+    }
+
+    /// Switches between wrapping and truncating the current output. Returns
+    /// whether it's now truncated
+    pub fn toggle_truncating(&mut self) -> bool {
+        if self.truncated_views.remove(&self.action_kind) {
+            return false;
+        }
+        self.truncated_views.insert(self.action_kind);
+        true
     }
 
     fn content_height(&self, available_size: AvailableSize) -> usize {
-        if self.cursor.is_some() {
+        if self.cursor.is_some() || self.is_truncating() {
             self.filtered_lines().count()
         } else {
             let width = available_size.width;
             self.filtered_lines()
-                .map(|l| (l.len() + width - 1) / width)
+                .map(|l| ((visible_width(l) + width - 1) / width).max(1))
                 .sum()
         }
     }
@@ -316,6 +1002,7 @@ impl ScrollView {
                 .min(content_height as i32 - available_size.height as i32)
                 .max(0) as usize;
         }
+        self.keep_selected_hunk_visible(available_size);
     }
 
     fn on_filter_changed<W>(
@@ -328,6 +1015,14 @@ impl ScrollView {
     {
         self.scroll = 0;
         self.cursor = self.cursor.map(|_| 0);
+        self.keep_selected_hunk_visible(self.available_size(terminal_size));
         self.draw_content(writer, terminal_size)
     }
 }
+
+/// Whether the plain `line` names the file at `path` as its last word
+fn ends_with_path(line: &str, path: &str) -> bool {
+    line.trim_end().strip_suffix(path).is_some_and(|rest| {
+        rest.is_empty() || rest.ends_with(char::is_whitespace)
+    })
+}
@@ -1,66 +1,55 @@
 use crossterm::{
     cursor,
-    event::{self, KeyCode, KeyEvent, KeyModifiers},
+    event::{self, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent},
     handle_command,
     style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
     terminal::{Clear, ClearType},
     QueueableCommand, Result,
 };
 
-use std::io::Write;
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    fs,
+    io::Write,
+    mem,
+    ops::RangeInclusive,
+    path::Path,
+    time::{Duration, SystemTime},
+};
 
 use crate::{
     input,
+    theme::theme,
     tui_util::{
-        draw_filter_bar, fuzzy_matches, move_cursor, AvailableSize,
-        TerminalSize, SELECTED_BG_COLOR,
+        draw_filter_bar, fit_width_end, fuzzy_matches, move_cursor, str_width,
+        AvailableSize, TerminalSize, MOUSE_SCROLL_DELTA, PIN_MARKER,
     },
 };
 
-const UNTRACKED_COLOR: Color = Color::Rgb {
-    r: 100,
-    g: 180,
-    b: 255,
-};
-const UNMODIFIED_COLOR: Color = Color::Rgb {
-    r: 255,
-    g: 255,
-    b: 255,
-};
-const MODIFIED_COLOR: Color = Color::Rgb {
-    r: 255,
-    g: 200,
-    b: 0,
-};
-const ADDED_COLOR: Color = Color::Rgb { r: 0, g: 255, b: 0 };
-const DELETED_COLOR: Color = Color::Rgb { r: 255, g: 0, b: 0 };
-const RENAMED_COLOR: Color = Color::Rgb {
-    r: 100,
-    g: 100,
-    b: 255,
-};
-const COPIED_COLOR: Color = Color::Rgb {
-    r: 255,
-    g: 0,
-    b: 255,
-};
-const UNMERGED_COLOR: Color = Color::Rgb {
-    r: 255,
-    g: 180,
-    b: 100,
-};
-const MISSING_COLOR: Color = Color::Rgb { r: 255, g: 0, b: 0 };
-const IGNORED_COLOR: Color = Color::Rgb {
-    r: 255,
-    g: 180,
-    b: 0,
-};
-const CLEAN_COLOR: Color = Color::Rgb {
-    r: 100,
-    g: 180,
-    b: 255,
-};
 const ITEM_NAME_COLUMN: usize = 16;
+const SIZE_COLUMN_WIDTH: usize = 8;
+const AGE_COLUMN_WIDTH: usize = 6;
+/// Entries matched against the filter at a time while waiting for input
+const SCAN_CHUNK_LEN: usize = 4096;
+/// How often the side pane is updated while waiting for input
+const SIDE_PANE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Pane drawn at the right of the entries while selecting, like a preview of
+/// the hovered one
+pub trait SidePane<W> {
+    /// Width left for the entries
+    fn list_width(&self, terminal_size: TerminalSize) -> usize;
+    /// Keeps the pane up to date with the `hovered` entry while waiting for
+    /// input. `redrawn` when the entries were just drawn over it
+    fn update(
+        &mut self,
+        write: &mut W,
+        hovered: &Entry,
+        terminal_size: TerminalSize,
+        redrawn: bool,
+    ) -> Result<()>;
+}
 
 #[derive(Clone, Debug)]
 pub enum State {
@@ -78,19 +67,36 @@ pub enum State {
 }
 
 impl State {
+    pub fn name(&self) -> &'static str {
+        match self {
+            State::Untracked => "untracked",
+            State::Unmodified => "unmodified",
+            State::Modified => "modified",
+            State::Added => "added",
+            State::Deleted => "deleted",
+            State::Renamed => "renamed",
+            State::Copied => "copied",
+            State::Unmerged => "unmerged",
+            State::Missing => "missing",
+            State::Ignored => "ignored",
+            State::Clean => "clean",
+        }
+    }
+
     fn color(&self) -> Color {
+        let theme = theme();
         match self {
-            State::Untracked => UNTRACKED_COLOR,
-            State::Unmodified => UNMODIFIED_COLOR,
-            State::Modified => MODIFIED_COLOR,
-            State::Added => ADDED_COLOR,
-            State::Deleted => DELETED_COLOR,
-            State::Renamed => RENAMED_COLOR,
-            State::Copied => COPIED_COLOR,
-            State::Unmerged => UNMERGED_COLOR,
-            State::Missing => MISSING_COLOR,
-            State::Ignored => IGNORED_COLOR,
-            State::Clean => CLEAN_COLOR,
+            State::Untracked => theme.untracked,
+            State::Unmodified => theme.unmodified,
+            State::Modified => theme.modified,
+            State::Added => theme.added,
+            State::Deleted => theme.deleted,
+            State::Renamed => theme.renamed,
+            State::Copied => theme.copied,
+            State::Unmerged => theme.unmerged,
+            State::Missing => theme.missing,
+            State::Ignored => theme.ignored,
+            State::Clean => theme.clean,
         }
     }
 }
@@ -100,27 +106,400 @@ pub struct Entry {
     pub filename: String,
     pub selected: bool,
     pub state: State,
+    /// Original path when the file was renamed or copied
+    pub renamed_from: Option<String>,
+}
+
+impl Entry {
+    /// Filename as drawn, `old -> new` for renames
+    pub fn display_name(&self) -> Cow<'_, str> {
+        match &self.renamed_from {
+            Some(from) => format!("{} -> {}", from, self.filename).into(),
+            None => Cow::Borrowed(&self.filename),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Status,
+    Path,
+    Modified,
+    Size,
+}
+
+impl SortKey {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "status" => Some(SortKey::Status),
+            "path" => Some(SortKey::Path),
+            "modified" => Some(SortKey::Modified),
+            "size" => Some(SortKey::Size),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortKey::Status => "status",
+            SortKey::Path => "path",
+            SortKey::Modified => "modified",
+            SortKey::Size => "size",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            SortKey::Status => SortKey::Path,
+            SortKey::Path => SortKey::Modified,
+            SortKey::Modified => SortKey::Size,
+            SortKey::Size => SortKey::Status,
+        }
+    }
+}
+
+/// Select ui options that persist between selections
+pub struct SelectOptions {
+    pub show_file_info: bool,
+    pub sort_key: SortKey,
+}
+
+impl Default for SelectOptions {
+    fn default() -> Self {
+        Self {
+            show_file_info: false,
+            sort_key: SortKey::Status,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct FileInfo {
+    status_index: usize,
+    pinned: bool,
+    /// Gathered the first time the entry is drawn with its file info or
+    /// sorted by it, as huge lists would take long to stat up front
+    stat: Option<FileStat>,
+}
+
+/// Filesystem info of an entry
+#[derive(Clone, Copy)]
+struct FileStat {
+    size: Option<u64>,
+    modified: Option<SystemTime>,
+}
+
+impl FileStat {
+    fn gather(root: &Path, entry: &Entry) -> Self {
+        let metadata = fs::metadata(root.join(&entry.filename)).ok();
+        Self {
+            size: metadata.as_ref().filter(|m| m.is_file()).map(|m| m.len()),
+            modified: metadata.and_then(|m| m.modified().ok()),
+        }
+    }
+}
+
+/// Entries fuzzy matching the filter, checked only as far as the ui needs
+/// and in chunks while waiting for input, so huge lists don't lag
+struct Matches {
+    /// Filter the entries are matched against
+    filter: Vec<char>,
+    /// Indices of the matching entries found so far, in order
+    found: Vec<usize>,
+    /// Entries which matched a shorter filter and still need checking, as
+    /// only those can match the longer one
+    candidates: Vec<usize>,
+    /// How many of `candidates` were checked
+    checked_candidates: usize,
+    /// Entries from here on weren't matched against any filter
+    unchecked: usize,
+}
+
+impl Matches {
+    fn new() -> Self {
+        Self {
+            filter: Vec::new(),
+            found: Vec::new(),
+            candidates: Vec::new(),
+            checked_candidates: 0,
+            unchecked: 0,
+        }
+    }
+
+    /// Matches every entry again, as after they're reordered
+    fn reset(&mut self) {
+        self.found.clear();
+        self.candidates.clear();
+        self.checked_candidates = 0;
+        self.unchecked = 0;
+    }
+
+    /// Matches against `filter` reusing the previous matches when it only
+    /// got longer
+    fn set_filter(&mut self, filter: &[char]) {
+        if filter.starts_with(&self.filter) {
+            let mut candidates = mem::take(&mut self.found);
+            candidates
+                .extend_from_slice(&self.candidates[self.checked_candidates..]);
+            self.candidates = candidates;
+            self.checked_candidates = 0;
+        } else {
+            self.reset();
+        }
+        self.filter.clear();
+        self.filter.extend_from_slice(filter);
+    }
+
+    fn is_complete(&self, entries: &[Entry]) -> bool {
+        self.checked_candidates == self.candidates.len()
+            && self.unchecked == entries.len()
+    }
+
+    /// Checks at most `budget` entries until `count` matches were found
+    fn scan(&mut self, entries: &[Entry], count: usize, mut budget: usize) {
+        while self.found.len() < count && budget > 0 {
+            let index = if self.checked_candidates < self.candidates.len() {
+                self.checked_candidates += 1;
+                self.candidates[self.checked_candidates - 1]
+            } else if self.unchecked < entries.len() {
+                self.unchecked += 1;
+                self.unchecked - 1
+            } else {
+                break;
+            };
+            if fuzzy_matches(&entries[index].filename[..], &self.filter[..]) {
+                self.found.push(index);
+            }
+            budget -= 1;
+        }
+    }
+}
+
+fn format_size(size: Option<u64>) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+
+    let size = match size {
+        Some(size) => size,
+        None => return "-".into(),
+    };
+    if size < 1024 {
+        return format!("{}B", size);
+    }
+
+    let mut size = size as f64;
+    let mut unit = UNITS[0];
+    for u in &UNITS {
+        size /= 1024.0;
+        unit = u;
+        if size < 1024.0 {
+            break;
+        }
+    }
+    if size < 10.0 {
+        format!("{:.1}{}", size, unit)
+    } else {
+        format!("{:.0}{}", size, unit)
+    }
+}
+
+fn format_age(modified: Option<SystemTime>) -> String {
+    let age = match modified.and_then(|m| m.elapsed().ok()) {
+        Some(age) => age,
+        None => return "-".into(),
+    };
+
+    let secs = age.as_secs();
+    let (value, unit) = if secs < 60 {
+        (secs, "s")
+    } else if secs < 60 * 60 {
+        (secs / 60, "m")
+    } else if secs < 24 * 60 * 60 {
+        (secs / (60 * 60), "h")
+    } else if secs < 365 * 24 * 60 * 60 {
+        (secs / (24 * 60 * 60), "d")
+    } else {
+        (secs / (365 * 24 * 60 * 60), "y")
+    };
+    format!("{}{}", value, unit)
 }
 
 struct Select<'a> {
     entries: &'a mut [Entry],
+    infos: Vec<FileInfo>,
+    root: &'a Path,
+    options: &'a mut SelectOptions,
     scroll: usize,
     cursor: usize,
+    /// Where a range of entries to select starts, the cursor being its end
+    mark: Option<usize>,
+    /// Whether each entry was selected when the mark was set, which the
+    /// ones leaving the range get back
+    premark_selection: Vec<bool>,
     filter: Vec<char>,
+    matches: Matches,
 }
 
 impl<'a> Select<'a> {
-    fn filtered_entries(&self) -> impl Iterator<Item = &Entry> {
-        self.entries
-            .iter()
-            .filter(move |e| fuzzy_matches(&e.filename[..], &self.filter[..]))
+    /// Finds up to `count` filtered entries, returning how many there are
+    fn scan_matches(&mut self, count: usize) -> usize {
+        self.matches.scan(self.entries, count, usize::MAX);
+        self.matches.found.len()
+    }
+
+    /// Finds every filtered entry, returning how many there are
+    fn scan_all_matches(&mut self) -> usize {
+        self.scan_matches(usize::MAX)
+    }
+
+    /// Filtered entry at `index`
+    fn filtered_entry_mut(&mut self, index: usize) -> Option<&mut Entry> {
+        self.scan_matches(index + 1);
+        let entry_index = *self.matches.found.get(index)?;
+        Some(&mut self.entries[entry_index])
+    }
+
+    fn set_filtered_selected(&mut self, selected: bool) {
+        self.scan_all_matches();
+        for &i in &self.matches.found {
+            self.entries[i].selected = selected;
+        }
+    }
+
+    /// Number of filtered entries to move the cursor by `delta` within,
+    /// which is only all of them when it may wrap around to the last one
+    fn move_count(
+        &mut self,
+        available_size: AvailableSize,
+        delta: i32,
+    ) -> usize {
+        if self.cursor == 0 && delta < 0 {
+            self.scan_all_matches()
+        } else {
+            let target = (self.cursor as i32 + delta).max(0) as usize;
+            self.scan_matches(target + available_size.height + 1)
+        }
+    }
+
+    fn stat(&mut self, index: usize) -> FileStat {
+        let root = self.root;
+        let entry = &self.entries[index];
+        *self.infos[index]
+            .stat
+            .get_or_insert_with(|| FileStat::gather(root, entry))
+    }
+
+    /// Sets the mark at the cursor
+    fn set_mark(&mut self) {
+        self.mark = Some(self.cursor);
+        self.premark_selection =
+            self.entries.iter().map(|e| e.selected).collect();
     }
 
-    fn filtered_entries_mut(&mut self) -> impl Iterator<Item = &mut Entry> {
-        let filter = &self.filter;
-        self.entries
-            .iter_mut()
-            .filter(move |e| fuzzy_matches(&e.filename[..], &filter[..]))
+    /// Filtered entries from the mark to the cursor, both included
+    fn marked_range(&self) -> Option<RangeInclusive<usize>> {
+        let mark = self.mark?;
+        Some(mark.min(self.cursor)..=mark.max(self.cursor))
+    }
+
+    fn set_range_selected(
+        &mut self,
+        range: RangeInclusive<usize>,
+        selected: bool,
+    ) {
+        let (start, end) = range.into_inner();
+        self.scan_matches(end + 1);
+        for &i in self.matches.found.iter().skip(start).take(end + 1 - start) {
+            self.entries[i].selected = selected;
+        }
+    }
+
+    fn invert_selection(&mut self) {
+        self.scan_all_matches();
+        for &i in &self.matches.found {
+            self.entries[i].selected = !self.entries[i].selected;
+        }
+    }
+
+    /// Moves the cursor selecting every entry from the mark, set at the
+    /// cursor if there's none, to where it lands
+    fn extend_selection<W>(
+        &mut self,
+        write: &mut W,
+        available_size: AvailableSize,
+        delta: i32,
+    ) -> Result<()>
+    where
+        W: Write,
+    {
+        if self.mark.is_none() {
+            self.set_mark();
+        }
+        let previous_range = self.marked_range();
+        let entry_count = self.move_count(available_size, delta);
+        move_cursor(
+            &mut self.scroll,
+            &mut self.cursor,
+            available_size,
+            entry_count,
+            delta,
+        );
+        if let (Some(previous), Some(range)) =
+            (previous_range, self.marked_range())
+        {
+            self.scan_matches(previous.end() + 1);
+            for (index, &i) in self.matches.found.iter().enumerate() {
+                if previous.contains(&index) && !range.contains(&index) {
+                    self.entries[i].selected = self.premark_selection[i];
+                }
+            }
+            self.set_range_selected(range, true);
+        }
+        self.draw_all_entries(write, available_size)
+    }
+
+    /// Reorders entries by the current sort key keeping the status order
+    /// for ties, with the pinned ones first
+    fn sort(&mut self) {
+        self.mark = None;
+        if matches!(self.options.sort_key, SortKey::Size | SortKey::Modified) {
+            for i in 0..self.entries.len() {
+                self.stat(i);
+            }
+        }
+        let mut order: Vec<_> = (0..self.entries.len()).collect();
+        let infos = &self.infos;
+        match self.options.sort_key {
+            SortKey::Status => {
+                order.sort_by_key(|&i| infos[i].status_index);
+            }
+            SortKey::Path => {
+                let entries = &self.entries;
+                order.sort_by(|&a, &b| {
+                    entries[a].filename.cmp(&entries[b].filename)
+                });
+            }
+            SortKey::Size => order.sort_by(|&a, &b| {
+                let size = |i: usize| infos[i].stat.and_then(|s| s.size);
+                size(b)
+                    .cmp(&size(a))
+                    .then(infos[a].status_index.cmp(&infos[b].status_index))
+            }),
+            SortKey::Modified => order.sort_by(|&a, &b| {
+                let modified =
+                    |i: usize| infos[i].stat.and_then(|s| s.modified);
+                modified(b)
+                    .cmp(&modified(a))
+                    .then(infos[a].status_index.cmp(&infos[b].status_index))
+            }),
+        }
+
+        order.sort_by_key(|&i| !infos[i].pinned);
+
+        let entries: Vec<_> =
+            order.iter().map(|&i| self.entries[i].clone()).collect();
+        self.entries.clone_from_slice(&entries[..]);
+        self.infos = order.iter().map(|&i| self.infos[i]).collect();
+        self.matches.reset();
     }
 
     fn move_cursor<W>(
@@ -132,7 +511,7 @@ impl<'a> Select<'a> {
     where
         W: Write,
     {
-        let entry_count = self.filtered_entries().count();
+        let entry_count = self.move_count(available_size, delta);
         move_cursor(
             &mut self.scroll,
             &mut self.cursor,
@@ -145,7 +524,7 @@ impl<'a> Select<'a> {
     }
 
     fn draw_all_entries<W>(
-        &self,
+        &mut self,
         write: &mut W,
         available_size: AvailableSize,
     ) -> Result<()>
@@ -155,47 +534,95 @@ impl<'a> Select<'a> {
         handle_command!(write, cursor::MoveTo(0, 1))?;
         handle_command!(write, ResetColor)?;
 
-        for (i, entry) in self
-            .filtered_entries()
-            .enumerate()
-            .skip(self.scroll)
-            .take(available_size.height)
-        {
+        self.scan_matches(self.scroll + available_size.height);
+        let marked_range = self.marked_range();
+        let name_column = if self.options.show_file_info {
+            ITEM_NAME_COLUMN + SIZE_COLUMN_WIDTH + AGE_COLUMN_WIDTH
+        } else {
+            ITEM_NAME_COLUMN
+        };
+
+        let visible_len = self
+            .matches
+            .found
+            .len()
+            .saturating_sub(self.scroll)
+            .min(available_size.height);
+        for i in self.scroll..self.scroll + visible_len {
+            let index = self.matches.found[i];
+            let stat = match self.options.show_file_info {
+                true => Some(self.stat(index)),
+                false => None,
+            };
+            let entry = &self.entries[index];
+            let info = &self.infos[index];
             if i == self.cursor {
-                handle_command!(write, SetBackgroundColor(SELECTED_BG_COLOR))?;
+                handle_command!(
+                    write,
+                    SetBackgroundColor(theme().selected_bg)
+                )?;
             } else {
                 handle_command!(write, ResetColor)?;
             }
 
             let select_char = if entry.selected { '+' } else { ' ' };
+            let mark_char = match &marked_range {
+                Some(range) if range.contains(&i) => '|',
+                _ => ' ',
+            };
             let state_name = format!("{:?}", entry.state);
 
             handle_command!(write, Print(select_char))?;
-            handle_command!(write, Print(' '))?;
+            handle_command!(write, Print(mark_char))?;
             handle_command!(write, SetForegroundColor(entry.state.color()))?;
             handle_command!(write, Print(&state_name))?;
             handle_command!(write, ResetColor)?;
 
             if i == self.cursor {
-                handle_command!(write, SetBackgroundColor(SELECTED_BG_COLOR))?;
+                handle_command!(
+                    write,
+                    SetBackgroundColor(theme().selected_bg)
+                )?;
             } else {
                 handle_command!(write, ResetColor)?;
             }
 
-            let cursor_x = 2 + state_name.len();
+            let cursor_x = 2 + str_width(&state_name);
             for _ in cursor_x..ITEM_NAME_COLUMN {
                 handle_command!(write, Print(' '))?;
             }
-            let slice_start = entry
-                .filename
-                .char_indices()
-                .rev()
-                .take(available_size.width - ITEM_NAME_COLUMN)
-                .last()
-                .map(|(i, _)| i)
-                .unwrap_or(0);
-
-            handle_command!(write, Print(&entry.filename[slice_start..]))?;
+            if let Some(stat) = stat {
+                let size = format_size(stat.size);
+                let age = format_age(stat.modified);
+                handle_command!(
+                    write,
+                    Print(format!(
+                        "{:>size_width$} {:>age_width$} ",
+                        size,
+                        age,
+                        size_width = SIZE_COLUMN_WIDTH - 1,
+                        age_width = AGE_COLUMN_WIDTH - 1,
+                    ))
+                )?;
+            }
+            let marker_width = match info.pinned {
+                true => PIN_MARKER.len() + 1,
+                false => 0,
+            };
+            let name = entry.display_name();
+            let name = fit_width_end(
+                &name,
+                available_size
+                    .width
+                    .saturating_sub(name_column + marker_width),
+            );
+            handle_command!(write, Print(name))?;
+            if info.pinned {
+                handle_command!(write, SetForegroundColor(theme().entry))?;
+                handle_command!(write, Print(' '))?;
+                handle_command!(write, Print(PIN_MARKER))?;
+                handle_command!(write, ResetColor)?;
+            }
             handle_command!(write, Clear(ClearType::UntilNewLine))?;
             handle_command!(write, cursor::MoveToNextLine(1))?;
         }
@@ -217,42 +644,119 @@ impl<'a> Select<'a> {
     {
         self.cursor = 0;
         self.scroll = 0;
+        self.mark = None;
+        self.matches.set_filter(&self.filter);
         self.draw_all_entries(write, available_size)?;
         Ok(())
     }
 }
 
-pub fn select<W>(write: &mut W, entries: &mut [Entry]) -> Result<bool>
+pub fn select<W>(
+    write: &mut W,
+    entries: &mut [Entry],
+    root: &str,
+    options: &mut SelectOptions,
+    pinned_files: &BTreeSet<String>,
+    mut side_pane: Option<&mut dyn SidePane<W>>,
+) -> Result<bool>
 where
     W: Write,
 {
-    if entries.len() == 0 {
+    if entries.is_empty() {
         return Ok(false);
     }
 
+    let infos = entries
+        .iter()
+        .enumerate()
+        .map(|(i, e)| FileInfo {
+            status_index: i,
+            pinned: pinned_files.contains(&e.filename),
+            stat: None,
+        })
+        .collect();
     let mut select = Select {
         entries,
+        infos,
+        root: Path::new(root),
+        options,
         scroll: 0,
         cursor: 0,
+        mark: None,
+        premark_selection: Vec::new(),
         filter: Vec::new(),
+        matches: Matches::new(),
     };
+    select.sort();
 
-    let mut available_size =
-        AvailableSize::from_temrinal_size(TerminalSize::get()?);
+    let mut terminal_size = TerminalSize::get()?;
+    let mut available_size = list_size(terminal_size, side_pane.as_deref());
     select.draw_all_entries(write, available_size)?;
 
     loop {
         write.queue(cursor::MoveTo(0, 2))?;
         write.flush()?;
-        match event::read()? {
+        while !select.matches.is_complete(select.entries)
+            && !input::poll(Duration::from_millis(0))?
+        {
+            select
+                .matches
+                .scan(select.entries, usize::MAX, SCAN_CHUNK_LEN);
+        }
+        if let Some(pane) = side_pane.as_deref_mut() {
+            // every event below draws the entries over the pane
+            let mut redrawn = true;
+            loop {
+                if let Some(hovered) = select.filtered_entry_mut(select.cursor)
+                {
+                    pane.update(write, hovered, terminal_size, redrawn)?;
+                }
+                redrawn = false;
+                write.queue(cursor::MoveTo(0, 2))?;
+                write.flush()?;
+                if input::poll(SIDE_PANE_POLL_INTERVAL)? {
+                    break;
+                }
+            }
+        }
+        match input::read()? {
             event::Event::Resize(width, height) => {
-                available_size =
-                    AvailableSize::from_temrinal_size(TerminalSize {
-                        width,
-                        height,
-                    });
+                terminal_size = TerminalSize { width, height };
+                available_size = list_size(terminal_size, side_pane.as_deref());
             }
-            event::Event::Key(key_event) => match key_event {
+            event::Event::Mouse(mouse_event) => match mouse_event {
+                MouseEvent::ScrollDown(..) => {
+                    select.move_cursor(
+                        write,
+                        available_size,
+                        MOUSE_SCROLL_DELTA,
+                    )?;
+                }
+                MouseEvent::ScrollUp(..) => {
+                    select.move_cursor(
+                        write,
+                        available_size,
+                        -MOUSE_SCROLL_DELTA,
+                    )?;
+                }
+                MouseEvent::Down(MouseButton::Left, _, row, _) if row > 0 => {
+                    let index = select.scroll + row as usize - 1;
+                    if let Some(e) = select.filtered_entry_mut(index) {
+                        e.selected = !e.selected;
+                        select.cursor = index;
+                    }
+                    select.draw_all_entries(write, available_size)?;
+                }
+                _ => (),
+            },
+            event::Event::Key(KeyEvent {
+                code: code @ (KeyCode::Down | KeyCode::Up),
+                modifiers: KeyModifiers::SHIFT,
+            }) => {
+                let delta = if code == KeyCode::Down { 1 } else { -1 };
+                select.extend_selection(write, available_size, delta)?;
+            }
+            event::Event::Key(key) => match input::normalize_key(key) {
                 KeyEvent {
                     code: KeyCode::Esc, ..
                 }
@@ -264,13 +768,14 @@ where
                     code: KeyCode::Char('q'),
                     modifiers: KeyModifiers::CONTROL,
                 } => {
-                    if select.filter.len() > 0 {
+                    if !select.filter.is_empty() {
                         select.filter.clear();
                         select.on_filter_changed(write, available_size)?;
+                    } else if select.mark.is_some() {
+                        select.mark = None;
+                        select.draw_all_entries(write, available_size)?;
                     } else {
-                        for e in select.filtered_entries_mut() {
-                            e.selected = false;
-                        }
+                        select.set_filtered_selected(false);
                         return Ok(false);
                     }
                 }
@@ -285,9 +790,7 @@ where
                     let cursor = select.cursor;
                     if select.entries.iter().filter(|e| e.selected).count() == 0
                     {
-                        if let Some(e) =
-                            select.filtered_entries_mut().nth(cursor)
-                        {
+                        if let Some(e) = select.filtered_entry_mut(cursor) {
                             e.selected = true;
                         }
                     }
@@ -329,8 +832,7 @@ where
                     ..
                 } => {
                     let height = select
-                        .filtered_entries()
-                        .count()
+                        .scan_matches(available_size.height)
                         .min(available_size.height);
                     select.move_cursor(
                         write,
@@ -347,8 +849,7 @@ where
                     ..
                 } => {
                     let height = select
-                        .filtered_entries()
-                        .count()
+                        .scan_matches(available_size.height)
                         .min(available_size.height);
                     select.move_cursor(
                         write,
@@ -379,11 +880,11 @@ where
                 | KeyEvent {
                     code: KeyCode::End, ..
                 } => {
-                    let entries_len = select.filtered_entries().count();
+                    let entries_len = select.scan_all_matches();
                     select.scroll = 0
                         .max(entries_len as i32 - available_size.height as i32)
                         as usize;
-                    select.cursor = entries_len - 1;
+                    select.cursor = entries_len.saturating_sub(1);
                     select.draw_all_entries(write, available_size)?;
                 }
                 KeyEvent {
@@ -391,7 +892,7 @@ where
                     ..
                 } => {
                     let cursor = select.cursor;
-                    if let Some(e) = select.filtered_entries_mut().nth(cursor) {
+                    if let Some(e) = select.filtered_entry_mut(cursor) {
                         e.selected = !e.selected;
                     }
                     select.draw_all_entries(write, available_size)?;
@@ -400,10 +901,41 @@ where
                     code: KeyCode::Char('a'),
                     modifiers: KeyModifiers::CONTROL,
                 } => {
-                    let all_selected =
-                        select.filtered_entries().all(|e| e.selected);
-                    for e in select.filtered_entries_mut() {
-                        e.selected = !all_selected;
+                    select.scan_all_matches();
+                    let all_selected = select
+                        .matches
+                        .found
+                        .iter()
+                        .all(|&i| select.entries[i].selected);
+                    select.set_filtered_selected(!all_selected);
+                    select.draw_all_entries(write, available_size)?;
+                }
+                KeyEvent {
+                    code: KeyCode::Char('r'),
+                    modifiers: KeyModifiers::CONTROL,
+                } => {
+                    select.invert_selection();
+                    select.draw_all_entries(write, available_size)?;
+                }
+                KeyEvent {
+                    code: KeyCode::Char('v'),
+                    modifiers: KeyModifiers::CONTROL,
+                } => {
+                    match select.mark {
+                        Some(mark) if mark == select.cursor => {
+                            select.mark = None
+                        }
+                        _ => select.set_mark(),
+                    }
+                    select.draw_all_entries(write, available_size)?;
+                }
+                KeyEvent {
+                    code: KeyCode::Char('x'),
+                    modifiers: KeyModifiers::CONTROL,
+                } => {
+                    if let Some(range) = select.marked_range() {
+                        select.set_range_selected(range, true);
+                        select.mark = None;
                     }
                     select.draw_all_entries(write, available_size)?;
                 }
@@ -415,7 +947,7 @@ where
                     code: KeyCode::Backspace,
                     ..
                 } => {
-                    if select.filter.len() > 0 {
+                    if !select.filter.is_empty() {
                         select.filter.remove(select.filter.len() - 1);
                     }
                     select.on_filter_changed(write, available_size)?;
@@ -427,6 +959,22 @@ where
                     select.filter.clear();
                     select.on_filter_changed(write, available_size)?;
                 }
+                KeyEvent {
+                    code: KeyCode::Char('t'),
+                    modifiers: KeyModifiers::CONTROL,
+                } => {
+                    select.options.show_file_info =
+                        !select.options.show_file_info;
+                    select.draw_all_entries(write, available_size)?;
+                }
+                KeyEvent {
+                    code: KeyCode::Char('s'),
+                    modifiers: KeyModifiers::CONTROL,
+                } => {
+                    select.options.sort_key = select.options.sort_key.next();
+                    select.sort();
+                    select.draw_all_entries(write, available_size)?;
+                }
                 key_event => {
                     if let Some(c) = input::key_to_char(key_event) {
                         select.filter.push(c);
@@ -434,7 +982,18 @@ where
                     }
                 }
             },
-            _ => (),
         }
     }
 }
+
+/// Size left for the entries at the left of the side pane, if any
+fn list_size<W>(
+    terminal_size: TerminalSize,
+    side_pane: Option<&dyn SidePane<W>>,
+) -> AvailableSize {
+    let mut size = AvailableSize::from_temrinal_size(terminal_size);
+    if let Some(pane) = side_pane {
+        size.width = pane.list_width(terminal_size);
+    }
+    size
+}
@@ -0,0 +1,385 @@
+use std::{
+    collections::HashMap,
+    env,
+    io::{self, BufRead, BufReader, Write},
+};
+
+use crate::{
+    application::Application,
+    args::StartupMode,
+    cli::{self, json_object, Field, Fields},
+    json::{parse_object, quote, Value},
+    tui_util::strip_ansi,
+};
+
+// error codes defined by json-rpc 2.0
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+/// Code of the errors telling the output of a failed backend command
+const BACKEND_ERROR: i32 = 1;
+
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn backend(output: &str) -> Self {
+        Self::new(BACKEND_ERROR, strip_ansi(output.trim()))
+    }
+}
+
+/// Answers json-rpc 2.0 requests, one per line, asking for the status,
+/// diffs, log, branches, tags or a commit of the repository at `directory`
+/// or the current one, so editor plugins don't run the backend themselves.
+/// Requests come from stdin, or from the clients connecting one at a time to
+/// the unix socket at `socket`. Returns the exit code of the process
+pub fn serve(socket: Option<&str>, directory: Option<&str>) -> i32 {
+    let mut app = match cli::open_application(directory) {
+        Some(app) => app,
+        None => return 1,
+    };
+    let result = match socket {
+        Some(path) => serve_socket(&mut app, path),
+        None => {
+            let stdin = io::stdin();
+            let stdout = io::stdout();
+            serve_connection(&mut app, stdin.lock(), stdout.lock()).map(|_| ())
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("{}", error);
+            1
+        }
+    }
+}
+
+#[cfg(unix)]
+fn serve_socket(app: &mut Application, path: &str) -> io::Result<()> {
+    use std::{fs, os::unix::net::UnixListener};
+
+    let listener = UnixListener::bind(path)?;
+    let result = (|| {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let reader = BufReader::new(stream.try_clone()?);
+            // a client going away shouldn't stop serving the next ones
+            if let Ok(true) = serve_connection(app, reader, stream) {
+                break;
+            }
+        }
+        Ok(())
+    })();
+    let _ = fs::remove_file(path);
+    result
+}
+
+/// Serves the clients of the named pipe at `path`, under `\\.\pipe\`
+/// unless it already is, one at a time
+#[cfg(windows)]
+fn serve_socket(app: &mut Application, path: &str) -> io::Result<()> {
+    use std::{
+        ffi::{c_void, OsStr},
+        fs::File,
+        os::windows::{ffi::OsStrExt, io::FromRawHandle},
+        ptr,
+    };
+
+    const PIPE_ACCESS_DUPLEX: u32 = 0x3;
+    const PIPE_REJECT_REMOTE_CLIENTS: u32 = 0x8;
+    const PIPE_UNLIMITED_INSTANCES: u32 = 255;
+    const BUFFER_SIZE: u32 = 4096;
+    const ERROR_PIPE_CONNECTED: i32 = 535;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreateNamedPipeW(
+            name: *const u16,
+            open_mode: u32,
+            pipe_mode: u32,
+            max_instances: u32,
+            out_buffer_size: u32,
+            in_buffer_size: u32,
+            default_timeout: u32,
+            security_attributes: *mut c_void,
+        ) -> *mut c_void;
+        fn ConnectNamedPipe(pipe: *mut c_void, overlapped: *mut c_void) -> i32;
+        fn DisconnectNamedPipe(pipe: *mut c_void) -> i32;
+    }
+
+    let path = match path.starts_with(r"\\.\pipe\") {
+        true => path.into(),
+        false => format!(r"\\.\pipe\{}", path),
+    };
+    let name: Vec<_> = OsStr::new(&path).encode_wide().chain(Some(0)).collect();
+    loop {
+        // each instance of the pipe serves a single client, in byte mode
+        // and blocking like the unix socket
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        if handle as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // closes the handle when dropped
+        let pipe = unsafe { File::from_raw_handle(handle) };
+        // a client connecting between the two calls is already connected
+        let connected = unsafe { ConnectNamedPipe(handle, ptr::null_mut()) };
+        if connected == 0 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() != Some(ERROR_PIPE_CONNECTED) {
+                return Err(error);
+            }
+        }
+
+        let reader = BufReader::new(pipe.try_clone()?);
+        // a client going away shouldn't stop serving the next ones
+        let shutdown = serve_connection(app, reader, &pipe);
+        unsafe { DisconnectNamedPipe(handle) };
+        if let Ok(true) = shutdown {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn serve_socket(_app: &mut Application, _path: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "sockets are only supported on unix and windows, serve on stdin and \
+         stdout instead",
+    ))
+}
+
+/// Answers the requests read from `reader` until it ends. Returns whether a
+/// client asked to shut down
+fn serve_connection<R, W>(
+    app: &mut Application,
+    reader: R,
+    mut writer: W,
+) -> io::Result<bool>
+where
+    R: BufRead,
+    W: Write,
+{
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request = match parse_object(&line) {
+            Some(request) => request,
+            None => {
+                let error = RpcError::new(PARSE_ERROR, "invalid json");
+                write_response(&mut writer, "null", Err(error))?;
+                continue;
+            }
+        };
+        // requests without an id are notifications which get no response
+        let id = match request.get("id") {
+            Some(Value::Number(id)) => Some(id.clone()),
+            Some(Value::String(id)) => Some(quote(id)),
+            Some(Value::Null) => Some("null".into()),
+            None => None,
+            Some(_) => {
+                let error = RpcError::new(INVALID_REQUEST, "invalid id");
+                write_response(&mut writer, "null", Err(error))?;
+                continue;
+            }
+        };
+
+        let empty = HashMap::new();
+        let params = match request.get("params") {
+            Some(Value::Object(params)) => params,
+            _ => &empty,
+        };
+        let (result, shutdown) = match request.get("method") {
+            Some(Value::String(method)) if method == "shutdown" => {
+                (Ok("null".into()), true)
+            }
+            Some(Value::String(method)) => (call(app, method, params), false),
+            _ => {
+                let error = RpcError::new(INVALID_REQUEST, "missing method");
+                (Err(error), false)
+            }
+        };
+
+        if let Some(id) = id {
+            write_response(&mut writer, &id, result)?;
+        }
+        if shutdown {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn write_response<W>(
+    writer: &mut W,
+    id: &str,
+    result: Result<String, RpcError>,
+) -> io::Result<()>
+where
+    W: Write,
+{
+    let member = match result {
+        Ok(result) => format!("\"result\": {}", result),
+        Err(error) => format!(
+            "\"error\": {{\"code\": {}, \"message\": {}}}",
+            error.code,
+            quote(&error.message)
+        ),
+    };
+    writeln!(
+        writer,
+        "{{\"jsonrpc\": \"2.0\", \"id\": {}, {}}}",
+        id, member
+    )?;
+    writer.flush()
+}
+
+/// Runs `method`, returning its json result
+fn call(
+    app: &mut Application,
+    method: &str,
+    params: &HashMap<String, Value>,
+) -> Result<String, RpcError> {
+    let mode = match method {
+        "status" => Some(StartupMode::Status),
+        "log" => Some(StartupMode::Log),
+        "branches" => Some(StartupMode::Branches),
+        "tags" => Some(StartupMode::Tags),
+        _ => None,
+    };
+    if let Some(mode) = mode {
+        let count = match params.get("count") {
+            None | Some(Value::Null) => None,
+            Some(count) => match count.as_usize() {
+                Some(count) => Some(count),
+                None => return Err(invalid_param("count")),
+            },
+        };
+        let entries = cli::entries(app, mode, count)
+            .map_err(|error| RpcError::backend(&error))?;
+        return Ok(json_array(&entries));
+    }
+
+    match method {
+        "info" => {
+            let fields = cli::info_fields(app)
+                .map_err(|error| RpcError::backend(&error))?;
+            Ok(json_object(&fields))
+        }
+        "diff" => {
+            let paths = string_array(params, "paths")?;
+            let current_dir = env::current_dir()
+                .map_err(|error| RpcError::backend(&error.to_string()))?;
+            let diff = if paths.is_empty() {
+                app.version_control.current_diff_all(None)
+            } else {
+                let entries = cli::path_entries(app, &current_dir, &paths)
+                    .map_err(|error| RpcError::new(INVALID_PARAMS, error))?;
+                app.version_control.current_diff_selected(&entries)
+            };
+            let result = app.run_to_completion(diff);
+            if !result.success {
+                return Err(RpcError::backend(&result.output));
+            }
+            Ok(json_object(&[(
+                "diff",
+                Field::Text(strip_ansi(&result.output)),
+            )]))
+        }
+        "commit" => {
+            let message = match params.get("message") {
+                Some(Value::String(message)) if !message.trim().is_empty() => {
+                    message
+                }
+                _ => return Err(invalid_param("message")),
+            };
+            let no_verify = match params.get("no_verify") {
+                Some(Value::Bool(no_verify)) => *no_verify,
+                None | Some(Value::Null) => false,
+                Some(_) => return Err(invalid_param("no_verify")),
+            };
+            let paths = string_array(params, "paths")?;
+            let current_dir = env::current_dir()
+                .map_err(|error| RpcError::backend(&error.to_string()))?;
+            let entries = cli::path_entries(app, &current_dir, &paths)
+                .map_err(|error| RpcError::new(INVALID_PARAMS, error))?;
+            if entries.is_empty() {
+                return Err(RpcError::backend("nothing to commit"));
+            }
+
+            let result = cli::run_commit(
+                app,
+                message,
+                &entries,
+                paths.is_empty(),
+                no_verify,
+                None,
+            );
+            if !result.success {
+                return Err(RpcError::backend(&result.output));
+            }
+            Ok(json_object(&[(
+                "output",
+                Field::Text(strip_ansi(result.output.trim())),
+            )]))
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method '{}'", method),
+        )),
+    }
+}
+
+fn json_array(entries: &[Fields]) -> String {
+    let entries: Vec<_> =
+        entries.iter().map(|fields| json_object(fields)).collect();
+    format!("[{}]", entries.join(", "))
+}
+
+fn invalid_param(name: &str) -> RpcError {
+    RpcError::new(INVALID_PARAMS, format!("invalid '{}'", name))
+}
+
+/// Strings of the array param `name`, empty when it's missing
+fn string_array(
+    params: &HashMap<String, Value>,
+    name: &str,
+) -> Result<Vec<String>, RpcError> {
+    let values = match params.get(name) {
+        Some(Value::Array(values)) => values,
+        None | Some(Value::Null) => return Ok(Vec::new()),
+        Some(_) => return Err(invalid_param(name)),
+    };
+    values
+        .iter()
+        .map(|value| match value {
+            Value::String(value) => Ok(value.clone()),
+            _ => Err(invalid_param(name)),
+        })
+        .collect()
+}
@@ -0,0 +1,164 @@
+use std::ops::Range;
+
+use crate::{
+    folds,
+    theme::{ansi_color, theme},
+    tui_util::{char_width, strip_ansi, HunkLines},
+    word_diff::{self, HIGHLIGHT, HIGHLIGHT_END},
+};
+
+/// Narrower terminals keep showing diffs unified
+pub const MIN_WIDTH: usize = 120;
+
+const RESET_COLOR: &str = "\x1b[0m";
+const SEPARATOR: &str = " \u{2502} ";
+
+/// Rearranges the colored unified `diff` into two columns fitting `width`,
+/// the old lines on the left and the new ones on the right. Removed and
+/// added lines of a hunk are paired up in order, file headers and hunk
+/// headers span both columns as they were. With `word_diff` the changed
+/// words of each pair are highlighted
+pub fn layout(diff: &str, width: usize, word_diff: bool) -> String {
+    let column_width = (width - SEPARATOR.chars().count()) / 2;
+    let theme = theme();
+    let mut layout = Layout {
+        removed_color: ansi_color(theme.diff_removed),
+        added_color: ansi_color(theme.diff_added),
+        output: String::with_capacity(diff.len() * 2),
+        column_width,
+        word_diff,
+        removed: Vec::new(),
+        added: Vec::new(),
+    };
+
+    let mut hunk_lines = HunkLines::default();
+    for line in diff.lines() {
+        let plain = strip_ansi(line);
+        if let Some(count) = folds::hidden_lines(line) {
+            hunk_lines.skip_unchanged(count);
+            layout.flush();
+            layout.push_full_width(line);
+            continue;
+        }
+        if hunk_lines.start(&plain) || !hunk_lines.next(&plain) {
+            layout.flush();
+            layout.push_full_width(line);
+            continue;
+        }
+
+        match plain.chars().next() {
+            Some('-') => layout.removed.push(plain[1..].into()),
+            Some('+') => layout.added.push(plain[1..].into()),
+            Some(' ') => {
+                layout.flush();
+                layout.push_row(
+                    Some((&plain[1..], "", &[])),
+                    Some((&plain[1..], "", &[])),
+                );
+            }
+            // like `\ No newline at end of file`
+            _ => {
+                layout.flush();
+                layout.push_full_width(line);
+            }
+        }
+    }
+    layout.flush();
+    layout.output
+}
+
+struct Layout {
+    output: String,
+    column_width: usize,
+    word_diff: bool,
+    removed_color: String,
+    added_color: String,
+    /// Lines of the current block of changes waiting to be paired
+    removed: Vec<String>,
+    added: Vec<String>,
+}
+
+impl Layout {
+    fn push_full_width(&mut self, line: &str) {
+        self.output.push_str(line);
+        self.output.push('\n');
+    }
+
+    /// Pairs the removed lines with the added ones since the last context
+    fn flush(&mut self) {
+        let removed = std::mem::take(&mut self.removed);
+        let added = std::mem::take(&mut self.added);
+        let removed_color = self.removed_color.clone();
+        let added_color = self.added_color.clone();
+        for i in 0..removed.len().max(added.len()) {
+            let changes = match (removed.get(i), added.get(i)) {
+                (Some(old), Some(new)) if self.word_diff => {
+                    word_diff::changed_ranges(old, new)
+                }
+                _ => None,
+            };
+            let (removed_ranges, added_ranges) = match &changes {
+                Some((old, new)) => (&old[..], &new[..]),
+                None => (&[][..], &[][..]),
+            };
+            self.push_row(
+                removed
+                    .get(i)
+                    .map(|l| (&l[..], &removed_color[..], removed_ranges)),
+                added
+                    .get(i)
+                    .map(|l| (&l[..], &added_color[..], added_ranges)),
+            );
+        }
+    }
+
+    fn push_row(
+        &mut self,
+        left: Option<(&str, &str, &[Range<usize>])>,
+        right: Option<(&str, &str, &[Range<usize>])>,
+    ) {
+        self.push_column(left, true);
+        self.output.push_str(SEPARATOR);
+        self.push_column(right, false);
+        self.output.push('\n');
+    }
+
+    /// Pushes `text` colored with `color` and its `ranges` highlighted
+    fn push_column(
+        &mut self,
+        column: Option<(&str, &str, &[Range<usize>])>,
+        pad: bool,
+    ) {
+        let mut len = 0;
+        if let Some((text, color, ranges)) = column {
+            self.output.push_str(color);
+            for (i, c) in text.char_indices() {
+                if ranges.iter().any(|r| r.start == i) {
+                    self.output.push_str(HIGHLIGHT);
+                } else if ranges.iter().any(|r| r.end == i) {
+                    self.output.push_str(HIGHLIGHT_END);
+                }
+                let (c, count) = match c {
+                    '\t' => (' ', 4),
+                    c => (c, 1),
+                };
+                let width = char_width(c);
+                for _ in 0..count {
+                    if len + width > self.column_width {
+                        break;
+                    }
+                    self.output.push(c);
+                    len += width;
+                }
+            }
+            if !color.is_empty() {
+                self.output.push_str(RESET_COLOR);
+            }
+        }
+        if pad {
+            for _ in len..self.column_width {
+                self.output.push(' ');
+            }
+        }
+    }
+}
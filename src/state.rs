@@ -0,0 +1,143 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::user_config_dir,
+    json::{parse_object, quote, Value},
+    select::SortKey,
+};
+
+/// Where the state was kept inside the work tree, still read when there's
+/// no state in the user config dir yet
+const LEGACY_STATE_PATH: &str =
+    concat!(".", env!("CARGO_PKG_NAME"), "/state.json");
+
+/// Position inside the output of an action
+#[derive(Default, Clone)]
+pub struct ViewState {
+    pub scroll: usize,
+    pub cursor: Option<usize>,
+    pub filter: String,
+}
+
+/// Ui state restored when reopening the same repository
+#[derive(Default)]
+pub struct UiState {
+    /// Key chord of the last shown action
+    pub mode: String,
+    pub view: ViewState,
+    pub sort_key: Option<SortKey>,
+    pub show_file_info: bool,
+    /// Files shown first in status and select menus
+    pub pinned_files: Vec<String>,
+    /// Branch the status and log headers tell the divergence from
+    pub compare_target: Option<String>,
+}
+
+impl UiState {
+    pub fn load() -> UiState {
+        Self::try_load().unwrap_or_default()
+    }
+
+    fn try_load() -> io::Result<UiState> {
+        let path = state_path()?;
+        let content = fs::read_to_string(&path).or_else(|error| {
+            let legacy_path = env::current_dir()?.join(LEGACY_STATE_PATH);
+            fs::read_to_string(legacy_path).map_err(|_| error)
+        })?;
+        let values = parse_object(&content).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "invalid state")
+        })?;
+
+        let string = |key| match values.get(key) {
+            Some(Value::String(s)) => s.clone(),
+            _ => String::new(),
+        };
+        let number = |key| values.get(key).and_then(Value::as_usize);
+
+        Ok(UiState {
+            mode: string("mode"),
+            view: ViewState {
+                scroll: number("scroll").unwrap_or(0),
+                cursor: number("cursor"),
+                filter: string("filter"),
+            },
+            sort_key: SortKey::from_name(&string("select_sort")),
+            show_file_info: matches!(
+                values.get("show_file_info"),
+                Some(Value::Bool(true))
+            ),
+            pinned_files: string("pinned_files")
+                .lines()
+                .filter(|p| !p.is_empty())
+                .map(String::from)
+                .collect(),
+            compare_target: Some(string("compare_target"))
+                .filter(|t| !t.is_empty()),
+        })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = state_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let cursor = match self.view.cursor {
+            Some(cursor) => cursor.to_string(),
+            None => "null".into(),
+        };
+        let sort_key = match self.sort_key {
+            Some(sort_key) => quote(sort_key.name()),
+            None => "null".into(),
+        };
+        let compare_target = match &self.compare_target {
+            Some(target) => quote(target),
+            None => "null".into(),
+        };
+        let fields = [
+            ("mode", quote(&self.mode)),
+            ("scroll", self.view.scroll.to_string()),
+            ("cursor", cursor),
+            ("filter", quote(&self.view.filter)),
+            ("select_sort", sort_key),
+            ("show_file_info", self.show_file_info.to_string()),
+            ("pinned_files", quote(&self.pinned_files.join("\n"))),
+            ("compare_target", compare_target),
+        ];
+        let mut content = String::from("{\n");
+        for (i, (key, value)) in fields.iter().enumerate() {
+            let separator = if i + 1 < fields.len() { "," } else { "" };
+            content
+                .push_str(&format!("  \"{}\": {}{}\n", key, value, separator));
+        }
+        content.push_str("}\n");
+        fs::write(path, content)
+    }
+}
+
+/// State file of the repository at the current directory. It's kept in the
+/// user config dir so it never shows up as an untracked file, named after the
+/// repository root like `state/%home%me%project.json`
+fn state_path() -> io::Result<PathBuf> {
+    let root = env::current_dir()?;
+    let dir = user_config_dir().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "no user config dir")
+    })?;
+    Ok(dir.join("state").join(state_file_name(&root)))
+}
+
+fn state_file_name(root: &Path) -> String {
+    let mut name: String = root
+        .to_string_lossy()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' => '%',
+            c => c,
+        })
+        .collect();
+    name.push_str(".json");
+    name
+}
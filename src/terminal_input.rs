@@ -0,0 +1,594 @@
+use std::time::Duration;
+
+use crossterm::{event::Event, Result};
+
+/// Only asks the terminal to disambiguate the escape codes. Keys typing text
+/// keep coming as plain characters
+const PUSH_FLAGS: &[u8] = b"\x1b[>1u";
+const POP_FLAGS: &[u8] = b"\x1b[<u";
+
+/// Starts reading the input here instead of in crossterm, whose parser drops
+/// the sequences it doesn't know, like the modified keys of rxvt or of the
+/// xterm `modifyOtherKeys` mode. Must be called in raw mode. With
+/// `kitty_keyboard`, the kitty keyboard protocol is also enabled if the
+/// terminal supports it, which has to happen on the alternate screen since
+/// the terminal drops its flags when leaving it
+pub fn start(kitty_keyboard: bool) {
+    imp::start(kitty_keyboard)
+}
+
+pub fn stop() {
+    imp::stop()
+}
+
+pub fn kitty_keyboard_enabled() -> bool {
+    imp::kitty_keyboard_enabled()
+}
+
+/// Goes back to the legacy keys while another reader, like rustyline, owns
+/// the input
+pub fn suspend() {
+    imp::write_kitty_keyboard_flags(POP_FLAGS)
+}
+
+pub fn resume() {
+    imp::write_kitty_keyboard_flags(PUSH_FLAGS)
+}
+
+/// Like `crossterm::event::poll`. `None` if crossterm reads the input
+pub fn poll(timeout: Duration) -> Option<Result<bool>> {
+    imp::poll(timeout)
+}
+
+/// Like `crossterm::event::read`. `None` if crossterm reads the input
+pub fn read() -> Option<Result<Event>> {
+    imp::read()
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::{
+        collections::VecDeque,
+        fs::{File, OpenOptions},
+        io::{Read, Write},
+        os::unix::io::AsRawFd,
+        sync::Mutex,
+        time::{Duration, Instant},
+    };
+
+    use crossterm::{
+        event::{
+            Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent,
+        },
+        terminal, Result,
+    };
+
+    use super::{POP_FLAGS, PUSH_FLAGS};
+
+    /// Asks for the current flags followed by the primary device attributes,
+    /// which every terminal answers, so a missing flags reply means no support
+    const QUERY: &[u8] = b"\x1b[?u\x1b[c";
+    const QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+    /// How long the rest of an escape sequence is waited for before its bytes
+    /// are taken as keys
+    const ESCAPE_TIMEOUT: Duration = Duration::from_millis(30);
+    /// The terminal size is checked this often while waiting for input
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    /// Modifier masks as sent by the terminal, one more than the bits
+    const SHIFT_MASK: u32 = 1 + 1;
+    const CONTROL_MASK: u32 = 1 + 4;
+    const CONTROL_SHIFT_MASK: u32 = 1 + 4 + 1;
+
+    const KEYPAD_ENTER: u32 = 57414;
+    const F13: u32 = 57376;
+    const F24: u32 = 57387;
+
+    enum Sequence {
+        Event(Event),
+        FlagsReply,
+        AttributesReply,
+        Unknown,
+    }
+
+    static READER: Mutex<Option<Reader>> = Mutex::new(None);
+
+    struct Reader {
+        tty: File,
+        kitty_keyboard: bool,
+        buffer: Vec<u8>,
+        events: VecDeque<Event>,
+        flags_replied: bool,
+        attributes_replied: bool,
+        last_read_at: Instant,
+        terminal_size: (u16, u16),
+    }
+
+    impl Reader {
+        fn open() -> Result<Self> {
+            let tty =
+                OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+            Ok(Self {
+                tty,
+                kitty_keyboard: false,
+                buffer: Vec::new(),
+                events: VecDeque::new(),
+                flags_replied: false,
+                attributes_replied: false,
+                last_read_at: Instant::now(),
+                terminal_size: terminal::size()?,
+            })
+        }
+
+        fn write(&mut self, bytes: &[u8]) -> Result<()> {
+            self.tty.write_all(bytes)?;
+            self.tty.flush()?;
+            Ok(())
+        }
+
+        /// Keys pressed while waiting for the replies are kept
+        fn is_kitty_keyboard_supported(&mut self) -> Result<bool> {
+            self.write(QUERY)?;
+            let deadline = Instant::now() + QUERY_TIMEOUT;
+            while !self.attributes_replied {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                self.read_available(deadline - now)?;
+                self.parse_buffer();
+            }
+            Ok(self.flags_replied)
+        }
+
+        fn poll(&mut self, timeout: Duration) -> Result<bool> {
+            let deadline = Instant::now() + timeout;
+            loop {
+                self.check_resize()?;
+                self.parse_buffer();
+                if !self.buffer.is_empty()
+                    && self.last_read_at.elapsed() >= ESCAPE_TIMEOUT
+                {
+                    self.flush_incomplete();
+                    continue;
+                }
+                if !self.events.is_empty() {
+                    return Ok(true);
+                }
+
+                let now = Instant::now();
+                if now >= deadline {
+                    return Ok(false);
+                }
+                let mut wait = (deadline - now).min(POLL_INTERVAL);
+                if !self.buffer.is_empty() {
+                    wait = wait.min(ESCAPE_TIMEOUT);
+                }
+                self.read_available(wait)?;
+            }
+        }
+
+        fn read_available(&mut self, timeout: Duration) -> Result<()> {
+            let mut fd = libc::pollfd {
+                fd: self.tty.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout = timeout.as_millis() as libc::c_int;
+            if unsafe { libc::poll(&mut fd, 1, timeout) } <= 0 {
+                return Ok(());
+            }
+
+            let mut bytes = [0; 1024];
+            let len = self.tty.read(&mut bytes)?;
+            self.buffer.extend_from_slice(&bytes[..len]);
+            self.last_read_at = Instant::now();
+            Ok(())
+        }
+
+        fn parse_buffer(&mut self) {
+            while let Some((sequence, len)) = parse_sequence(&self.buffer) {
+                self.buffer.drain(..len);
+                match sequence {
+                    Sequence::Event(event) => self.events.push_back(event),
+                    Sequence::FlagsReply => self.flags_replied = true,
+                    Sequence::AttributesReply => self.attributes_replied = true,
+                    Sequence::Unknown => (),
+                }
+            }
+        }
+
+        /// Nothing else arrived so a lone escape is the esc key itself
+        fn flush_incomplete(&mut self) {
+            if self.buffer.remove(0) == 0x1b {
+                self.events.push_back(Event::Key(KeyCode::Esc.into()));
+            }
+        }
+
+        fn check_resize(&mut self) -> Result<()> {
+            let size = terminal::size()?;
+            if size != self.terminal_size {
+                self.terminal_size = size;
+                self.events.push_back(Event::Resize(size.0, size.1));
+            }
+            Ok(())
+        }
+    }
+
+    pub fn start(kitty_keyboard: bool) {
+        let mut reader = match Reader::open() {
+            Ok(reader) => reader,
+            Err(_) => return,
+        };
+        if kitty_keyboard
+            && matches!(reader.is_kitty_keyboard_supported(), Ok(true))
+        {
+            reader.kitty_keyboard = reader.write(PUSH_FLAGS).is_ok();
+        }
+        *READER.lock().unwrap() = Some(reader);
+    }
+
+    pub fn stop() {
+        if let Some(mut reader) = READER.lock().unwrap().take() {
+            if reader.kitty_keyboard {
+                let _ = reader.write(POP_FLAGS);
+            }
+        }
+    }
+
+    pub fn kitty_keyboard_enabled() -> bool {
+        let reader = READER.lock().unwrap();
+        reader.as_ref().is_some_and(|r| r.kitty_keyboard)
+    }
+
+    pub fn write_kitty_keyboard_flags(flags: &[u8]) {
+        if let Some(reader) = READER.lock().unwrap().as_mut() {
+            if reader.kitty_keyboard {
+                let _ = reader.write(flags);
+            }
+        }
+    }
+
+    pub fn poll(timeout: Duration) -> Option<Result<bool>> {
+        let mut reader = READER.lock().unwrap();
+        Some(reader.as_mut()?.poll(timeout))
+    }
+
+    pub fn read() -> Option<Result<Event>> {
+        let mut reader = READER.lock().unwrap();
+        let reader = reader.as_mut()?;
+        loop {
+            match reader.poll(POLL_INTERVAL) {
+                Ok(true) => return reader.events.pop_front().map(Ok),
+                Ok(false) => (),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+
+    /// Parses the sequence at the start of `bytes` returning it with its length,
+    /// or `None` if it's still incomplete
+    fn parse_sequence(bytes: &[u8]) -> Option<(Sequence, usize)> {
+        match bytes {
+            [] | [0x1b] | [0x1b, b'O'] => None,
+            [0x1b, b'[', rest @ ..] => {
+                parse_csi(rest).map(|(sequence, len)| (sequence, len + 2))
+            }
+            [0x1b, b'O', c, ..] => {
+                let sequence = match (legacy_key_code(*c), rxvt_arrow(*c)) {
+                    (Some(code), _) => key(code, None),
+                    // rxvt ctrl modified arrows
+                    (None, Some(code)) => key(code, Some(CONTROL_MASK)),
+                    (None, None) => Sequence::Unknown,
+                };
+                Some((sequence, 3))
+            }
+            [0x1b, rest @ ..] => {
+                let (sequence, len) = parse_sequence(rest)?;
+                let sequence = match sequence {
+                    Sequence::Event(Event::Key(key)) => {
+                        Sequence::Event(Event::Key(KeyEvent::new(
+                            key.code,
+                            key.modifiers | KeyModifiers::ALT,
+                        )))
+                    }
+                    _ => Sequence::Unknown,
+                };
+                Some((sequence, len + 1))
+            }
+            _ => parse_char(bytes),
+        }
+    }
+
+    fn parse_csi(bytes: &[u8]) -> Option<(Sequence, usize)> {
+        match bytes {
+            [b'['] => return None,
+            // F1 to F5 of the linux console
+            [b'[', c, ..] => {
+                let sequence = match c {
+                    b'A'..=b'E' => key(KeyCode::F(c - b'A' + 1), None),
+                    _ => Sequence::Unknown,
+                };
+                return Some((sequence, 2));
+            }
+            // X10 mouse reports, with the button and position as bytes
+            // offset by 32 after the `M`
+            [b'M', rest @ ..] => {
+                if rest.len() < 3 {
+                    return None;
+                }
+                let button = rest[0].saturating_sub(32) as u32;
+                let column = rest[1].saturating_sub(33) as u16;
+                let row = rest[2].saturating_sub(33) as u16;
+                return Some((mouse(button, column, row, button & 3 == 3), 4));
+            }
+            _ => (),
+        }
+
+        let end = bytes.iter().position(|b| (0x40..=0x7e).contains(b))?;
+        let params = std::str::from_utf8(&bytes[..end]).unwrap_or("");
+        let final_byte = bytes[end];
+        let numbers = parse_numbers(params);
+        let sequence = match (params.as_bytes().first(), final_byte) {
+            (Some(b'?'), b'u') => Sequence::FlagsReply,
+            (Some(b'?'), b'c') => Sequence::AttributesReply,
+            (Some(b'<'), b'M') | (Some(b'<'), b'm') => {
+                parse_mouse(&params[1..], final_byte == b'm')
+            }
+            // urxvt mouse reports, like X10 ones but in decimal
+            (Some(_), b'M') => match numbers[..] {
+                [Some(button), Some(column), Some(row)] => {
+                    let button = button.saturating_sub(32);
+                    let column = column.saturating_sub(1) as u16;
+                    let row = row.saturating_sub(1) as u16;
+                    mouse(button, column, row, button & 3 == 3)
+                }
+                _ => Sequence::Unknown,
+            },
+            (_, b'u') => key_from_code(numbers.first(), numbers.get(1)),
+            // xterm `modifyOtherKeys`, `CSI 27;modifiers;code ~`
+            (Some(b'2'), b'~') if numbers.first() == Some(&Some(27)) => {
+                key_from_code(numbers.get(2), numbers.get(1))
+            }
+            (_, b'~') => parse_special_key(&numbers, None),
+            // rxvt ctrl and ctrl+shift modified special keys
+            (_, b'^') => parse_special_key(&numbers, Some(CONTROL_MASK)),
+            (_, b'@') => parse_special_key(&numbers, Some(CONTROL_SHIFT_MASK)),
+            (_, final_byte) => {
+                match (legacy_key_code(final_byte), rxvt_arrow(final_byte)) {
+                    (Some(code), _) => {
+                        key(code, numbers.get(1).copied().flatten())
+                    }
+                    // rxvt shift modified arrows
+                    (None, Some(code)) => key(code, Some(SHIFT_MASK)),
+                    (None, None) => Sequence::Unknown,
+                }
+            }
+        };
+        Some((sequence, end + 1))
+    }
+
+    /// Takes the first number of each `;` separated parameter, ignoring the
+    /// `:` separated sub parameters like the kitty event type
+    fn parse_numbers(params: &str) -> Vec<Option<u32>> {
+        params
+            .split(';')
+            .map(|p| p.split(':').next().and_then(|n| n.parse().ok()))
+            .collect()
+    }
+
+    fn parse_modifiers(mask: u32) -> KeyModifiers {
+        let mask = mask.saturating_sub(1);
+        let mut modifiers = KeyModifiers::NONE;
+        if mask & 1 != 0 {
+            modifiers |= KeyModifiers::SHIFT;
+        }
+        if mask & 2 != 0 {
+            modifiers |= KeyModifiers::ALT;
+        }
+        if mask & 4 != 0 {
+            modifiers |= KeyModifiers::CONTROL;
+        }
+        modifiers
+    }
+
+    fn key(code: KeyCode, modifier_mask: Option<u32>) -> Sequence {
+        let modifiers =
+            modifier_mask.map_or(KeyModifiers::NONE, parse_modifiers);
+        let code = match code {
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => {
+                KeyCode::BackTab
+            }
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            code => code,
+        };
+        Sequence::Event(Event::Key(KeyEvent::new(code, modifiers)))
+    }
+
+    /// Keys reported by their unicode code point, as `CSI code;modifiers u`
+    /// does in the kitty protocol
+    fn key_from_code(
+        code: Option<&Option<u32>>,
+        modifier_mask: Option<&Option<u32>>,
+    ) -> Sequence {
+        let code = match code.copied().flatten() {
+            Some(9) => KeyCode::Tab,
+            Some(13) | Some(KEYPAD_ENTER) => KeyCode::Enter,
+            Some(27) => KeyCode::Esc,
+            Some(127) => KeyCode::Backspace,
+            Some(n @ F13..=F24) => KeyCode::F((n - F13) as u8 + 13),
+            Some(n) => match std::char::from_u32(n) {
+                // the private use area holds keys that don't type text
+                Some(c) if !('\u{e000}'..='\u{f8ff}').contains(&c) => {
+                    KeyCode::Char(c)
+                }
+                _ => return Sequence::Unknown,
+            },
+            None => return Sequence::Unknown,
+        };
+        key(code, modifier_mask.copied().flatten())
+    }
+
+    /// `CSI number;modifiers ~`, or with the modifiers given by the final
+    /// byte in rxvt
+    fn parse_special_key(
+        numbers: &[Option<u32>],
+        modifier_mask: Option<u32>,
+    ) -> Sequence {
+        let code = match numbers.first().copied().flatten() {
+            Some(1) | Some(7) => KeyCode::Home,
+            Some(2) => KeyCode::Insert,
+            Some(3) => KeyCode::Delete,
+            Some(4) | Some(8) => KeyCode::End,
+            Some(5) => KeyCode::PageUp,
+            Some(6) => KeyCode::PageDown,
+            Some(n @ 11..=15) => KeyCode::F(n as u8 - 10),
+            Some(n @ 17..=21) => KeyCode::F(n as u8 - 11),
+            Some(n @ 23..=24) => KeyCode::F(n as u8 - 12),
+            _ => return Sequence::Unknown,
+        };
+        let modifier_mask =
+            modifier_mask.or_else(|| numbers.get(1).copied().flatten());
+        key(code, modifier_mask)
+    }
+
+    fn rxvt_arrow(final_byte: u8) -> Option<KeyCode> {
+        match final_byte {
+            b'a' => Some(KeyCode::Up),
+            b'b' => Some(KeyCode::Down),
+            b'c' => Some(KeyCode::Right),
+            b'd' => Some(KeyCode::Left),
+            _ => None,
+        }
+    }
+
+    fn legacy_key_code(final_byte: u8) -> Option<KeyCode> {
+        let code = match final_byte {
+            b'A' => KeyCode::Up,
+            b'B' => KeyCode::Down,
+            b'C' => KeyCode::Right,
+            b'D' => KeyCode::Left,
+            b'H' => KeyCode::Home,
+            b'F' => KeyCode::End,
+            b'Z' => KeyCode::BackTab,
+            b'P'..=b'S' => KeyCode::F(final_byte - b'P' + 1),
+            _ => return None,
+        };
+        Some(code)
+    }
+
+    /// SGR mouse reports, `CSI < button;column;row M` or `m` when released
+    fn parse_mouse(params: &str, released: bool) -> Sequence {
+        match parse_numbers(params)[..] {
+            [Some(button), Some(column), Some(row)] => {
+                let column = column.saturating_sub(1) as u16;
+                let row = row.saturating_sub(1) as u16;
+                mouse(button, column, row, released)
+            }
+            _ => Sequence::Unknown,
+        }
+    }
+
+    fn mouse(button: u32, column: u16, row: u16, released: bool) -> Sequence {
+        let mut modifiers = KeyModifiers::NONE;
+        if button & 4 != 0 {
+            modifiers |= KeyModifiers::SHIFT;
+        }
+        if button & 8 != 0 {
+            modifiers |= KeyModifiers::ALT;
+        }
+        if button & 16 != 0 {
+            modifiers |= KeyModifiers::CONTROL;
+        }
+
+        let mouse_button = match button & 3 {
+            1 => MouseButton::Middle,
+            2 => MouseButton::Right,
+            // X10 and urxvt releases don't tell the button
+            _ => MouseButton::Left,
+        };
+        let event = if button & 64 != 0 {
+            if button & 1 == 0 {
+                MouseEvent::ScrollUp(column, row, modifiers)
+            } else {
+                MouseEvent::ScrollDown(column, row, modifiers)
+            }
+        } else if button & 32 != 0 {
+            MouseEvent::Drag(mouse_button, column, row, modifiers)
+        } else if released {
+            MouseEvent::Up(mouse_button, column, row, modifiers)
+        } else {
+            MouseEvent::Down(mouse_button, column, row, modifiers)
+        };
+        Sequence::Event(Event::Mouse(event))
+    }
+
+    /// Control bytes and utf-8 characters, mapped the way crossterm does
+    fn parse_char(bytes: &[u8]) -> Option<(Sequence, usize)> {
+        let control = |c: char| {
+            let key = KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+            Some((Sequence::Event(Event::Key(key)), 1))
+        };
+        let code = match bytes[0] {
+            b'\r' => KeyCode::Enter,
+            b'\t' => KeyCode::Tab,
+            0x7f => KeyCode::Backspace,
+            0 => return control(' '),
+            c @ 0x01..=0x1a => return control((c - 0x01 + b'a') as char),
+            c @ 0x1c..=0x1f => return control((c - 0x1c + b'4') as char),
+            _ => {
+                let len = match bytes[0] {
+                    0x00..=0x7f => 1,
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    0xf0..=0xf7 => 4,
+                    _ => return Some((Sequence::Unknown, 1)),
+                };
+                if bytes.len() < len {
+                    return None;
+                }
+                match std::str::from_utf8(&bytes[..len]) {
+                    Ok(text) => {
+                        let c = text.chars().next()?;
+                        let modifiers = if c.is_uppercase() {
+                            KeyModifiers::SHIFT
+                        } else {
+                            KeyModifiers::NONE
+                        };
+                        let key = KeyEvent::new(KeyCode::Char(c), modifiers);
+                        return Some((Sequence::Event(Event::Key(key)), len));
+                    }
+                    Err(_) => return Some((Sequence::Unknown, 1)),
+                }
+            }
+        };
+        Some((Sequence::Event(Event::Key(code.into())), 1))
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::time::Duration;
+
+    use crossterm::{event::Event, Result};
+
+    pub fn start(_kitty_keyboard: bool) {}
+
+    pub fn stop() {}
+
+    pub fn kitty_keyboard_enabled() -> bool {
+        false
+    }
+
+    pub fn write_kitty_keyboard_flags(_flags: &[u8]) {}
+
+    pub fn poll(_timeout: Duration) -> Option<Result<bool>> {
+        None
+    }
+
+    pub fn read() -> Option<Result<Event>> {
+        None
+    }
+}
@@ -0,0 +1,241 @@
+use std::{convert::TryFrom, sync::RwLock};
+
+use crossterm::{
+    style::{Color, SetForegroundColor},
+    Command,
+};
+
+use crate::color_support::ColorSupport;
+
+/// Colors of every part of the ui
+#[derive(Clone, Copy)]
+pub struct Theme {
+    pub header: Color,
+    pub header_action: Color,
+    pub waiting: Color,
+    pub waiting_dark: Color,
+    pub ok: Color,
+    pub ok_dark: Color,
+    pub error: Color,
+    pub error_dark: Color,
+    pub canceled: Color,
+    pub canceled_dark: Color,
+    /// Plain text parts of log and tag lines
+    pub text: Color,
+    pub entry: Color,
+    pub selected_bg: Color,
+    pub line_number: Color,
+    pub note: Color,
+    pub filter: Color,
+    pub filter_active_bg: Color,
+    pub filter_inactive_bg: Color,
+    pub diff_added: Color,
+    pub diff_removed: Color,
+    pub untracked: Color,
+    pub unmodified: Color,
+    pub modified: Color,
+    pub added: Color,
+    pub deleted: Color,
+    pub renamed: Color,
+    pub copied: Color,
+    pub unmerged: Color,
+    pub missing: Color,
+    pub ignored: Color,
+    pub clean: Color,
+    support: ColorSupport,
+}
+
+const fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+const DARK: Theme = Theme {
+    header: Color::Black,
+    header_action: Color::White,
+    waiting: Color::Magenta,
+    waiting_dark: Color::DarkMagenta,
+    ok: Color::Green,
+    ok_dark: Color::DarkGreen,
+    error: Color::Red,
+    error_dark: Color::DarkRed,
+    canceled: Color::Yellow,
+    canceled_dark: Color::DarkYellow,
+    text: Color::White,
+    entry: rgb(255, 180, 100),
+    selected_bg: rgb(80, 80, 80),
+    line_number: Color::DarkGrey,
+    note: Color::Cyan,
+    filter: Color::Black,
+    filter_active_bg: rgb(255, 180, 100),
+    filter_inactive_bg: rgb(180, 90, 50),
+    diff_added: Color::DarkGreen,
+    diff_removed: Color::DarkRed,
+    untracked: rgb(100, 180, 255),
+    unmodified: rgb(255, 255, 255),
+    modified: rgb(255, 200, 0),
+    added: rgb(0, 255, 0),
+    deleted: rgb(255, 0, 0),
+    renamed: rgb(100, 100, 255),
+    copied: rgb(255, 0, 255),
+    unmerged: rgb(255, 180, 100),
+    missing: rgb(255, 0, 0),
+    ignored: rgb(255, 180, 0),
+    clean: rgb(100, 180, 255),
+    support: ColorSupport::TrueColor,
+};
+
+/// For terminals with a light background
+const LIGHT: Theme = Theme {
+    text: Color::Black,
+    entry: rgb(190, 90, 0),
+    selected_bg: rgb(210, 210, 210),
+    line_number: Color::Grey,
+    note: Color::DarkCyan,
+    untracked: rgb(0, 90, 200),
+    unmodified: rgb(0, 0, 0),
+    modified: rgb(160, 110, 0),
+    added: rgb(0, 140, 0),
+    deleted: rgb(200, 0, 0),
+    renamed: rgb(60, 60, 200),
+    copied: rgb(170, 0, 170),
+    unmerged: rgb(190, 90, 0),
+    missing: rgb(200, 0, 0),
+    ignored: rgb(150, 110, 0),
+    clean: rgb(0, 90, 200),
+    ..DARK
+};
+
+const BUILT_IN_THEMES: &[(&str, Theme)] = &[("dark", DARK), ("light", LIGHT)];
+
+static THEME: RwLock<Theme> = RwLock::new(DARK);
+
+/// Names of the parts of a theme that can be colored
+const PARTS: &[&str] = &[
+    "header",
+    "header_action",
+    "waiting",
+    "waiting_dark",
+    "ok",
+    "ok_dark",
+    "error",
+    "error_dark",
+    "canceled",
+    "canceled_dark",
+    "text",
+    "entry",
+    "selected_bg",
+    "line_number",
+    "note",
+    "filter",
+    "filter_active_bg",
+    "filter_inactive_bg",
+    "diff_added",
+    "diff_removed",
+    "untracked",
+    "unmodified",
+    "modified",
+    "added",
+    "deleted",
+    "renamed",
+    "copied",
+    "unmerged",
+    "missing",
+    "ignored",
+    "clean",
+];
+
+/// Colors the ui with the built in theme `name`, `dark` when unknown, with
+/// `colors` overriding some of its parts as `(part, color)` pairs. Colors
+/// the terminal can't show per `support` are replaced by the closest ones
+pub fn set_theme(
+    name: &str,
+    colors: &[(String, String)],
+    support: ColorSupport,
+) {
+    let mut theme = BUILT_IN_THEMES
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map_or(DARK, |(_, theme)| *theme);
+    for (part, color) in colors {
+        if let (Some(part), Some(color)) =
+            (theme.color_mut(part), parse_color(color))
+        {
+            *part = color;
+        }
+    }
+    theme.support = support;
+    for part in PARTS {
+        if let Some(color) = theme.color_mut(part) {
+            *color = support.fit(*color);
+        }
+    }
+    *THEME.write().unwrap() = theme;
+}
+
+pub fn theme() -> Theme {
+    *THEME.read().unwrap()
+}
+
+/// Escape sequence setting the foreground to `color`, for text drawn as
+/// part of an output
+pub fn ansi_color(color: Color) -> String {
+    SetForegroundColor(color).ansi_code().to_string()
+}
+
+impl Theme {
+    /// Closest color to `color` the terminal can show
+    pub fn fit(&self, color: Color) -> Color {
+        self.support.fit(color)
+    }
+
+    fn color_mut(&mut self, part: &str) -> Option<&mut Color> {
+        let color = match part {
+            "header" => &mut self.header,
+            "header_action" => &mut self.header_action,
+            "waiting" => &mut self.waiting,
+            "waiting_dark" => &mut self.waiting_dark,
+            "ok" => &mut self.ok,
+            "ok_dark" => &mut self.ok_dark,
+            "error" => &mut self.error,
+            "error_dark" => &mut self.error_dark,
+            "canceled" => &mut self.canceled,
+            "canceled_dark" => &mut self.canceled_dark,
+            "text" => &mut self.text,
+            "entry" => &mut self.entry,
+            "selected_bg" => &mut self.selected_bg,
+            "line_number" => &mut self.line_number,
+            "note" => &mut self.note,
+            "filter" => &mut self.filter,
+            "filter_active_bg" => &mut self.filter_active_bg,
+            "filter_inactive_bg" => &mut self.filter_inactive_bg,
+            "diff_added" => &mut self.diff_added,
+            "diff_removed" => &mut self.diff_removed,
+            "untracked" => &mut self.untracked,
+            "unmodified" => &mut self.unmodified,
+            "modified" => &mut self.modified,
+            "added" => &mut self.added,
+            "deleted" => &mut self.deleted,
+            "renamed" => &mut self.renamed,
+            "copied" => &mut self.copied,
+            "unmerged" => &mut self.unmerged,
+            "missing" => &mut self.missing,
+            "ignored" => &mut self.ignored,
+            "clean" => &mut self.clean,
+            _ => return None,
+        };
+        Some(color)
+    }
+}
+
+/// Parses `#rrggbb` or a name like `dark_red`
+fn parse_color(text: &str) -> Option<Color> {
+    match text.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 => {
+            let channel =
+                |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+            Some(rgb(channel(0)?, channel(2)?, channel(4)?))
+        }
+        Some(_) => None,
+        None => Color::try_from(text).ok(),
+    }
+}
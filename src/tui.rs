@@ -1,8 +1,11 @@
 use crossterm::{
     cursor,
-    event::{KeyCode, KeyEvent, KeyModifiers},
+    event::{
+        DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEvent,
+        KeyModifiers,
+    },
     execute, queue,
-    style::{Print, ResetColor, SetForegroundColor},
+    style::{Print, ResetColor, SetBackgroundColor, SetForegroundColor},
     terminal::{
         self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
         SetTitle,
@@ -11,28 +14,353 @@ use crossterm::{
 };
 
 use std::{
+    borrow::Cow,
+    collections::HashMap,
+    env, fs,
     io::{stdout, Write},
-    iter, thread,
-    time::Duration,
+    iter,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+    thread,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    action::{ActionKind, ActionResult, ActionTask},
+    action::{
+        decoded, map_result, map_result_in_thread, parallel, serial,
+        serial_until_error, task_vec, ActionKind, ActionResult, ActionTask,
+    },
     application::{ActionFuture, Application},
-    input::{self, Event},
+    args::StartupMode,
+    askpass,
+    backend_error::{BackendResult, ErrorKind},
+    browser::{self, WebTarget},
+    clipboard::{self, Copied},
+    config::Config,
+    conflict_markers,
+    custom_actions::{self, CustomAction, Run},
+    encoding, folds,
+    hunks::{self, Hunk},
+    input::{self, Event, MessageEdit, MessageEditor},
+    key_bindings::KEY_BINDINGS,
+    locale::{self, tr, trf},
+    log_pager::LogPager,
+    notification::Notification,
+    palette,
+    preview::Preview,
     scroll_view::ScrollView,
-    select::{select, Entry},
-    tui_util::{show_header, Header, HeaderKind, TerminalSize, ENTRY_COLOR},
+    select::{select, Entry, SelectOptions, SidePane, State},
+    state::{UiState, ViewState},
+    terminal_input,
+    theme::theme,
+    tui_util::{
+        fit_width, show_header, str_width, strip_ansi, update_header,
+        AvailableSize, Header, HeaderKind, TerminalSize, LOG_SUBJECT_PART,
+        QUIET_REDRAW_INTERVAL, SPINNER_FRAME_DURATION,
+    },
+    version_control_actions::{CommitOptions, Operation, PHASES},
 };
 
-const BIN_NAME: &'static str = env!("CARGO_PKG_NAME");
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+const BIN_NAME: &str = env!("CARGO_PKG_NAME");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const DISCARD_SUMMARY_THRESHOLD: usize = 10;
+const EXPORT_TO_CLIPBOARD: &str = "clipboard";
+const KEY_DEBUG_HISTORY: usize = 100;
+const LOG_END_MARKER: &str = "(end of history)";
+/// How long a pressed key stays on screen when showing them
+const PRESSED_KEY_DURATION: Duration = Duration::from_secs(2);
+
+/// Number of revisions in a log output, not counting graph only lines
+fn log_entry_count(output: &str) -> usize {
+    output.lines().filter(|l| l.contains('\x1e')).count()
+}
+
+/// Identifies a set of selected entries
+fn selected_files(entries: &[Entry]) -> String {
+    let files: Vec<_> = entries
+        .iter()
+        .filter(|e| e.selected)
+        .map(|e| &e.filename[..])
+        .collect();
+    files.join("\n")
+}
+
+/// Recently viewed revisions kept for `Lr`
+const MAX_VIEWED_REVISIONS: usize = 50;
+
+/// Actions that are run again when reopening verco with their key chords
+const RESTORABLE_ACTIONS: [(ActionKind, &[char]); 5] = [
+    (ActionKind::Status, &['s']),
+    (ActionKind::Log, &['l']),
+    (ActionKind::CurrentDiffAll, &['d', 'd']),
+    (ActionKind::UnresolvedConflicts, &['r', 'r']),
+    (ActionKind::ListBranches, &['b', 'b']),
+];
+
+/// Patterns offered when ignoring `path`: the path itself, every file with
+/// the same extension and its directory
+fn ignore_candidates(path: &str) -> Vec<String> {
+    let mut candidates = vec![path.to_string()];
+    if path.ends_with('/') {
+        return candidates;
+    }
+    let path = Path::new(path);
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        candidates.push(format!("*.{}", extension));
+    }
+    if let Some(parent) = path.parent().and_then(|p| p.to_str()) {
+        if !parent.is_empty() {
+            candidates.push(format!("{}/", parent));
+        }
+    }
+    candidates
+}
+
+/// Writes `text` to the clipboard or to the file `destination` relative to
+/// the repository root, resulting in `message` if it did
+fn export_to(
+    app: &Application,
+    destination: &str,
+    text: &str,
+    message: String,
+) -> ActionResult {
+    if destination == EXPORT_TO_CLIPBOARD {
+        copy_to_clipboard(text, message)
+    } else {
+        let path = Path::new(app.version_control.get_root()).join(destination);
+        match fs::write(path, text) {
+            Ok(()) => ActionResult::from_ok(message),
+            Err(error) => ActionResult::from_err(error.to_string()),
+        }
+    }
+}
+
+/// Copies `text` resulting in `message` if it did, noting when only the
+/// terminal was asked to as it can't tell whether it copied
+fn copy_to_clipboard(text: &str, message: String) -> ActionResult {
+    match clipboard::copy(text) {
+        Ok(Copied::Command) => ActionResult::from_ok(message),
+        Ok(Copied::Terminal) => ActionResult::from_ok(trf(
+            "{} (asked the terminal to, which does nothing if it doesn't support OSC 52)",
+            &[&message],
+        )),
+        Err(error) => ActionResult::from_err(error),
+    }
+}
+
+/// Lists the unresolved conflicts marking the files already edited to have
+/// no conflict markers
+fn conflicts_action(app: &Application) -> Box<dyn ActionTask> {
+    let root = PathBuf::from(app.version_control.get_root());
+    map_result_in_thread(app.version_control.conflicts(), move |mut result| {
+        if result.success {
+            result.output =
+                conflict_markers::mark_edited(&root, &result.output);
+        }
+        result
+    })
+}
+
+/// Describes `operation` left midway and the keys to go on with it
+fn operation_banner(operation: Operation) -> String {
+    let name = operation.name();
+    let mut keys = Vec::new();
+    if operation != Operation::Bisect {
+        keys.push(("Oc", trf("continue the {}", &[&name])));
+    }
+    keys.push(("Oa", trf("abort the {}", &[&name])));
+    if operation != Operation::Bisect {
+        keys.push(("rr", tr("inspect unresolved conflicts").into()));
+    }
+    keys.push(("s", tr("status").into()));
+
+    let mut banner = trf("a {} is in progress", &[&name]);
+    banner.push_str("\n\n");
+    for (key, description) in keys {
+        banner.push_str(&format!("{}\t{}\n", key, description));
+    }
+    banner
+}
+
+/// Creates and switches to `branch`, keeping the pending changes, before
+/// running `commit`
+pub fn branch_off_before_commit(
+    app: &Application,
+    branch: Option<String>,
+    commit: Box<dyn ActionTask>,
+) -> Box<dyn ActionTask> {
+    match branch {
+        Some(branch) => {
+            let mut tasks = task_vec();
+            tasks.push(app.version_control.branch_off(&branch));
+            tasks.push(commit);
+            serial_until_error(tasks)
+        }
+        None => commit,
+    }
+}
+
+/// Runs the configured formatter on the selected entries. `None` when there's
+/// none or nothing to format. As files are only staged by the commit,
+/// formatting changes end up in it
+pub fn format_before_commit(
+    app: &Application,
+    entries: &[Entry],
+) -> Option<Box<dyn ActionTask>> {
+    let format_command = app.config.format_command.as_ref()?;
+    let files: Vec<_> = entries
+        .iter()
+        .filter(|e| e.selected && !e.filename.ends_with('/'))
+        .filter(|e| !matches!(e.state, State::Deleted | State::Missing))
+        .map(|e| &e.filename[..])
+        .collect();
+    if files.is_empty() {
+        return None;
+    }
+    Some(custom_actions::run_on_files(
+        app.version_control.get_root(),
+        format_command,
+        &files[..],
+    ))
+}
+
+/// Reads the status again once formatted so the selected entries commit as
+/// what they became, leaving out the files whose changes the formatter undid
+pub fn refresh_formatted(
+    app: &Application,
+    entries: &mut [Entry],
+) -> BackendResult<()> {
+    let current = app.get_current_changed_files()?;
+    let root = Path::new(app.version_control.get_root());
+    for e in entries.iter_mut().filter(|e| e.selected) {
+        match current.iter().find(|c| c.filename == e.filename) {
+            Some(c) => {
+                e.state = c.state.clone();
+                e.renamed_from = c.renamed_from.clone();
+            }
+            None if !root.join(&e.filename).is_dir() => e.selected = false,
+            None => (),
+        }
+    }
+    Ok(())
+}
+
+/// Separates the revision from the path in the preview targets of files,
+/// which go without revision in the working tree
+const WORKING_TREE_PREVIEW: &str = "\x1e";
+
+/// Requests the preview of `target` once the cursor rests on it, drawing it
+/// next to the list when it arrives or the list was `redrawn` over it
+fn update_preview<W, F>(
+    write: &mut W,
+    app: &mut Application,
+    preview: &mut Preview,
+    target: &str,
+    terminal_size: TerminalSize,
+    redrawn: bool,
+    task: F,
+) -> Result<()>
+where
+    W: Write,
+    F: FnOnce(&Application) -> Box<dyn ActionTask>,
+{
+    let mut needs_redraw = preview.hover(target) || redrawn;
+    if app.get_preview_result(target).is_none()
+        && !app.is_preview_pending(target)
+        && preview.is_hover_settled()
+    {
+        let task = decoded(task(app), app.diff_decoder.clone());
+        app.request_preview(target, task);
+    }
+    if app.poll_preview() {
+        needs_redraw = true;
+    }
+
+    if needs_redraw {
+        let content = match app.get_preview_result(target) {
+            Some(result) => &result.output[..],
+            None => tr("loading..."),
+        };
+        preview.draw(write, content, terminal_size)?;
+    }
+    Ok(())
+}
+
+/// Preview of the diff of the working tree file hovered in the select ui
+struct FilePreview<'a> {
+    app: &'a mut Application,
+    preview: &'a mut Preview,
+}
+
+impl<W> SidePane<W> for FilePreview<'_>
+where
+    W: Write,
+{
+    fn list_width(&self, terminal_size: TerminalSize) -> usize {
+        self.preview.list_width(terminal_size)
+    }
+
+    fn update(
+        &mut self,
+        write: &mut W,
+        hovered: &Entry,
+        terminal_size: TerminalSize,
+        redrawn: bool,
+    ) -> Result<()> {
+        let target = format!("{}{}", WORKING_TREE_PREVIEW, hovered.filename);
+        let entry = Entry {
+            selected: true,
+            ..hovered.clone()
+        };
+        update_preview(
+            write,
+            self.app,
+            self.preview,
+            &target,
+            terminal_size,
+            redrawn,
+            |app| app.version_control.current_diff_selected(&[entry]),
+        )
+    }
+}
 
-pub fn show_tui(mut app: Application) {
+pub fn show_tui(mut app: Application, startup_mode: Option<StartupMode>) {
     let stdout = stdout();
     let stdout = stdout.lock();
     let mut tui = Tui::new(stdout);
-    tui.show(&mut app).unwrap();
+    let state = UiState::load();
+    tui.select_options.sort_key =
+        state.sort_key.unwrap_or(app.config.select_sort);
+    tui.select_options.show_file_info = state.show_file_info;
+    tui.scroll_view.truncated_views = app.config.truncated_views.clone();
+    tui.scroll_view.pinned_files = state.pinned_files.iter().cloned().collect();
+    app.compare_target = state.compare_target.clone();
+    tui.show(&mut app, state, startup_mode).unwrap();
+    let _ = tui.ui_state(&app).save();
+
+    // so catalogs left behind by changes to the english strings are noticed
+    if cfg!(debug_assertions) {
+        if let Some((name, missing)) = locale::missing_translations() {
+            eprintln!("strings shown untranslated to {}:", name);
+            for text in missing {
+                eprintln!("  {}", text);
+            }
+        }
+    }
+}
+
+/// What to do before committing on a protected branch
+enum BranchOff {
+    Commit,
+    CreateBranch(String),
+    Abort,
+}
+
+/// Commit that can be retried skipping the hooks if one rejects it
+struct LastCommit {
+    message: String,
+    /// Files of a commit selected, `None` when committing all
+    entries: Option<Vec<Entry>>,
 }
 
 enum HandleChordResult {
@@ -48,10 +376,56 @@ where
     previous_action_kind: ActionKind,
     current_action_kind: ActionKind,
     current_key_chord: Vec<char>,
+    action_key_chords: HashMap<ActionKind, Vec<char>>,
 
     write: W,
     terminal_size: TerminalSize,
     scroll_view: ScrollView,
+    preview: Preview,
+    select_options: SelectOptions,
+    revision_changes_target: String,
+    pending_file_jump: Option<String>,
+    pending_view: Option<ViewState>,
+    /// Set by background refreshes so their output, and redrawing it, keeps
+    /// the filter and the position until another action is requested
+    keeps_view: bool,
+    /// Action and content, like a revision, of the output being shown
+    view_key: Option<(ActionKind, String)>,
+    view_positions: HashMap<(ActionKind, String), ViewState>,
+    /// Log entries requested by the last log action
+    log_count: usize,
+    /// Reads more of the log shown without running it again
+    log_pager: Option<LogPager>,
+    /// Revision to move the cursor to once the log arrives
+    pending_log_jump: Option<String>,
+    last_commit: Option<LastCommit>,
+    /// Commit waiting for the formatter run by its action to finish, with
+    /// the branch to create first
+    pending_commit: Option<(ActionKind, LastCommit, Option<String>)>,
+    /// Revisions whose changes or diff were shown this session, most recent
+    /// first, as `revision subject`
+    viewed_revisions: Vec<String>,
+    /// Recently pressed keys shown in the bottom left corner for screencasts.
+    /// `None` when not showing them
+    pressed_keys: Option<Vec<(String, Instant)>>,
+    /// When the header telling how long the current action has been running
+    /// was last drawn. `None` when the header isn't telling that
+    progress_drawn_at: Option<Instant>,
+    /// When a key or the mouse was last used
+    last_input_at: Instant,
+    /// When `auto_refresh` last reran the current action or it was last
+    /// showing one it doesn't rerun
+    refreshed_at: Instant,
+    /// Action run from the keyboard whose output is drawn as it's printed,
+    /// until it finishes
+    streaming: Option<ActionKind>,
+    /// Byte of the output of the streaming action drawn up to
+    streamed_len: usize,
+    /// When the output of the streaming action was last drawn
+    streamed_at: Instant,
+    /// Draws without the spinner, color changes and bell, redrawing running
+    /// actions less often for screen sharing and slow connections
+    quiet: bool,
 }
 
 impl<W> Tui<W>
@@ -63,31 +437,376 @@ where
             previous_action_kind: ActionKind::Quit,
             current_action_kind: ActionKind::Quit,
             current_key_chord: Vec::new(),
+            action_key_chords: HashMap::new(),
             write,
             terminal_size: Default::default(),
             scroll_view: Default::default(),
+            preview: Default::default(),
+            select_options: Default::default(),
+            revision_changes_target: String::new(),
+            pending_file_jump: None,
+            pending_view: None,
+            keeps_view: false,
+            view_key: None,
+            view_positions: HashMap::new(),
+            log_count: 0,
+            log_pager: None,
+            pending_log_jump: None,
+            last_commit: None,
+            pending_commit: None,
+            viewed_revisions: Vec::new(),
+            pressed_keys: None,
+            progress_drawn_at: None,
+            last_input_at: Instant::now(),
+            refreshed_at: Instant::now(),
+            streaming: None,
+            streamed_len: 0,
+            streamed_at: Instant::now(),
+            quiet: false,
+        }
+    }
+
+    fn ui_state(&self, app: &Application) -> UiState {
+        let mode = RESTORABLE_ACTIONS
+            .iter()
+            .find(|(kind, _)| *kind == self.scroll_view.action_kind())
+            .map(|(kind, _)| kind.name().into())
+            .unwrap_or_default();
+        UiState {
+            mode,
+            view: self.scroll_view.view_state(),
+            sort_key: Some(self.select_options.sort_key)
+                .filter(|&k| k != app.config.select_sort),
+            show_file_info: self.select_options.show_file_info,
+            pinned_files: self
+                .scroll_view
+                .pinned_files
+                .iter()
+                .cloned()
+                .collect(),
+            compare_target: app.compare_target.clone(),
+        }
+    }
+
+    /// Runs the last action from the previous session again returning
+    /// to the same position once its output arrives
+    fn restore_mode(
+        &mut self,
+        app: &mut Application,
+        state: UiState,
+    ) -> Result<()> {
+        let key_chord = RESTORABLE_ACTIONS
+            .iter()
+            .find(|(kind, _)| kind.name() == state.mode)
+            .map(|(_, key_chord)| key_chord);
+        if let Some(key_chord) = key_chord {
+            self.run_key_chord(app, key_chord)?;
+            self.pending_view = Some(state.view);
+        }
+        Ok(())
+    }
+
+    /// Shows the view asked for on the command line
+    fn start_in_mode(
+        &mut self,
+        app: &mut Application,
+        mode: StartupMode,
+    ) -> Result<()> {
+        match mode {
+            StartupMode::Status => self.run_key_chord(app, &['s']),
+            StartupMode::Log => self.run_key_chord(app, &['l']),
+            StartupMode::Branches => self.run_key_chord(app, &['b', 'b']),
+            // skips the pattern prompt of `tt` listing every tag
+            StartupMode::Tags => self
+                .action_context(ActionKind::ListTags, |s| {
+                    let action = app.version_control.list_tags(None);
+                    s.show_action(app, action)
+                })
+                .map(|_| ()),
         }
     }
 
+    fn run_key_chord(
+        &mut self,
+        app: &mut Application,
+        key_chord: &[char],
+    ) -> Result<()> {
+        self.current_key_chord.clear();
+        self.current_key_chord.extend_from_slice(key_chord);
+        self.handle_key_chord(app)?;
+        self.current_key_chord.clear();
+        Ok(())
+    }
+
+    /// Forgets everything about the previous repository after switching
+    fn reset(&mut self, app: &Application) -> Result<()> {
+        self.previous_action_kind = ActionKind::Quit;
+        self.action_key_chords.clear();
+        self.revision_changes_target.clear();
+        self.viewed_revisions.clear();
+        self.scroll_view.file_notes.clear();
+        self.scroll_view.notes_revision.clear();
+        self.scroll_view.pinned_files =
+            UiState::load().pinned_files.into_iter().collect();
+        self.scroll_view.truncated_views = app.config.truncated_views.clone();
+        self.view_key = None;
+        self.view_positions.clear();
+        self.log_count = 0;
+        self.log_pager = None;
+        self.write
+            .execute(SetTitle(app.version_control.get_root()))?;
+        Ok(())
+    }
+
     fn show_header(
         &mut self,
         app: &Application,
         kind: HeaderKind,
     ) -> Result<()> {
+        let (action_name, directory_name) = self.header_names(app);
+        let progress = match kind {
+            HeaderKind::Waiting => {
+                app.pending_action_progress(self.current_action_kind)
+            }
+            _ => None,
+        };
+        self.progress_drawn_at = progress.as_ref().map(|_| Instant::now());
         let header = Header {
-            action_name: self.current_action_kind.name(),
-            directory_name: app.version_control.get_root(),
+            action_name: &action_name,
+            directory_name: &directory_name,
+            progress: progress.as_ref().map(|(e, c)| (*e, c.as_deref())),
+            quiet: self.quiet,
         };
         show_header(&mut self.write, header, kind, self.terminal_size)
     }
 
+    /// Keeps the spinner and elapsed time of a waiting header moving
+    fn update_progress(&mut self, app: &Application) -> Result<()> {
+        let interval = match self.quiet {
+            true => QUIET_REDRAW_INTERVAL,
+            false => SPINNER_FRAME_DURATION,
+        };
+        let is_due = self
+            .progress_drawn_at
+            .is_some_and(|t| t.elapsed() >= interval);
+        if !is_due {
+            return Ok(());
+        }
+        let (elapsed, command) =
+            match app.pending_action_progress(self.current_action_kind) {
+                Some(progress) => progress,
+                None => return Ok(()),
+            };
+        self.progress_drawn_at = Some(Instant::now());
+        let (action_name, directory_name) = self.header_names(app);
+        let header = Header {
+            action_name: &action_name,
+            directory_name: &directory_name,
+            progress: Some((elapsed, command.as_deref())),
+            quiet: self.quiet,
+        };
+        update_header(
+            &mut self.write,
+            header,
+            HeaderKind::Waiting,
+            self.terminal_size,
+        )
+    }
+
+    /// Redraws the output of a running status or custom action as it's
+    /// printed
+    fn stream_output(&mut self, app: &Application) -> Result<()> {
+        let kind = match self.streaming {
+            Some(kind) => kind,
+            None => return Ok(()),
+        };
+        if !app.has_pending_action_of_type(kind) {
+            self.streaming = None;
+            return Ok(());
+        }
+        if kind != self.current_action_kind {
+            return Ok(());
+        }
+        if self.quiet && self.streamed_at.elapsed() < QUIET_REDRAW_INTERVAL {
+            return Ok(());
+        }
+        let (lines, end) =
+            match app.pending_partial_lines(kind, self.streamed_len) {
+                Some((lines, end)) if end != self.streamed_len => (lines, end),
+                _ => return Ok(()),
+            };
+        self.streamed_at = Instant::now();
+        if self.streamed_len == 0 {
+            self.show_result(app, &ActionResult::from_ok(lines))?;
+        } else {
+            self.scroll_view.append_content(&lines, self.terminal_size);
+            self.scroll_view
+                .draw_content(&mut self.write, self.terminal_size)?;
+        }
+        self.streamed_len = end;
+        Ok(())
+    }
+
+    /// Reruns status or the unresolved conflicts in the background after
+    /// `auto_refresh` without input while they're shown, so changes made in
+    /// an editor show up. They also rerun when the watcher notices files
+    /// changing
+    fn auto_refresh(&mut self, app: &mut Application) {
+        // while a commit or a pull runs its changes show up once it's done
+        if app.has_pending_changes() {
+            return;
+        }
+        let kind = self.current_action_kind;
+        // changes noticed while another view is shown wait for status or
+        // the unresolved conflicts
+        let files_changed =
+            matches!(
+                kind,
+                ActionKind::Status | ActionKind::UnresolvedConflicts
+            ) && app.watcher.as_ref().is_some_and(|w| w.poll_changed());
+        if files_changed {
+            return self.refresh_in_background(app, kind);
+        }
+
+        let interval = match app.config.auto_refresh {
+            Some(interval) => interval,
+            None => return,
+        };
+        if !matches!(kind, ActionKind::Status | ActionKind::UnresolvedConflicts)
+        {
+            self.refreshed_at = Instant::now();
+            return;
+        }
+        if self.last_input_at.elapsed() >= interval
+            && self.refreshed_at.elapsed() >= interval
+        {
+            self.refresh_in_background(app, kind);
+        }
+    }
+
+    /// Runs status or the unresolved conflicts again unless they're already
+    /// running. Their result shows up as if it was requested
+    fn refresh_in_background(
+        &mut self,
+        app: &mut Application,
+        kind: ActionKind,
+    ) {
+        self.refreshed_at = Instant::now();
+        if app.has_pending_action_of_type(kind) {
+            return;
+        }
+        let task = match kind {
+            ActionKind::Status => {
+                app.request_badge(ActionKind::Log);
+                app.version_control
+                    .status(app.status_options, app.scope.as_deref())
+            }
+            _ => conflicts_action(app),
+        };
+        app.run_action(ActionFuture { kind, task });
+        self.keeps_view = true;
+    }
+
+    /// Action and directory names shown in the header
+    fn header_names(&self, app: &Application) -> (String, String) {
+        let root = app.version_control.get_root();
+        let mut directory_name = match &app.scope {
+            Some(scope) => format!("{} [{}]", root, scope),
+            None => root.into(),
+        };
+        if app.config.header_backend {
+            directory_name.push_str(" (");
+            directory_name.push_str(app.version_control.executable_name());
+            directory_name.push(')');
+        }
+        let action_kind = self.current_action_kind;
+        let mut action_name = String::from(tr(action_kind.name()));
+        if action_kind == ActionKind::Log {
+            if let Some(branch) = &app.log_branch {
+                action_name = trf("{} of {}", &[&action_name, branch]);
+            }
+            let result = app.get_cached_action_result(action_kind);
+            let count = log_entry_count(&result.output);
+            // while more is loading the count asked for is ahead of the output
+            let is_loading = app.has_pending_action_of_type(action_kind);
+            if !is_loading && self.is_log_complete(result) {
+                action_name.push(' ');
+                action_name.push_str(&trf("(all {} loaded)", &[&count]));
+            } else if count > 0 {
+                action_name.push(' ');
+                action_name.push_str(&trf("({} loaded)", &[&count]));
+            }
+        }
+        if let Some(badge) = app.get_badge(action_kind) {
+            action_name.push(' ');
+            action_name.push_str(badge);
+        }
+        if action_kind == ActionKind::Status {
+            if app.status_options.skip_untracked {
+                action_name.push(' ');
+                action_name
+                    .push_str(tr("(untracked files hidden, Tu scans them)"));
+            }
+            if app.config.sign_commits {
+                action_name.push(' ');
+                action_name.push_str(tr("(signing commits)"));
+            }
+        }
+        if matches!(
+            action_kind,
+            ActionKind::Status | ActionKind::Log | ActionKind::LogCount
+        ) {
+            if let Some(badge) = app.get_badge(ActionKind::CompareWithTarget) {
+                action_name.push_str(" [");
+                action_name.push_str(badge);
+                action_name.push(']');
+            }
+        }
+        (action_name, directory_name)
+    }
+
     fn show_select_ui(
         &mut self,
         app: &Application,
         entries: &mut [Entry],
     ) -> Result<bool> {
         self.show_header(app, HeaderKind::Waiting)?;
-        select(&mut self.write, entries)
+        select(
+            &mut self.write,
+            entries,
+            app.version_control.get_root(),
+            &mut self.select_options,
+            &self.scroll_view.pinned_files,
+            None,
+        )
+    }
+
+    /// Select ui of changed files of the working tree, previewing the diff
+    /// of the hovered one while the preview pane is enabled
+    fn show_changed_files_select_ui(
+        &mut self,
+        app: &mut Application,
+        entries: &mut [Entry],
+    ) -> Result<bool> {
+        if !self.preview.enabled {
+            return self.show_select_ui(app, entries);
+        }
+        self.show_header(app, HeaderKind::Waiting)?;
+        // the files may have changed since they were last previewed
+        app.forget_previews(WORKING_TREE_PREVIEW);
+        let root = String::from(app.version_control.get_root());
+        let mut pane = FilePreview {
+            app,
+            preview: &mut self.preview,
+        };
+        select(
+            &mut self.write,
+            entries,
+            &root,
+            &mut self.select_options,
+            &self.scroll_view.pinned_files,
+            Some(&mut pane),
+        )
     }
 
     fn show_action(
@@ -95,6 +814,12 @@ where
         app: &mut Application,
         task: Box<dyn ActionTask>,
     ) -> Result<()> {
+        self.action_key_chords
+            .insert(self.current_action_kind, self.current_key_chord.clone());
+        if self.current_action_kind.streams_output() {
+            self.streaming = Some(self.current_action_kind);
+            self.streamed_len = 0;
+        }
         app.run_action(ActionFuture {
             kind: self.current_action_kind,
             task,
@@ -103,9 +828,22 @@ where
         self.show_result(app, result)
     }
 
+    /// Shows an action whose output may contain files in legacy encodings
+    fn show_diff_action(
+        &mut self,
+        app: &mut Application,
+        mut task: Box<dyn ActionTask>,
+    ) -> Result<()> {
+        let flags =
+            app.version_control.diff_context_flags(folds::CONTEXT_LINES);
+        task.add_flags(&["diff"], &flags);
+        let task = decoded(task, app.diff_decoder.clone());
+        self.show_action(app, task)
+    }
+
     fn show_empty_entries(&mut self, app: &Application) -> Result<()> {
         self.show_header(app, HeaderKind::Error)?;
-        self.write.queue(Print("nothing to select"))?;
+        self.write.queue(Print(tr("nothing to select")))?;
         Ok(())
     }
 
@@ -123,11 +861,37 @@ where
     where
         F: FnOnce(&mut Self) -> Result<()>,
     {
+        self.save_view_position();
         self.previous_action_kind = self.current_action_kind;
         self.current_action_kind = action;
+        self.pending_file_jump = None;
+        self.pending_view = None;
+        self.keeps_view = false;
         callback(self).map(|_| HandleChordResult::Handled)
     }
 
+    fn save_view_position(&mut self) {
+        if let Some(key) = self.view_key.take() {
+            if key.0 == self.scroll_view.action_kind() {
+                let mut view = self.scroll_view.view_state();
+                view.filter.clear();
+                self.view_positions.insert(key, view);
+            }
+        }
+    }
+
+    /// Returns to where the output of the current action for `content` was
+    /// left the last time it was shown during this session
+    fn restore_view_position(&mut self, content: String) {
+        let key = (self.current_action_kind, content);
+        let view = self.view_positions.get(&key).cloned();
+        self.pending_view = Some(view.unwrap_or(ViewState {
+            cursor: Some(0),
+            ..Default::default()
+        }));
+        self.view_key = Some(key);
+    }
+
     fn previous_target<'a>(&self, app: &'a Application) -> Option<&'a str> {
         let previous_result =
             app.get_cached_action_result(self.previous_action_kind);
@@ -141,14 +905,21 @@ where
             .and_then(|l| self.previous_action_kind.parse_target(l))
     }
 
-    fn show(&mut self, app: &mut Application) -> Result<()> {
+    fn show(
+        &mut self,
+        app: &mut Application,
+        state: UiState,
+        startup_mode: Option<StartupMode>,
+    ) -> Result<()> {
         execute!(
             self.write,
             SetTitle(app.version_control.get_root()),
             EnterAlternateScreen,
+            EnableMouseCapture,
             cursor::Hide
         )?;
         terminal::enable_raw_mode()?;
+        terminal_input::start(app.config.kitty_keyboard);
 
         self.write.flush()?;
         self.terminal_size = TerminalSize::get()?;
@@ -162,21 +933,99 @@ where
 
             app.set_cached_action_result(ActionKind::Help, help);
         }
+        if app.config.show_keys {
+            self.pressed_keys = Some(Vec::new());
+            input::keep_pressed_keys(true);
+        }
+        self.scroll_view.show_line_numbers = app.config.line_numbers;
+        self.quiet = app.config.quiet;
+        self.scroll_view.side_by_side = app.config.side_by_side;
+        self.scroll_view.word_diff = app.config.word_diff;
+        match startup_mode {
+            // stays on help telling what's wrong with the config
+            _ if !app.config.errors.is_empty() => {
+                let errors = app.config.errors.join("\n");
+                self.show_result(app, &ActionResult::from_err(errors))?
+            }
+            Some(mode) => self.start_in_mode(app, mode)?,
+            // an interrupted operation is shown instead of a view that
+            // could hide it
+            None if app.version_control.operation_in_progress().is_some() => {
+                self.run_key_chord(app, &['O', 'o'])?
+            }
+            None => self.restore_mode(app, state)?,
+        }
 
         loop {
+            let mut redrawn = false;
             if app.poll_and_check_action(self.current_action_kind) {
+                if self.current_action_kind == ActionKind::Log {
+                    self.apply_log_jump(app);
+                }
+                app.request_badge(self.current_action_kind);
                 let result =
                     app.get_cached_action_result(self.current_action_kind);
-                self.show_result(app, result)?;
-                self.write.flush()?;
+                let error_kind = result.error.as_ref().map(|e| e.kind);
+                if result.canceled {
+                    self.handle_retry(app)?;
+                } else if error_kind == Some(ErrorKind::HookFailure) {
+                    self.handle_commit_failure(app)?;
+                } else if error_kind == Some(ErrorKind::Conflict)
+                    && !self.current_action_kind.is_read_only()
+                {
+                    self.action_context(
+                        ActionKind::UnresolvedConflicts,
+                        |s| {
+                            let action = conflicts_action(app);
+                            s.show_action(app, action)
+                        },
+                    )?;
+                } else {
+                    self.show_result(app, result)?;
+                }
+                redrawn = true;
+            }
+            self.commit_formatted(app)?;
+            if app.poll_badges() {
+                self.show_current_action_result(app)?;
+                redrawn = true;
+            }
+            for (kind, success) in app.take_finished_in_background() {
+                let message = match success {
+                    true => trf("{} finished", &[&tr(kind.name())]),
+                    false => trf("{} failed", &[&tr(kind.name())]),
+                };
+                let notification = app.config.notification;
+                if !(self.quiet && notification == Notification::Bell) {
+                    notification.send(&mut self.write, &message)?;
+                }
             }
 
-            match input::poll_event() {
+            if let Some(prompt) =
+                app.askpass.as_ref().and_then(|a| a.poll_prompt())
+            {
+                self.handle_askpass(app, prompt.trim())?;
+                redrawn = true;
+            }
+
+            let event = input::poll_event();
+            if let Event::Key(_) | Event::Mouse(_) = event {
+                self.last_input_at = Instant::now();
+            }
+            // also those read while selecting or typing in other loops
+            if let Some(pressed_keys) = &mut self.pressed_keys {
+                let keys = input::take_pressed_keys().into_iter();
+                pressed_keys.extend(
+                    keys.map(|(key, time)| (input::key_name(key), time)),
+                );
+            }
+            match event {
                 Event::Resize(terminal_size) => {
                     self.terminal_size = terminal_size;
                     let result =
                         app.get_cached_action_result(self.current_action_kind);
                     self.show_result(app, result)?;
+                    redrawn = true;
                 }
                 Event::Key(KeyEvent {
                     code: KeyCode::Esc, ..
@@ -195,17 +1044,37 @@ where
                         esc_key_event,
                         self.terminal_size,
                     )? {
-                        self.write.flush()?;
-                        continue;
-                    }
-
-                    if self.current_key_chord.len() == 0 {
+                        redrawn = true;
+                    } else if app.cancel_action(self.current_action_kind) {
+                        self.show_current_action_result(app)?;
+                        redrawn = true;
+                    } else if self.current_key_chord.is_empty() {
                         break;
+                    } else {
+                        self.current_key_chord.clear();
+                        self.show_current_key_chord()?;
                     }
-
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('p'),
+                    modifiers: KeyModifiers::CONTROL,
+                }) => {
                     self.current_key_chord.clear();
+                    if let HandleChordResult::Quit =
+                        self.handle_command_palette(app)?
+                    {
+                        break;
+                    }
                     self.show_current_key_chord()?;
-                    self.write.flush()?;
+                    redrawn = true;
+                }
+                Event::Mouse(mouse_event) => {
+                    self.scroll_view.update_mouse(
+                        &mut self.write,
+                        mouse_event,
+                        self.terminal_size,
+                    )?;
+                    redrawn = true;
                 }
                 Event::Key(key_event) => {
                     if self.scroll_view.update(
@@ -213,32 +1082,71 @@ where
                         key_event,
                         self.terminal_size,
                     )? {
-                        self.write.flush()?;
-                        continue;
-                    }
-
-                    if let Some(c) = input::key_to_char(key_event) {
-                        self.current_key_chord.push(c);
-                    }
+                        redrawn = true;
+                    } else if key_event.code == KeyCode::Enter
+                        && matches!(
+                            self.current_action_kind,
+                            ActionKind::RevisionChanges
+                                | ActionKind::BaseChanges
+                        )
+                    {
+                        self.show_changed_file_diff(app)?;
+                        redrawn = true;
+                    } else if key_event.code == KeyCode::Enter
+                        && self.current_action_kind
+                            == ActionKind::ViewedRevisions
+                    {
+                        let revision = self
+                            .scroll_view
+                            .cursor_line()
+                            .and_then(|l| {
+                                ActionKind::ViewedRevisions.parse_target(l)
+                            })
+                            .map(String::from);
+                        if let Some(revision) = revision {
+                            self.action_context(ActionKind::LogJump, |s| {
+                                s.jump_in_log(app, &revision)
+                            })?;
+                        }
+                        redrawn = true;
+                    } else {
+                        if let Some(c) = input::key_to_chord_char(key_event) {
+                            self.current_key_chord.push(c);
+                        }
 
-                    match self.handle_key_chord(app)? {
-                        HandleChordResult::Handled => {
-                            self.current_key_chord.clear()
+                        match self.handle_key_chord(app)? {
+                            HandleChordResult::Handled => {
+                                self.current_key_chord.clear()
+                            }
+                            HandleChordResult::Unhandled => (),
+                            HandleChordResult::Quit => break,
                         }
-                        HandleChordResult::Unhandled => (),
-                        HandleChordResult::Quit => break,
-                    }
 
-                    self.show_current_key_chord()?;
-                    self.write.flush()?;
+                        self.show_current_key_chord()?;
+                        redrawn = true;
+                    }
                 }
                 _ => (),
             }
 
+            if redrawn {
+                self.load_more_log(app)?;
+            } else {
+                self.update_progress(app)?;
+                self.stream_output(app)?;
+                self.auto_refresh(app);
+            }
+            self.sync_preview(app, redrawn)?;
+            if self.expire_pressed_keys() || redrawn {
+                self.show_pressed_keys()?;
+            }
+            self.write.flush()?;
+
             thread::sleep(Duration::from_millis(20));
         }
 
-        execute!(self.write, ResetColor, cursor::Show)?;
+        execute!(self.write, ResetColor, DisableMouseCapture, cursor::Show)?;
+        terminal_input::stop();
         terminal::disable_raw_mode()?;
         self.write.execute(LeaveAlternateScreen)?;
         Ok(())
@@ -257,28 +1165,86 @@ where
                 Ok(HandleChordResult::Handled)
             }
             ['s'] => self.action_context(ActionKind::Status, |s| {
-                let action = app.version_control.status();
+                let action = app
+                    .version_control
+                    .status(app.status_options, app.scope.as_deref());
                 s.show_action(app, action)
             }),
             ['l'] => self.action_context(ActionKind::Log, |s| {
-                let action =
-                    app.version_control.log(s.terminal_size.height as usize);
+                s.log_count = s.terminal_size.height as usize;
+                let action = app.version_control.log(
+                    s.log_count,
+                    app.scope.as_deref(),
+                    app.log_branch.as_deref(),
+                );
                 s.show_action(app, action)
             }),
             ['L'] => Ok(HandleChordResult::Unhandled),
+            ['L', 'b'] => self.action_context(ActionKind::LogBranch, |s| {
+                let branch = match s.handle_input(
+                    app,
+                    "log of branch or revision",
+                    s.previous_target(app),
+                )? {
+                    Some(branch) => branch.trim().to_owned(),
+                    None => return s.show_previous_action_result(app),
+                };
+                app.log_branch = Some(branch);
+                s.current_action_kind = ActionKind::Log;
+                s.log_count = s.terminal_size.height as usize;
+                let action = app.version_control.log(
+                    s.log_count,
+                    app.scope.as_deref(),
+                    app.log_branch.as_deref(),
+                );
+                s.show_action(app, action)
+            }),
+            ['L', 'j'] => self.action_context(ActionKind::LogJump, |s| {
+                let input = match s.handle_input(
+                    app,
+                    "jump to date or revision",
+                    None,
+                )? {
+                    Some(input) => input,
+                    None => return s.show_previous_action_result(app),
+                };
+                s.jump_in_log(app, input.trim())
+            }),
+            ['L', 'r'] => {
+                self.action_context(ActionKind::ViewedRevisions, |s| {
+                    let result = if s.viewed_revisions.is_empty() {
+                        ActionResult::from_err(tr(
+                            "no revision viewed yet, revisions shown with DC, DD or DS are listed here",
+                        ))
+                    } else {
+                        ActionResult::from_ok(s.viewed_revisions.join("\n"))
+                    };
+                    app.set_cached_action_result(
+                        ActionKind::ViewedRevisions,
+                        result.clone(),
+                    );
+                    s.show_result(app, &result)
+                })
+            }
             ['L', 'C'] => self.action_context(ActionKind::LogCount, |s| {
                 if let Some(input) =
                     s.handle_input(app, "logs to show", None)?
                 {
                     if let Ok(count) = input.trim().parse() {
-                        let action = app.version_control.log(count);
+                        let action = app.version_control.log(
+                            count,
+                            app.scope.as_deref(),
+                            app.log_branch.as_deref(),
+                        );
                         s.show_action(app, action)
                     } else {
                         s.show_header(app, HeaderKind::Error)?;
                         queue!(
                             s.write,
-                            Print("could not parse a number from "),
-                            Print(input)
+                            Print(trf(
+                                "could not parse a number from {}",
+                                &[&input]
+                            ))
                         )
                     }
                 } else {
@@ -289,27 +1255,116 @@ where
             ['e', 'e'] => {
                 self.action_context(ActionKind::CurrentFullRevision, |s| {
                     let action = app.version_control.current_export();
-                    s.show_action(app, action)
-                })
-            }
-            ['d'] => Ok(HandleChordResult::Unhandled),
-            ['d', 'd'] => {
-                self.action_context(ActionKind::CurrentDiffAll, |s| {
-                    let action = app.version_control.current_diff_all();
-                    s.show_action(app, action)
+                    s.show_diff_action(app, action)
                 })
             }
-            ['d', 's'] => {
-                self.action_context(ActionKind::CurrentDiffSelected, |s| {
-                    match app.version_control.get_current_changed_files() {
-                        Ok(mut entries) => {
-                            if entries.len() == 0 {
-                                s.show_empty_entries(app)
-                            } else if s.show_select_ui(app, &mut entries[..])? {
-                                let action = app
+            ['e', 'p'] => {
+                self.action_context(ActionKind::ExportSelected, |s| {
+                    let mut entries =
+                        match app.get_current_changed_files() {
+                            Ok(entries) => entries,
+                            Err(error) => {
+                                return s.show_result(
+                                    app,
+                                    &ActionResult::from_err(error),
+                                )
+                            }
+                        };
+                    if entries.is_empty() {
+                        return s.show_empty_entries(app);
+                    }
+                    if !s.show_changed_files_select_ui(app, &mut entries[..])? {
+                        return s.show_previous_action_result(app);
+                    }
+                    let destination = match s.handle_input(
+                        app,
+                        "export to file or clipboard",
+                        Some(EXPORT_TO_CLIPBOARD),
+                    )? {
+                        Some(destination) => destination,
+                        None => return s.show_previous_action_result(app),
+                    };
+
+                    let mut paths = String::new();
+                    for e in entries.iter().filter(|e| e.selected) {
+                        paths.push_str(&e.filename);
+                        paths.push('\n');
+                    }
+                    let count = paths.lines().count();
+                    let destination = destination.trim();
+                    let result = export_to(
+                        app,
+                        destination,
+                        &paths,
+                        trf(
+                            "exported {} paths to {}",
+                            &[&count, &destination],
+                        ),
+                    );
+                    s.show_result(app, &result)
+                })
+            }
+            ['!'] => self.action_context(ActionKind::RunOnSelected, |s| {
+                let mut entries = match app.get_current_changed_files()
+                {
+                    Ok(entries) => entries,
+                    Err(error) => {
+                        return s
+                            .show_result(app, &ActionResult::from_err(error))
+                    }
+                };
+                if entries.is_empty() {
+                    return s.show_empty_entries(app);
+                }
+                if !s.show_changed_files_select_ui(app, &mut entries[..])? {
+                    return s.show_previous_action_result(app);
+                }
+                let command_line = match s.handle_input(
+                    app,
+                    "command ('{}' is replaced by the selected files)",
+                    None,
+                )? {
+                    Some(command_line) if !command_line.trim().is_empty() => {
+                        command_line
+                    }
+                    _ => return s.show_previous_action_result(app),
+                };
+
+                let files: Vec<_> = entries
+                    .iter()
+                    .filter(|e| e.selected)
+                    .map(|e| &e.filename[..])
+                    .collect();
+                let action = custom_actions::run_on_files(
+                    app.version_control.get_root(),
+                    command_line.trim(),
+                    &files[..],
+                );
+                s.show_action(app, action)
+            }),
+            ['d'] => Ok(HandleChordResult::Unhandled),
+            ['d', 'd'] => {
+                self.action_context(ActionKind::CurrentDiffAll, |s| {
+                    let action = app
+                        .version_control
+                        .current_diff_all(app.scope.as_deref());
+                    s.show_diff_action(app, action)
+                })
+            }
+            ['d', 's'] => {
+                self.action_context(ActionKind::CurrentDiffSelected, |s| {
+                    match app.get_current_changed_files() {
+                        Ok(mut entries) => {
+                            if entries.is_empty() {
+                                s.show_empty_entries(app)
+                            } else if s.show_changed_files_select_ui(app, &mut entries[..])? {
+                                s.restore_view_position(selected_files(
+                                    &entries,
+                                ));
+                                let action = app
                                     .version_control
                                     .current_diff_selected(&entries);
-                                s.show_action(app, action)
+                                s.show_diff_action(app, action)
                             } else {
                                 s.show_previous_action_result(app)
                             }
@@ -328,9 +1383,28 @@ where
                         "show changes from",
                         s.previous_target(app),
                     )? {
-                        let action =
-                            app.version_control.revision_changes(input.trim());
-                        s.show_action(app, action)
+                        let target = input.trim();
+                        s.remember_viewed_revision(app, target);
+                        s.revision_changes_target = target.into();
+                        s.scroll_view.notes_revision = target.into();
+                        s.restore_view_position(target.into());
+                        let mut tasks = task_vec();
+                        tasks.push(
+                            app.version_control
+                                .revision_changes(target, app.scope.as_deref()),
+                        );
+                        tasks.push(
+                            app.version_control.revision_signature(target),
+                        );
+                        if app.version_control.supports_notes() {
+                            tasks.push(
+                                app.version_control.revision_notes(
+                                    target,
+                                    &app.config.notes_ref,
+                                ),
+                            );
+                        }
+                        s.show_action(app, serial(tasks))
                     } else {
                         s.show_previous_action_result(app)
                     }
@@ -343,9 +1417,13 @@ where
                         "show diff from",
                         s.previous_target(app),
                     )? {
-                        let action =
-                            app.version_control.revision_diff_all(input.trim());
-                        s.show_action(app, action)
+                        let target = input.trim();
+                        s.remember_viewed_revision(app, target);
+                        s.restore_view_position(target.into());
+                        let action = app
+                            .version_control
+                            .revision_diff_all(target, app.scope.as_deref());
+                        s.show_diff_action(app, action)
                     } else {
                         s.show_previous_action_result(app)
                     }
@@ -358,23 +1436,29 @@ where
                         "show diff from",
                         s.previous_target(app),
                     )? {
+                        s.remember_viewed_revision(app, input.trim());
                         match app
                             .version_control
                             .get_revision_changed_files(input.trim())
                         {
                             Ok(mut entries) => {
-                                if entries.len() == 0 {
+                                if entries.is_empty() {
                                     s.show_empty_entries(app)
                                 } else if s
                                     .show_select_ui(app, &mut entries[..])?
                                 {
+                                    s.restore_view_position(format!(
+                                        "{}\n{}",
+                                        input.trim(),
+                                        selected_files(&entries)
+                                    ));
                                     let action = app
                                         .version_control
                                         .revision_diff_selected(
                                             input.trim(),
                                             &entries,
                                         );
-                                    s.show_action(app, action)
+                                    s.show_diff_action(app, action)
                                 } else {
                                     s.show_previous_action_result(app)
                                 }
@@ -389,37 +1473,188 @@ where
                     }
                 })
             }
+            ['D', 'A'] => {
+                if matches!(
+                    self.current_action_kind,
+                    ActionKind::RevisionDiffAll
+                        | ActionKind::RevisionDiffSelected
+                ) {
+                    return self.apply_selected_hunk(app);
+                }
+                self.show_header(app, HeaderKind::Error)?;
+                self.write.queue(Print(tr(
+                    "hunks are applied from the diff of a revision",
+                )))?;
+                Ok(HandleChordResult::Handled)
+            }
+            ['D', 'B'] => self.action_context(ActionKind::BaseChanges, |s| {
+                if let Some(input) = s.handle_input(
+                    app,
+                    "compare working tree with",
+                    s.previous_target(app),
+                )? {
+                    let base = input.trim();
+                    s.revision_changes_target = base.into();
+                    s.restore_view_position(base.into());
+                    let action = app
+                        .version_control
+                        .base_changes(base, app.scope.as_deref());
+                    s.show_action(app, action)
+                } else {
+                    s.show_previous_action_result(app)
+                }
+            }),
+            ['D', 'R'] => self
+                .action_context(ActionKind::CompareRevisions, |s| {
+                    s.compare_revisions(app)
+                }),
+            ['D', 'N'] if app.version_control.supports_notes() => self
+                .action_context(ActionKind::RevisionNote, |s| {
+                    if let Some(input) = s.handle_input(
+                        app,
+                        "edit note of",
+                        s.previous_target(app),
+                    )? {
+                        let target = input.trim();
+                        let notes_ref = &app.config.notes_ref;
+                        let note = match app
+                            .version_control
+                            .get_note(target, notes_ref)
+                        {
+                            Ok(note) => note,
+                            Err(error) => {
+                                return s.show_result(
+                                    app,
+                                    &ActionResult::from_err(error),
+                                );
+                            }
+                        };
+                        if let Some(message) = s.handle_multiline_input(
+                            app,
+                            "note message",
+                            &note,
+                        )? {
+                            let action = app
+                                .version_control
+                                .set_note(target, notes_ref, &message);
+                            s.show_action(app, action)
+                        } else {
+                            s.show_previous_action_result(app)
+                        }
+                    } else {
+                        s.show_previous_action_result(app)
+                    }
+                }),
+            ['D', 'P'] if app.version_control.supports_phases() => self
+                .action_context(ActionKind::SetPhase, |s| {
+                    let target = match s.previous_action_kind {
+                        ActionKind::RevisionChanges => {
+                            Some(s.revision_changes_target.clone())
+                        }
+                        _ => s.previous_target(app).map(String::from),
+                    };
+                    let target = match s.handle_input(
+                        app,
+                        "set phase of",
+                        target.as_deref(),
+                    )? {
+                        Some(target) => target,
+                        None => return s.show_previous_action_result(app),
+                    };
+                    let phase = match s.handle_input_with_candidates(
+                        app,
+                        "phase",
+                        None,
+                        PHASES.iter().map(|&p| p.into()).collect(),
+                    )? {
+                        Some(phase) => phase,
+                        None => return s.show_previous_action_result(app),
+                    };
+                    let phase = phase.trim();
+                    if !PHASES.contains(&phase) {
+                        let error = trf("unknown phase '{}'", &[&phase]);
+                        return s.show_result(app, &ActionResult::from_err(error));
+                    }
+                    let target = target.trim();
+                    let phases = match app.version_control.get_phases(target) {
+                        Ok(phases) => phases,
+                        Err(error) => {
+                            return s.show_result(
+                                app,
+                                &ActionResult::from_err(error),
+                            )
+                        }
+                    };
+                    let rank = |p: &str| PHASES.iter().position(|&q| q == p);
+                    // hg refuses to move a revision back without --force
+                    let force = phases.iter().any(|p| rank(p) < rank(phase));
+                    // others may have pulled public revisions already
+                    if force
+                        && phases.iter().any(|p| p == PHASES[0])
+                        && !s.handle_confirm(
+                            app,
+                            "moving a public revision back rewrites history others may have. continue?",
+                        )?
+                    {
+                        return s.show_previous_action_result(app);
+                    }
+                    let action =
+                        app.version_control.set_phase(target, phase, force);
+                    s.show_action(app, action)
+                }),
             ['c'] => Ok(HandleChordResult::Unhandled),
             ['c', 'c'] => self.action_context(ActionKind::CommitAll, |s| {
-                if let Some(input) =
-                    s.handle_input(app, "commit message", None)?
-                {
-                    let action = app.version_control.commit_all(input.trim());
-                    s.show_action(app, action)
+                let branch_off = match s.handle_protected_branch(app)? {
+                    BranchOff::Commit => None,
+                    BranchOff::CreateBranch(name) => Some(name),
+                    BranchOff::Abort => {
+                        return s.show_previous_action_result(app)
+                    }
+                };
+                if let Some(input) = s.handle_commit_message(app)? {
+                    let message = match s.add_trailers(app, &input)? {
+                        Some(message) => message,
+                        None => return s.show_previous_action_result(app),
+                    };
+                    // with a scope, all is what the status shows
+                    let entries = match &app.scope {
+                        Some(_) => match app.get_current_changed_files() {
+                            Ok(mut entries) => {
+                                for e in &mut entries {
+                                    e.selected = true;
+                                }
+                                Some(entries)
+                            }
+                            Err(error) => {
+                                return s.show_result(
+                                    app,
+                                    &ActionResult::from_err(error),
+                                )
+                            }
+                        },
+                        None => None,
+                    };
+                    let commit = LastCommit { message, entries };
+                    s.format_and_commit(app, commit, branch_off)
                 } else {
                     s.show_previous_action_result(app)
                 }
             }),
             ['c', 's'] => {
                 self.action_context(ActionKind::CommitSelected, |s| {
-                    match app.version_control.get_current_changed_files() {
+                    let branch_off = match s.handle_protected_branch(app)? {
+                        BranchOff::Commit => None,
+                        BranchOff::CreateBranch(name) => Some(name),
+                        BranchOff::Abort => {
+                            return s.show_previous_action_result(app)
+                        }
+                    };
+                    match app.get_current_changed_files() {
                         Ok(mut entries) => {
-                            if entries.len() == 0 {
+                            if entries.is_empty() {
                                 s.show_empty_entries(app)
-                            } else if s.show_select_ui(app, &mut entries[..])? {
-                                s.show_header(app, HeaderKind::Waiting)?;
-                                if let Some(input) =
-                                    s.handle_input(app, "commit message", None)?
-                                {
-                                    let action =
-                                        app.version_control.commit_selected(
-                                            input.trim(),
-                                            &entries,
-                                        );
-                                    s.show_action(app, action)
-                                } else {
-                                    s.show_previous_action_result(app)
-                                }
+                            } else if s.show_changed_files_select_ui(app, &mut entries[..])? {
+                                s.commit_entries(app, entries, branch_off)
                             } else {
                                 s.show_previous_action_result(app)
                             }
@@ -430,11 +1665,48 @@ where
                     }
                 })
             }
+            ['c', 'p'] => self.action_context(ActionKind::CommitPinned, |s| {
+                let mut entries =
+                    match app.get_current_changed_files() {
+                        Ok(entries) => entries,
+                        Err(error) => {
+                            return s
+                                .show_result(app, &ActionResult::from_err(error))
+                        }
+                    };
+                for entry in &mut entries {
+                    entry.selected =
+                        s.scroll_view.pinned_files.contains(&entry.filename);
+                }
+                if !entries.iter().any(|e| e.selected) {
+                    let error = tr("no pinned files changed, pin them with np");
+                    return s.show_result(app, &ActionResult::from_err(error));
+                }
+                let branch_off = match s.handle_protected_branch(app)? {
+                    BranchOff::Commit => None,
+                    BranchOff::CreateBranch(name) => Some(name),
+                    BranchOff::Abort => return s.show_previous_action_result(app),
+                };
+                s.commit_entries(app, entries, branch_off)
+            }),
             ['u'] => self.action_context(ActionKind::Update, |s| {
                 if let Some(input) =
                     s.handle_input(app, "update to", s.previous_target(app))?
                 {
-                    let action = app.version_control.update(input.trim());
+                    let target = input.trim();
+                    let is_tag = app
+                        .version_control
+                        .get_tag_names()
+                        .map(|tags| tags.iter().any(|t| t == target))
+                        .unwrap_or(false);
+                    let action = if is_tag {
+                        match s.checkout_tag(app, target)? {
+                            Some(action) => action,
+                            None => return s.show_previous_action_result(app),
+                        }
+                    } else {
+                        app.version_control.update(target)
+                    };
                     s.show_action(app, action)
                 } else {
                     s.show_previous_action_result(app)
@@ -450,19 +1722,111 @@ where
                     s.show_previous_action_result(app)
                 }
             }),
+            ['I'] => self.action_context(ActionKind::Ignore, |s| {
+                let mut entries = match app.get_current_changed_files()
+                {
+                    Ok(entries) => entries,
+                    Err(error) => {
+                        return s
+                            .show_result(app, &ActionResult::from_err(error))
+                    }
+                };
+                entries.retain(|e| matches!(e.state, State::Untracked));
+                if entries.is_empty() {
+                    return s.show_empty_entries(app);
+                }
+                if !s.show_select_ui(app, &mut entries[..])? {
+                    return s.show_previous_action_result(app);
+                }
+
+                let mut tasks = task_vec();
+                for e in entries.iter().filter(|e| e.selected) {
+                    match s.handle_input_with_candidates(
+                        app,
+                        "ignore pattern (tab for alternatives)",
+                        Some(&e.filename),
+                        ignore_candidates(&e.filename),
+                    )? {
+                        Some(pattern) if !pattern.trim().is_empty() => tasks
+                            .push(app.version_control.ignore(pattern.trim())),
+                        _ => (),
+                    }
+                }
+                if tasks.is_empty() {
+                    return s.show_previous_action_result(app);
+                }
+                tasks.push(
+                    app.version_control
+                        .status(app.status_options, app.scope.as_deref()),
+                );
+                s.current_action_kind = ActionKind::Status;
+                s.show_action(app, serial(tasks))
+            }),
             ['R'] => Ok(HandleChordResult::Unhandled),
             ['R', 'A'] => self.action_context(ActionKind::RevertAll, |s| {
-                let action = app.version_control.revert_all();
+                let mut entries = match app.get_current_changed_files()
+                {
+                    Ok(entries) => entries,
+                    Err(error) => {
+                        return s
+                            .show_result(app, &ActionResult::from_err(error))
+                    }
+                };
+                for e in &mut entries {
+                    e.selected = true;
+                }
+                if !s.confirm_discard(app, &entries)? {
+                    s.show_previous_action_result(app)
+                } else if let Err(error) = s.snapshot_before_discard(app) {
+                    s.show_result(app, &ActionResult::from_err(error))
+                } else {
+                    // with a scope, all is what the status shows
+                    let action = match &app.scope {
+                        Some(_) => {
+                            app.version_control.revert_selected(&entries)
+                        }
+                        None => app.version_control.revert_all(),
+                    };
+                    s.show_action(app, action)
+                }
+            }),
+            ['R', 'H'] => {
+                if matches!(
+                    self.current_action_kind,
+                    ActionKind::CurrentDiffAll
+                        | ActionKind::CurrentDiffSelected
+                ) {
+                    self.revert_selected_hunk(app)?;
+                } else {
+                    self.show_header(app, HeaderKind::Error)?;
+                    self.write.queue(Print(tr(
+                        "hunks are reverted from the diff of the working tree",
+                    )))?;
+                }
+                Ok(HandleChordResult::Handled)
+            }
+            ['R', 'U'] => self.action_context(ActionKind::UndoDiscard, |s| {
+                let action = app.version_control.restore_discard_snapshot();
                 s.show_action(app, action)
             }),
             ['r'] => Ok(HandleChordResult::Unhandled),
             ['r', 's'] => {
                 self.action_context(ActionKind::RevertSelected, |s| {
-                    match app.version_control.get_current_changed_files() {
+                    match app.get_current_changed_files() {
                         Ok(mut entries) => {
-                            if entries.len() == 0 {
+                            if entries.is_empty() {
                                 s.show_empty_entries(app)
-                            } else if s.show_select_ui(app, &mut entries[..])? {
+                            } else if s.show_changed_files_select_ui(app, &mut entries[..])?
+                                && s.confirm_discard(app, &entries)?
+                            {
+                                if let Err(error) =
+                                    s.snapshot_before_discard(app)
+                                {
+                                    return s.show_result(
+                                        app,
+                                        &ActionResult::from_err(error),
+                                    );
+                                }
                                 let action = app
                                     .version_control
                                     .revert_selected(&entries);
@@ -477,12 +1841,18 @@ where
                     }
                 })
             }
+            ['r', 'h'] => self.action_context(ActionKind::RevertHunks, |s| {
+                s.revert_hunks(app)
+            }),
             ['r', 'r'] => {
                 self.action_context(ActionKind::UnresolvedConflicts, |s| {
-                    let action = app.version_control.conflicts();
+                    let action = conflicts_action(app);
                     s.show_action(app, action)
                 })
             }
+            ['r', 'e'] => self.action_context(ActionKind::EditConflicts, |s| {
+                s.edit_conflicts(app)
+            }),
             ['r', 'o'] => {
                 self.action_context(ActionKind::MergeTakingOther, |s| {
                     let action = app.version_control.take_other();
@@ -495,18 +1865,105 @@ where
                     s.show_action(app, action)
                 })
             }
+            ['O'] => Ok(HandleChordResult::Unhandled),
+            ['O', 'o'] => self
+                .action_context(ActionKind::InterruptedOperation, |s| {
+                    let result =
+                        match app.version_control.operation_in_progress() {
+                            Some(operation) => {
+                                ActionResult::from_ok(operation_banner(operation))
+                            }
+                            None => ActionResult::from_ok(
+                                tr("no interrupted operation").into(),
+                            ),
+                        };
+                    s.show_result(app, &result)
+                }),
+            ['O', 'c'] => {
+                self.action_context(ActionKind::ContinueOperation, |s| {
+                    match app.version_control.operation_in_progress() {
+                        Some(operation) => {
+                            let action =
+                                app.version_control.continue_operation(operation);
+                            s.show_action(app, action)
+                        }
+                        None => s.show_result(
+                            app,
+                            &ActionResult::from_err(tr("no interrupted operation")),
+                        ),
+                    }
+                })
+            }
+            ['O', 'a'] => self.action_context(ActionKind::AbortOperation, |s| {
+                let operation = match app.version_control.operation_in_progress()
+                {
+                    Some(operation) => operation,
+                    None => {
+                        return s.show_result(
+                            app,
+                            &ActionResult::from_err(tr("no interrupted operation")),
+                        )
+                    }
+                };
+                let prompt = trf("abort the {}?", &[&operation.name()]);
+                if s.handle_confirm(app, &prompt)? {
+                    let action = app.version_control.abort_operation(operation);
+                    s.show_action(app, action)
+                } else {
+                    s.show_previous_action_result(app)
+                }
+            }),
             ['f'] => self.action_context(ActionKind::Fetch, |s| {
                 let action = app.version_control.fetch();
                 s.show_action(app, action)
             }),
             ['p'] => self.action_context(ActionKind::Pull, |s| {
-                let action = app.version_control.pull();
+                let action = match app.version_control.get_incoming_patches() {
+                    Ok(None) => app.version_control.pull(),
+                    Ok(Some(mut patches)) => {
+                        if !s.pick_patches(
+                            app,
+                            &mut patches,
+                            "there are no patches to pull",
+                        )? {
+                            return Ok(());
+                        }
+                        app.version_control.pull_selected(&patches)
+                    }
+                    Err(error) => {
+                        return s.show_result(app, &ActionResult::from_err(error))
+                    }
+                };
                 s.show_action(app, action)
             }),
             ['P'] => self.action_context(ActionKind::Push, |s| {
-                let action = app.version_control.push();
+                let action = match app.version_control.get_outgoing_patches() {
+                    Ok(None) => app.version_control.push(),
+                    Ok(Some(mut patches)) => {
+                        if !s.pick_patches(
+                            app,
+                            &mut patches,
+                            "there are no patches to push",
+                        )? {
+                            return Ok(());
+                        }
+                        app.version_control.push_selected(&patches)
+                    }
+                    Err(error) => {
+                        return s.show_result(app, &ActionResult::from_err(error))
+                    }
+                };
                 s.show_action(app, action)
             }),
+            ['F'] => Ok(HandleChordResult::Unhandled),
+            ['F', 'P'] => self.action_context(ActionKind::ForcePush, |s| {
+                if s.handle_confirm(app, "force push current branch?")? {
+                    let action = app.version_control.force_push();
+                    s.show_action(app, action)
+                } else {
+                    s.show_previous_action_result(app)
+                }
+            }),
             ['t'] => Ok(HandleChordResult::Unhandled),
             ['t', 'n'] => self.action_context(ActionKind::NewTag, |s| {
                 if let Some(input) =
@@ -518,6 +1975,19 @@ where
                     s.show_previous_action_result(app)
                 }
             }),
+            ['t', 't'] => self.action_context(ActionKind::ListTags, |s| {
+                if let Some(input) =
+                    s.handle_input(app, "tag pattern", Some("*"))?
+                {
+                    let action =
+                        app.version_control.list_tags(Some(input.trim()));
+                    s.show_action(app, action)
+                } else {
+                    s.show_previous_action_result(app)
+                }
+            }),
+            ['t', 'r'] => self
+                .action_context(ActionKind::RenameTag, |s| s.rename_tag(app)),
             ['b'] => Ok(HandleChordResult::Unhandled),
             ['b', 'b'] => self.action_context(ActionKind::ListBranches, |s| {
                 let action = app.version_control.list_branches();
@@ -534,6 +2004,17 @@ where
                     s.show_previous_action_result(app)
                 }
             }),
+            ['b', 'm'] => self.action_context(ActionKind::NewBookmark, |s| {
+                if let Some(input) =
+                    s.handle_input(app, "new bookmark name", None)?
+                {
+                    let action =
+                        app.version_control.create_bookmark(input.trim());
+                    s.show_action(app, action)
+                } else {
+                    s.show_previous_action_result(app)
+                }
+            }),
             ['b', 'd'] => self.action_context(ActionKind::DeleteBranch, |s| {
                 if let Some(input) = s.handle_input(
                     app,
@@ -546,74 +2027,603 @@ where
                     s.show_previous_action_result(app)
                 }
             }),
-            ['x'] => self.action_context(ActionKind::CustomAction, |s| {
-                if app.custom_actions.len() > 0 {
-                    s.show_header(app, HeaderKind::Ok)?;
-                    for c in &app.custom_actions {
-                        s.write
-                            .queue(SetForegroundColor(ENTRY_COLOR))?
-                            .queue(Print(&c.shortcut))?
-                            .queue(ResetColor)?
-                            .queue(Print('\t'))?
-                            .queue(Print(&c.command))?;
-                        for a in &c.args {
-                            s.write.queue(Print(' '))?.queue(Print(a))?;
+            ['b', 'p'] => self.action_context(ActionKind::PushBranchTo, |s| {
+                let vc = &app.version_control;
+                let remotes = vc.get_remotes().unwrap_or_default();
+                let initial_remote = remotes.first().cloned();
+                let remote = match s.handle_input_with_candidates(
+                    app,
+                    "push to remote",
+                    initial_remote.as_deref(),
+                    remotes,
+                )? {
+                    Some(remote) => remote,
+                    None => return s.show_previous_action_result(app),
+                };
+
+                let current_branch = vc.get_current_branch().ok();
+                let branches = vc.get_branch_names().unwrap_or_default();
+                let branch = match s.handle_input_with_candidates(
+                    app,
+                    "remote branch",
+                    current_branch.as_deref(),
+                    branches,
+                )? {
+                    Some(branch) => branch,
+                    None => return s.show_previous_action_result(app),
+                };
+
+                let set_upstream = !vc.has_upstream();
+                let action =
+                    vc.push_to(remote.trim(), branch.trim(), set_upstream);
+                s.show_action(app, action)
+            }),
+            ['g'] => Ok(HandleChordResult::Unhandled),
+            ['g', 'l'] => match app.forge() {
+                Some(forge) => {
+                    self.action_context(ActionKind::ListPullRequests, |s| {
+                        let root = app.version_control.get_root();
+                        let action = forge.list_pull_requests(root);
+                        s.show_action(app, action)
+                    })
+                }
+                None => Ok(HandleChordResult::Handled),
+            },
+            ['g', 'c'] => match app.forge() {
+                Some(forge) => {
+                    self.action_context(ActionKind::CheckoutPullRequest, |s| {
+                        let number = match s.previous_action_kind {
+                            ActionKind::ListPullRequests => {
+                                s.previous_target(app)
+                            }
+                            _ => None,
+                        };
+                        if let Some(input) = s.handle_input(
+                            app,
+                            "check out pull request",
+                            number,
+                        )? {
+                            let root = app.version_control.get_root();
+                            let action =
+                                forge.checkout_pull_request(root, input.trim());
+                            s.show_action(app, action)
+                        } else {
+                            s.show_previous_action_result(app)
                         }
-                        s.write.queue(cursor::MoveToNextLine(1))?;
+                    })
+                }
+                None => Ok(HandleChordResult::Handled),
+            },
+            ['g', 'o'] => self.action_context(ActionKind::OpenInBrowser, |s| {
+                s.open_in_browser(app)
+            }),
+            ['n'] => Ok(HandleChordResult::Unhandled),
+            ['n', 'n'] => self.action_context(ActionKind::NoteFiles, |s| {
+                let (revision, paths) = match s.files_to_note(app)? {
+                    Some(files) => files,
+                    None => return s.show_previous_action_result(app),
+                };
+                let current = paths
+                    .iter()
+                    .find_map(|p| {
+                        let key = (revision.clone(), p.clone());
+                        s.scroll_view.file_notes.get(&key)
+                    })
+                    .cloned();
+                let mut candidates: Vec<_> =
+                    s.scroll_view.file_notes.values().cloned().collect();
+                candidates.sort();
+                candidates.dedup();
+                if let Some(note) = s.handle_input_with_candidates(
+                    app,
+                    "note",
+                    current.as_deref(),
+                    candidates,
+                )? {
+                    let note = note.trim();
+                    for path in paths {
+                        let key = (revision.clone(), path);
+                        s.scroll_view.file_notes.insert(key, note.into());
                     }
-                    s.handle_custom_action(app)?;
-                    s.current_key_chord.clear();
+                }
+                s.show_previous_action_result(app)
+            }),
+            ['n', 'r'] => self.action_context(ActionKind::RemoveNotes, |s| {
+                if let Some((revision, paths)) = s.files_to_note(app)? {
+                    for path in paths {
+                        let key = (revision.clone(), path);
+                        s.scroll_view.file_notes.remove(&key);
+                    }
+                }
+                s.show_previous_action_result(app)
+            }),
+            ['n', 'l'] => self.action_context(ActionKind::ListNotes, |s| {
+                let result = if s.scroll_view.file_notes.is_empty() {
+                    ActionResult::from_err(tr("no notes yet, add them with nn"))
                 } else {
-                    s.show_header(app, HeaderKind::Error)?;
-                    queue!(
-                        s.write,
-                        ResetColor,
-                        Print("no commands available"),
-                        cursor::MoveToNextLine(2),
-                        Print(concat!(
-                            "create custom actions by placing them inside '.",
-                            env!("CARGO_PKG_NAME"),
-                            "/custom_actions.txt'"
-                        )),
-                    )?;
+                    ActionResult::from_ok(s.notes_text())
+                };
+                s.show_result(app, &result)
+            }),
+            ['n', 'p'] => self.action_context(ActionKind::PinFiles, |s| {
+                if let Some((_, paths)) = s.files_to_note(app)? {
+                    s.scroll_view.pinned_files.extend(paths);
+                    let _ = s.ui_state(app).save();
                 }
-                Ok(())
+                s.show_previous_action_result(app)
             }),
-            _ => Ok(HandleChordResult::Handled),
-        }
-    }
-
-    fn handle_custom_action(&mut self, app: &mut Application) -> Result<()> {
-        self.current_key_chord.clear();
-        self.write.queue(cursor::SavePosition)?;
-
-        'outer: loop {
-            self.write.flush()?;
-            match input::poll_event() {
-                Event::Resize(terminal_size) => {
-                    self.terminal_size = terminal_size;
+            ['n', 'u'] => self.action_context(ActionKind::UnpinFiles, |s| {
+                if s.scroll_view.pinned_files.is_empty() {
+                    let error = tr("no pinned files, pin them with np");
+                    return s.show_result(app, &ActionResult::from_err(error));
                 }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Esc, ..
-                })
-                | Event::Key(KeyEvent {
-                    code: KeyCode::Char('c'),
-                    modifiers: KeyModifiers::CONTROL,
-                }) => {
-                    return self.show_previous_action_result(app);
+                let mut entries: Vec<_> = s
+                    .scroll_view
+                    .pinned_files
+                    .iter()
+                    .map(|path| Entry {
+                        filename: path.clone(),
+                        selected: false,
+                        state: State::Unmodified,
+                        renamed_from: None,
+                    })
+                    .collect();
+                if s.show_select_ui(app, &mut entries[..])? {
+                    for entry in entries.iter().filter(|e| e.selected) {
+                        s.scroll_view.pinned_files.remove(&entry.filename);
+                    }
+                    let _ = s.ui_state(app).save();
                 }
-                Event::Key(key_event) => {
-                    if let Some(c) = input::key_to_char(key_event) {
-                        self.current_key_chord.push(c);
+                s.show_previous_action_result(app)
+            }),
+            ['n', 'e'] => self.action_context(ActionKind::ExportNotes, |s| {
+                if s.scroll_view.file_notes.is_empty() {
+                    return s.show_result(
+                        app,
+                        &ActionResult::from_err(tr(
+                            "no notes yet, add them with nn",
+                        )),
+                    );
+                }
+                let destination = match s.handle_input(
+                    app,
+                    "export to file or clipboard",
+                    Some(EXPORT_TO_CLIPBOARD),
+                )? {
+                    Some(destination) => destination,
+                    None => return s.show_previous_action_result(app),
+                };
+                let destination = destination.trim();
+                let count = s.scroll_view.file_notes.len();
+                let result = export_to(
+                    app,
+                    destination,
+                    &s.notes_text(),
+                    trf("exported {} notes to {}", &[&count, &destination]),
+                );
+                s.show_result(app, &result)
+            }),
+            ['y'] => Ok(HandleChordResult::Unhandled),
+            ['y', 'y'] => self.action_context(ActionKind::CopyEntry, |s| {
+                let text = match s.hovered_text(app) {
+                    Some(text) => text,
+                    None => {
+                        return s.show_result(
+                            app,
+                            &ActionResult::from_err(tr(
+                                "move the cursor to a commit, branch, tag or changed file to copy",
+                            )),
+                        )
                     }
-                    for action in &app.custom_actions {
-                        if action
+                };
+                let result =
+                    copy_to_clipboard(&text, trf("copied {}", &[&text]));
+                s.show_result(app, &result)
+            }),
+            ['y', 'd'] => self.action_context(ActionKind::CopyDiff, |s| {
+                let kind = s.previous_action_kind;
+                if !kind.is_diff() {
+                    return s.show_result(
+                        app,
+                        &ActionResult::from_err(tr("show a diff to copy it")),
+                    );
+                }
+                let mut diff = String::new();
+                for line in app.get_cached_action_result(kind).output.lines() {
+                    diff.push_str(&strip_ansi(line));
+                    diff.push('\n');
+                }
+                let result = copy_to_clipboard(
+                    &diff,
+                    trf("copied {} lines of diff", &[&diff.lines().count()]),
+                );
+                s.show_result(app, &result)
+            }),
+            ['C'] => Ok(HandleChordResult::Unhandled),
+            ['C', 's'] => self
+                .action_context(ActionKind::SetCompareTarget, |s| {
+                    s.set_compare_target(app)
+                }),
+            ['C', 'c'] => self
+                .action_context(ActionKind::ClearCompareTarget, |s| {
+                    app.set_compare_target(None);
+                    let _ = s.ui_state(app).save();
+                    let output = String::from(tr("compare target cleared"));
+                    s.show_result(app, &ActionResult::from_ok(output))
+                }),
+            ['C', 'd'] => self
+                .action_context(ActionKind::CompareWithTarget, |s| {
+                    s.compare_with_target(app)
+                }),
+            ['S'] => Ok(HandleChordResult::Unhandled),
+            ['S', 's'] => self.action_context(ActionKind::SetScope, |s| {
+                let scope = match s.handle_input(
+                    app,
+                    "limit status, diff and log to directory",
+                    app.scope.as_deref(),
+                )? {
+                    Some(scope) => scope,
+                    None => return s.show_previous_action_result(app),
+                };
+                let scope = scope.trim().trim_end_matches('/');
+                let root = app.version_control.get_root();
+                if scope.is_empty() || !Path::new(root).join(scope).is_dir() {
+                    return s.show_result(
+                        app,
+                        &ActionResult::from_err(trf(
+                            "'{}' is not a directory inside the repository",
+                            &[&scope],
+                        )),
+                    );
+                }
+                app.set_scope(Some(scope.into()));
+
+                s.current_action_kind = ActionKind::Status;
+                let action = app
+                    .version_control
+                    .status(app.status_options, app.scope.as_deref());
+                s.show_action(app, action)
+            }),
+            ['S', 'c'] => self.action_context(ActionKind::ClearScope, |s| {
+                app.set_scope(None);
+                s.current_action_kind = ActionKind::Status;
+                let action =
+                    app.version_control.status(app.status_options, None);
+                s.show_action(app, action)
+            }),
+            ['T'] => Ok(HandleChordResult::Unhandled),
+            ['T', 'i'] => {
+                let options = &mut app.status_options;
+                options.show_ignored = !options.show_ignored;
+                self.action_context(ActionKind::Status, |s| {
+                    let action = app
+                        .version_control
+                        .status(app.status_options, app.scope.as_deref());
+                    s.show_action(app, action)
+                })
+            }
+            ['T', 'u'] => {
+                let options = &mut app.status_options;
+                options.skip_untracked = !options.skip_untracked;
+                self.action_context(ActionKind::Status, |s| {
+                    let action = app
+                        .version_control
+                        .status(app.status_options, app.scope.as_deref());
+                    s.show_action(app, action)
+                })
+            }
+            ['T', 'd'] => {
+                let options = &mut app.status_options;
+                options.expand_untracked = !options.expand_untracked;
+                self.action_context(ActionKind::Status, |s| {
+                    let action = app
+                        .version_control
+                        .status(app.status_options, app.scope.as_deref());
+                    s.show_action(app, action)
+                })
+            }
+            ['T', 'b'] => {
+                app.log_branch = match app.log_branch {
+                    Some(_) => None,
+                    None => app
+                        .version_control
+                        .get_current_branch()
+                        .ok()
+                        .map(|b| b.trim().to_owned()),
+                };
+                self.action_context(ActionKind::Log, |s| {
+                    s.log_count = s.terminal_size.height as usize;
+                    let action = app.version_control.log(
+                        s.log_count,
+                        app.scope.as_deref(),
+                        app.log_branch.as_deref(),
+                    );
+                    s.show_action(app, action)
+                })
+            }
+            ['T', 'k'] => {
+                self.pressed_keys = match self.pressed_keys {
+                    Some(_) => None,
+                    None => Some(Vec::new()),
+                };
+                input::keep_pressed_keys(self.pressed_keys.is_some());
+                self.show_current_action_result(app)?;
+                Ok(HandleChordResult::Handled)
+            }
+            ['T', 'q'] => {
+                self.quiet = !self.quiet;
+                self.show_current_action_result(app)?;
+                Ok(HandleChordResult::Handled)
+            }
+            ['T', 'l'] => {
+                self.scroll_view.show_line_numbers =
+                    !self.scroll_view.show_line_numbers;
+                self.show_current_action_result(app)?;
+                Ok(HandleChordResult::Handled)
+            }
+            ['T', 's'] => {
+                self.scroll_view.side_by_side = !self.scroll_view.side_by_side;
+                self.show_current_action_result(app)?;
+                Ok(HandleChordResult::Handled)
+            }
+            ['T', 'w'] => {
+                let kind = self.scroll_view.action_kind();
+                let truncated = self.scroll_view.toggle_truncating();
+                if let Err(error) = Config::save_truncated(kind, truncated) {
+                    self.show_header(app, HeaderKind::Error)?;
+                    self.write.queue(Print(trf(
+                        "could not save it in the config: {}",
+                        &[&error],
+                    )))?;
+                    return Ok(HandleChordResult::Handled);
+                }
+                self.show_current_action_result(app)?;
+                Ok(HandleChordResult::Handled)
+            }
+            ['T', 'p'] => {
+                self.preview.enabled = !self.preview.enabled;
+                self.show_current_action_result(app)?;
+                Ok(HandleChordResult::Handled)
+            }
+            ['<'] => {
+                self.preview.grow();
+                self.show_current_action_result(app)?;
+                Ok(HandleChordResult::Handled)
+            }
+            ['>'] => {
+                self.preview.shrink();
+                self.show_current_action_result(app)?;
+                Ok(HandleChordResult::Handled)
+            }
+            ['K'] => self.action_context(ActionKind::KeyDebug, |s| {
+                s.handle_key_debug(app)?;
+                s.show_previous_action_result(app)
+            }),
+            [':'] => {
+                let input = self.handle_input(app, "go to line", None)?;
+                let input = input.as_deref().map_or("", str::trim);
+                if !input.is_empty() {
+                    match input.parse() {
+                        Ok(number) => self
+                            .scroll_view
+                            .jump_to_line(number, self.terminal_size),
+                        Err(_) => {
+                            self.show_header(app, HeaderKind::Error)?;
+                            let error = trf("invalid line number: {}", &[&input]);
+                            self.write.queue(Print(error))?;
+                            return Ok(HandleChordResult::Handled);
+                        }
+                    }
+                }
+                self.show_current_action_result(app)?;
+                Ok(HandleChordResult::Handled)
+            }
+            ['z'] => {
+                self.scroll_view.expand_visible_folds(self.terminal_size);
+                self.show_current_action_result(app)?;
+                Ok(HandleChordResult::Handled)
+            }
+            ['Z'] => Ok(HandleChordResult::Unhandled),
+            ['Z', 'f'] | ['Z', 'c'] | ['Z', 'e'] => {
+                let path = match self.current_key_chord[1] {
+                    'f' => self.scroll_view.toggle_top_file(),
+                    c => self.scroll_view.set_files_collapsed(c == 'c'),
+                };
+                self.pending_file_jump = path;
+                self.show_current_action_result(app)?;
+                Ok(HandleChordResult::Handled)
+            }
+            ['J'] => {
+                if !self.current_action_kind.is_diff() {
+                    return Ok(HandleChordResult::Handled);
+                }
+                let files = self.scroll_view.diff_files();
+                if let Some(path) = self.handle_input_with_candidates(
+                    app,
+                    "jump to file",
+                    None,
+                    files,
+                )? {
+                    self.scroll_view
+                        .jump_to_file(path.trim(), self.terminal_size);
+                }
+                self.show_current_action_result(app)?;
+                Ok(HandleChordResult::Handled)
+            }
+            ['w'] => self.action_context(ActionKind::SwitchRepository, |s| {
+                let repositories = app.workspace_repositories();
+                let dir = match s.handle_input_with_candidates(
+                    app,
+                    "switch to repository",
+                    None,
+                    repositories,
+                )? {
+                    Some(dir) if !dir.trim().is_empty() => dir,
+                    _ => return s.show_previous_action_result(app),
+                };
+
+                let _ = s.ui_state(app).save();
+                if let Err(error) = app.switch_repository(dir.trim()) {
+                    return s.show_result(app, &ActionResult::from_err(error));
+                }
+                app.compare_target = UiState::load().compare_target;
+                s.reset(app)?;
+
+                s.current_action_kind = ActionKind::Status;
+                let action = app
+                    .version_control
+                    .status(app.status_options, app.scope.as_deref());
+                s.show_action(app, action)
+            }),
+            ['x'] => self.action_context(ActionKind::CustomAction, |s| {
+                if !app.custom_actions.is_empty() {
+                    s.show_header(app, HeaderKind::Ok)?;
+                    for c in &app.custom_actions {
+                        s.write
+                            .queue(SetForegroundColor(theme().entry))?
+                            .queue(Print(input::chord_name(
+                                c.shortcut.chars(),
+                            )))?
+                            .queue(ResetColor)?
+                            .queue(Print('\t'))?
+                            .queue(Print(&c.command))?;
+                        for a in &c.args {
+                            s.write.queue(Print(' '))?.queue(Print(a))?;
+                        }
+                        if c.run == Run::Detached {
+                            s.write.queue(Print(trf(" ({})", &[&tr("detached")])))?;
+                        }
+                        s.write.queue(cursor::MoveToNextLine(1))?;
+                    }
+                    s.handle_custom_action(app)?;
+                    s.current_key_chord.clear();
+                } else {
+                    s.show_header(app, HeaderKind::Error)?;
+                    queue!(
+                        s.write,
+                        ResetColor,
+                        Print(tr("no commands available")),
+                        cursor::MoveToNextLine(2),
+                        Print(trf(
+                            "create custom actions by placing them inside '{}' or '{}'",
+                            &[
+                                &concat!(
+                                    ".",
+                                    env!("CARGO_PKG_NAME"),
+                                    "/custom_actions.toml"
+                                ),
+                                &concat!(
+                                    ".",
+                                    env!("CARGO_PKG_NAME"),
+                                    "/custom_actions.txt"
+                                ),
+                            ],
+                        )),
+                    )?;
+                }
+                Ok(())
+            }),
+            _ => Ok(HandleChordResult::Handled),
+        }
+    }
+
+    /// Shows every key event as received and as verco understands it so
+    /// terminals sending unexpected sequences can be diagnosed
+    fn handle_key_debug(&mut self, app: &Application) -> Result<()> {
+        let mut lines = vec![
+            format!(
+                "TERM={} COLORTERM={} kitty keyboard protocol: {}",
+                env::var("TERM").unwrap_or_default(),
+                env::var("COLORTERM").unwrap_or_default(),
+                terminal_input::kitty_keyboard_enabled(),
+            ),
+            String::from(tr("press keys to inspect them. esc twice to exit")),
+            String::new(),
+        ];
+        let header_len = lines.len();
+        let mut last_was_esc = false;
+
+        loop {
+            self.show_header(app, HeaderKind::Waiting)?;
+            let available_size =
+                AvailableSize::from_temrinal_size(self.terminal_size);
+            let skip = lines.len().saturating_sub(available_size.height);
+            for line in lines.iter().skip(skip) {
+                queue!(
+                    self.write,
+                    Print(line),
+                    Clear(ClearType::UntilNewLine),
+                    cursor::MoveToNextLine(1)
+                )?;
+            }
+            self.write.flush()?;
+
+            let key_event = loop {
+                match input::poll_raw_event() {
+                    Event::Resize(terminal_size) => {
+                        self.terminal_size = terminal_size
+                    }
+                    Event::Key(key_event) => break key_event,
+                    _ => (),
+                }
+            };
+
+            let normalized = input::normalize_key(key_event);
+            if normalized.code == KeyCode::Esc {
+                if last_was_esc {
+                    return Ok(());
+                }
+                last_was_esc = true;
+            } else {
+                last_was_esc = false;
+            }
+
+            if lines.len() > header_len + KEY_DEBUG_HISTORY {
+                lines.remove(header_len);
+            }
+            lines.push(format!(
+                "{:?} => {:?} {:?} chord: {:?}",
+                key_event,
+                normalized.code,
+                normalized.modifiers,
+                input::key_to_chord_char(normalized)
+                    .map(input::chord_char_name),
+            ));
+        }
+    }
+
+    fn handle_custom_action(&mut self, app: &mut Application) -> Result<()> {
+        self.current_key_chord.clear();
+        self.write.queue(cursor::SavePosition)?;
+
+        'outer: loop {
+            self.write.flush()?;
+            match input::poll_event() {
+                Event::Resize(terminal_size) => {
+                    self.terminal_size = terminal_size;
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Esc, ..
+                })
+                | Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers: KeyModifiers::CONTROL,
+                }) => {
+                    return self.show_previous_action_result(app);
+                }
+                Event::Key(key_event) => {
+                    if let Some(c) = input::key_to_chord_char(key_event) {
+                        self.current_key_chord.push(c);
+                    }
+                    for (index, action) in app.custom_actions.iter().enumerate()
+                    {
+                        if action
                             .shortcut
                             .chars()
                             .zip(
                                 self.current_key_chord
                                     .iter()
-                                    .map(|c| *c)
+                                    .copied()
                                     .chain(iter::repeat('\0')),
                             )
                             .all(|(a, b)| a == b)
@@ -621,7 +2631,7 @@ where
                             self.write
                                 .queue(cursor::RestorePosition)?
                                 .queue(cursor::MoveToNextLine(2))?
-                                .queue(SetForegroundColor(ENTRY_COLOR))?
+                                .queue(SetForegroundColor(theme().entry))?
                                 .queue(Print(&action.command))?
                                 .queue(ResetColor)?;
                             for arg in &action.args {
@@ -631,10 +2641,7 @@ where
                             }
                             self.write.queue(cursor::MoveToNextLine(2))?;
 
-                            let result =
-                                action.execute(app.version_control.get_root());
-                            self.show_result(app, &result)?;
-                            return Ok(());
+                            return self.run_custom_action(app, index);
                         }
                     }
                     self.show_current_key_chord()?;
@@ -651,7 +2658,7 @@ where
                     }
 
                     self.show_header(app, HeaderKind::Error)?;
-                    self.write.queue(Print("no match found"))?;
+                    self.write.queue(Print(tr("no match found")))?;
                     return Ok(());
                 }
                 _ => (),
@@ -659,30 +2666,193 @@ where
         }
     }
 
+    /// Fills the placeholders of the custom action at `index`, asking for the
+    /// prompted ones, then runs it
+    fn run_custom_action(
+        &mut self,
+        app: &mut Application,
+        index: usize,
+    ) -> Result<()> {
+        let action = app.custom_actions[index].clone();
+        // prompts go last since they draw over the entry under the cursor
+        let mut placeholders = action.placeholders();
+        placeholders.sort_by_key(|p| CustomAction::prompt_label(p).is_some());
+
+        let mut values = Vec::new();
+        for name in placeholders {
+            let value = match CustomAction::prompt_label(name) {
+                Some(label) => match self.handle_input(app, label, None)? {
+                    Some(input) => input,
+                    None => return self.show_previous_action_result(app),
+                },
+                // status has no cursor so the file is picked instead
+                None if name == "file"
+                    && self.previous_action_kind == ActionKind::Status =>
+                {
+                    match self.pick_status_file(app)? {
+                        Some(file) => file,
+                        None => return Ok(()),
+                    }
+                }
+                None => match self.placeholder_value(app, name) {
+                    Some(value) => value,
+                    None => {
+                        let error = trf(
+                            "nothing under the cursor to fill '{}'",
+                            &[&name],
+                        );
+                        return self
+                            .show_result(app, &ActionResult::from_err(error));
+                    }
+                },
+            };
+            values.push((name, value));
+        }
+
+        let task = action.task(app.version_control.get_root(), &values);
+        self.show_action(app, task)
+    }
+
+    /// Changed file picked in the select ui to fill the `{file}` placeholder
+    /// from status, the first one when several are. `None` when there's
+    /// nothing to pick or it was canceled, which is already shown
+    fn pick_status_file(
+        &mut self,
+        app: &Application,
+    ) -> Result<Option<String>> {
+        let mut entries = match app.get_current_changed_files() {
+            Ok(entries) => entries,
+            Err(error) => {
+                self.show_result(app, &ActionResult::from_err(error))?;
+                return Ok(None);
+            }
+        };
+        match entries.len() {
+            0 => {
+                self.show_empty_entries(app)?;
+                return Ok(None);
+            }
+            1 => return Ok(entries.pop().map(|e| e.filename)),
+            _ => (),
+        }
+        if !self.show_select_ui(app, &mut entries[..])? {
+            self.show_previous_action_result(app)?;
+            return Ok(None);
+        }
+        match entries.into_iter().find(|e| e.selected) {
+            Some(entry) => Ok(Some(entry.filename)),
+            None => {
+                self.show_previous_action_result(app)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Value of a custom action placeholder taken from the entry under the
+    /// cursor of the mode shown before
+    fn placeholder_value(
+        &self,
+        app: &Application,
+        name: &str,
+    ) -> Option<String> {
+        let kind = self.previous_action_kind;
+        let revision_diff = match &self.view_key {
+            Some((key_kind, key))
+                if *key_kind == kind
+                    && matches!(
+                        kind,
+                        ActionKind::RevisionDiffAll
+                            | ActionKind::RevisionDiffSelected
+                    ) =>
+            {
+                key.lines().next()
+            }
+            _ => None,
+        };
+        match (name, kind) {
+            (
+                "hash",
+                ActionKind::Log
+                | ActionKind::LogCount
+                | ActionKind::ViewedRevisions,
+            )
+            | ("branch", ActionKind::ListBranches) => {
+                self.previous_target(app).map(String::from)
+            }
+            ("hash", ActionKind::RevisionChanges) => {
+                Some(self.revision_changes_target.clone())
+            }
+            ("hash", _) => revision_diff.map(String::from),
+            ("file", ActionKind::RevisionChanges | ActionKind::BaseChanges) => {
+                self.hovered_text(app)
+            }
+            ("file", _) => self.scroll_view.top_file_path(),
+            ("branch", _) => app.version_control.get_current_branch().ok(),
+            _ => None,
+        }
+    }
+
+    /// Lists every available action by name so one can be run without
+    /// remembering its keys
+    fn handle_command_palette(
+        &mut self,
+        app: &mut Application,
+    ) -> Result<HandleChordResult> {
+        let bindings: Vec<_> = KEY_BINDINGS
+            .iter()
+            .flat_map(|group| group.iter())
+            .filter(|b| b.chord().is_some() && b.is_available(app))
+            .collect();
+
+        let kind = self.current_action_kind;
+        self.current_action_kind = ActionKind::CommandPalette;
+        self.show_header(app, HeaderKind::Waiting)?;
+        let picked = palette::pick(&mut self.write, &bindings)?;
+        self.current_action_kind = kind;
+        self.show_current_action_result(app)?;
+
+        match picked.and_then(|i| bindings[i].chord()) {
+            Some(chord) => {
+                self.current_key_chord = chord;
+                let result = self.handle_key_chord(app);
+                self.current_key_chord.clear();
+                result
+            }
+            None => Ok(HandleChordResult::Handled),
+        }
+    }
+
     fn handle_input(
         &mut self,
         app: &Application,
         prompt: &str,
         initial: Option<&str>,
+    ) -> Result<Option<String>> {
+        self.handle_input_with_candidates(app, prompt, initial, Vec::new())
+    }
+
+    fn handle_input_with_candidates(
+        &mut self,
+        app: &Application,
+        prompt: &str,
+        initial: Option<&str>,
+        candidates: Vec<String>,
     ) -> Result<Option<String>> {
         self.show_header(app, HeaderKind::Waiting)?;
         execute!(
             self.write,
-            SetForegroundColor(ENTRY_COLOR),
-            Print(prompt),
+            SetForegroundColor(theme().entry),
+            Print(tr(prompt)),
             ResetColor,
             cursor::MoveToNextLine(1),
+            DisableMouseCapture,
             cursor::Show,
         )?;
 
-        let initial = if let Some(initial) = initial {
-            initial
-        } else {
-            ""
-        };
-        let res = match input::read_line(initial) {
+        let initial = initial.unwrap_or_default();
+        let res = match input::read_line(initial, candidates) {
             Ok(line) => {
-                if line.len() > 0 {
+                if !line.is_empty() {
                     Some(line)
                 } else {
                     None
@@ -690,135 +2860,1681 @@ where
             }
             Err(_error) => None,
         };
-        self.write.execute(cursor::Hide)?;
+        execute!(self.write, EnableMouseCapture, cursor::Hide)?;
         Ok(res)
     }
 
-    fn show_result(
+    /// Commit messages span multiple lines when the kitty keyboard protocol
+    /// tells ctrl+enter, which finishes them, apart from enter
+    fn handle_commit_message(
         &mut self,
         app: &Application,
-        result: &ActionResult,
-    ) -> Result<()> {
-        if app.has_pending_action_of_type(self.current_action_kind) {
-            self.show_header(app, HeaderKind::Waiting)?;
-        } else if result.success {
-            self.show_header(app, HeaderKind::Ok)?;
+    ) -> Result<Option<String>> {
+        if terminal_input::kitty_keyboard_enabled() {
+            let message =
+                self.handle_multiline_input(app, "commit message", "")?;
+            Ok(message.filter(|m| !m.is_empty()))
         } else {
-            self.show_header(app, HeaderKind::Error)?;
+            self.handle_input(app, "commit message", None)
         }
-
-        self.scroll_view.set_content(
-            &result.output[..],
-            self.current_action_kind,
-            self.terminal_size,
-        );
-        self.scroll_view
-            .draw_content(&mut self.write, self.terminal_size)
     }
 
-    fn show_current_key_chord(&mut self) -> Result<()> {
-        let TerminalSize { width, height } = self.terminal_size;
-        queue!(
+    fn handle_multiline_input(
+        &mut self,
+        app: &Application,
+        prompt: &str,
+        initial: &str,
+    ) -> Result<Option<String>> {
+        self.show_header(app, HeaderKind::Waiting)?;
+        execute!(
             self.write,
-            cursor::MoveTo(
-                width - self.current_key_chord.len() as u16,
-                height - 1
-            ),
-            Clear(ClearType::CurrentLine),
-            SetForegroundColor(ENTRY_COLOR),
+            SetForegroundColor(theme().entry),
+            Print(tr(prompt)),
+            ResetColor,
+            Print(' '),
         )?;
-        for c in &self.current_key_chord {
-            self.write.queue(Print(c))?;
+        if terminal_input::kitty_keyboard_enabled() {
+            return self.handle_message_editor(initial);
         }
-        self.write.queue(ResetColor)?;
-        Ok(())
-    }
+        execute!(
+            self.write,
+            Print(tr("(empty line to finish)")),
+            cursor::MoveToNextLine(1),
+            DisableMouseCapture,
+            cursor::Show,
+        )?;
 
-    fn show_help(&mut self, app: &Application) -> Result<ActionResult> {
-        let mut write = Vec::with_capacity(1024);
+        let res = input::read_lines(initial).unwrap_or(None);
+        execute!(self.write, EnableMouseCapture, cursor::Hide)?;
+        Ok(res)
+    }
 
+    /// Edits a multi-line message with enter breaking lines, which the kitty
+    /// keyboard protocol tells apart from ctrl+enter
+    fn handle_message_editor(
+        &mut self,
+        initial: &str,
+    ) -> Result<Option<String>> {
         queue!(
-            &mut write,
-            Print(BIN_NAME),
-            Print(' '),
-            Print(VERSION),
-            cursor::MoveToNextLine(2),
+            self.write,
+            Print(tr("(ctrl+enter to finish)")),
+            cursor::MoveToNextLine(1),
+            cursor::SavePosition,
+            cursor::Show,
         )?;
 
-        if let Ok(version) = app.version_control.version() {
-            queue!(&mut write, Print(version), cursor::MoveToNextLine(2))?;
+        let mut editor = MessageEditor::new(initial);
+        loop {
+            queue!(
+                self.write,
+                cursor::RestorePosition,
+                Clear(ClearType::FromCursorDown)
+            )?;
+            for line in &editor.lines {
+                queue!(self.write, Print(line), cursor::MoveToNextLine(1))?;
+            }
+            let before_cursor = editor.lines[editor.line]
+                .chars()
+                .take(editor.column)
+                .collect::<String>();
+            queue!(self.write, cursor::RestorePosition)?;
+            if editor.line > 0 {
+                queue!(self.write, cursor::MoveDown(editor.line as u16))?;
+            }
+            // columns start at 1 for crossterm 0.17
+            let column = str_width(&before_cursor) as u16 + 1;
+            queue!(self.write, cursor::MoveToColumn(column))?;
+            self.write.flush()?;
+
+            let key_event = loop {
+                match input::poll_raw_event() {
+                    Event::Resize(terminal_size) => {
+                        self.terminal_size = terminal_size
+                    }
+                    Event::Key(key_event) => break key_event,
+                    _ => (),
+                }
+            };
+            match editor.on_key(key_event) {
+                MessageEdit::Editing => (),
+                MessageEdit::Submit => break,
+                MessageEdit::Cancel => {
+                    self.write.execute(cursor::Hide)?;
+                    return Ok(None);
+                }
+            }
         }
 
-        write
-            .queue(Print("press a key and peform an action"))?
-            .queue(cursor::MoveToNextLine(2))?;
+        self.write.execute(cursor::Hide)?;
+        Ok(Some(editor.text()))
+    }
+
+    fn handle_confirm(
+        &mut self,
+        app: &Application,
+        prompt: &str,
+    ) -> Result<bool> {
+        self.show_header(app, HeaderKind::Waiting)?;
+        execute!(
+            self.write,
+            SetForegroundColor(theme().entry),
+            Print(tr(prompt)),
+            ResetColor,
+            Print(" [y/n]"),
+        )?;
+        Ok(self.read_char()? == Some('y'))
+    }
+
+    /// Warns when about to commit on one of the configured protected
+    /// branches offering to create a new branch carrying the changes
+    fn handle_protected_branch(
+        &mut self,
+        app: &Application,
+    ) -> Result<BranchOff> {
+        let branch = match app.protected_branch() {
+            Some(branch) => branch,
+            None => return Ok(BranchOff::Commit),
+        };
+
+        self.show_header(app, HeaderKind::Waiting)?;
+        execute!(
+            self.write,
+            SetForegroundColor(theme().entry),
+            Print(trf("'{}' is a protected branch", &[&branch])),
+            ResetColor,
+            cursor::MoveToNextLine(1),
+            Print(tr(
+                "create a new branch for this commit? [y/n] (n commits anyway)"
+            )),
+        )?;
+        match self.read_char()? {
+            Some('y') => {
+                match self.handle_input(app, "new branch name", None)? {
+                    Some(name) if !name.trim().is_empty() => {
+                        Ok(BranchOff::CreateBranch(name.trim().into()))
+                    }
+                    _ => Ok(BranchOff::Abort),
+                }
+            }
+            Some('n') => Ok(BranchOff::Commit),
+            _ => Ok(BranchOff::Abort),
+        }
+    }
+
+    /// Answers a credential prompt from a running action
+    fn handle_askpass(
+        &mut self,
+        app: &Application,
+        prompt: &str,
+    ) -> Result<()> {
+        let answer = if askpass::is_secret(prompt) {
+            self.handle_secret_input(app, prompt)?
+        } else {
+            self.handle_input(app, prompt, None)?
+        };
+        if let Some(askpass) = &app.askpass {
+            askpass.answer(answer.as_deref());
+        }
+        self.show_current_action_result(app)
+    }
+
+    /// Reads a line without echoing it back
+    fn handle_secret_input(
+        &mut self,
+        app: &Application,
+        prompt: &str,
+    ) -> Result<Option<String>> {
+        self.show_header(app, HeaderKind::Waiting)?;
+        execute!(
+            self.write,
+            SetForegroundColor(theme().entry),
+            Print(prompt),
+            ResetColor,
+            cursor::MoveToNextLine(1),
+        )?;
+
+        let mut secret = String::new();
+        loop {
+            match input::poll_event() {
+                Event::Resize(terminal_size) => {
+                    self.terminal_size = terminal_size;
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Enter,
+                    ..
+                }) => return Ok(Some(secret)),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Esc, ..
+                })
+                | Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers: KeyModifiers::CONTROL,
+                }) => return Ok(None),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Backspace,
+                    ..
+                }) => {
+                    secret.pop();
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(c),
+                    modifiers,
+                }) if !modifiers.contains(KeyModifiers::CONTROL) => {
+                    secret.push(c);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Opens the diff of the revision shown in revision changes scrolled to
+    /// the file under the cursor
+    fn show_changed_file_diff(&mut self, app: &mut Application) -> Result<()> {
+        let path = match self
+            .scroll_view
+            .cursor_line()
+            .and_then(|l| self.current_action_kind.parse_changed_file(l))
+        {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let diff_kind = match self.current_action_kind {
+            ActionKind::BaseChanges => ActionKind::BaseDiffAll,
+            _ => ActionKind::RevisionDiffAll,
+        };
+        self.action_context(diff_kind, |s| {
+            s.restore_view_position(s.revision_changes_target.clone());
+            s.pending_file_jump = Some(path);
+            let target = &s.revision_changes_target;
+            let scope = app.scope.as_deref();
+            let action = match diff_kind {
+                ActionKind::BaseDiffAll => {
+                    app.version_control.base_diff_all(target, scope)
+                }
+                _ => app.version_control.revision_diff_all(target, scope),
+            };
+            s.show_diff_action(app, action)
+        })?;
+        Ok(())
+    }
+
+    /// Requests more log entries once the cursor reaches the last one unless
+    /// the whole history is already shown
+    fn load_more_log(&mut self, app: &mut Application) -> Result<()> {
+        if self.current_action_kind != ActionKind::Log
+            || self.scroll_view.action_kind() != ActionKind::Log
+            || !self.scroll_view.is_cursor_at_end()
+            || app.has_pending_action_of_type(ActionKind::Log)
+        {
+            return Ok(());
+        }
+
+        let result = app.get_cached_action_result(ActionKind::Log);
+        if !result.success || self.is_log_complete(result) {
+            return Ok(());
+        }
+
+        // the pager continues the log shown unless it was loaded again
+        let continues = match &self.log_pager {
+            Some(pager) => pager.is_showing(&result.output),
+            None => false,
+        };
+        let scope = app.scope.as_deref();
+        let branch = app.log_branch.as_deref();
+        if !continues {
+            self.log_pager = app
+                .version_control
+                .log_command(scope, branch)
+                .and_then(|command| LogPager::start(command).ok());
+        }
+        self.log_count += self.terminal_size.height as usize;
+        let task = match &self.log_pager {
+            Some(pager) => pager.read(self.log_count),
+            None => app.version_control.log(self.log_count, scope, branch),
+        };
+        app.run_action(ActionFuture {
+            kind: ActionKind::Log,
+            task,
+        });
+        self.show_current_action_result(app)
+    }
+
+    /// Whether the log `result` returned fewer entries than requested
+    fn is_log_complete(&self, result: &ActionResult) -> bool {
+        result.success
+            && !result.canceled
+            && log_entry_count(&result.output) < self.log_count
+    }
+
+    /// Moves the cursor to the revision the log was loaded for, centering it
+    fn apply_log_jump(&mut self, app: &Application) {
+        let revision = match self.pending_log_jump.take() {
+            Some(revision) => revision,
+            None => return,
+        };
+        let output = &app.get_cached_action_result(ActionKind::Log).output;
+        let line = output.lines().position(|l| {
+            ActionKind::Log
+                .parse_target(l)
+                .map(|target| revision.starts_with(target))
+                .unwrap_or(false)
+        });
+        if let Some(line) = line {
+            let page = self.terminal_size.height as usize;
+            self.pending_view = Some(ViewState {
+                scroll: line.saturating_sub(page / 2),
+                cursor: Some(line),
+                filter: String::new(),
+            });
+        }
+    }
+
+    /// Offers to run the current action again after it timed out
+    fn handle_retry(&mut self, app: &mut Application) -> Result<()> {
+        let result = app.get_cached_action_result(self.current_action_kind);
+        let prompt = trf("{}, retry?", &[&result.output]);
+        if !self.handle_confirm(app, &prompt)? {
+            return self.show_current_action_result(app);
+        }
+
+        let key_chord = self.action_key_chords.get(&self.current_action_kind);
+        match key_chord {
+            Some(key_chord) => {
+                self.current_key_chord = key_chord.clone();
+                self.handle_key_chord(app)?;
+                self.current_key_chord.clear();
+                Ok(())
+            }
+            None => self.show_current_action_result(app),
+        }
+    }
+
+    /// Shows the output of a failed commit and, when hooks may have
+    /// rejected it, offers to retry skipping them
+    fn handle_commit_failure(&mut self, app: &mut Application) -> Result<()> {
+        let commit = match self.last_commit.take() {
+            Some(commit) if app.version_control.has_commit_hooks() => commit,
+            _ => return self.show_current_action_result(app),
+        };
+
+        let result = app.get_cached_action_result(self.current_action_kind);
+        let lines: Vec<_> = result.output.lines().map(String::from).collect();
+        // the reason is usually at the end of the hook output
+        let max_lines = (self.terminal_size.height as usize).saturating_sub(5);
+        let skip = lines.len().saturating_sub(max_lines);
+
+        self.show_header(app, HeaderKind::Error)?;
+        queue!(
+            self.write,
+            SetForegroundColor(theme().entry),
+            Print(tr("commit failed, possibly rejected by a hook:")),
+            ResetColor,
+            cursor::MoveToNextLine(2),
+        )?;
+        for line in &lines[skip..] {
+            queue!(self.write, Print(line), cursor::MoveToNextLine(1))?;
+        }
+        execute!(
+            self.write,
+            cursor::MoveToNextLine(1),
+            SetForegroundColor(theme().entry),
+            Print(tr("retry with --no-verify? [y/n]")),
+            ResetColor,
+        )?;
+        if self.read_char()? != Some('y') {
+            return self.show_current_action_result(app);
+        }
+
+        let options = CommitOptions {
+            no_verify: true,
+            ..app.commit_options()
+        };
+        let action = match &commit.entries {
+            Some(entries) => app.version_control.commit_selected(
+                &commit.message,
+                entries,
+                options,
+            ),
+            None => app.version_control.commit_all(&commit.message, options),
+        };
+        self.show_action(app, action)
+    }
+
+    /// Asks for confirmation before discarding many files or whole
+    /// directories, listing them on demand
+    fn confirm_discard(
+        &mut self,
+        app: &Application,
+        entries: &[Entry],
+    ) -> Result<bool> {
+        let selected: Vec<_> = entries.iter().filter(|e| e.selected).collect();
+        let directory_count = selected
+            .iter()
+            .filter(|e| e.filename.ends_with('/'))
+            .count();
+        if selected.len() < DISCARD_SUMMARY_THRESHOLD && directory_count == 0 {
+            return Ok(true);
+        }
+
+        let delete_count = selected
+            .iter()
+            .filter(|e| matches!(e.state, State::Untracked))
+            .count();
+        let summary = trf(
+            "will delete {} untracked files ({} directories), revert {} modified files",
+            &[
+                &delete_count,
+                &directory_count,
+                &(selected.len() - delete_count),
+            ],
+        );
+
+        self.show_header(app, HeaderKind::Waiting)?;
+        execute!(
+            self.write,
+            SetForegroundColor(theme().entry),
+            Print(&summary),
+            ResetColor,
+            cursor::MoveToNextLine(1),
+            Print(tr("discard? [y/n/l to list files]")),
+        )?;
+
+        match self.read_char()? {
+            Some('y') => Ok(true),
+            Some('l') => {
+                self.show_header(app, HeaderKind::Waiting)?;
+                queue!(
+                    self.write,
+                    SetForegroundColor(theme().entry),
+                    Print(&summary),
+                    ResetColor,
+                    cursor::MoveToNextLine(2),
+                )?;
+                let height = self.terminal_size.height as usize;
+                for e in selected.iter().take(height.saturating_sub(5)) {
+                    queue!(
+                        self.write,
+                        Print(format!("{:?}", e.state)),
+                        Print('\t'),
+                        Print(&e.filename),
+                        cursor::MoveToNextLine(1),
+                    )?;
+                }
+                if selected.len() + 5 > height {
+                    queue!(
+                        self.write,
+                        Print("..."),
+                        cursor::MoveToNextLine(1)
+                    )?;
+                }
+                execute!(
+                    self.write,
+                    cursor::MoveToNextLine(1),
+                    Print(tr("discard? [y/n]"))
+                )?;
+                Ok(self.read_char()? == Some('y'))
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Offers to create a branch from `tag` instead of leaving a detached
+    /// HEAD, which is pointed out otherwise. `None` when aborted
+    fn checkout_tag(
+        &mut self,
+        app: &Application,
+        tag: &str,
+    ) -> Result<Option<Box<dyn ActionTask>>> {
+        self.show_header(app, HeaderKind::Waiting)?;
+        execute!(
+            self.write,
+            SetForegroundColor(theme().entry),
+            Print(trf("'{}' is a tag", &[&tag])),
+            ResetColor,
+            cursor::MoveToNextLine(1),
+            Print(tr(
+                "create a branch from it? [y/n] (n leaves a detached HEAD)"
+            )),
+        )?;
+        match self.read_char()? {
+            Some('y') => {
+                match self.handle_input(app, "branch name", Some(tag))? {
+                    Some(name) if !name.trim().is_empty() => Ok(Some(
+                        app.version_control.branch_from(name.trim(), tag),
+                    )),
+                    _ => Ok(None),
+                }
+            }
+            Some('n') => {
+                let notice = format!(
+                    "HEAD is detached at tag '{}'. new commits will not \
+                     belong to any branch, create one with bn to keep them\n\n",
+                    tag
+                );
+                let update = app.version_control.update(tag);
+                Ok(Some(map_result(update, move |mut result| {
+                    if result.success {
+                        result.output.insert_str(0, &notice);
+                    }
+                    result
+                })))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Asks for a base and a target, offering branches and tags, and shows
+    /// what merging the target into the base would bring. The base defaults
+    /// to the compare target
+    fn compare_revisions(&mut self, app: &mut Application) -> Result<()> {
+        let mut candidates =
+            app.version_control.get_branch_names().unwrap_or_default();
+        candidates
+            .extend(app.version_control.get_tag_names().unwrap_or_default());
+
+        let base = match self.handle_input_with_candidates(
+            app,
+            "compare from base",
+            self.previous_target(app).or(app.compare_target.as_deref()),
+            candidates.clone(),
+        )? {
+            Some(base) if !base.trim().is_empty() => base.trim().to_owned(),
+            _ => return self.show_previous_action_result(app),
+        };
+        let current_branch = app.version_control.get_current_branch().ok();
+        let target = match self.handle_input_with_candidates(
+            app,
+            "to target",
+            current_branch.as_deref().map(str::trim),
+            candidates,
+        )? {
+            Some(target) if !target.trim().is_empty() => {
+                target.trim().to_owned()
+            }
+            _ => return self.show_previous_action_result(app),
+        };
+
+        let action = app.version_control.compare(&base, &target);
+        self.show_diff_action(app, action)
+    }
+
+    /// Asks for the branch status and log headers tell the divergence from
+    fn set_compare_target(&mut self, app: &mut Application) -> Result<()> {
+        let branches =
+            app.version_control.get_branch_names().unwrap_or_default();
+        let target = match self.handle_input_with_candidates(
+            app,
+            "compare current branch with",
+            app.compare_target.as_deref(),
+            branches,
+        )? {
+            Some(target) if !target.trim().is_empty() => {
+                target.trim().to_owned()
+            }
+            _ => return self.show_previous_action_result(app),
+        };
+
+        let output = trf("comparing with '{}'", &[&target]);
+        app.set_compare_target(Some(target));
+        let _ = self.ui_state(app).save();
+        self.show_result(app, &ActionResult::from_ok(output))
+    }
+
+    /// Shows what merging the current branch into the compare target would
+    /// bring, asking for the target if there's none
+    fn compare_with_target(&mut self, app: &mut Application) -> Result<()> {
+        if app.compare_target.is_none() {
+            self.set_compare_target(app)?;
+        }
+        let base = match &app.compare_target {
+            Some(target) => target.clone(),
+            None => return Ok(()),
+        };
+        let current_branch = app.version_control.get_current_branch();
+        let target = current_branch.as_deref().map_or("HEAD", str::trim);
+
+        self.current_action_kind = ActionKind::CompareRevisions;
+        let action = app.version_control.compare(&base, target);
+        self.show_diff_action(app, action)
+    }
+
+    /// Loads the log down to the date or revision `target` moving the cursor
+    /// to it
+    fn jump_in_log(
+        &mut self,
+        app: &mut Application,
+        target: &str,
+    ) -> Result<()> {
+        let (position, revision) = match app.version_control.log_position(
+            target,
+            app.scope.as_deref(),
+            app.log_branch.as_deref(),
+        ) {
+            Ok(found) => found,
+            Err(error) => {
+                return self.show_result(app, &ActionResult::from_err(error))
+            }
+        };
+
+        // a page past the revision so it shows up around the middle
+        let page = self.terminal_size.height as usize;
+        self.log_count = self.log_count.max(position + page);
+        self.pending_log_jump = Some(revision);
+        self.current_action_kind = ActionKind::Log;
+        let action = app.version_control.log(
+            self.log_count,
+            app.scope.as_deref(),
+            app.log_branch.as_deref(),
+        );
+        self.show_action(app, action)
+    }
+
+    /// Adds `revision` to the top of the recently viewed ones, described by
+    /// its subject when it's in the log
+    fn remember_viewed_revision(&mut self, app: &Application, revision: &str) {
+        if revision.is_empty() {
+            return;
+        }
+        let subject = app
+            .get_cached_action_result(ActionKind::Log)
+            .output
+            .lines()
+            .find(|l| ActionKind::Log.parse_target(l) == Some(revision))
+            .and_then(|l| l.split('\x1e').nth(LOG_SUBJECT_PART));
+
+        self.viewed_revisions
+            .retain(|r| r.split_whitespace().next() != Some(revision));
+        let entry = match subject {
+            Some(subject) => format!("{} {}", revision, subject),
+            None => revision.into(),
+        };
+        self.viewed_revisions.insert(0, entry);
+        self.viewed_revisions.truncate(MAX_VIEWED_REVISIONS);
+    }
+
+    /// Asks for the commit message and commits the selected `entries`
+    fn commit_entries(
+        &mut self,
+        app: &mut Application,
+        entries: Vec<Entry>,
+        branch_off: Option<String>,
+    ) -> Result<()> {
+        self.show_header(app, HeaderKind::Waiting)?;
+        let input = match self.handle_commit_message(app)? {
+            Some(input) => input,
+            None => return self.show_previous_action_result(app),
+        };
+        let message = match self.add_trailers(app, &input)? {
+            Some(message) => message,
+            None => return self.show_previous_action_result(app),
+        };
+        let commit = LastCommit {
+            message,
+            entries: Some(entries),
+        };
+        self.format_and_commit(app, commit, branch_off)
+    }
+
+    /// Runs the configured formatter on the files of `commit` before it,
+    /// which is left waiting for the formatter to succeed
+    fn format_and_commit(
+        &mut self,
+        app: &mut Application,
+        commit: LastCommit,
+        branch_off: Option<String>,
+    ) -> Result<()> {
+        let kind = self.current_action_kind;
+        if app.config.format_command.is_none() {
+            return self.start_commit(app, kind, commit, branch_off);
+        }
+        let entries = match &commit.entries {
+            Some(entries) => entries.clone(),
+            None => match app.get_current_changed_files() {
+                Ok(mut entries) => {
+                    for e in &mut entries {
+                        e.selected = true;
+                    }
+                    entries
+                }
+                Err(error) => {
+                    return self
+                        .show_result(app, &ActionResult::from_err(error))
+                }
+            },
+        };
+
+        match format_before_commit(app, &entries) {
+            Some(action) => {
+                self.pending_commit = Some((kind, commit, branch_off));
+                self.show_action(app, action)
+            }
+            None => self.start_commit(app, kind, commit, branch_off),
+        }
+    }
+
+    /// Starts the commit left waiting for the formatter once it succeeds,
+    /// reading the status again as formatting may have changed it
+    fn commit_formatted(&mut self, app: &mut Application) -> Result<()> {
+        let kind = match &self.pending_commit {
+            Some((kind, ..)) => *kind,
+            None => return Ok(()),
+        };
+        if app.has_pending_action_of_type(kind) {
+            return Ok(());
+        }
+        let (kind, mut commit, branch_off) = match self.pending_commit.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        let result = app.get_cached_action_result(kind);
+        if !result.success || result.canceled {
+            return Ok(());
+        }
+
+        if let Some(entries) = &mut commit.entries {
+            if let Err(error) = refresh_formatted(app, entries) {
+                let result = ActionResult::from_err(error);
+                app.set_cached_action_result(kind, result);
+                return self.show_current_action_result(app);
+            }
+        }
+        self.start_commit(app, kind, commit, branch_off)
+    }
+
+    /// Runs `commit` as the action `kind`, showing it if that's the one
+    /// shown
+    fn start_commit(
+        &mut self,
+        app: &mut Application,
+        kind: ActionKind,
+        commit: LastCommit,
+        branch_off: Option<String>,
+    ) -> Result<()> {
+        let action = match &commit.entries {
+            Some(entries) => app.version_control.commit_selected(
+                &commit.message,
+                entries,
+                app.commit_options(),
+            ),
+            None => app
+                .version_control
+                .commit_all(&commit.message, app.commit_options()),
+        };
+        let action = branch_off_before_commit(app, branch_off, action);
+        self.last_commit = Some(commit);
+        if self.current_action_kind == kind {
+            self.show_action(app, action)
+        } else {
+            app.run_action(ActionFuture { kind, task: action });
+            Ok(())
+        }
+    }
+
+    /// Changed file under the cursor of revision changes or changes since
+    /// base, otherwise the changed files picked in the select ui, together
+    /// with the revision they changed in, empty for the working tree. `None`
+    /// when canceled
+    fn files_to_note(
+        &mut self,
+        app: &Application,
+    ) -> Result<Option<(String, Vec<String>)>> {
+        let kind = self.previous_action_kind;
+        let hovered = self
+            .scroll_view
+            .cursor_line()
+            .and_then(|line| kind.parse_changed_file(line));
+        if let Some(path) = hovered {
+            let revision = match kind {
+                ActionKind::RevisionChanges => {
+                    self.scroll_view.notes_revision.clone()
+                }
+                _ => String::new(),
+            };
+            return Ok(Some((revision, vec![path])));
+        }
+
+        let mut entries = match app.get_current_changed_files() {
+            Ok(entries) => entries,
+            Err(error) => {
+                self.show_result(app, &ActionResult::from_err(error))?;
+                return Ok(None);
+            }
+        };
+        if entries.is_empty() || !self.show_select_ui(app, &mut entries[..])? {
+            return Ok(None);
+        }
+        let paths: Vec<_> = entries
+            .into_iter()
+            .filter(|e| e.selected)
+            .map(|e| e.filename)
+            .collect();
+        Ok(Some((String::new(), paths)).filter(|(_, p)| !p.is_empty()))
+    }
+
+    /// Shows the select ui to pick which of `patches` to transfer. Shows
+    /// `empty_message` and returns false when there's nothing to pick
+    fn pick_patches(
+        &mut self,
+        app: &mut Application,
+        patches: &mut [Entry],
+        empty_message: &str,
+    ) -> Result<bool> {
+        if patches.is_empty() {
+            let result = ActionResult::from_ok(tr(empty_message).into());
+            self.show_result(app, &result)?;
+            return Ok(false);
+        }
+        if !self.show_select_ui(app, patches)?
+            || !patches.iter().any(|p| p.selected)
+        {
+            self.show_previous_action_result(app)?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Noted files as `path: note` lines
+    fn notes_text(&self) -> String {
+        let mut text = String::new();
+        for ((revision, path), note) in &self.scroll_view.file_notes {
+            if !revision.is_empty() {
+                text.push_str(revision);
+                text.push(':');
+            }
+            text.push_str(path);
+            text.push_str(": ");
+            text.push_str(note);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Commit, tag, branch, pull request or changed file under the cursor of
+    /// the view shown before the current action
+    fn hovered_text(&self, app: &Application) -> Option<String> {
+        let kind = self.previous_action_kind;
+        match kind {
+            ActionKind::RevisionChanges | ActionKind::BaseChanges => self
+                .scroll_view
+                .cursor_line()
+                .and_then(|line| kind.parse_changed_file(line)),
+            _ => self.previous_target(app).map(|t| t.trim().to_owned()),
+        }
+    }
+
+    /// Opens the pull request, commit, branch or changed file under the
+    /// cursor in the web interface of the repository. Status picks the
+    /// changed files to open at the current branch
+    fn open_in_browser(&mut self, app: &mut Application) -> Result<()> {
+        let kind = self.previous_action_kind;
+        let target = self.previous_target(app);
+        let root = app.version_control.get_root();
+        match (kind, app.forge(), target) {
+            (ActionKind::ListPullRequests, Some(forge), Some(number)) => {
+                let action = forge.open_pull_request(root, number);
+                return self.show_action(app, action);
+            }
+            (
+                ActionKind::Log | ActionKind::LogCount | ActionKind::ListTags,
+                Some(forge),
+                Some(revision),
+            ) if app.config.web_urls.commit.is_none() => {
+                if let Some(action) = forge.open_commit(root, revision) {
+                    return self.show_action(app, action);
+                }
+            }
+            _ => (),
+        }
 
-        Self::show_help_action(&mut write, "h", ActionKind::Help)?;
-        Self::show_help_action(&mut write, "q", ActionKind::Quit)?;
+        if kind == ActionKind::Status {
+            return self.open_status_files_in_browser(app);
+        }
 
-        write.queue(cursor::MoveToNextLine(1))?;
+        let remotes = app.version_control.get_remotes().unwrap_or_default();
+        let changed_file = self
+            .scroll_view
+            .cursor_line()
+            .and_then(|line| kind.parse_changed_file(line));
+        let target = match kind {
+            ActionKind::Log | ActionKind::LogCount | ActionKind::ListTags => {
+                target.map(WebTarget::Commit)
+            }
+            ActionKind::ListBranches => target.map(|branch| {
+                let local = remotes.iter().find_map(|remote| {
+                    branch.strip_prefix(remote.as_str())?.strip_prefix('/')
+                });
+                WebTarget::Branch(local.unwrap_or(branch))
+            }),
+            ActionKind::RevisionChanges => {
+                changed_file.as_deref().map(|path| WebTarget::File {
+                    revision: &self.revision_changes_target,
+                    path,
+                })
+            }
+            _ => None,
+        };
+        let target = match target {
+            Some(target) => target,
+            None => {
+                return self.show_result(
+                    app,
+                    &ActionResult::from_err(tr(
+                        "move the cursor to a commit, branch, changed file or pull request to open",
+                    )),
+                )
+            }
+        };
 
-        Self::show_help_action(&mut write, "s", ActionKind::Status)?;
-        Self::show_help_action(&mut write, "l", ActionKind::Log)?;
-        Self::show_help_action(&mut write, "LC", ActionKind::LogCount)?;
+        let url = self.web_url(app, &remotes, target);
+        match url {
+            Ok(url) => self.show_action(app, browser::open(&url)),
+            Err(error) => self.show_result(app, &ActionResult::from_err(error)),
+        }
+    }
 
-        Self::show_help_action(
-            &mut write,
-            "ee",
-            ActionKind::CurrentFullRevision,
+    /// Picks changed files and opens them at the current branch
+    fn open_status_files_in_browser(
+        &mut self,
+        app: &mut Application,
+    ) -> Result<()> {
+        let mut entries = match app.get_current_changed_files() {
+            Ok(entries) => entries,
+            Err(error) => {
+                return self.show_result(app, &ActionResult::from_err(error))
+            }
+        };
+        if entries.is_empty() || !self.show_select_ui(app, &mut entries[..])? {
+            return self.show_previous_action_result(app);
+        }
+        let branch = match app.version_control.get_current_branch() {
+            Ok(branch) => branch,
+            Err(error) => {
+                return self.show_result(app, &ActionResult::from_err(error))
+            }
+        };
+
+        let remotes = app.version_control.get_remotes().unwrap_or_default();
+        let mut actions = task_vec();
+        for entry in entries.iter().filter(|e| e.selected) {
+            let target = WebTarget::File {
+                revision: branch.trim(),
+                path: &entry.filename,
+            };
+            match self.web_url(app, &remotes, target) {
+                Ok(url) => actions.push(browser::open(&url)),
+                Err(error) => {
+                    return self
+                        .show_result(app, &ActionResult::from_err(error))
+                }
+            }
+        }
+        self.show_action(app, parallel(actions))
+    }
+
+    /// Url of `target` on the web interface of `origin`, or of the first
+    /// remote when there's no `origin`
+    fn web_url(
+        &self,
+        app: &Application,
+        remotes: &[String],
+        target: WebTarget,
+    ) -> std::result::Result<String, String> {
+        let remote = remotes
+            .iter()
+            .find(|r| *r == "origin")
+            .or_else(|| remotes.first());
+        match remote {
+            Some(remote) => app
+                .version_control
+                .get_remote_url(remote)
+                .map_err(String::from)
+                .and_then(|remote_url| {
+                    browser::web_url(&app.config.web_urls, &remote_url, target)
+                }),
+            None => Err(tr("there are no remotes").into()),
+        }
+    }
+
+    /// Asks for a tag, its new name and whether to rename it on the remote
+    /// too before renaming it
+    fn rename_tag(&mut self, app: &mut Application) -> Result<()> {
+        let tags = match app.version_control.get_tag_names() {
+            Ok(tags) => tags,
+            Err(error) => {
+                return self.show_result(app, &ActionResult::from_err(error))
+            }
+        };
+        let old_name = match self.handle_input_with_candidates(
+            app,
+            "tag to rename",
+            None,
+            tags,
+        )? {
+            Some(name) if !name.trim().is_empty() => name.trim().to_owned(),
+            _ => return self.show_previous_action_result(app),
+        };
+        let new_name =
+            match self.handle_input(app, "new tag name", Some(&old_name))? {
+                Some(name)
+                    if !name.trim().is_empty() && name.trim() != old_name =>
+                {
+                    name.trim().to_owned()
+                }
+                _ => return self.show_previous_action_result(app),
+            };
+
+        let remote = app
+            .version_control
+            .get_remotes()
+            .ok()
+            .and_then(|r| r.into_iter().next());
+        let choices = match &remote {
+            Some(remote) => trf("[y/n] (r also renames it on '{}')", &[remote]),
+            None => "[y/n]".into(),
+        };
+        self.show_header(app, HeaderKind::Waiting)?;
+        execute!(
+            self.write,
+            SetForegroundColor(theme().entry),
+            Print(trf("rename tag '{}' to '{}'?", &[&old_name, &new_name])),
+            ResetColor,
+            cursor::MoveToNextLine(1),
+            Print(choices),
         )?;
-        Self::show_help_action(&mut write, "dd", ActionKind::CurrentDiffAll)?;
-        Self::show_help_action(
-            &mut write,
-            "ds",
-            ActionKind::CurrentDiffSelected,
+        let remote = match (self.read_char()?, remote) {
+            (Some('y'), _) => None,
+            (Some('r'), Some(remote)) => Some(remote),
+            _ => return self.show_previous_action_result(app),
+        };
+
+        let action = app.version_control.rename_tag(
+            &old_name,
+            &new_name,
+            remote.as_deref(),
+        );
+        self.show_action(app, action)
+    }
+
+    /// Appends the configured trailers kept selected in the select ui to
+    /// `message`. `None` when the selection is canceled
+    fn add_trailers(
+        &mut self,
+        app: &Application,
+        message: &str,
+    ) -> Result<Option<String>> {
+        let mut message = String::from(message.trim());
+        if app.config.trailers.is_empty() {
+            return Ok(Some(message));
+        }
+
+        let mut entries: Vec<_> = app
+            .config
+            .trailers
+            .iter()
+            .map(|t| Entry {
+                filename: t.clone(),
+                selected: true,
+                state: State::Added,
+                renamed_from: None,
+            })
+            .collect();
+        if !self.show_select_ui(app, &mut entries[..])? {
+            return Ok(None);
+        }
+
+        let trailers: Vec<_> = entries
+            .iter()
+            .filter(|e| e.selected)
+            .map(|e| &e.filename[..])
+            .collect();
+        if !trailers.is_empty() {
+            message.push_str("\n\n");
+            message.push_str(&trailers.join("\n"));
+        }
+        Ok(Some(message))
+    }
+
+    /// Opens the chosen conflicted files in the merge tool and lists the
+    /// unresolved conflicts again once it exits, as it may have resolved them
+    fn edit_conflicts(&mut self, app: &mut Application) -> Result<()> {
+        let mut entries = match app.get_current_changed_files() {
+            Ok(entries) => entries,
+            Err(error) => {
+                return self.show_result(app, &ActionResult::from_err(error))
+            }
+        };
+        entries.retain(|e| matches!(e.state, State::Unmerged));
+        match entries.len() {
+            0 => {
+                let result =
+                    ActionResult::from_ok(tr("no unresolved conflicts").into());
+                return self.show_result(app, &result);
+            }
+            1 => entries[0].selected = true,
+            _ => {
+                if !self.show_select_ui(app, &mut entries[..])? {
+                    return self.show_previous_action_result(app);
+                }
+            }
+        }
+        let files: Vec<_> = entries
+            .iter()
+            .filter(|e| e.selected)
+            .map(|e| &e.filename[..])
+            .collect();
+        if files.is_empty() {
+            return self.show_previous_action_result(app);
+        }
+
+        let merge_tool = app
+            .config
+            .merge_tool
+            .clone()
+            .or_else(|| env::var("VISUAL").ok())
+            .or_else(|| env::var("EDITOR").ok())
+            .filter(|tool| !tool.trim().is_empty())
+            .unwrap_or_else(|| "vi".into());
+        let command = custom_actions::files_command(
+            app.version_control.get_root(),
+            &merge_tool,
+            &files,
+        );
+        if let Err(error) = self.run_interactive(command)? {
+            let result = ActionResult::from_err(error.to_string());
+            return self.show_result(app, &result);
+        }
+
+        self.current_action_kind = ActionKind::UnresolvedConflicts;
+        self.current_key_chord = vec!['r', 'r'];
+        let action = conflicts_action(app);
+        self.show_action(app, action)
+    }
+
+    /// Hands the terminal over to `command` until it exits
+    fn run_interactive(
+        &mut self,
+        mut command: Command,
+    ) -> Result<std::io::Result<ExitStatus>> {
+        terminal_input::suspend();
+        execute!(
+            self.write,
+            DisableMouseCapture,
+            cursor::Show,
+            LeaveAlternateScreen
         )?;
-        Self::show_help_action(&mut write, "DC", ActionKind::RevisionChanges)?;
-        Self::show_help_action(&mut write, "DD", ActionKind::RevisionDiffAll)?;
-        Self::show_help_action(
-            &mut write,
-            "DS",
-            ActionKind::RevisionDiffSelected,
+        terminal::disable_raw_mode()?;
+        let status = command.status();
+        terminal::enable_raw_mode()?;
+        execute!(
+            self.write,
+            EnterAlternateScreen,
+            EnableMouseCapture,
+            cursor::Hide
         )?;
+        terminal_input::resume();
+        self.terminal_size = TerminalSize::get()?;
+        Ok(status)
+    }
+
+    /// Picks modified files and then which of their hunks to revert
+    fn revert_hunks(&mut self, app: &mut Application) -> Result<()> {
+        let mut entries = match app.get_current_changed_files() {
+            Ok(entries) => entries,
+            Err(error) => {
+                return self.show_result(app, &ActionResult::from_err(error))
+            }
+        };
+        entries.retain(|e| matches!(e.state, State::Modified));
+        if entries.is_empty() {
+            return self.show_empty_entries(app);
+        }
+        if !self.show_changed_files_select_ui(app, &mut entries[..])? {
+            return self.show_previous_action_result(app);
+        }
+
+        let flags = app.action_flags(ActionKind::CurrentDiffSelected);
+        let diff = match app.version_control.get_current_diff(&entries, flags) {
+            Ok(diff) => diff,
+            Err(error) => {
+                return self.show_result(app, &ActionResult::from_err(error))
+            }
+        };
+        let hunks = hunks::parse_hunks(&diff);
+        // summaries point at distinct lines so they identify the hunks even
+        // after the select ui reorders them
+        let mut hunk_entries: Vec<_> = hunks
+            .iter()
+            .map(|h| Entry {
+                filename: h.summary(),
+                selected: false,
+                state: State::Modified,
+                renamed_from: None,
+            })
+            .collect();
+        if hunk_entries.is_empty() {
+            return self.show_empty_entries(app);
+        }
+        if !self.show_select_ui(app, &mut hunk_entries[..])? {
+            return self.show_previous_action_result(app);
+        }
 
-        write.queue(cursor::MoveToNextLine(1))?;
+        let selected: Vec<_> = hunk_entries
+            .iter()
+            .filter(|e| e.selected)
+            .map(|e| &e.filename)
+            .collect();
+        let patch = hunks::build_patch(
+            hunks.iter().filter(|h| selected.contains(&&h.summary())),
+        );
+        if patch.is_empty() {
+            return self.show_previous_action_result(app);
+        }
+        if let Err(error) = self.snapshot_before_discard(app) {
+            return self.show_result(app, &ActionResult::from_err(error));
+        }
+        let action = app.version_control.revert_hunks(&patch);
+        self.show_action(app, action)
+    }
 
-        Self::show_help_action(&mut write, "cc", ActionKind::CommitAll)?;
-        Self::show_help_action(&mut write, "cs", ActionKind::CommitSelected)?;
-        Self::show_help_action(&mut write, "u", ActionKind::Update)?;
-        Self::show_help_action(&mut write, "m", ActionKind::Merge)?;
-        Self::show_help_action(&mut write, "RA", ActionKind::RevertAll)?;
-        Self::show_help_action(&mut write, "rs", ActionKind::RevertSelected)?;
+    /// Hunk selected in the diff being shown, taken from the diff as it was
+    /// output. Shows why when there's none
+    fn selected_hunk(&mut self, app: &Application) -> Result<Option<Hunk>> {
+        let hunk =
+            self.scroll_view.selected_hunk().and_then(|(path, index)| {
+                let diff = strip_ansi(self.scroll_view.diff_source()?);
+                hunks::parse_hunks(&diff)
+                    .into_iter()
+                    .filter(|h| h.path == path)
+                    .nth(index)
+            });
+        let error = match hunk {
+            None => "no hunk selected, select one with { and }",
+            Some(hunk)
+                if encoding::is_decoded(&app.config.encodings, &hunk.path) =>
+            {
+                "the hunk is shown converted from another encoding"
+            }
+            Some(hunk) => return Ok(Some(hunk)),
+        };
+        self.show_header(app, HeaderKind::Error)?;
+        self.write.queue(Print(tr(error)))?;
+        Ok(None)
+    }
 
-        write.queue(cursor::MoveToNextLine(1))?;
+    /// Reverts the selected hunk of the working tree diff and shows the diff
+    /// again
+    fn revert_selected_hunk(&mut self, app: &mut Application) -> Result<()> {
+        let hunk = match self.selected_hunk(app)? {
+            Some(hunk) => hunk,
+            None => return Ok(()),
+        };
+        if !self.confirm_hunk(app, &hunk, "revert this hunk? [y/n]")? {
+            return self.show_current_action_result(app);
+        }
+        if let Err(error) = self.snapshot_before_discard(app) {
+            return self.show_result(app, &ActionResult::from_err(error));
+        }
 
-        Self::show_help_action(
-            &mut write,
-            "rr",
-            ActionKind::UnresolvedConflicts,
+        let diff = match self.current_action_kind {
+            ActionKind::CurrentDiffAll => {
+                app.version_control.current_diff_all(app.scope.as_deref())
+            }
+            _ => {
+                let entries: Vec<_> = self
+                    .scroll_view
+                    .diff_files()
+                    .into_iter()
+                    .map(|filename| Entry {
+                        filename,
+                        selected: true,
+                        state: State::Modified,
+                        renamed_from: None,
+                    })
+                    .collect();
+                app.version_control.current_diff_selected(&entries)
+            }
+        };
+        let mut tasks = task_vec();
+        tasks.push(
+            app.version_control
+                .revert_hunks(&hunks::build_patch(iter::once(&hunk))),
+        );
+        tasks.push(diff);
+        // not through `show_action` so retrying after a timeout shows the
+        // diff again instead of reverting another hunk
+        app.run_action(ActionFuture {
+            kind: self.current_action_kind,
+            task: decoded(serial_until_error(tasks), app.diff_decoder.clone()),
+        });
+        self.show_current_action_result(app)
+    }
+
+    /// Applies the selected hunk of the diff of a revision to the working
+    /// tree. Conflicts show like the ones of a merge
+    fn apply_selected_hunk(
+        &mut self,
+        app: &mut Application,
+    ) -> Result<HandleChordResult> {
+        let hunk = match self.selected_hunk(app)? {
+            Some(hunk) => hunk,
+            None => return Ok(HandleChordResult::Handled),
+        };
+        if !self.confirm_hunk(
+            app,
+            &hunk,
+            "apply this hunk to the working tree? [y/n]",
+        )? {
+            self.show_current_action_result(app)?;
+            return Ok(HandleChordResult::Handled);
+        }
+
+        let hunk = hunk.onto_current_path();
+        self.action_context(ActionKind::ApplySelectedHunk, |s| {
+            let summary = hunk.summary();
+            let patch = hunks::build_patch(iter::once(&hunk));
+            let action = app.version_control.apply_hunks(&patch);
+            let action = map_result(action, move |mut result| {
+                if result.success {
+                    result.output =
+                        trf("applied {} to the working tree", &[&summary]);
+                }
+                result
+            });
+            s.show_action(app, action)
+        })
+    }
+
+    /// Shows `hunk` asking `prompt`
+    fn confirm_hunk(
+        &mut self,
+        app: &Application,
+        hunk: &Hunk,
+        prompt: &str,
+    ) -> Result<bool> {
+        self.show_header(app, HeaderKind::Waiting)?;
+        queue!(
+            self.write,
+            SetForegroundColor(theme().entry),
+            Print(hunk.summary()),
+            ResetColor,
+            cursor::MoveToNextLine(2),
+        )?;
+        let height = (self.terminal_size.height as usize).saturating_sub(6);
+        let width = self.terminal_size.width as usize;
+        let line_count = hunk.lines().count();
+        for line in hunk.lines().take(height) {
+            let color = match line.chars().next() {
+                Some('+') => theme().diff_added,
+                Some('-') => theme().diff_removed,
+                _ => theme().text,
+            };
+            queue!(
+                self.write,
+                SetForegroundColor(color),
+                Print(fit_width(line, width)),
+                ResetColor,
+                cursor::MoveToNextLine(1),
+            )?;
+        }
+        if line_count > height {
+            queue!(self.write, Print("..."), cursor::MoveToNextLine(1))?;
+        }
+        execute!(self.write, cursor::MoveToNextLine(1), Print(tr(prompt)),)?;
+        Ok(self.read_char()? == Some('y'))
+    }
+
+    fn snapshot_before_discard(
+        &mut self,
+        app: &Application,
+    ) -> BackendResult<()> {
+        if !app.config.discard_snapshot {
+            return Ok(());
+        }
+
+        let vc = &app.version_control;
+        vc.prune_discard_snapshots(app.config.discard_snapshot_max_age)?;
+        vc.create_discard_snapshot()
+    }
+
+    fn read_char(&mut self) -> Result<Option<char>> {
+        loop {
+            match input::poll_event() {
+                Event::Resize(terminal_size) => {
+                    self.terminal_size = terminal_size;
+                }
+                Event::Key(key_event) => {
+                    return Ok(input::key_to_char(key_event));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn show_current_action_result(&mut self, app: &Application) -> Result<()> {
+        let result = app.get_cached_action_result(self.current_action_kind);
+        self.show_result(app, result)
+    }
+
+    fn is_preview_visible(&self) -> bool {
+        self.preview.enabled
+            && matches!(
+                self.current_action_kind,
+                ActionKind::Log
+                    | ActionKind::LogCount
+                    | ActionKind::RevisionChanges
+            )
+    }
+
+    /// Requests a preview of the hovered revision, or of the hovered file of
+    /// revision changes, whenever it changes and draws it next to the list
+    fn sync_preview(
+        &mut self,
+        app: &mut Application,
+        redrawn: bool,
+    ) -> Result<()> {
+        if !self.is_preview_visible()
+            || self.scroll_view.action_kind() != self.current_action_kind
+        {
+            return Ok(());
+        }
+        let line = match self.scroll_view.cursor_line() {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+
+        let kind = self.current_action_kind;
+        if let Some(path) = kind.parse_changed_file(line) {
+            let revision = self.revision_changes_target.clone();
+            let target =
+                format!("{}{}{}", revision, WORKING_TREE_PREVIEW, path);
+            let entry = Entry {
+                filename: path,
+                selected: true,
+                state: State::Modified,
+                renamed_from: None,
+            };
+            return update_preview(
+                &mut self.write,
+                app,
+                &mut self.preview,
+                &target,
+                self.terminal_size,
+                redrawn,
+                |app| {
+                    app.version_control
+                        .revision_diff_selected(&revision, &[entry])
+                },
+            );
+        }
+        let target = match kind.parse_target(line) {
+            Some(target) => String::from(target),
+            None => return Ok(()),
+        };
+        update_preview(
+            &mut self.write,
+            app,
+            &mut self.preview,
+            &target,
+            self.terminal_size,
+            redrawn,
+            |app| {
+                app.version_control
+                    .revision_diff_all(&target, app.scope.as_deref())
+            },
+        )
+    }
+
+    fn show_result(
+        &mut self,
+        app: &Application,
+        result: &ActionResult,
+    ) -> Result<()> {
+        if app.has_pending_action_of_type(self.current_action_kind) {
+            self.show_header(app, HeaderKind::Waiting)?;
+        } else if result.canceled {
+            self.show_header(app, HeaderKind::Canceled)?;
+        } else if result.success {
+            self.show_header(app, HeaderKind::Ok)?;
+        } else {
+            self.show_header(app, HeaderKind::Error)?;
+        }
+
+        let max_width = if self.is_preview_visible() {
+            Some(self.preview.list_width(self.terminal_size))
+        } else {
+            None
+        };
+        self.scroll_view.set_max_width(max_width);
+        let kept_view = match self.keeps_view
+            && self.scroll_view.action_kind() == self.current_action_kind
+        {
+            true => Some((
+                self.scroll_view.view_state(),
+                self.scroll_view.is_filtering(),
+            )),
+            false => None,
+        };
+        let is_running =
+            app.has_pending_action_of_type(self.current_action_kind);
+        let is_streaming = self.streaming == Some(self.current_action_kind);
+        // the end of the log is told below its last entry
+        let mut output = Cow::Borrowed(&result.output[..]);
+        if self.current_action_kind == ActionKind::Log
+            && !is_running
+            && self.is_log_complete(result)
+        {
+            let mut marked = result.output.clone();
+            if !marked.is_empty() {
+                marked.push('\n');
+            }
+            marked.push_str(tr(LOG_END_MARKER));
+            output = Cow::Owned(marked);
+        }
+        // the streamed lines stay where they are once the action finishes
+        if is_streaming && self.streamed_len > 0 && !is_running {
+            self.streaming = None;
+            self.scroll_view.finish_content(
+                &output,
+                self.current_action_kind,
+                self.terminal_size,
+            );
+        } else {
+            // the streamed lines were replaced so they're drawn again
+            if is_streaming {
+                self.streamed_len = 0;
+            }
+            self.scroll_view.set_content(
+                &output,
+                self.current_action_kind,
+                self.terminal_size,
+            );
+        }
+        if let Some((view, is_filtering)) = kept_view {
+            self.scroll_view.restore(&view, self.terminal_size);
+            self.scroll_view.set_filtering(is_filtering);
+        }
+        if !is_running {
+            if let Some(view) = self.pending_view.take() {
+                self.scroll_view.restore(&view, self.terminal_size);
+            }
+            if let Some(path) = self.pending_file_jump.take() {
+                self.scroll_view.jump_to_file(&path, self.terminal_size);
+            }
+        }
+        self.scroll_view
+            .draw_content(&mut self.write, self.terminal_size)
+    }
+
+    fn show_current_key_chord(&mut self) -> Result<()> {
+        let TerminalSize { width, height } = self.terminal_size;
+        let chord = input::chord_name(self.current_key_chord.iter().copied());
+        let chord_width = chord.chars().count() as u16;
+        queue!(
+            self.write,
+            cursor::MoveTo(width.saturating_sub(chord_width), height - 1),
+            Clear(ClearType::CurrentLine),
+            SetForegroundColor(theme().entry),
+            Print(chord),
+            ResetColor,
         )?;
-        Self::show_help_action(&mut write, "ro", ActionKind::MergeTakingOther)?;
-        Self::show_help_action(&mut write, "rl", ActionKind::MergeTakingLocal)?;
+        Ok(())
+    }
 
-        write.queue(cursor::MoveToNextLine(1))?;
+    /// Forgets the pressed keys shown for long enough returning whether any
+    /// was removed
+    fn expire_pressed_keys(&mut self) -> bool {
+        let pressed_keys = match &mut self.pressed_keys {
+            Some(pressed_keys) => pressed_keys,
+            None => return false,
+        };
+        let count = pressed_keys.len();
+        pressed_keys.retain(|(_, time)| time.elapsed() < PRESSED_KEY_DURATION);
+        pressed_keys.len() != count
+    }
 
-        Self::show_help_action(&mut write, "f", ActionKind::Fetch)?;
-        Self::show_help_action(&mut write, "p", ActionKind::Pull)?;
-        Self::show_help_action(&mut write, "P", ActionKind::Push)?;
+    /// Draws the recently pressed keys on the last line, newest to the right
+    fn show_pressed_keys(&mut self) -> Result<()> {
+        let pressed_keys = match &self.pressed_keys {
+            Some(pressed_keys) => pressed_keys,
+            None => return Ok(()),
+        };
+        // leaves the right half for the current key chord
+        let max_width = self.terminal_size.width as usize / 2;
+        let mut keys = String::new();
+        for (name, _) in pressed_keys.iter().rev() {
+            if keys.chars().count() + name.chars().count() + 2 > max_width {
+                break;
+            }
+            keys.insert_str(0, &format!(" {} ", name));
+        }
 
-        write.queue(cursor::MoveToNextLine(1))?;
+        let chord = input::chord_name(self.current_key_chord.iter().copied());
+        let chord_width = chord.chars().count() as u16;
+        let TerminalSize { width, height } = self.terminal_size;
+        queue!(
+            self.write,
+            cursor::MoveTo(0, height - 1),
+            Clear(ClearType::CurrentLine),
+            SetBackgroundColor(theme().selected_bg),
+            Print(keys),
+            ResetColor,
+            cursor::MoveTo(width.saturating_sub(chord_width), height - 1),
+            SetForegroundColor(theme().entry),
+            Print(chord),
+            ResetColor,
+        )?;
+        Ok(())
+    }
 
-        Self::show_help_action(&mut write, "tn", ActionKind::NewTag)?;
+    fn show_help(&mut self, app: &Application) -> Result<ActionResult> {
+        let mut write = Vec::with_capacity(1024);
 
-        write.queue(cursor::MoveToNextLine(1))?;
+        queue!(
+            &mut write,
+            Print(BIN_NAME),
+            Print(' '),
+            Print(VERSION),
+            cursor::MoveToNextLine(2),
+            Print(tr("backend:")),
+            Print(' '),
+            SetForegroundColor(theme().entry),
+            Print(app.version_control.executable_name()),
+            ResetColor,
+            cursor::MoveToNextLine(1),
+            Print(tr("root:")),
+            Print(' '),
+            SetForegroundColor(theme().entry),
+            Print(app.version_control.get_root()),
+            ResetColor,
+            cursor::MoveToNextLine(2),
+        )?;
 
-        Self::show_help_action(&mut write, "bb", ActionKind::ListBranches)?;
-        Self::show_help_action(&mut write, "bn", ActionKind::NewBranch)?;
-        Self::show_help_action(&mut write, "bd", ActionKind::DeleteBranch)?;
+        if let Ok(version) = app.version_control.version() {
+            queue!(&mut write, Print(version), cursor::MoveToNextLine(2))?;
+        }
 
-        write.queue(cursor::MoveToNextLine(1))?;
+        write
+            .queue(Print(tr("press a key and peform an action")))?
+            .queue(cursor::MoveToNextLine(2))?;
 
-        Self::show_help_action(&mut write, "x", ActionKind::CustomAction)?;
+        for (i, group) in KEY_BINDINGS.iter().enumerate() {
+            let mut bindings =
+                group.iter().filter(|b| b.is_available(app)).peekable();
+            if bindings.peek().is_none() {
+                continue;
+            }
+            if i > 0 {
+                write.queue(cursor::MoveToNextLine(1))?;
+            }
+            for binding in bindings {
+                Self::show_help_action(
+                    &mut write,
+                    binding.keys,
+                    binding.action,
+                )?;
+            }
+        }
 
         write.flush()?;
         Ok(ActionResult::from_ok(String::from_utf8(write)?))
@@ -834,13 +4550,13 @@ where
     {
         queue!(
             write,
-            SetForegroundColor(ENTRY_COLOR),
+            SetForegroundColor(theme().entry),
             Print('\t'),
             Print(shortcut),
             ResetColor,
             Print('\t'),
             Print('\t'),
-            Print(action.name()),
+            Print(tr(action.name())),
             cursor::MoveToNextLine(1),
         )
     }
@@ -1,4 +1,4 @@
-use std::io::Write;
+use std::{io::Write, time::Duration};
 
 use crossterm::{
     cursor, handle_command, queue,
@@ -7,93 +7,130 @@ use crossterm::{
     Result,
 };
 
-pub const SELECTED_BG_COLOR: Color = Color::Rgb {
-    r: 80,
-    g: 80,
-    b: 80,
-};
-pub const ENTRY_COLOR: Color = Color::Rgb {
-    r: 255,
-    g: 180,
-    b: 100,
-};
+use unicode_width::UnicodeWidthChar;
+
+use crate::{locale::tr, theme::theme};
+
+/// Colors of the parts of a log line, from the hash to the refs
+pub fn log_colors() -> [Color; 7] {
+    let theme = theme();
+    let text = theme.text;
+    [
+        text,
+        theme.fit(Color::Rgb {
+            r: 211,
+            g: 153,
+            b: 33,
+        }),
+        text,
+        theme.fit(Color::Rgb {
+            r: 52,
+            g: 113,
+            b: 134,
+        }),
+        theme.fit(Color::Rgb {
+            r: 137,
+            g: 151,
+            b: 29,
+        }),
+        theme.fit(Color::Rgb {
+            r: 251,
+            g: 73,
+            b: 47,
+        }),
+        text,
+    ]
+}
 
-pub const LOG_COLORS: &[Color] = &[
-    Color::White,
-    Color::Rgb {
-        r: 211,
-        g: 153,
-        b: 33,
-    },
-    Color::Rgb {
-        r: 52,
-        g: 113,
-        b: 134,
-    },
-    Color::Rgb {
-        r: 137,
-        g: 151,
-        b: 29,
-    },
-    Color::Rgb {
-        r: 251,
-        g: 73,
-        b: 47,
-    },
-    Color::White,
-];
-
-const HEADER_COLOR: Color = Color::Black;
-const ACTION_COLOR: Color = Color::White;
-const HEADER_BG_WAITING_COLOR: Color = Color::Magenta;
-const HEADER_BG_WAITING_DARK_COLOR: Color = Color::DarkMagenta;
-const HEADER_BG_OK_COLOR: Color = Color::Green;
-const HEADER_BG_OK_DARK_COLOR: Color = Color::DarkGreen;
-const HEADER_BG_ERROR_COLOR: Color = Color::Red;
-const HEADER_BG_ERROR_DARK_COLOR: Color = Color::DarkRed;
-
-const FILTER_COLOR: Color = Color::Black;
-const FILTER_ACTIVE_BG_COLOR: Color = Color::Rgb {
-    r: 255,
-    g: 180,
-    b: 100,
-};
-const FILTER_INACTIVE_BG_COLOR: Color = Color::Rgb {
-    r: 180,
-    g: 90,
-    b: 50,
-};
+/// Colors of the name, target, date and subject parts of a tags line
+pub fn tag_colors() -> [Color; 4] {
+    let log_colors = log_colors();
+    [log_colors[4], log_colors[1], log_colors[3], theme().text]
+}
+
+/// Index of the signature status part of a log line
+pub const LOG_SIGNATURE_PART: usize = 2;
+pub const LOG_REFS_PART: usize = 5;
+pub const LOG_SUBJECT_PART: usize = 6;
+/// Index of the phase part hg log lines end with, followed by the topics
+pub const LOG_PHASE_PART: usize = 7;
+pub const LOG_TOPICS_PART: usize = 8;
+const SIGNATURE_GOOD_COLOR: Color = Color::Green;
+const SIGNATURE_BAD_COLOR: Color = Color::Red;
+const SIGNATURE_UNKNOWN_COLOR: Color = Color::Yellow;
+
+/// Badge and color for a `%G?` signature status code
+pub fn signature_badge(code: &str) -> (&'static str, Color) {
+    match code {
+        "G" => ("G", SIGNATURE_GOOD_COLOR),
+        "B" => ("B", SIGNATURE_BAD_COLOR),
+        "U" | "X" | "Y" | "R" | "E" => ("?", SIGNATURE_UNKNOWN_COLOR),
+        _ => (" ", Color::Reset),
+    }
+}
+
+const PHASE_DRAFT_COLOR: Color = Color::Yellow;
+const PHASE_SECRET_COLOR: Color = Color::Magenta;
+pub const TOPICS_COLOR: Color = Color::Cyan;
+
+/// Badge and color for an hg phase, `None` for public revisions as most are
+pub fn phase_badge(phase: &str) -> Option<(&str, Color)> {
+    match phase {
+        "draft" => Some((phase, PHASE_DRAFT_COLOR)),
+        "secret" => Some((phase, PHASE_SECRET_COLOR)),
+        _ => None,
+    }
+}
+
+pub const MOUSE_SCROLL_DELTA: i32 = 3;
+
+/// Drawn next to the pinned files
+pub const PIN_MARKER: &str = "[pinned]";
 
 const HEADER_PREFIX: &str = concat!(env!("CARGO_PKG_NAME"), " @ ");
 const DIR_NAME_MAX_LENGTH: usize = 32;
+const SPINNER_FRAMES: &[char] = &['|', '/', '-', '\\'];
+pub const SPINNER_FRAME_DURATION: Duration = Duration::from_millis(250);
+/// How often quiet mode redraws the elapsed time and streamed output
+pub const QUIET_REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+/// Narrower spaces left between the header parts don't get a command line
+const MIN_COMMAND_WIDTH: usize = 12;
 
 pub enum HeaderKind {
     Waiting,
     Ok,
     Error,
+    Canceled,
 }
 
 pub struct Header<'a> {
     pub action_name: &'a str,
     pub directory_name: &'a str,
+    /// For waiting headers, how long the action has been running and the
+    /// command it's running
+    pub progress: Option<(Duration, Option<&'a str>)>,
+    /// Draws waiting and canceled headers like ok ones and without the
+    /// spinner, so only errors change the header color
+    pub quiet: bool,
 }
 
 impl<'a> Header<'a> {
     pub fn full_length(&self) -> usize {
         HEADER_PREFIX.len()
-            + self.directory_name.len()
+            + str_width(self.directory_name)
             + 3
-            + self.action_name.len()
+            + str_width(self.action_name)
     }
 
     pub fn min_length(&self) -> usize {
         HEADER_PREFIX.len()
-            + self.directory_name.len().min(DIR_NAME_MAX_LENGTH)
+            + str_width(self.directory_name).min(DIR_NAME_MAX_LENGTH)
             + 3
-            + self.action_name.len()
+            + str_width(self.action_name)
     }
 }
 
+/// Clears the screen and draws the header on its first line
 pub fn show_header<W>(
     write: &mut W,
     header: Header,
@@ -103,23 +140,69 @@ pub fn show_header<W>(
 where
     W: Write,
 {
-    let background_color = match kind {
-        HeaderKind::Waiting => HEADER_BG_WAITING_COLOR,
-        HeaderKind::Ok => HEADER_BG_OK_COLOR,
-        HeaderKind::Error => HEADER_BG_ERROR_COLOR,
-    };
+    queue!(write, Clear(ClearType::All))?;
+    draw_header(write, header, kind, terminal_size)?;
+    queue!(write, cursor::MoveTo(0, 1))
+}
+
+/// Redraws the header leaving the rest of the screen as it is
+pub fn update_header<W>(
+    write: &mut W,
+    header: Header,
+    kind: HeaderKind,
+    terminal_size: TerminalSize,
+) -> Result<()>
+where
+    W: Write,
+{
+    queue!(write, cursor::SavePosition)?;
+    draw_header(write, header, kind, terminal_size)?;
+    queue!(write, cursor::RestorePosition)
+}
 
-    let background_dark_color = match kind {
-        HeaderKind::Waiting => HEADER_BG_WAITING_DARK_COLOR,
-        HeaderKind::Ok => HEADER_BG_OK_DARK_COLOR,
-        HeaderKind::Error => HEADER_BG_ERROR_DARK_COLOR,
+fn draw_header<W>(
+    write: &mut W,
+    header: Header,
+    kind: HeaderKind,
+    terminal_size: TerminalSize,
+) -> Result<()>
+where
+    W: Write,
+{
+    let theme = theme();
+    let (background_color, background_dark_color) = match kind {
+        HeaderKind::Error => (theme.error, theme.error_dark),
+        _ if header.quiet => (theme.ok, theme.ok_dark),
+        HeaderKind::Waiting => (theme.waiting, theme.waiting_dark),
+        HeaderKind::Ok => (theme.ok, theme.ok_dark),
+        HeaderKind::Canceled => (theme.canceled, theme.canceled_dark),
     };
 
     let status = match kind {
-        HeaderKind::Waiting => "waiting",
-        HeaderKind::Ok => "ok",
-        HeaderKind::Error => "error",
+        HeaderKind::Waiting => tr("waiting"),
+        HeaderKind::Ok => tr("ok"),
+        HeaderKind::Error => tr("error"),
+        HeaderKind::Canceled => tr("canceled"),
     };
+    let (status, command) = match (kind, header.progress) {
+        (HeaderKind::Waiting, Some((elapsed, command))) if header.quiet => {
+            (format!("{} {}", status, format_elapsed(elapsed)), command)
+        }
+        (HeaderKind::Waiting, Some((elapsed, command))) => {
+            let frame = elapsed.as_millis()
+                / SPINNER_FRAME_DURATION.as_millis()
+                % SPINNER_FRAMES.len() as u128;
+            let status = format!(
+                "{} {} {}",
+                SPINNER_FRAMES[frame as usize],
+                status,
+                format_elapsed(elapsed)
+            );
+            (status, command)
+        }
+        _ => (status.into(), None),
+    };
+    let status = &status[..];
 
     let header_prefix;
     let directory_name;
@@ -133,7 +216,7 @@ where
         status: &str,
         padding: &mut usize,
     ) -> bool {
-        let needed_width = header_length + status.len() + 2;
+        let needed_width = header_length + str_width(status) + 2;
         if terminal_width >= needed_width {
             *padding = terminal_width - needed_width;
             true
@@ -147,39 +230,60 @@ where
         directory_name = header.directory_name;
     } else if fit(terminal_width, header.min_length(), status, &mut padding) {
         header_prefix = HEADER_PREFIX;
-        directory_name = &header.directory_name
-            [(header.directory_name.len() - DIR_NAME_MAX_LENGTH)..];
+        directory_name =
+            fit_width_end(header.directory_name, DIR_NAME_MAX_LENGTH);
     } else {
         panic!("window too small");
     }
 
+    // the command goes at the end of the space between the action name and
+    // the status
+    let command = match command {
+        Some(command) if padding >= MIN_COMMAND_WIDTH => {
+            let command = fit_width(command, padding - 2);
+            padding -= str_width(command) + 1;
+            command
+        }
+        _ => "",
+    };
+
     queue!(
         write,
-        Clear(ClearType::All),
         cursor::MoveTo(0, 0),
         SetBackgroundColor(background_color),
-        SetForegroundColor(HEADER_COLOR),
+        SetForegroundColor(theme.header),
         Print(header_prefix),
         Print(directory_name),
         Print(' '),
         SetBackgroundColor(background_dark_color),
-        SetForegroundColor(ACTION_COLOR),
+        SetForegroundColor(theme.header_action),
         Print(' '),
         Print(header.action_name),
         Print(' '),
         SetBackgroundColor(background_color),
-        SetForegroundColor(HEADER_COLOR),
+        SetForegroundColor(theme.header),
         Print(" ".repeat(padding)),
+        Print(command),
+        Print(if command.is_empty() { "" } else { " " }),
         SetBackgroundColor(background_dark_color),
-        SetForegroundColor(ACTION_COLOR),
+        SetForegroundColor(theme.header_action),
         Print(' '),
         Print(status),
         Print(' '),
         ResetColor,
-        cursor::MoveToNextLine(1),
     )
 }
 
+/// Like `12s` or `2m05s`
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    }
+}
+
 #[derive(Default, Clone, Copy)]
 pub struct TerminalSize {
     pub width: u16,
@@ -249,6 +353,326 @@ pub fn move_cursor(
     }
 }
 
+/// Columns `c` takes in the terminal
+pub fn char_width(c: char) -> usize {
+    // control chars, like tabs, still move the cursor
+    if c.is_ascii() {
+        1
+    } else {
+        UnicodeWidthChar::width(c).unwrap_or(0)
+    }
+}
+
+/// Columns `text` takes in the terminal
+pub fn str_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+/// Longest start of `text` that fits in `width` columns
+pub fn fit_width(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (i, c) in text.char_indices() {
+        used += char_width(c);
+        if used > width {
+            return &text[..i];
+        }
+    }
+    text
+}
+
+/// Longest end of `text` that fits in `width` columns
+pub fn fit_width_end(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (i, c) in text.char_indices().rev() {
+        used += char_width(c);
+        if used > width {
+            return &text[i + c.len_utf8()..];
+        }
+    }
+    text
+}
+
+/// Prints at most `width` visible columns of `line` while passing ansi escape
+/// sequences through untouched
+pub fn print_clipped<W>(write: &mut W, line: &str, width: usize) -> Result<()>
+where
+    W: Write,
+{
+    enum Escape {
+        None,
+        Start,
+        Csi,
+    }
+
+    let mut escape = Escape::None;
+    let mut visible_count = 0;
+    let mut end = 0;
+    for (i, c) in line.char_indices() {
+        match escape {
+            Escape::None => {
+                if c == '\x1b' {
+                    escape = Escape::Start;
+                } else if visible_count + char_width(c) > width {
+                    break;
+                } else {
+                    visible_count += char_width(c);
+                }
+            }
+            Escape::Start => {
+                escape = if c == '[' { Escape::Csi } else { Escape::None };
+            }
+            Escape::Csi => {
+                if ('@'..='~').contains(&c) {
+                    escape = Escape::None;
+                }
+            }
+        }
+        end = i + c.len_utf8();
+    }
+
+    handle_command!(write, Print(&line[..end]))
+}
+
+/// Tells the lines of the hunks of a unified diff apart from the file
+/// headers after them by counting down the lines each `@@ -a,b +c,d @@`
+/// header announces
+#[derive(Default)]
+pub struct HunkLines {
+    /// Lines left of each old side, more than one in combined diffs
+    old_left: Vec<usize>,
+    new_left: usize,
+    /// The header had no counts so the hunk lasts until the next file
+    uncounted: bool,
+    /// Whether the last line belonged to a hunk, which a following
+    /// `\ No newline at end of file` also does
+    in_hunk: bool,
+}
+
+impl HunkLines {
+    /// Starts a hunk if the uncolored `line` is its header
+    pub fn start(&mut self, line: &str) -> bool {
+        let at = line.bytes().take_while(|&b| b == b'@').count();
+        if at < 2 {
+            return false;
+        }
+        self.old_left.clear();
+        self.new_left = 0;
+        self.in_hunk = false;
+        let ranges = match line[at..].find(&line[..at]) {
+            Some(end) => &line[at..at + end],
+            None => "",
+        };
+        for range in ranges.split_whitespace() {
+            // `-a,b` or just `-a` when it's a single line
+            let count = match range[1..].split(',').nth(1) {
+                Some(count) => count.parse().unwrap_or(0),
+                None => 1,
+            };
+            match range.as_bytes()[0] {
+                b'-' => self.old_left.push(count),
+                b'+' => self.new_left = count,
+                _ => (),
+            }
+        }
+        self.uncounted = self.old_left.is_empty();
+        true
+    }
+
+    /// Counts the uncolored `line` following a hunk header or the lines
+    /// after it, telling whether it still belongs to the hunk
+    pub fn next(&mut self, line: &str) -> bool {
+        if self.uncounted {
+            self.uncounted = !line.starts_with("diff ");
+            return self.uncounted;
+        }
+        if self.new_left == 0 && self.old_left.iter().all(|&l| l == 0) {
+            self.in_hunk &= line.starts_with('\\');
+            return self.in_hunk;
+        }
+
+        let prefix = line.as_bytes();
+        let mut removed = false;
+        for (i, left) in self.old_left.iter_mut().enumerate() {
+            match prefix.get(i) {
+                Some(b'+') => (),
+                Some(b'-') => {
+                    removed = true;
+                    *left = left.saturating_sub(1);
+                }
+                _ => *left = left.saturating_sub(1),
+            }
+        }
+        if !removed {
+            self.new_left = self.new_left.saturating_sub(1);
+        }
+        self.in_hunk = true;
+        true
+    }
+
+    /// Counts `count` unchanged lines left out of the diff, like the ones
+    /// of a fold
+    pub fn skip_unchanged(&mut self, count: usize) {
+        for left in &mut self.old_left {
+            *left = left.saturating_sub(count);
+        }
+        self.new_left = self.new_left.saturating_sub(count);
+    }
+}
+
+/// Path of the file a `diff ...` header line introduces
+pub fn diff_header_path(line: &str) -> Option<String> {
+    let line = strip_ansi(line);
+    let line = line.trim_end();
+    if !line.starts_with("diff ") {
+        return None;
+    }
+
+    let path = unquote_path(line.rsplit(' ').next()?);
+    let path = path.strip_prefix("b/").unwrap_or(&path);
+    Some(path.into())
+}
+
+/// Decodes a path git quoted for having unusual chars, like `"a\tb"` or
+/// `"\346\227\245"` for non ascii ones when `core.quotepath` is on
+pub fn unquote_path(path: &str) -> String {
+    let quoted = match path.strip_prefix('"').and_then(|p| p.strip_suffix('"'))
+    {
+        Some(quoted) => quoted,
+        None => return path.into(),
+    };
+
+    let mut bytes = Vec::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let byte = match chars.next() {
+            Some('a') => 0x07,
+            Some('b') => 0x08,
+            Some('f') => 0x0c,
+            Some('n') => b'\n',
+            Some('r') => b'\r',
+            Some('t') => b'\t',
+            Some('v') => 0x0b,
+            Some(c @ '0'..='7') => {
+                let octal: String =
+                    std::iter::once(c).chain(chars.by_ref().take(2)).collect();
+                u8::from_str_radix(&octal, 8).unwrap_or(b'?')
+            }
+            Some(c) => c as u8,
+            None => b'\\',
+        };
+        bytes.push(byte);
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+pub fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in &mut chars {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// Keeps only the parts of command output a scroll view can draw: text and
+/// sgr color sequences. Other escape sequences, like cursor movement, line
+/// clearing or hyperlinks, are dropped and text overwritten after a `\r`
+/// is replaced by what overwrote it, as progress output does
+pub fn sanitize_ansi(text: &str) -> String {
+    enum Escape {
+        None,
+        Start,
+        Csi(usize),
+        Osc,
+        OscEnd,
+    }
+
+    let mut sanitized = String::with_capacity(text.len());
+    let mut line_start = 0;
+    let mut escape = Escape::None;
+    for (i, c) in text.char_indices() {
+        escape = match escape {
+            Escape::None => match c {
+                '\x1b' => Escape::Start,
+                '\r' if text[i + 1..].starts_with('\n') => Escape::None,
+                '\r' => {
+                    sanitized.truncate(line_start);
+                    Escape::None
+                }
+                '\n' => {
+                    sanitized.push(c);
+                    line_start = sanitized.len();
+                    Escape::None
+                }
+                // other control chars are kept as log and tag lines use
+                // them to separate their columns
+                '\x07' | '\x08' | '\x0b' | '\x0c' | '\x7f' => Escape::None,
+                c => {
+                    sanitized.push(c);
+                    Escape::None
+                }
+            },
+            Escape::Start => match c {
+                '[' => Escape::Csi(i - 1),
+                ']' => Escape::Osc,
+                _ => Escape::None,
+            },
+            Escape::Csi(start) => {
+                if ('@'..='~').contains(&c) {
+                    if c == 'm' {
+                        sanitized.push_str(&text[start..=i]);
+                    }
+                    Escape::None
+                } else {
+                    Escape::Csi(start)
+                }
+            }
+            Escape::Osc => match c {
+                '\x07' => Escape::None,
+                '\x1b' => Escape::OscEnd,
+                _ => Escape::Osc,
+            },
+            Escape::OscEnd => match c {
+                '\\' => Escape::None,
+                _ => Escape::Osc,
+            },
+        };
+    }
+    sanitized
+}
+
+/// Columns `line` takes in the terminal once its escape sequences are
+/// interpreted
+pub fn visible_width(line: &str) -> usize {
+    let mut width = 0;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in &mut chars {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            width += char_width(c);
+        }
+    }
+    width
+}
+
 pub fn fuzzy_matches(text: &str, pattern: &[char]) -> bool {
     let pattern_len = pattern.len();
     let mut pattern_index = 0;
@@ -273,21 +697,22 @@ pub fn draw_filter_bar<W>(
 where
     W: Write,
 {
-    if !active && filter.len() == 0 {
+    if !active && filter.is_empty() {
         return Ok(());
     }
 
+    let theme = theme();
     let bg_color = if active {
-        FILTER_ACTIVE_BG_COLOR
+        theme.filter_active_bg
     } else {
-        FILTER_INACTIVE_BG_COLOR
+        theme.filter_inactive_bg
     };
 
     queue!(
         write,
         cursor::MoveTo(0, 9999),
         SetBackgroundColor(bg_color),
-        SetForegroundColor(FILTER_COLOR),
+        SetForegroundColor(theme.filter),
         Print('/'),
     )?;
 
@@ -1,16 +1,111 @@
-use std::process::{Command, Stdio};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use crate::{
-    action::{ActionTask, CommandTask},
+    action::{
+        deferred, ready, serial_until_error, task_vec, ActionKind,
+        ActionResult, ActionTask, CommandTask,
+    },
+    async_process::{command_line, ChildOptions},
+    backend_error::{BackendError, BackendResult},
     select::Entry,
 };
 
+/// Prefix of the stash/shelve names used to snapshot changes before a discard
+const DISCARD_SNAPSHOT_PREFIX: &str =
+    concat!(env!("CARGO_PKG_NAME"), "-discard-");
+
+/// Ends the lines of bookmarks listed together with branches
+pub const BOOKMARK_SUFFIX: &str = "\x1ebookmark";
+
+/// Phases of revisions, from the least to the most private
+pub const PHASES: &[&str] = &["public", "draft", "secret"];
+
+const BOOKMARKS_UNSUPPORTED: &str = "bookmarks are only supported for hg";
+const PHASES_UNSUPPORTED: &str = "phases are only supported for hg";
+const COMPARE_UNSUPPORTED: &str =
+    "comparing revisions is only supported for git";
+const HUNKS_UNSUPPORTED: &str = "reverting hunks is only supported for git";
+const APPLY_HUNKS_UNSUPPORTED: &str =
+    "applying hunks is only supported for git";
+const LOG_JUMP_UNSUPPORTED: &str =
+    "jumping in the log is only supported for git";
+const LIST_TAGS_UNSUPPORTED: &str = "listing tags is only supported for git";
+const OPEN_IN_BROWSER_UNSUPPORTED: &str =
+    "opening in the browser is only supported for git";
+const RENAME_TAG_UNSUPPORTED: &str = "renaming tags is only supported for git";
+const OPERATIONS_UNSUPPORTED: &str =
+    "continuing interrupted operations is only supported for git";
+
+#[derive(Default, Clone, Copy)]
+pub struct StatusOptions {
+    pub show_ignored: bool,
+    /// Skips scanning for untracked files which is slow in huge trees
+    pub skip_untracked: bool,
+    /// Lists every untracked file instead of collapsing directories that
+    /// only hold untracked files into a single entry
+    pub expand_untracked: bool,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct CommitOptions {
+    pub sign: bool,
+    /// Skips the pre-commit and commit-msg hooks
+    pub no_verify: bool,
+}
+
+/// Operation left midway, like a rebase stopped by conflicts
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Rebase,
+    Merge,
+    CherryPick,
+    Revert,
+    Bisect,
+    /// Applying patches from a mailbox with `git am`
+    Am,
+}
+
+impl Operation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rebase => "rebase",
+            Self::Merge => "merge",
+            Self::CherryPick => "cherry-pick",
+            Self::Revert => "revert",
+            Self::Bisect => "bisect",
+            Self::Am => "am",
+        }
+    }
+}
+
 pub trait VersionControlActions: Send {
     fn executable_name(&self) -> &'static str;
     fn current_dir(&self) -> &str;
 
+    /// Arguments passed to every command before the subcommand
+    fn global_args(&self) -> &'static [&'static str] {
+        &[]
+    }
+    /// Subcommand of the main command `kind` runs, which gets the flags
+    /// configured for it. `None` when it takes none
+    fn flags_subcommand(&self, _kind: ActionKind) -> Option<&'static str> {
+        None
+    }
+    /// Flags of the `diff` subcommand showing `lines` unchanged lines around
+    /// each change. Empty when it can't take that many
+    fn diff_context_flags(&self, _lines: usize) -> Vec<String> {
+        Vec::new()
+    }
+
     fn command(&self) -> Command {
         let mut command = Command::new(self.executable_name());
+        command.args(self.global_args());
         command.current_dir(self.current_dir());
         command.stdin(Stdio::null());
         command.stdout(Stdio::piped());
@@ -19,45 +114,190 @@ pub trait VersionControlActions: Send {
     }
 
     /// Sets the root of the current repository
-    fn set_root(&mut self) -> Result<(), String>;
+    fn set_root(&mut self) -> BackendResult<()>;
     /// Get the root of the current repository
     fn get_root(&self) -> &str;
 
-    fn get_current_changed_files(&self) -> Result<Vec<Entry>, String>;
+    fn get_current_changed_files(&self) -> BackendResult<Vec<Entry>>;
     fn get_revision_changed_files(
         &self,
         target: &str,
-    ) -> Result<Vec<Entry>, String>;
+    ) -> BackendResult<Vec<Entry>>;
+
+    fn get_remotes(&self) -> BackendResult<Vec<String>>;
+    /// Address the remote `name` fetches from
+    fn get_remote_url(&self, _name: &str) -> BackendResult<String> {
+        Err(OPEN_IN_BROWSER_UNSUPPORTED.into())
+    }
+    fn get_branch_names(&self) -> BackendResult<Vec<String>>;
+    fn get_current_branch(&self) -> BackendResult<String>;
+    /// Whether the current branch tracks a remote branch
+    fn has_upstream(&self) -> bool;
 
-    fn version(&self) -> Result<String, String>;
+    fn version(&self) -> BackendResult<String>;
 
-    fn status(&self) -> Box<dyn ActionTask>;
+    /// `scope` limits the output to a directory relative to the root
+    fn status(
+        &self,
+        options: StatusOptions,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask>;
     /// Shows the header and all diffs for the current revision
     fn current_export(&self) -> Box<dyn ActionTask>;
-    fn log(&self, count: usize) -> Box<dyn ActionTask>;
-
-    fn current_diff_all(&self) -> Box<dyn ActionTask>;
-    fn current_diff_selected(
+    /// `branch` limits the log to the history of a branch or revision
+    /// instead of showing all of them
+    fn log(
         &self,
-        entries: &Vec<Entry>,
+        count: usize,
+        scope: Option<&str>,
+        branch: Option<&str>,
     ) -> Box<dyn ActionTask>;
+    /// Command printing the whole log as `log` does, so more entries are
+    /// read from it as they're needed instead of running the log again.
+    /// `None` when the lines of `log` are formatted from its whole output
+    fn log_command(
+        &self,
+        _scope: Option<&str>,
+        _branch: Option<&str>,
+    ) -> Option<Command> {
+        None
+    }
+    /// Same fields as `log` for the cli, without the graph nor fields
+    /// shortened to line them up
+    fn log_for_scripts(&self, count: usize) -> Box<dyn ActionTask> {
+        self.log(count, None, None)
+    }
+    /// Number of log entries down to the revision `target`, or the newest
+    /// one before the date `target`, together with its full hash
+    fn log_position(
+        &self,
+        _target: &str,
+        _scope: Option<&str>,
+        _branch: Option<&str>,
+    ) -> BackendResult<(usize, String)> {
+        Err(LOG_JUMP_UNSUPPORTED.into())
+    }
+
+    fn current_diff_all(&self, scope: Option<&str>) -> Box<dyn ActionTask>;
+    fn current_diff_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask>;
 
-    fn revision_changes(&self, target: &str) -> Box<dyn ActionTask>;
-    fn revision_diff_all(&self, target: &str) -> Box<dyn ActionTask>;
+    /// `scope` limits the changes to a directory relative to the root
+    fn revision_changes(
+        &self,
+        target: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask>;
+    fn revision_diff_all(
+        &self,
+        target: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask>;
     fn revision_diff_selected(
         &self,
         target: &str,
-        entries: &Vec<Entry>,
+        entries: &[Entry],
+    ) -> Box<dyn ActionTask>;
+
+    /// Files changed in the working tree compared to revision `base`
+    fn base_changes(
+        &self,
+        base: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask>;
+    /// Diff of the working tree against revision `base`
+    fn base_diff_all(
+        &self,
+        base: &str,
+        scope: Option<&str>,
+    ) -> Box<dyn ActionTask>;
+    /// Commits in `target` that are not in `base` followed by the diff of
+    /// `target` since it forked from `base`
+    fn compare(&self, _base: &str, _target: &str) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(COMPARE_UNSUPPORTED))
+    }
+
+    /// Whether revisions have one of the `PHASES`
+    fn supports_phases(&self) -> bool {
+        false
+    }
+    /// Phases of the revisions in `target`
+    fn get_phases(&self, _target: &str) -> BackendResult<Vec<String>> {
+        Err(PHASES_UNSUPPORTED.into())
+    }
+    /// Moves revision `target` to `phase`. Moving it to a more private one
+    /// needs `force`
+    fn set_phase(
+        &self,
+        _target: &str,
+        _phase: &str,
+        _force: bool,
+    ) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(PHASES_UNSUPPORTED))
+    }
+
+    fn supports_notes(&self) -> bool;
+    fn get_note(&self, target: &str, notes_ref: &str) -> BackendResult<String>;
+    /// Signature verification status of revision `target`
+    fn revision_signature(&self, target: &str) -> Box<dyn ActionTask>;
+    /// Shows the notes attached to a revision, if any
+    fn revision_notes(
+        &self,
+        target: &str,
+        notes_ref: &str,
+    ) -> Box<dyn ActionTask>;
+    /// Replaces the note attached to a revision or removes it if `message`
+    /// is empty
+    fn set_note(
+        &self,
+        target: &str,
+        notes_ref: &str,
+        message: &str,
     ) -> Box<dyn ActionTask>;
 
-    fn commit_all(&self, message: &str) -> Box<dyn ActionTask>;
+    fn commit_all(
+        &self,
+        message: &str,
+        options: CommitOptions,
+    ) -> Box<dyn ActionTask>;
     fn commit_selected(
         &self,
         message: &str,
-        entries: &Vec<Entry>,
+        entries: &[Entry],
+        options: CommitOptions,
     ) -> Box<dyn ActionTask>;
+    /// Whether hooks that can reject a commit are installed
+    fn has_commit_hooks(&self) -> bool {
+        false
+    }
     fn revert_all(&self) -> Box<dyn ActionTask>;
-    fn revert_selected(&self, entries: &Vec<Entry>) -> Box<dyn ActionTask>;
+    fn revert_selected(&self, entries: &[Entry]) -> Box<dyn ActionTask>;
+    /// Uncolored diff of the selected files to pick the hunks to revert
+    /// from, with the `flags` of the diff being shown
+    fn get_current_diff(
+        &self,
+        _entries: &[Entry],
+        _flags: &[String],
+    ) -> BackendResult<String> {
+        Err(HUNKS_UNSUPPORTED.into())
+    }
+    /// Reverts the changes of `patch`, made of hunks of the diff of the
+    /// working tree
+    fn revert_hunks(&self, _patch: &str) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(HUNKS_UNSUPPORTED))
+    }
+    /// Applies `patch`, made of hunks of the diff of a revision, to the
+    /// working tree, leaving conflict markers where it doesn't apply cleanly
+    fn apply_hunks(&self, _patch: &str) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(APPLY_HUNKS_UNSUPPORTED))
+    }
+    /// Saves all local changes, keeping them in the working tree, so a
+    /// following discard can be undone
+    fn create_discard_snapshot(&self) -> BackendResult<()>;
+    /// Drops discard snapshots older than `max_age`
+    fn prune_discard_snapshots(&self, max_age: Duration) -> BackendResult<()>;
+    /// Reapplies the most recent discard snapshot
+    fn restore_discard_snapshot(&self) -> Box<dyn ActionTask>;
+
     fn update(&self, target: &str) -> Box<dyn ActionTask>;
     fn merge(&self, target: &str) -> Box<dyn ActionTask>;
 
@@ -68,11 +308,128 @@ pub trait VersionControlActions: Send {
     fn fetch(&self) -> Box<dyn ActionTask>;
     fn pull(&self) -> Box<dyn ActionTask>;
     fn push(&self) -> Box<dyn ActionTask>;
+    /// Patches `pull` would bring in, to pick them one by one with the
+    /// select ui. `None` when pulling always takes every change
+    fn get_incoming_patches(&self) -> BackendResult<Option<Vec<Entry>>> {
+        Ok(None)
+    }
+    /// Like `get_incoming_patches` for the patches `push` would send
+    fn get_outgoing_patches(&self) -> BackendResult<Option<Vec<Entry>>> {
+        Ok(None)
+    }
+    /// Pulls the selected entries of `get_incoming_patches`
+    fn pull_selected(&self, _entries: &[Entry]) -> Box<dyn ActionTask> {
+        self.pull()
+    }
+    /// Pushes the selected entries of `get_outgoing_patches`
+    fn push_selected(&self, _entries: &[Entry]) -> Box<dyn ActionTask> {
+        self.push()
+    }
+    fn push_to(
+        &self,
+        remote: &str,
+        branch: &str,
+        set_upstream: bool,
+    ) -> Box<dyn ActionTask>;
+    /// Pushes rewritten history only if the remote did not move since the
+    /// last fetch
+    fn force_push(&self) -> Box<dyn ActionTask>;
 
     fn create_tag(&self, name: &str) -> Box<dyn ActionTask>;
+    /// Tags matching the glob `pattern`, or all of them, newest first with
+    /// their target, date and subject
+    fn list_tags(&self, _pattern: Option<&str>) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(LIST_TAGS_UNSUPPORTED))
+    }
+    /// Operation interrupted midway in the repository, if any
+    fn operation_in_progress(&self) -> Option<Operation> {
+        None
+    }
+    /// Resumes `operation` once its conflicts are resolved
+    fn continue_operation(&self, _operation: Operation) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(OPERATIONS_UNSUPPORTED))
+    }
+    /// Gives up on `operation` going back to where it started
+    fn abort_operation(&self, _operation: Operation) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(OPERATIONS_UNSUPPORTED))
+    }
+    fn get_tag_names(&self) -> BackendResult<Vec<String>> {
+        Err(RENAME_TAG_UNSUPPORTED.into())
+    }
+    /// Creates tag `new_name` pointing at the target of `old_name`, keeping
+    /// its message, and deletes `old_name`. Both happen on `remote` too
+    fn rename_tag(
+        &self,
+        _old_name: &str,
+        _new_name: &str,
+        _remote: Option<&str>,
+    ) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(RENAME_TAG_UNSUPPORTED))
+    }
+    /// Lists one branch per line. Bookmarks, on backends with both, are
+    /// followed by `BOOKMARK_SUFFIX`
     fn list_branches(&self) -> Box<dyn ActionTask>;
     fn create_branch(&self, name: &str) -> Box<dyn ActionTask>;
+    /// Creates branch `name` and switches to it keeping the local changes
+    /// but, unlike `create_branch`, without publishing it
+    fn branch_off(&self, name: &str) -> Box<dyn ActionTask> {
+        self.create_branch(name)
+    }
+    /// Creates branch `name` at `target`, like a tag, and switches to it
+    fn branch_from(&self, name: &str, target: &str) -> Box<dyn ActionTask> {
+        let mut tasks = task_vec();
+        tasks.push(self.update(target));
+        tasks.push(self.branch_off(name));
+        serial_until_error(tasks)
+    }
     fn close_branch(&self, name: &str) -> Box<dyn ActionTask>;
+    /// Whether there are bookmarks besides branches
+    fn supports_bookmarks(&self) -> bool {
+        false
+    }
+    /// Creates bookmark `name` at the working copy and makes it active
+    fn create_bookmark(&self, _name: &str) -> Box<dyn ActionTask> {
+        ready(ActionResult::from_err(BOOKMARKS_UNSUPPORTED))
+    }
+    /// Adds a glob `pattern` relative to the root to the ignore rules
+    fn ignore(&self, pattern: &str) -> Box<dyn ActionTask>;
+
+    /// Number of changed files in the working tree, counted from the output
+    /// of `status` so it follows its options and scope without running again
+    fn changed_files_badge(&self, _status: &str) -> Option<String> {
+        None
+    }
+    // header badges show the trimmed output of these tasks and are hidden
+    // when there's no task, it fails or the output is empty
+    /// Commits the current branch is ahead and behind its upstream
+    fn ahead_behind_badge(&self) -> Option<Box<dyn ActionTask>> {
+        None
+    }
+    /// Counts of `ahead_behind_badge` as numbers, for the cli. `None` when
+    /// the backend can't tell them
+    fn ahead_behind(&self) -> Option<BackendResult<(usize, usize)>> {
+        None
+    }
+    /// Commits the current branch is ahead and behind `target`, shown even
+    /// when it's up to date with it
+    fn compare_target_badge(
+        &self,
+        _target: &str,
+    ) -> Option<Box<dyn ActionTask>> {
+        None
+    }
+    /// Number of local branches with commits not pushed to their upstream
+    fn unpushed_branches_badge(&self) -> Option<Box<dyn ActionTask>> {
+        None
+    }
+}
+
+/// Badge telling how many files changed
+pub fn changed_files_text(count: usize) -> String {
+    match count {
+        0 => "clean".into(),
+        count => format!("{} changed", count),
+    }
 }
 
 pub fn task<F>(
@@ -84,20 +441,119 @@ where
 {
     let mut command = version_control.command();
     (builder)(&mut command);
-    Box::new(CommandTask::Waiting(command))
+    Box::new(CommandTask::new(command))
 }
 
-pub fn handle_command(command: &mut Command) -> Result<String, String> {
+/// Like `task` but writes `input` to the stdin of the command
+pub fn task_with_input<F>(
+    version_control: &dyn VersionControlActions,
+    input: &str,
+    builder: F,
+) -> Box<dyn ActionTask>
+where
+    F: FnOnce(&mut Command),
+{
+    let mut command = version_control.command();
+    (builder)(&mut command);
+    let options = ChildOptions {
+        input: Some(input.into()),
+        ..Default::default()
+    };
+    Box::new(CommandTask::Waiting(command, options))
+}
+
+/// Like `task` but exit code 1 is not a failure, for commands that use it to
+/// report they found differences
+pub fn task_allowing_exit_one<F>(
+    version_control: &dyn VersionControlActions,
+    builder: F,
+) -> Box<dyn ActionTask>
+where
+    F: FnOnce(&mut Command),
+{
+    let mut command = version_control.command();
+    (builder)(&mut command);
+    let options = ChildOptions {
+        allow_exit_one: true,
+        ..Default::default()
+    };
+    Box::new(CommandTask::Waiting(command, options))
+}
+
+/// Appends `line` to the file at `path` relative to the repository root,
+/// creating it if needed. Written once the task runs so it follows the tasks
+/// before it
+pub fn append_to_root_file(
+    version_control: &dyn VersionControlActions,
+    path: &str,
+    line: &str,
+) -> Box<dyn ActionTask> {
+    let full_path = Path::new(version_control.get_root()).join(path);
+    let path = String::from(path);
+    let line = String::from(line);
+    deferred(move || {
+        let append = || -> io::Result<()> {
+            let needs_newline = match fs::read(&full_path) {
+                Ok(content) => !content.is_empty() && !content.ends_with(b"\n"),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => false,
+                Err(error) => return Err(error),
+            };
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&full_path)?;
+            if needs_newline {
+                file.write_all(b"\n")?;
+            }
+            writeln!(file, "{}", line)
+        };
+        match append() {
+            Ok(()) => {
+                ActionResult::from_ok(format!("added {} to {}", line, path))
+            }
+            Err(error) => ActionResult::from_err(format!(
+                "could not write to {}: {}",
+                path, error
+            )),
+        }
+    })
+}
+
+pub fn handle_command(command: &mut Command) -> BackendResult<String> {
+    let command_line = command_line(command);
     match command.output() {
         Ok(output) => {
             if output.status.success() {
-                String::from_utf8(output.stdout).map_err(|e| e.to_string())
+                String::from_utf8(output.stdout)
+                    .map_err(|e| e.to_string().into())
             } else {
-                String::from_utf8(output.stderr)
-                    .map_err(|e| e.to_string())
-                    .and_then(|o| Err(o))
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(BackendError::from_command(
+                    command_line,
+                    output.status.code(),
+                    stderr.into_owned(),
+                ))
             }
         }
-        Err(error) => Err(error.to_string()),
+        Err(error) => {
+            Err(BackendError::from_spawn(command_line, error.to_string()))
+        }
     }
 }
+
+pub fn discard_snapshot_name() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!("{}{}", DISCARD_SNAPSHOT_PREFIX, now)
+}
+
+/// Returns the age of a snapshot from its name if it is a discard snapshot
+pub fn discard_snapshot_age(name: &str) -> Option<Duration> {
+    let start = name.find(DISCARD_SNAPSHOT_PREFIX)?;
+    let timestamp = &name[start + DISCARD_SNAPSHOT_PREFIX.len()..];
+    let timestamp = timestamp.trim().parse().ok()?;
+    let time = UNIX_EPOCH + Duration::from_secs(timestamp);
+    Some(SystemTime::now().duration_since(time).unwrap_or_default())
+}
@@ -0,0 +1,245 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use notify::{RawEvent, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use crate::encoding::glob_matches;
+
+/// Changes are reported once no other arrives for this long so a checkout
+/// or a build refreshes once
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+/// Directories whose changes are skipped, where the backends keep their data
+const SKIPPED_DIRS: &[&str] =
+    &[".git", ".hg", ".jj", ".bzr", "_darcs", ".verco"];
+/// Backend files that change when the status changes without any file of
+/// the working tree changing, like after `git add`
+const BACKEND_FILES: &[&str] = &[".git/index", ".git/HEAD", ".hg/dirstate"];
+const GITIGNORE: &str = ".gitignore";
+/// Ignore files that only apply from the root
+const ROOT_IGNORE_FILES: &[&str] = &[".git/info/exclude", ".hgignore"];
+
+/// Watches the working tree of a repository through the file notifications
+/// of the operating system, skipping the ignored files
+pub struct Watcher {
+    changed: Arc<AtomicBool>,
+    /// Dropping it stops the notifications, which ends the thread
+    _watcher: RecommendedWatcher,
+}
+
+impl Watcher {
+    pub fn new(root: &str) -> Result<Self, String> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher =
+            notify::raw_watcher(sender).map_err(|e| e.to_string())?;
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .map_err(|e| format!("could not watch {}: {}", root, e))?;
+
+        let changed = Arc::new(AtomicBool::new(false));
+        let thread_changed = changed.clone();
+        let mut rules = IgnoreRules::new(PathBuf::from(root));
+        thread::spawn(move || {
+            while let Ok(event) = receiver.recv() {
+                let mut relevant = rules.is_relevant(&event);
+                while let Ok(event) = receiver.recv_timeout(SETTLE_TIME) {
+                    relevant |= rules.is_relevant(&event);
+                }
+                if relevant {
+                    thread_changed.store(true, Ordering::Relaxed);
+                }
+            }
+        });
+
+        Ok(Self {
+            changed,
+            _watcher: watcher,
+        })
+    }
+
+    /// Whether files changed since last called
+    pub fn poll_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Pattern of an ignore file
+struct Rule {
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    /// Matches the path from the directory of the ignore file instead of
+    /// at any depth below it
+    anchored: bool,
+}
+
+impl Rule {
+    /// Parses a `.gitignore` line
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        // escapes a leading `#` or `!`
+        let line = line.strip_prefix('\\').unwrap_or(line);
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let (anchored, line) = match line.strip_prefix("**/") {
+            Some(line) => (false, line),
+            None => (line.contains('/'), line),
+        };
+        let pattern = line.trim_start_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(Self {
+            pattern: pattern.into(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// `path` is relative to the directory of the ignore file
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let pattern = self.pattern.as_bytes();
+        if self.anchored {
+            return glob_matches(pattern, path.as_bytes());
+        }
+        if !self.pattern.contains('/') {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            return glob_matches(pattern, name.as_bytes());
+        }
+        std::iter::once(0)
+            .chain(path.match_indices('/').map(|(i, _)| i + 1))
+            .any(|i| glob_matches(pattern, &path.as_bytes()[i..]))
+    }
+}
+
+/// Rules of the ignore files, read as the directories holding them are
+/// first needed and again when they change
+struct IgnoreRules {
+    root: PathBuf,
+    /// Keyed by the directory relative to the root, `""` for the root
+    dirs: HashMap<String, Vec<Rule>>,
+}
+
+impl IgnoreRules {
+    fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            dirs: HashMap::new(),
+        }
+    }
+
+    /// Whether the event may change the status
+    fn is_relevant(&mut self, event: &RawEvent) -> bool {
+        // events without a path, like an overflow, may hide anything
+        let path = match &event.path {
+            Some(path) => path,
+            None => return true,
+        };
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+            Err(_) => return false,
+        };
+
+        if ROOT_IGNORE_FILES.contains(&&relative[..]) {
+            self.dirs.remove("");
+            return true;
+        }
+        if let Some(dir) = relative.strip_suffix(GITIGNORE) {
+            if dir.is_empty() || dir.ends_with('/') {
+                self.dirs.remove(dir.trim_end_matches('/'));
+                return true;
+            }
+        }
+
+        let first = relative.split('/').next().unwrap_or("");
+        if SKIPPED_DIRS.contains(&first) {
+            return BACKEND_FILES.contains(&&relative[..]);
+        }
+        !relative.is_empty() && !self.is_ignored(&relative, path.is_dir())
+    }
+
+    /// Like git, a path inside an ignored directory stays ignored and the
+    /// last matching rule wins, with rules of deeper directories going last
+    fn is_ignored(&mut self, relative: &str, is_dir: bool) -> bool {
+        let components: Vec<_> = relative.split('/').collect();
+        for end in 1..=components.len() {
+            let is_dir = end < components.len() || is_dir;
+            let mut ignored = false;
+            for base_end in 0..end {
+                let base = components[..base_end].join("/");
+                let path = components[base_end..end].join("/");
+                for rule in self.rules(base) {
+                    if rule.matches(&path, is_dir) {
+                        ignored = !rule.negated;
+                    }
+                }
+            }
+            if ignored {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn rules(&mut self, dir: String) -> &[Rule] {
+        let root = &self.root;
+        self.dirs.entry(dir).or_insert_with_key(|dir| {
+            let mut rules = Vec::new();
+            if dir.is_empty() {
+                read_root_rules(root, &mut rules);
+            }
+            let path = root.join(dir).join(GITIGNORE);
+            let content = fs::read_to_string(path).unwrap_or_default();
+            rules.extend(content.lines().filter_map(Rule::parse));
+            rules
+        })
+    }
+}
+
+/// Rules of `.git/info/exclude` and the glob ones of `.hgignore`, whose
+/// regular expressions are skipped
+fn read_root_rules(root: &Path, rules: &mut Vec<Rule>) {
+    let exclude = fs::read_to_string(root.join(".git/info/exclude"));
+    rules.extend(exclude.unwrap_or_default().lines().filter_map(Rule::parse));
+
+    let hgignore = fs::read_to_string(root.join(".hgignore"));
+    let mut glob_syntax = false;
+    for line in hgignore.unwrap_or_default().lines() {
+        let line = line.trim();
+        if let Some(syntax) = line.strip_prefix("syntax:") {
+            glob_syntax = syntax.trim() == "glob";
+            continue;
+        }
+        let pattern = match line.strip_prefix("glob:") {
+            Some(pattern) => pattern,
+            None if glob_syntax && !line.contains(':') => line,
+            None => continue,
+        };
+        let rule = Rule::parse(pattern).filter(|r| !r.negated);
+        rules.extend(rule.map(|rule| Rule {
+            anchored: false,
+            ..rule
+        }));
+    }
+}
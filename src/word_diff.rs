@@ -0,0 +1,233 @@
+use std::ops::Range;
+
+use crate::{
+    folds,
+    theme::{ansi_color, theme},
+    tui_util::{strip_ansi, HunkLines},
+};
+
+pub const HIGHLIGHT: &str = "\x1b[7m";
+pub const HIGHLIGHT_END: &str = "\x1b[27m";
+
+const RESET_COLOR: &str = "\x1b[0m";
+
+/// Lines with more words than this in both sides are left without word
+/// highlights as comparing them gets slow
+const MAX_TOKEN_PAIRS: usize = 250 * 250;
+
+/// Changed byte ranges of the old line and of the new line
+pub type Changes = (Vec<Range<usize>>, Vec<Range<usize>>);
+
+/// Colors the removed and added lines of the colored unified `diff` with the
+/// theme, replacing the colors the backend gave them. With
+/// `highlight_words`, also highlights the words that changed between each
+/// removed line and the added line in the same position of its block
+pub fn color_changes(diff: &str, highlight_words: bool) -> String {
+    let theme = theme();
+    let colors = Colors {
+        removed: ansi_color(theme.diff_removed),
+        added: ansi_color(theme.diff_added),
+        highlight_words,
+    };
+    let mut output = String::with_capacity(diff.len());
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+
+    let mut hunk_lines = HunkLines::default();
+    for line in diff.lines() {
+        let plain = strip_ansi(line);
+        let in_hunk = match folds::hidden_lines(line) {
+            Some(count) => {
+                hunk_lines.skip_unchanged(count);
+                false
+            }
+            None => !hunk_lines.start(&plain) && hunk_lines.next(&plain),
+        };
+        if in_hunk && plain.starts_with('-') {
+            if !added.is_empty() {
+                flush(&mut output, &colors, &mut removed, &mut added);
+            }
+            removed.push(plain);
+            continue;
+        } else if in_hunk && plain.starts_with('+') {
+            added.push(plain);
+            continue;
+        }
+
+        flush(&mut output, &colors, &mut removed, &mut added);
+        output.push_str(line);
+        output.push('\n');
+    }
+    flush(&mut output, &colors, &mut removed, &mut added);
+    output
+}
+
+struct Colors {
+    removed: String,
+    added: String,
+    highlight_words: bool,
+}
+
+/// Writes the block of changes, highlighting the words of the lines that
+/// have a pair
+fn flush(
+    output: &mut String,
+    colors: &Colors,
+    removed: &mut Vec<String>,
+    added: &mut Vec<String>,
+) {
+    let mut push_line = |plain: &str, color: &str, ranges: &[Range<usize>]| {
+        output.push_str(color);
+        output.push_str(&plain[..1]);
+        output.push_str(&highlight(&plain[1..], ranges));
+        output.push_str(RESET_COLOR);
+        output.push('\n');
+    };
+
+    let pairs = match colors.highlight_words {
+        true => removed.len().min(added.len()),
+        false => 0,
+    };
+    let changes: Vec<_> = (0..pairs)
+        .map(|i| changed_ranges(&removed[i][1..], &added[i][1..]))
+        .collect();
+    for (i, plain) in removed.iter().enumerate() {
+        match changes.get(i) {
+            Some(Some((ranges, _))) => {
+                push_line(plain, &colors.removed, ranges)
+            }
+            _ => push_line(plain, &colors.removed, &[]),
+        }
+    }
+    for (i, plain) in added.iter().enumerate() {
+        match changes.get(i) {
+            Some(Some((_, ranges))) => push_line(plain, &colors.added, ranges),
+            _ => push_line(plain, &colors.added, &[]),
+        }
+    }
+
+    removed.clear();
+    added.clear();
+}
+
+/// `text` with the byte `ranges` in reverse video
+pub fn highlight(text: &str, ranges: &[Range<usize>]) -> String {
+    let mut highlighted = String::with_capacity(text.len());
+    let mut last_end = 0;
+    for range in ranges {
+        highlighted.push_str(&text[last_end..range.start]);
+        highlighted.push_str(HIGHLIGHT);
+        highlighted.push_str(&text[range.clone()]);
+        highlighted.push_str(HIGHLIGHT_END);
+        last_end = range.end;
+    }
+    highlighted.push_str(&text[last_end..]);
+    highlighted
+}
+
+/// Byte ranges of the words of `old` missing from `new` and of the words of
+/// `new` missing from `old`. `None` when the lines have nothing but
+/// whitespace in common, as then the whole lines changed
+pub fn changed_ranges(old: &str, new: &str) -> Option<Changes> {
+    let old_tokens = tokenize(old);
+    let new_tokens = tokenize(new);
+    if old_tokens.len() * new_tokens.len() > MAX_TOKEN_PAIRS {
+        return None;
+    }
+
+    // longest common subsequence of tokens, `lengths[i][j]` being the one
+    // of `old_tokens[i..]` and `new_tokens[j..]`
+    let columns = new_tokens.len() + 1;
+    let mut lengths = vec![0u32; (old_tokens.len() + 1) * columns];
+    for i in (0..old_tokens.len()).rev() {
+        for j in (0..new_tokens.len()).rev() {
+            lengths[i * columns + j] = if old[old_tokens[i].clone()]
+                == new[new_tokens[j].clone()]
+            {
+                lengths[(i + 1) * columns + j + 1] + 1
+            } else {
+                lengths[(i + 1) * columns + j].max(lengths[i * columns + j + 1])
+            };
+        }
+    }
+
+    let mut old_changed = vec![true; old_tokens.len()];
+    let mut new_changed = vec![true; new_tokens.len()];
+    let mut has_common_words = false;
+    let (mut i, mut j) = (0, 0);
+    while i < old_tokens.len() && j < new_tokens.len() {
+        let token = &old[old_tokens[i].clone()];
+        if token == &new[new_tokens[j].clone()] {
+            has_common_words |= !token.trim().is_empty();
+            old_changed[i] = false;
+            new_changed[j] = false;
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * columns + j] >= lengths[i * columns + j + 1]
+        {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    if !has_common_words {
+        return None;
+    }
+    Some((
+        merge_changed(old, &old_tokens, &old_changed),
+        merge_changed(new, &new_tokens, &new_changed),
+    ))
+}
+
+/// Words, runs of whitespace and single punctuation chars of `text`
+fn tokenize(text: &str) -> Vec<Range<usize>> {
+    let kind = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            0
+        } else if c.is_whitespace() {
+            1
+        } else {
+            2
+        }
+    };
+
+    let mut tokens: Vec<Range<usize>> = Vec::new();
+    let mut previous_kind = None;
+    for (i, c) in text.char_indices() {
+        let c_kind = kind(c);
+        match tokens.last_mut() {
+            Some(token) if c_kind != 2 && previous_kind == Some(c_kind) => {
+                token.end = i + c.len_utf8();
+            }
+            _ => tokens.push(i..i + c.len_utf8()),
+        }
+        previous_kind = Some(c_kind);
+    }
+    tokens
+}
+
+/// Joins the changed tokens into ranges, including the whitespace between
+/// two changed tokens
+fn merge_changed(
+    text: &str,
+    tokens: &[Range<usize>],
+    changed: &[bool],
+) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let is_whitespace = text[token.clone()].trim().is_empty();
+        let joins_changes = is_whitespace
+            && i > 0
+            && changed[i - 1]
+            && changed.get(i + 1).copied().unwrap_or(false);
+        if !changed[i] && !joins_changes {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(range) if range.end == token.start => range.end = token.end,
+            _ => ranges.push(token.clone()),
+        }
+    }
+    ranges
+}